@@ -1,9 +1,39 @@
+pub mod actor_local;
+pub mod comando_consola;
 mod conexion_tcp;
+pub mod configuracion_ecommerce;
+pub mod configuracion_generador;
+pub mod configuracion_local;
+pub mod contadores;
+pub mod control;
 mod cordinador;
+pub mod despacho;
+pub mod error_csv;
 pub mod error_local;
+pub mod fallas;
+pub mod fuente_ordenes;
+pub mod generador_ordenes;
+pub mod historial;
+pub mod http;
+pub mod indice_espacial;
+pub mod info_local;
+pub mod invariantes;
 pub mod lector_csv;
 pub mod local;
+pub mod logs;
+pub mod mensaje_local;
 pub mod mensaje_protocolo;
+pub mod metricas;
 pub mod orden;
+pub mod politica_entrega;
+pub mod recientes;
+pub mod registro_ordenes;
+pub mod reloj;
+pub mod reposicion;
+pub mod simulacion;
 pub mod socket;
 pub mod stock_producto;
+pub mod supervision;
+pub mod transferencia;
+pub mod trazas;
+pub mod validacion;
@@ -0,0 +1,1679 @@
+use actix::dev::SendError;
+use actix::io::SinkWrite;
+use actix::{
+    Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Context, Handler, Message,
+    MessageResult, Recipient, ResponseActFuture, Running, StreamHandler, Supervised, Supervisor,
+    WrapFuture,
+};
+use actix_rt::net::UdpSocket;
+use actix_rt::System;
+use futures::stream::SplitSink;
+use futures::StreamExt;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tokio_util::bytes::{Bytes, BytesMut};
+use tokio_util::codec::BytesCodec;
+use tokio_util::udp::UdpFramed;
+use tracing::{debug, error, info, info_span, warn};
+
+use crate::common::comando_consola::ModoFalla;
+use crate::common::contadores::Contadores;
+use crate::common::error_csv::ErrorCsv;
+use crate::common::error_local::ErrorLocal;
+use crate::common::fallas::{ConfiguracionFallas, Fallas};
+use crate::common::local::{Local, Productos};
+use crate::common::mensaje_local::{MensajeLocal, MotivoCancelacion, RespuestaOrden};
+use crate::common::metricas::EnCola;
+use crate::common::orden::Orden;
+use crate::common::politica_entrega::{Accion, PoliticaDeEntrega};
+use crate::common::reloj::Reloj;
+use crate::common::reposicion::{Reposicion, ReposicionAutomatica};
+use crate::common::socket::id_to_addr_local;
+use crate::common::stock_producto::StockProducto;
+use crate::common::supervision::{PoliticaDeReinicio, Reinicios};
+use crate::common::transferencia::{IdTransferencia, Transferencia};
+use crate::common::trazas::{con_padre, contexto_actual};
+use crate::common::validacion::leer_stock;
+
+type SinkItem = (Bytes, SocketAddr);
+type UdpSink = SplitSink<UdpFramed<BytesCodec, Arc<UdpSocket>>, SinkItem>;
+
+const INTERVALO_VENCIMIENTOS: Duration = Duration::from_millis(1000);
+/// Veces que se envia un aviso de cancelación al ecommerce antes de abandonarlo
+const INTENTOS_AVISO_CANCELACION: u32 = 5;
+/// Veces que se envia un pedido o un envio de transferencia antes de darla por perdida
+const INTENTOS_TRANSFERENCIA: u32 = 5;
+/// Cuanto espera el que no pudo dejarle un mensaje al ActorLocal antes de reintentar
+pub const ESPERA_LOCAL_OCUPADO: Duration = Duration::from_millis(500);
+/// Tiempo que sigue respondiendo LocalCerrado antes de detener el sistema
+const ESPERA_CIERRE: Duration = Duration::from_millis(1000);
+/// Tiempo maximo que se espera al cerrar a que se confirmen los avisos y las transferencias,
+/// alcanza para que se agoten sus reintentos
+const ESPERA_CIERRE_MAXIMA: Duration = Duration::from_millis(6000);
+const INTERVALO_CIERRE: Duration = Duration::from_millis(100);
+
+/// Lo que necesitan los actores de un local además del stock y el socket
+pub struct ConfiguracionActores {
+    pub id: usize,
+    pub politica: PoliticaDeEntrega,
+    pub plazo_ordenes: Duration,
+    pub reposicion_automatica: Option<ReposicionAutomatica>,
+    pub proveedor: Option<SocketAddr>,
+    pub vecinos: Vec<usize>,
+    /// Direcciones de los locales que no estan en la dirección por defecto de su id
+    pub direcciones_locales: HashMap<usize, SocketAddr>,
+    pub capacidad_mailbox: usize,
+    /// Donde se guarda el stock al cerrar
+    pub archivo_estado: String,
+    /// Donde se guarda el estado después de cada cambio, para retomarlo con `recuperar_local`
+    /// si se cae el proceso
+    pub archivo_recuperacion: Option<String>,
+    /// Tiempo entre entregas del Job de ordenes
+    pub intervalo_entregas: Duration,
+    /// De donde salen los vencimientos, los numeros de transferencia y las esperas del cierre
+    pub reloj: Arc<dyn Reloj>,
+    /// Si al terminar de cerrar se detiene el sistema de actix, el binario lo detiene para
+    /// terminar el proceso. Una simulación con varios locales en el mismo sistema no
+    pub detener_sistema: bool,
+}
+
+/// Direcciones de los actores de un local
+pub struct ActoresLocal {
+    pub local: Addr<ActorLocal>,
+    pub udp: Addr<UdpClientActor>,
+    pub job_ordenes: Addr<JobOrdenes>,
+    /// Mensajes que esperan en el mailbox del ActorLocal
+    pub en_cola: EnCola,
+    /// Reinicios de los actores supervisados del local
+    pub reinicios: Reinicios,
+}
+
+/// Arranca el ActorLocal con el UdpClientActor que atiende el socket y el Job que entrega
+/// las ordenes, se llama desde un sistema de actix. Las fallas se aplican a todo lo que
+/// entra y sale por el socket
+pub fn iniciar_actores(
+    configuracion: ConfiguracionActores,
+    local: Local,
+    socket: UdpSocket,
+    fallas: Arc<Fallas>,
+) -> ActoresLocal {
+    let en_cola = EnCola::default();
+    let reinicios = Reinicios::default();
+    let mut contexto = Context::new();
+    contexto.set_mailbox_capacity(configuracion.capacidad_mailbox);
+    let addr_local = contexto.address();
+
+    let udp = UdpClientActor::start(
+        socket,
+        &addr_local,
+        configuracion.capacidad_mailbox,
+        en_cola.clone(),
+        fallas,
+        reinicios.clone(),
+    );
+    let recipient_job_ordenes = addr_local.recipient();
+    let en_cola_job = en_cola.clone();
+    let intervalo = configuracion.intervalo_entregas;
+    let reinicios_job = reinicios.clone();
+    let job_ordenes = Supervisor::start(move |_| JobOrdenes {
+        recipient_local: recipient_job_ordenes,
+        en_cola_local: en_cola_job,
+        intervalo,
+        supervision: PoliticaDeReinicio::por_defecto("JobOrdenes").con_reinicios(reinicios_job),
+    });
+
+    let local = contexto.run(ActorLocal {
+        id: configuracion.id,
+        local,
+        politica: configuracion.politica,
+        plazo_ordenes: configuracion.plazo_ordenes,
+        reposicion_automatica: configuracion.reposicion_automatica,
+        proveedor: configuracion.proveedor,
+        vecinos: configuracion.vecinos,
+        proximo_vecino: 0,
+        // los numeros no se repiten aunque el local se reinicie
+        numero_transferencia: configuracion.reloj.ahora_en_ms(),
+        origenes: HashMap::new(),
+        avisos: BTreeMap::new(),
+        ecommerces: BTreeSet::new(),
+        pedidos_transferencia: BTreeMap::new(),
+        envios_transferencia: BTreeMap::new(),
+        direcciones_locales: configuracion.direcciones_locales,
+        cerrando: false,
+        archivo_estado: configuracion.archivo_estado,
+        archivo_recuperacion: configuracion.archivo_recuperacion,
+        detener_sistema: configuracion.detener_sistema,
+        reloj: configuracion.reloj,
+        contadores: Contadores::default(),
+        en_cola: en_cola.clone(),
+        reinicios: reinicios.clone(),
+        recipient_enviar_mensaje: udp.clone().recipient(),
+        recipient_job_ordenes: job_ordenes.clone().recipient(),
+        recipients_caer: vec![udp.clone().recipient(), job_ordenes.clone().recipient()],
+    });
+    ActoresLocal {
+        local,
+        udp,
+        job_ordenes,
+        en_cola,
+        reinicios,
+    }
+}
+
+/// Envia un mensaje sin bloquear, si el mailbox del destino esta lleno o el actor se detuvo
+/// se descarta el mensaje y se informa el error. Las respuestas del ActorLocal no pasan por
+/// acá sino por `do_send`, que no respeta la capacidad del mailbox: si se descarta la
+/// respuesta a una confirmación el ecommerce la reenvía hasta que le llegue
+fn enviar_o_informar<M>(recipient: &Recipient<M>, mensaje: M, destino: &str) -> bool
+where
+    M: Message + Send,
+    M::Result: Send,
+{
+    match recipient.try_send(mensaje) {
+        Ok(_) => true,
+        Err(e) => {
+            error!(target: "local", destino, "No se pudo enviar mensaje: {}", e);
+            false
+        }
+    }
+}
+
+/// Actor que convierte el socket udp en un stream y maneja los mensajes que recibe a través de él
+pub struct UdpClientActor {
+    socket: Arc<UdpSocket>,
+    sink: Option<SinkWrite<SinkItem, UdpSink>>,
+    aceptar_ordenes: bool,
+    fallas: Arc<Fallas>,
+    paquetes_descartados: u64,
+    respuestas_ocupado: u64,
+    recipient_ofertas: Recipient<OfrecerOrden>,
+    recipient_local: Recipient<AgregarOrden>,
+    recipient_mensajes: Recipient<RecibirMensajeLocal>,
+    /// Mensajes que esperan en el mailbox del ActorLocal
+    en_cola_local: EnCola,
+    supervision: PoliticaDeReinicio,
+}
+
+impl UdpClientActor {
+    /// Inicia el actor supervisado a partir de un UdpSocket y el ActorLocal al que le pasa los
+    /// mensajes OfrecerOrden, AgregarOrden y RecibirMensajeLocal, con un mailbox de
+    /// `capacidad_mailbox` mensajes. Las fallas se aplican a todo lo que entra y sale por el socket
+    fn start(
+        socket: UdpSocket,
+        addr_local: &Addr<ActorLocal>,
+        capacidad_mailbox: usize,
+        en_cola_local: EnCola,
+        fallas: Arc<Fallas>,
+        reinicios: Reinicios,
+    ) -> Addr<UdpClientActor> {
+        let socket = Arc::new(socket);
+        let recipient_ofertas = addr_local.clone().recipient();
+        let recipient_local = addr_local.clone().recipient();
+        let recipient_mensajes = addr_local.clone().recipient();
+        Supervisor::start(move |ctx: &mut Context<Self>| {
+            ctx.set_mailbox_capacity(capacidad_mailbox);
+            UdpClientActor {
+                socket,
+                sink: None,
+                aceptar_ordenes: true,
+                fallas,
+                paquetes_descartados: 0,
+                respuestas_ocupado: 0,
+                recipient_ofertas,
+                recipient_local,
+                recipient_mensajes,
+                en_cola_local,
+                supervision: PoliticaDeReinicio::por_defecto("UdpClientActor")
+                    .con_reinicios(reinicios),
+            }
+        })
+    }
+
+    /// Convierte el socket en un stream de paquetes y un sink para responder, se hace al iniciar
+    /// y en cada reinicio porque el supervisor descarta el stream del contexto anterior
+    fn escuchar(&mut self, ctx: &mut Context<Self>) {
+        let (sink, stream) = UdpFramed::new(self.socket.clone(), BytesCodec::new()).split();
+        ctx.add_stream(stream.filter_map(
+            |item: Result<(BytesMut, SocketAddr), io::Error>| async {
+                item.map(|(data, sender)| UdpPacket(data, sender)).ok()
+            },
+        ));
+        self.sink = Some(SinkWrite::new(sink, ctx));
+    }
+
+    /// Si el destino esta incomunicado por una falla el paquete se pierde sin error, como en la red
+    fn escribir(&mut self, datos: Bytes, direccion: SocketAddr) -> bool {
+        if self.fallas.incomunicado(self.fallas.nombre(&direccion)) {
+            debug!(target: "falla", destino = %direccion, "Se pierde paquete enviado");
+            return true;
+        }
+        self.sink
+            .as_mut()
+            .is_some_and(|sink| sink.write((datos, direccion)).is_ok())
+    }
+}
+
+impl Actor for UdpClientActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if let Some(espera) = self.supervision.espera() {
+            ctx.run_later(espera, |act, ctx| act.escuchar(ctx));
+        }
+    }
+}
+
+/// Si falla el socket el actor se detiene y el supervisor lo reinicia volviendo a escuchar
+impl Supervised for UdpClientActor {
+    fn restarting(&mut self, _ctx: &mut Self::Context) {
+        self.sink = None;
+        self.supervision.reiniciando(Instant::now());
+    }
+}
+
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+struct UdpPacket(BytesMut, SocketAddr);
+
+/// Cada vez que un mensaje entra por el socket, el mensaje entra en la queue del Actor
+/// El socket caido se simula con self.aceptar_ordenes en false
+/// Deserializa la orden validando que tenga el formato correcto, si lo tiene se la ofrece al actor Local.
+/// Si el mailbox del actor Local esta lleno se le responde al ecommerce que el local esta ocupado
+/// para que reenvie la orden a otro local, o la confirmación más tarde.
+/// Si no es una orden puede ser su confirmación o un mensaje de otro proceso, como una reposición
+/// o una transferencia
+/// Con fallas simuladas el paquete se descarta o se procesa con demora, una o dos veces
+impl StreamHandler<UdpPacket> for UdpClientActor {
+    fn handle(&mut self, item: UdpPacket, ctx: &mut Self::Context) {
+        let demoras = self.fallas.al_recibir(self.fallas.nombre(&item.1));
+        if demoras.is_empty() {
+            self.paquetes_descartados += 1;
+            debug!(target: "falla", remitente = %item.1, "Se descarta paquete");
+        }
+        for demora in demoras {
+            let item = item.clone();
+            if demora.is_zero() {
+                self.procesar_paquete(item);
+            } else {
+                ctx.run_later(demora, move |act, _ctx| act.procesar_paquete(item));
+            }
+        }
+    }
+}
+
+impl UdpClientActor {
+    fn procesar_paquete(&mut self, item: UdpPacket) {
+        debug!(target: "udp", remitente = %item.1, bytes = item.0.len(), "Paquete recibido");
+        // TODO: ver de sacar este atomicbool
+        if !self.aceptar_ordenes {
+            info!(target: "udp", "Simulando local caido, no acepta orden");
+            return;
+        }
+        match Orden::deserializar(item.0.iter().as_slice()) {
+            Ok(mut orden) => {
+                let _span = con_padre(
+                    info_span!(target: "udp", "recibir_orden", orden = orden.id.as_deref()),
+                    orden.traza.as_ref(),
+                )
+                .entered();
+                orden.traza = contexto_actual().or(orden.traza);
+                info!(
+                    target: "udp",
+                    orden = orden.id.as_deref(),
+                    id_producto = orden.id_producto,
+                    cantidad = orden.cantidad,
+                    remitente = %item.1,
+                    "Orden recibida"
+                );
+                let id_orden = orden.id.clone();
+                if enviar_o_informar(
+                    &self.recipient_ofertas,
+                    OfrecerOrden(orden, item.1),
+                    "ActorLocal",
+                ) {
+                    self.en_cola_local.encolado();
+                } else {
+                    self.responder_local_ocupado(id_orden, false, item.1);
+                }
+            }
+            Err(_e) => match MensajeLocal::deserializar(item.0.iter().as_slice()) {
+                Ok(MensajeLocal::ConfirmarOrden { orden }) => {
+                    let id_orden = orden.id.clone();
+                    if enviar_o_informar(
+                        &self.recipient_local,
+                        AgregarOrden(orden, item.1),
+                        "ActorLocal",
+                    ) {
+                        self.en_cola_local.encolado();
+                    } else {
+                        self.responder_local_ocupado(id_orden, true, item.1);
+                    }
+                }
+                Ok(mensaje) => {
+                    if enviar_o_informar(
+                        &self.recipient_mensajes,
+                        RecibirMensajeLocal(mensaje, item.1),
+                        "ActorLocal",
+                    ) {
+                        self.en_cola_local.encolado();
+                    }
+                }
+                Err(_e) => self.responder_mensaje_no_reconocido(item.1),
+            },
+        };
+    }
+}
+
+impl UdpClientActor {
+    /// Contesta que el local esta ocupado a la oferta o a la confirmación de una orden
+    fn responder_local_ocupado(
+        &mut self,
+        orden: Option<String>,
+        confirmacion: bool,
+        direccion: SocketAddr,
+    ) {
+        self.respuestas_ocupado += 1;
+        warn!(target: "udp", orden = orden.as_deref(), confirmacion, "Local ocupado, no se atiende la orden");
+        let respuesta = MensajeLocal::Respuesta {
+            orden: orden.unwrap_or_default(),
+            confirmacion,
+            respuesta: RespuestaOrden::Ocupado,
+        };
+        let escrito = match respuesta.serializar() {
+            Ok(mensaje) => self.escribir(mensaje.into(), direccion),
+            Err(_) => false,
+        };
+        if !escrito {
+            error!(target: "udp", "No se pudo enviar la respuesta de local ocupado");
+        }
+    }
+
+    fn responder_mensaje_no_reconocido(&mut self, direccion: SocketAddr) {
+        warn!(target: "udp", remitente = %direccion, "Mensaje no reconocido");
+        if self.escribir("MENSAJE NO RECONOCIDO".as_bytes().into(), direccion) {
+        } else {
+            error!(target: "udp", "No se pudo enviar mensaje MENSAJE NO RECONOCIDO");
+        };
+    }
+}
+
+/// Un error de escritura en el socket detiene al actor para que el supervisor lo reinicie
+impl actix::io::WriteHandler<io::Error> for UdpClientActor {
+    fn error(&mut self, err: io::Error, _ctx: &mut Self::Context) -> Running {
+        error!(target: "udp", "Error en el socket: {}", err);
+        Running::Stop
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct EnviarMensajeLocal(MensajeLocal, SocketAddr);
+
+/// Envia por el socket udp un mensaje del local a otro proceso
+impl Handler<EnviarMensajeLocal> for UdpClientActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: EnviarMensajeLocal, _ctx: &mut Self::Context) -> Self::Result {
+        match msg.0.serializar() {
+            Ok(mensaje) => {
+                if !self.escribir(mensaje.into(), msg.1) {
+                    error!(target: "udp", "No se pudo enviar mensaje {:?}", msg.0);
+                }
+            }
+            Err(e) => error!(target: "udp", "No se pudo serializar mensaje: {}", e),
+        }
+    }
+}
+
+/// Simula que se cae el proceso del local: sus actores se detienen sin guardar nada y lo que
+/// tenían en memoria se pierde. Se le envia al ActorLocal, que se lo reenvia al resto
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Caer;
+
+impl Handler<Caer> for UdpClientActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Caer, ctx: &mut Self::Context) -> Self::Result {
+        ctx.stop();
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CambiarModoFalla(pub ModoFalla);
+
+impl Handler<CambiarModoFalla> for UdpClientActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: CambiarModoFalla, _ctx: &mut Self::Context) -> Self::Result {
+        self.fallas.aplicar(msg.0);
+    }
+}
+
+/// Estado del UdpClientActor que se informa por el socket de control
+pub struct EstadoUdp {
+    pub aceptar_ordenes: bool,
+    pub fallas: ConfiguracionFallas,
+    pub paquetes_descartados: u64,
+    pub respuestas_ocupado: u64,
+}
+
+#[derive(Message)]
+#[rtype(result = "EstadoUdp")]
+pub struct ConsultarEstadoUdp;
+
+impl Handler<ConsultarEstadoUdp> for UdpClientActor {
+    type Result = MessageResult<ConsultarEstadoUdp>;
+
+    fn handle(&mut self, _msg: ConsultarEstadoUdp, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(EstadoUdp {
+            aceptar_ordenes: self.aceptar_ordenes,
+            fallas: self.fallas.configuracion(),
+            paquetes_descartados: self.paquetes_descartados,
+            respuestas_ocupado: self.respuestas_ocupado,
+        })
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct AceptarOrdenes(pub bool);
+
+impl Handler<AceptarOrdenes> for UdpClientActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: AceptarOrdenes, _ctx: &mut Self::Context) -> Self::Result {
+        debug!(target: "udp", aceptar_ordenes = msg.0, "Cambia la aceptación de ordenes");
+        self.aceptar_ordenes = msg.0;
+    }
+}
+
+// Empieza ActorLocal
+/// Es encargado de manejar el stock y ordenes del local, el resto de los actores
+/// les envian mensajes con el tipo de acción a realizar: agregar una orden, entregarla/cancelarla
+/// vender en local y les responde el resultado de la operación
+pub struct ActorLocal {
+    id: usize,
+    local: Local,
+    politica: PoliticaDeEntrega,
+    plazo_ordenes: Duration,
+    reposicion_automatica: Option<ReposicionAutomatica>,
+    proveedor: Option<SocketAddr>,
+    vecinos: Vec<usize>,
+    proximo_vecino: usize,
+    numero_transferencia: u64,
+    /// Direccion del ecommerce que envio cada orden en progreso, para avisarle si se cancela
+    origenes: HashMap<String, SocketAddr>,
+    /// Avisos de cancelación que el ecommerce todavia no confirmo, por id de orden
+    avisos: BTreeMap<String, AvisoCancelacion>,
+    /// Ecommerce que le enviaron ordenes, se les avisa cuando el local se cierra
+    ecommerces: BTreeSet<SocketAddr>,
+    /// Transferencias pedidas a otro local que todavia no llegaron
+    pedidos_transferencia: BTreeMap<IdTransferencia, Reintentos>,
+    /// Transferencias enviadas a otro local que todavia no confirmó
+    envios_transferencia: BTreeMap<IdTransferencia, Reintentos>,
+    /// Direcciones de los locales que no estan en la dirección por defecto de su id
+    direcciones_locales: HashMap<usize, SocketAddr>,
+    cerrando: bool,
+    archivo_estado: String,
+    archivo_recuperacion: Option<String>,
+    detener_sistema: bool,
+    reloj: Arc<dyn Reloj>,
+    contadores: Contadores,
+    en_cola: EnCola,
+    reinicios: Reinicios,
+    recipient_enviar_mensaje: Recipient<EnviarMensajeLocal>,
+    recipient_job_ordenes: Recipient<ResultadoEntregarOrden>,
+    /// Actores del local que se detienen con él si se cae
+    recipients_caer: Vec<Recipient<Caer>>,
+}
+
+/// Aviso de cancelación pendiente de confirmar por el ecommerce
+struct AvisoCancelacion {
+    motivo: MotivoCancelacion,
+    destino: SocketAddr,
+    intentos: u32,
+}
+
+/// A donde se reenvia un mensaje de transferencia sin respuesta y cuantas veces se envió
+struct Reintentos {
+    destino: SocketAddr,
+    intentos: u32,
+}
+
+impl Actor for ActorLocal {
+    type Context = Context<Self>;
+
+    /// Al arrancar retoma las transferencias del estado guardado, pide reposición de lo que
+    /// ya viene con stock bajo y guarda el estado con el que arranca. Cada cierto tiempo cancela las ordenes en progreso vencidas
+    /// para que una orden que nunca se entrega no deje bloqueado el stock del producto, y
+    /// reenvia los avisos de cancelación y los mensajes de transferencia que no tuvieron respuesta
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.retomar_transferencias();
+        self.pedir_reposicion_si_falta_stock();
+        self.guardar_recuperacion();
+        ctx.run_interval(INTERVALO_VENCIMIENTOS, |act, _ctx| {
+            for orden in act.local.cancelar_vencidas(act.reloj.ahora_en_ms()) {
+                act.contadores.ordenes_vencidas += 1;
+                info!(
+                    target: "vencimiento",
+                    orden = orden.id.as_deref(),
+                    id_producto = orden.id_producto,
+                    cantidad = orden.cantidad,
+                    "Orden vencida cancelada, se liberan sus unidades"
+                );
+                act.avisar_cancelacion(orden.id.as_deref(), MotivoCancelacion::Vencida);
+            }
+            act.reenviar_avisos();
+            act.reintentar_transferencias();
+            act.guardar_recuperacion();
+        });
+    }
+}
+
+impl ActorLocal {
+    /// Le contesta al ecommerce la oferta o la confirmación de una orden
+    fn responder_orden(
+        &self,
+        orden: Option<String>,
+        confirmacion: bool,
+        respuesta: RespuestaOrden,
+        destino: SocketAddr,
+    ) {
+        let mensaje = MensajeLocal::Respuesta {
+            orden: orden.unwrap_or_default(),
+            confirmacion,
+            respuesta,
+        };
+        self.recipient_enviar_mensaje
+            .do_send(EnviarMensajeLocal(mensaje, destino));
+    }
+
+    /// Guarda el estado para retomarlo si se cae el proceso, antes de contestar lo que cambió
+    fn guardar_recuperacion(&self) {
+        let Some(archivo) = &self.archivo_recuperacion else {
+            return;
+        };
+        if let Err(e) = guardar_estado(&self.local, archivo) {
+            error!(target: "local", archivo = %archivo, "No se pudo guardar el estado: {}", e);
+        }
+    }
+
+    /// Las transferencias del estado guardado siguen: las enviadas se reenvian hasta que se
+    /// confirmen y las pedidas se dejan de esperar, porque no se sabe a quien se le pidieron.
+    /// Si llegan igual se reciben
+    fn retomar_transferencias(&mut self) {
+        let mut salientes: Vec<IdTransferencia> = self
+            .local
+            .transferencias_salientes
+            .keys()
+            .copied()
+            .collect();
+        salientes.sort();
+        for id in salientes {
+            if let Some(destino) = self.direccion_local(id.id_local) {
+                info!(target: "transferencia", transferencia = %id, "Se retoma el envio");
+                self.envios_transferencia.insert(
+                    id,
+                    Reintentos {
+                        destino,
+                        intentos: 0,
+                    },
+                );
+            }
+        }
+        let mut entrantes: Vec<IdTransferencia> = self
+            .local
+            .transferencias_entrantes
+            .keys()
+            .copied()
+            .collect();
+        entrantes.sort();
+        for id in entrantes {
+            if self.local.vencer_transferencia_entrante(&id).is_ok() {
+                info!(target: "transferencia", transferencia = %id, "Se deja de esperar al retomar");
+            }
+        }
+    }
+
+    /// Olvida de donde vino la orden y, si se cancelo, le avisa al ecommerce que la envio
+    fn orden_finalizada(&mut self, id_orden: Option<&str>, accion: Accion) {
+        match accion {
+            Accion::Entregar => {
+                if let Some(id) = id_orden {
+                    self.origenes.remove(id);
+                }
+            }
+            Accion::Cancelar => self.avisar_cancelacion(id_orden, MotivoCancelacion::Cancelada),
+        }
+    }
+
+    /// Registra el aviso de cancelación de la orden y lo envia por primera vez. Las ordenes
+    /// sin ecommerce de origen (las cargadas desde el estado guardado) no se avisan
+    fn avisar_cancelacion(&mut self, id_orden: Option<&str>, motivo: MotivoCancelacion) {
+        let Some(id) = id_orden.map(str::to_string) else {
+            return;
+        };
+        let Some(destino) = self.origenes.remove(&id) else {
+            return;
+        };
+        self.avisos.insert(
+            id.clone(),
+            AvisoCancelacion {
+                motivo,
+                destino,
+                intentos: 0,
+            },
+        );
+        self.enviar_aviso(&id);
+    }
+
+    fn enviar_aviso(&mut self, id: &str) {
+        let Some(aviso) = self.avisos.get_mut(id) else {
+            return;
+        };
+        aviso.intentos += 1;
+        let mensaje = MensajeLocal::OrdenCancelada {
+            orden: id.to_string(),
+            local: self.id,
+            motivo: aviso.motivo,
+        };
+        self.recipient_enviar_mensaje
+            .do_send(EnviarMensajeLocal(mensaje, aviso.destino));
+    }
+
+    /// Reenvia los pedidos y envios de transferencias sin respuesta. Un pedido que agotó
+    /// sus intentos se deja de esperar y un envio que agotó los suyos libera sus unidades
+    fn reintentar_transferencias(&mut self) {
+        let pedidos: Vec<IdTransferencia> = self.pedidos_transferencia.keys().copied().collect();
+        for id in pedidos {
+            let Some(transferencia) = self.local.transferencias_entrantes.get(&id).cloned() else {
+                self.pedidos_transferencia.remove(&id);
+                continue;
+            };
+            let Some(pedido) = self.pedidos_transferencia.get_mut(&id) else {
+                continue;
+            };
+            if pedido.intentos < INTENTOS_TRANSFERENCIA {
+                pedido.intentos += 1;
+                let destino = pedido.destino;
+                self.recipient_enviar_mensaje.do_send(EnviarMensajeLocal(
+                    MensajeLocal::PedidoTransferencia(transferencia),
+                    destino,
+                ));
+                continue;
+            }
+            self.pedidos_transferencia.remove(&id);
+            match self.local.vencer_transferencia_entrante(&id) {
+                Ok(_) => {
+                    warn!(target: "transferencia", transferencia = %id, "No llegó, se deja de esperar")
+                }
+                Err(e) => warn!(target: "transferencia", transferencia = %id, "{}", e),
+            }
+        }
+
+        let envios: Vec<IdTransferencia> = self.envios_transferencia.keys().copied().collect();
+        for id in envios {
+            let Some(transferencia) = self.local.transferencias_salientes.get(&id).cloned() else {
+                self.envios_transferencia.remove(&id);
+                continue;
+            };
+            let Some(envio) = self.envios_transferencia.get_mut(&id) else {
+                continue;
+            };
+            if envio.intentos < INTENTOS_TRANSFERENCIA {
+                envio.intentos += 1;
+                let destino = envio.destino;
+                self.recipient_enviar_mensaje.do_send(EnviarMensajeLocal(
+                    MensajeLocal::EnvioTransferencia(transferencia),
+                    destino,
+                ));
+                continue;
+            }
+            self.envios_transferencia.remove(&id);
+            match self.local.liberar_transferencia_saliente(&id) {
+                Ok(_) => warn!(
+                    target: "transferencia",
+                    transferencia = %id,
+                    "No se confirmó la recepción, se liberan las unidades"
+                ),
+                Err(e) => warn!(target: "transferencia", transferencia = %id, "{}", e),
+            }
+        }
+    }
+
+    /// Reenvia los avisos sin confirmar, los que agotaron sus intentos se abandonan
+    fn reenviar_avisos(&mut self) {
+        let ids: Vec<String> = self.avisos.keys().cloned().collect();
+        for id in ids {
+            let agotado = self
+                .avisos
+                .get(&id)
+                .is_some_and(|aviso| aviso.intentos >= INTENTOS_AVISO_CANCELACION);
+            if agotado {
+                self.avisos.remove(&id);
+                warn!(
+                    target: "cancelacion",
+                    orden = %id,
+                    "El ecommerce no confirmo la cancelación, se deja de avisar"
+                );
+            } else {
+                self.enviar_aviso(&id);
+            }
+        }
+    }
+
+    fn contar_orden_finalizada(&mut self, accion: Accion) {
+        match accion {
+            Accion::Entregar => self.contadores.ordenes_entregadas += 1,
+            Accion::Cancelar => self.contadores.ordenes_canceladas += 1,
+        }
+    }
+
+    /// Pide reposición de los productos que quedaron con stock bajo, si hay un proveedor
+    /// configurado se le envia el pedido
+    fn pedir_reposicion_si_falta_stock(&mut self) {
+        let pedidos = match self.reposicion_automatica.as_mut() {
+            Some(reposicion_automatica) => {
+                reposicion_automatica.pedidos(&self.local.productos_en_stock)
+            }
+            None => return,
+        };
+        for pedido in pedidos {
+            info!(
+                target: "reposicion",
+                id_producto = pedido.id_producto,
+                cantidad = pedido.cantidad,
+                "Stock bajo, se pide reposición"
+            );
+            if let Some(proveedor) = self.proveedor {
+                self.recipient_enviar_mensaje.do_send(EnviarMensajeLocal(
+                    MensajeLocal::PedidoReposicion {
+                        id_local: self.id,
+                        pedido,
+                    },
+                    proveedor,
+                ));
+            }
+        }
+    }
+}
+
+impl ActorLocal {
+    /// Pide unidades de un producto a otro local y las registra como en transito
+    fn pedir_transferencia(&mut self, id_local_origen: usize, id_producto: usize, cantidad: usize) {
+        let id = IdTransferencia {
+            id_local: self.id,
+            numero: self.numero_transferencia,
+        };
+        self.numero_transferencia += 1;
+        let transferencia = Transferencia::new(id, id_producto, cantidad);
+        info!(
+            target: "transferencia",
+            transferencia = %id,
+            id_producto,
+            cantidad,
+            id_local_origen,
+            "Se pide transferencia"
+        );
+        let Some(destino) = self.direccion_local(id_local_origen) else {
+            return;
+        };
+        self.local
+            .registrar_transferencia_entrante(transferencia.clone());
+        self.pedidos_transferencia.insert(
+            id,
+            Reintentos {
+                destino,
+                intentos: 1,
+            },
+        );
+        self.recipient_enviar_mensaje.do_send(EnviarMensajeLocal(
+            MensajeLocal::PedidoTransferencia(transferencia),
+            destino,
+        ));
+    }
+
+    /// Si falta stock para una orden le pide a un vecino las unidades faltantes que no
+    /// esten ya en transito. Se le pide a cada vecino por turnos
+    fn pedir_faltante_a_vecino(&mut self, id_producto: usize, cantidad: usize) {
+        if self.vecinos.is_empty() {
+            return;
+        }
+        let disponibles = self
+            .local
+            .productos_en_stock
+            .get(&id_producto)
+            .map_or(0, |producto| producto.disponibles());
+        let faltante =
+            cantidad.saturating_sub(disponibles + self.local.unidades_entrantes(id_producto));
+        if faltante == 0 {
+            return;
+        }
+        let vecino = self.vecinos[self.proximo_vecino % self.vecinos.len()];
+        self.proximo_vecino += 1;
+        self.pedir_transferencia(vecino, id_producto, faltante);
+    }
+
+    fn direccion_local(&self, id_local: usize) -> Option<SocketAddr> {
+        if let Some(direccion) = self.direcciones_locales.get(&id_local) {
+            return Some(*direccion);
+        }
+        match id_to_addr_local(id_local).parse::<SocketAddr>() {
+            Ok(direccion) => Some(direccion),
+            Err(e) => {
+                error!(target: "transferencia", "Dirección de local invalida: {}", e);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct OfrecerOrden(Orden, SocketAddr);
+
+/// Un ecommerce ofrece una orden, se le contesta si hay stock para tomarla sin bloquearlo.
+/// La orden se agrega recién cuando el ecommerce la confirma
+impl Handler<OfrecerOrden> for ActorLocal {
+    type Result = ();
+
+    fn handle(&mut self, msg: OfrecerOrden, _ctx: &mut Self::Context) -> Self::Result {
+        self.en_cola.atendido();
+        self.ecommerces.insert(msg.1);
+        let OfrecerOrden(orden, remitente) = msg;
+        if self.cerrando {
+            self.responder_orden(orden.id, false, RespuestaOrden::Cerrado, remitente);
+            return;
+        }
+        let respuesta = match self.local.puede_aceptar(&orden) {
+            Ok(_) => RespuestaOrden::Disponible,
+            Err(e) => {
+                debug!(target: "local", orden = orden.id.as_deref(), "No puede tomar la orden: {}", e);
+                if e == ErrorLocal::StockInsuficiente {
+                    self.pedir_faltante_a_vecino(orden.id_producto, orden.cantidad);
+                    self.guardar_recuperacion();
+                }
+                RespuestaOrden::Rechazada
+            }
+        };
+        self.responder_orden(orden.id, false, respuesta, remitente);
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct AgregarOrden(Orden, SocketAddr);
+
+/// El ecommerce confirma la orden que ofreció, se agrega al local y se le contesta si se
+/// aceptó. La decisión sobre cada orden es definitiva, una confirmación repetida o demorada
+/// recibe la misma respuesta sin volver a agregarla
+impl Handler<AgregarOrden> for ActorLocal {
+    type Result = ();
+
+    fn handle(&mut self, msg: AgregarOrden, _ctx: &mut Self::Context) -> Self::Result {
+        self.en_cola.atendido();
+        self.ecommerces.insert(msg.1);
+        let remitente = msg.1;
+        let mut orden = msg.0;
+        if let Some(aceptada) = orden.id.as_deref().and_then(|id| self.local.decision(id)) {
+            debug!(target: "local", orden = orden.id.as_deref(), aceptada, "Confirmación repetida");
+            let respuesta = if aceptada {
+                RespuestaOrden::Aceptada
+            } else {
+                RespuestaOrden::Rechazada
+            };
+            self.responder_orden(orden.id, true, respuesta, remitente);
+            return;
+        }
+        if self.cerrando {
+            self.responder_orden(orden.id, true, RespuestaOrden::Cerrado, remitente);
+            return;
+        }
+        let _span = con_padre(
+            info_span!(target: "local", "agregar_orden", orden = orden.id.as_deref()),
+            orden.traza.as_ref(),
+        )
+        .entered();
+        // las entregas y cancelaciones de la orden quedan debajo de este span
+        orden.traza = contexto_actual().or(orden.traza);
+        if orden.vencimiento.is_none() {
+            orden.vencimiento =
+                Some(self.reloj.ahora_en_ms() + self.plazo_ordenes.as_millis() as u64);
+        }
+        let (id_producto, cantidad, id_orden) =
+            (orden.id_producto, orden.cantidad, orden.id.clone());
+        let respuesta = match self.local.agregar_orden(orden) {
+            Ok(_) => {
+                info!(target: "local", orden = id_orden.as_deref(), "Orden aceptada");
+                self.contadores.ordenes_aceptadas += 1;
+                if let Some(id) = id_orden.clone() {
+                    self.origenes.insert(id, remitente);
+                }
+                // las unidades bloqueadas dejan de estar disponibles
+                self.pedir_reposicion_si_falta_stock();
+                RespuestaOrden::Aceptada
+            }
+            Err(e) => {
+                info!(target: "local", orden = id_orden.as_deref(), motivo = ?e, "Orden rechazada");
+                self.contadores.ordenes_rechazadas += 1;
+                if e == ErrorLocal::StockInsuficiente {
+                    self.pedir_faltante_a_vecino(id_producto, cantidad);
+                }
+                RespuestaOrden::Rechazada
+            }
+        };
+        self.guardar_recuperacion();
+        self.responder_orden(id_orden, true, respuesta, remitente);
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct VenderEnLocal(pub Orden, pub Recipient<ResultadoVenderEnLocal>);
+
+/// Recibe orden y vende los productos del local, devuelve resultado al actor que la envió
+impl Handler<VenderEnLocal> for ActorLocal {
+    type Result = ();
+    fn handle(&mut self, msg: VenderEnLocal, _ctx: &mut Self::Context) -> Self::Result {
+        self.en_cola.atendido();
+        if self.cerrando {
+            return;
+        }
+        let VenderEnLocal(orden, vendedor) = msg;
+        match self.local.vender(orden) {
+            Ok(_) => {
+                self.contadores.ventas += 1;
+                info!(target: "vendedor", "Se vende producto en local");
+                self.pedir_reposicion_si_falta_stock();
+                self.guardar_recuperacion();
+                vendedor.do_send(ResultadoVenderEnLocal(None));
+            }
+            Err(e) => {
+                self.contadores.ventas_rechazadas += 1;
+                warn!(target: "vendedor", "No se pudo vender: {}", e);
+                vendedor.do_send(ResultadoVenderEnLocal(Some(e)));
+            }
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct EntregarOrden;
+
+/// Recibe un msg vacio, la politica de entrega elige que orden procesar y si se entrega o cancela
+/// y envia un msg a el JobOrdenes con el resultado
+impl Handler<EntregarOrden> for ActorLocal {
+    type Result = ();
+
+    fn handle(&mut self, _msg: EntregarOrden, _ctx: &mut Self::Context) {
+        self.en_cola.atendido();
+        let (indice, accion) = match self.politica.elegir(&self.local.ordenes_en_progreso) {
+            Some(eleccion) => eleccion,
+            None => {
+                debug!(target: "job", "No hay ordenes en progreso");
+                self.recipient_job_ordenes
+                    .do_send(ResultadoEntregarOrden(None));
+                return;
+            }
+        };
+        let (id_orden, traza) = self
+            .local
+            .ordenes_en_progreso
+            .get(indice)
+            .map(|orden| (orden.id.clone(), orden.traza.clone()))
+            .unwrap_or_default();
+        let _span = con_padre(
+            info_span!(target: "job", "finalizar_orden", orden = id_orden.as_deref(), accion = ?accion),
+            traza.as_ref(),
+        )
+        .entered();
+        let (resultado, respuesta) = match accion {
+            Accion::Entregar => (self.local.entregar_orden(|| indice), "entregada"),
+            Accion::Cancelar => (self.local.cancelar_orden(|| indice), "cancelada"),
+        };
+        match resultado {
+            Ok(_) => {
+                self.contar_orden_finalizada(accion);
+                info!(target: "job", orden = id_orden.as_deref(), "Orden {}", respuesta);
+                self.orden_finalizada(id_orden.as_deref(), accion);
+                self.pedir_reposicion_si_falta_stock();
+                self.guardar_recuperacion();
+                self.recipient_job_ordenes
+                    .do_send(ResultadoEntregarOrden(None));
+            }
+            Err(e) => {
+                error!(target: "job", orden = id_orden.as_deref(), "{}", e);
+                self.recipient_job_ordenes
+                    .do_send(ResultadoEntregarOrden(Some(e)));
+            }
+        }
+    }
+}
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Reponer(pub Reposicion);
+
+/// Aumenta el stock del producto con las unidades que ingresan al local
+impl Handler<Reponer> for ActorLocal {
+    type Result = ();
+
+    fn handle(&mut self, msg: Reponer, _ctx: &mut Self::Context) -> Self::Result {
+        info!(
+            target: "reposicion",
+            id_producto = msg.0.id_producto,
+            cantidad = msg.0.cantidad,
+            "Ingresan unidades"
+        );
+        if let Err(e) = self.local.reponer(msg.0.id_producto, msg.0.cantidad) {
+            warn!(target: "reposicion", id_producto = msg.0.id_producto, "No se repone: {}", e);
+            return;
+        }
+        self.contadores.reposiciones += 1;
+        if let Some(reposicion_automatica) = self.reposicion_automatica.as_mut() {
+            reposicion_automatica.recibida(msg.0.id_producto);
+        }
+        self.guardar_recuperacion();
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct PedirTransferencia {
+    pub id_local_origen: usize,
+    pub id_producto: usize,
+    pub cantidad: usize,
+}
+
+impl Handler<PedirTransferencia> for ActorLocal {
+    type Result = ();
+
+    fn handle(&mut self, msg: PedirTransferencia, _ctx: &mut Self::Context) -> Self::Result {
+        self.pedir_transferencia(msg.id_local_origen, msg.id_producto, msg.cantidad);
+        self.guardar_recuperacion();
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct RecibirMensajeLocal(MensajeLocal, SocketAddr);
+
+/// Maneja los mensajes de otros procesos: reposiciones y los pasos del protocolo de transferencia.
+/// El local que pide una transferencia la registra como entrante, el que la recibe bloquea
+/// las unidades y las envia, al llegar se repone el stock del que la pidió y este confirma
+/// la recepción para que el que la envió descuente las unidades bloqueadas
+impl Handler<RecibirMensajeLocal> for ActorLocal {
+    type Result = ();
+
+    fn handle(&mut self, msg: RecibirMensajeLocal, ctx: &mut Self::Context) -> Self::Result {
+        self.en_cola.atendido();
+        let remitente = msg.1;
+        match msg.0 {
+            MensajeLocal::Reposicion(reposicion) => {
+                info!(target: "udp", remitente = %remitente, "Reposición recibida");
+                self.handle(Reponer(reposicion), ctx);
+            }
+            MensajeLocal::PedidoTransferencia(transferencia) => {
+                let id = transferencia.id;
+                let respuesta = match self
+                    .local
+                    .reservar_transferencia_saliente(transferencia.clone())
+                {
+                    Ok(_) => {
+                        info!(
+                            target: "transferencia",
+                            transferencia = %id,
+                            id_producto = transferencia.id_producto,
+                            cantidad = transferencia.cantidad,
+                            "Se envian unidades"
+                        );
+                        // un pedido repetido reenvia el envio sin volver a contar los intentos
+                        self.envios_transferencia.entry(id).or_insert(Reintentos {
+                            destino: remitente,
+                            intentos: 1,
+                        });
+                        MensajeLocal::EnvioTransferencia(transferencia)
+                    }
+                    Err(ErrorLocal::TransferenciaRepetida) => {
+                        debug!(target: "transferencia", transferencia = %id, "Pedido repetido ignorado");
+                        return;
+                    }
+                    Err(e) => {
+                        warn!(target: "transferencia", transferencia = %id, "{}", e);
+                        MensajeLocal::TransferenciaRechazada { id }
+                    }
+                };
+                self.guardar_recuperacion();
+                self.recipient_enviar_mensaje
+                    .do_send(EnviarMensajeLocal(respuesta, remitente));
+            }
+            MensajeLocal::EnvioTransferencia(transferencia) => {
+                let id = transferencia.id;
+                self.pedidos_transferencia.remove(&id);
+                let respuesta = match self.local.recibir_transferencia(&transferencia) {
+                    Ok(transferencia) => {
+                        info!(
+                            target: "transferencia",
+                            transferencia = %id,
+                            id_producto = transferencia.id_producto,
+                            cantidad = transferencia.cantidad,
+                            "Llegaron unidades"
+                        );
+                        if let Some(reposicion_automatica) = self.reposicion_automatica.as_mut() {
+                            reposicion_automatica.recibida(transferencia.id_producto);
+                        }
+                        MensajeLocal::RecepcionTransferencia { id }
+                    }
+                    // Se confirma el envio duplicado para que el otro local deje de bloquear
+                    Err(ErrorLocal::TransferenciaRepetida) => {
+                        MensajeLocal::RecepcionTransferencia { id }
+                    }
+                    // Lo que no se pudo reponer se rechaza para que el otro local lo libere
+                    Err(e) => {
+                        warn!(target: "transferencia", transferencia = %id, "{}", e);
+                        MensajeLocal::TransferenciaRechazada { id }
+                    }
+                };
+                self.guardar_recuperacion();
+                self.recipient_enviar_mensaje
+                    .do_send(EnviarMensajeLocal(respuesta, remitente));
+            }
+            MensajeLocal::RecepcionTransferencia { id } => {
+                self.envios_transferencia.remove(&id);
+                match self.local.confirmar_transferencia_saliente(&id) {
+                    Ok(_) => {
+                        info!(target: "transferencia", transferencia = %id, "Recepción confirmada")
+                    }
+                    Err(e) => warn!(target: "transferencia", transferencia = %id, "{}", e),
+                }
+                self.guardar_recuperacion();
+            }
+            // la rechaza el local al que se le pidió o, si no la pudo recibir, el que la pidió
+            MensajeLocal::TransferenciaRechazada { id } => {
+                info!(target: "transferencia", transferencia = %id, "Rechazada");
+                self.pedidos_transferencia.remove(&id);
+                self.envios_transferencia.remove(&id);
+                if id.id_local == self.id {
+                    let _ = self.local.cancelar_transferencia_entrante(&id);
+                } else if self.local.liberar_transferencia_saliente(&id).is_ok() {
+                    info!(target: "transferencia", transferencia = %id, "Se liberan las unidades");
+                }
+                self.guardar_recuperacion();
+            }
+            MensajeLocal::CancelacionRecibida { orden } => {
+                if self.avisos.remove(&orden).is_some() {
+                    info!(target: "cancelacion", orden = %orden, "El ecommerce confirmo la cancelación");
+                }
+            }
+            MensajeLocal::PedidoReposicion { .. }
+            | MensajeLocal::OrdenCancelada { .. }
+            | MensajeLocal::LocalCerrando { .. }
+            | MensajeLocal::ConfirmarOrden { .. }
+            | MensajeLocal::Respuesta { .. } => {
+                debug!(target: "udp", remitente = %remitente, "Mensaje ignorado, no es para un local");
+            }
+        }
+    }
+}
+#[derive(Message)]
+#[rtype(result = "Vec<StockProducto>")]
+pub struct ConsultarStock;
+
+/// Devuelve el stock de cada producto ordenado por id
+impl Handler<ConsultarStock> for ActorLocal {
+    type Result = Vec<StockProducto>;
+
+    fn handle(&mut self, _msg: ConsultarStock, _ctx: &mut Self::Context) -> Self::Result {
+        let mut productos: Vec<StockProducto> =
+            self.local.productos_en_stock.values().cloned().collect();
+        productos.sort_by_key(|producto| producto.id_producto);
+        productos
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Vec<Orden>")]
+pub struct ConsultarOrdenes;
+
+impl Handler<ConsultarOrdenes> for ActorLocal {
+    type Result = Vec<Orden>;
+
+    fn handle(&mut self, _msg: ConsultarOrdenes, _ctx: &mut Self::Context) -> Self::Result {
+        self.local.ordenes_en_progreso.clone()
+    }
+}
+
+/// Estado del actor Local que se informa por el socket de control
+pub struct ResumenLocal {
+    pub ordenes_en_progreso: usize,
+    pub cerrando: bool,
+    pub contadores: Contadores,
+}
+
+#[derive(Message)]
+#[rtype(result = "ResumenLocal")]
+pub struct ConsultarResumen;
+
+impl Handler<ConsultarResumen> for ActorLocal {
+    type Result = MessageResult<ConsultarResumen>;
+
+    fn handle(&mut self, _msg: ConsultarResumen, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(ResumenLocal {
+            ordenes_en_progreso: self.local.ordenes_en_progreso.len(),
+            cerrando: self.cerrando,
+            contadores: Contadores {
+                reinicios: self.reinicios.por_actor(),
+                ..self.contadores.clone()
+            },
+        })
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Orden, ErrorLocal>")]
+pub struct FinalizarOrden {
+    pub indice: usize,
+    pub accion: Accion,
+}
+
+/// Entrega o cancela una orden en progreso elegida por el operador
+impl Handler<FinalizarOrden> for ActorLocal {
+    type Result = Result<Orden, ErrorLocal>;
+
+    fn handle(&mut self, msg: FinalizarOrden, _ctx: &mut Self::Context) -> Self::Result {
+        let orden = match msg.accion {
+            Accion::Entregar => self.local.entregar_orden_en(msg.indice)?,
+            Accion::Cancelar => self.local.cancelar_orden_en(msg.indice)?,
+        };
+        self.contar_orden_finalizada(msg.accion);
+        self.orden_finalizada(orden.id.as_deref(), msg.accion);
+        self.pedir_reposicion_si_falta_stock();
+        self.guardar_recuperacion();
+        Ok(orden)
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Cerrar;
+
+/// Cierra el local: deja de aceptar ordenes (se responde LocalCerrado para que el ecommerce
+/// las envie a otro local) y le avisa a los ecommerce que se cierra, cancela las ordenes en
+/// progreso avisandole a cada ecommerce, espera a que se confirmen los avisos y las
+/// transferencias enviadas, guarda el stock y, si así se configuró, detiene el sistema
+impl Handler<Cerrar> for ActorLocal {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Cerrar, ctx: &mut Self::Context) -> Self::Result {
+        if self.cerrando {
+            return;
+        }
+        self.cerrando = true;
+        info!(target: "cierre", "Cerrando local, no se aceptan más ordenes");
+        for direccion in &self.ecommerces {
+            self.recipient_enviar_mensaje.do_send(EnviarMensajeLocal(
+                MensajeLocal::LocalCerrando { local: self.id },
+                *direccion,
+            ));
+        }
+
+        for orden in self.local.cancelar_ordenes_en_progreso() {
+            self.contadores.ordenes_canceladas += 1;
+            info!(
+                target: "cierre",
+                orden = orden.id.as_deref(),
+                id_producto = orden.id_producto,
+                cantidad = orden.cantidad,
+                "Orden cancelada"
+            );
+            self.avisar_cancelacion(orden.id.as_deref(), MotivoCancelacion::Cierre);
+        }
+        if !self.local.ordenes_en_progreso.is_empty() {
+            error!(
+                target: "cierre",
+                "Quedaron {} ordenes sin cancelar",
+                self.local.ordenes_en_progreso.len()
+            );
+        }
+        self.guardar_recuperacion();
+
+        // los avisos y las transferencias se siguen reenviando en el intervalo de vencimientos
+        let inicio = self.reloj.ahora_en_ms();
+        ctx.run_interval(INTERVALO_CIERRE, move |act, ctx| {
+            let transcurrido =
+                Duration::from_millis(act.reloj.ahora_en_ms().saturating_sub(inicio));
+            let pendientes = !act.avisos.is_empty() || !act.envios_transferencia.is_empty();
+            if transcurrido < ESPERA_CIERRE || (pendientes && transcurrido < ESPERA_CIERRE_MAXIMA) {
+                return;
+            }
+            act.terminar_cierre();
+            ctx.stop();
+        });
+    }
+}
+
+impl ActorLocal {
+    /// Libera las transferencias enviadas que quedaron sin confirmar para no guardar sus
+    /// unidades como bloqueadas, guarda el stock y, si así se configuró, detiene el sistema
+    fn terminar_cierre(&mut self) {
+        let mut salientes: Vec<IdTransferencia> = self
+            .local
+            .transferencias_salientes
+            .keys()
+            .copied()
+            .collect();
+        salientes.sort();
+        for id in salientes {
+            match self.local.liberar_transferencia_saliente(&id) {
+                Ok(_) => warn!(
+                    target: "cierre",
+                    transferencia = %id,
+                    "Transferencia sin confirmar, se liberan las unidades"
+                ),
+                Err(e) => error!(target: "cierre", transferencia = %id, "{}", e),
+            }
+        }
+        self.guardar_recuperacion();
+        match guardar_stock(&self.local, &self.archivo_estado) {
+            Ok(_) => info!(target: "cierre", archivo = %self.archivo_estado, "Stock guardado"),
+            Err(e) => error!(target: "cierre", "No se pudo guardar el stock: {}", e),
+        }
+        info!(target: "cierre", "Local cerrado");
+        if self.detener_sistema {
+            System::current().stop();
+        }
+    }
+}
+/// Se detiene sin guardar nada junto con el resto de los actores del local. El ActorLocal
+/// se detiene antes de que lo atiendan los demás, así ya nadie los tiene y el supervisor
+/// no los reinicia
+impl Handler<Caer> for ActorLocal {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Caer, ctx: &mut Self::Context) -> Self::Result {
+        warn!(target: "falla", "Se cae el local, se pierde lo que no se guardó");
+        for recipient in &self.recipients_caer {
+            recipient.do_send(Caer);
+        }
+        ctx.stop();
+    }
+}
+// Termina ActorLocal
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ResultadoVenderEnLocal(pub Option<ErrorLocal>);
+
+pub struct JobOrdenes {
+    recipient_local: Recipient<EntregarOrden>,
+    en_cola_local: EnCola,
+    intervalo: Duration,
+    supervision: PoliticaDeReinicio,
+}
+
+impl Actor for JobOrdenes {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if let Some(espera) = self.supervision.espera() {
+            ctx.notify_later(EntregarOrdenJobOrdenes, espera);
+        }
+    }
+}
+
+/// Si no puede pedirle una entrega al actor Local se detiene y el supervisor lo reinicia
+impl Supervised for JobOrdenes {
+    fn restarting(&mut self, _ctx: &mut Self::Context) {
+        self.supervision.reiniciando(Instant::now());
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct EntregarOrdenJobOrdenes;
+
+impl Handler<EntregarOrdenJobOrdenes> for JobOrdenes {
+    type Result = ();
+
+    fn handle(&mut self, _msg: EntregarOrdenJobOrdenes, ctx: &mut Self::Context) {
+        match self.recipient_local.try_send(EntregarOrden) {
+            Ok(_) => self.en_cola_local.encolado(),
+            Err(SendError::Full(_)) => {
+                debug!(target: "job", "Local ocupado, reintenta la entrega");
+                ctx.notify_later(EntregarOrdenJobOrdenes, ESPERA_LOCAL_OCUPADO);
+            }
+            Err(SendError::Closed(_)) => {
+                error!(target: "job", "El actor Local se detuvo");
+                ctx.stop();
+            }
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct ResultadoEntregarOrden(Option<ErrorLocal>);
+
+impl Handler<ResultadoEntregarOrden> for JobOrdenes {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, msg: ResultadoEntregarOrden, _ctx: &mut Self::Context) -> Self::Result {
+        if msg.0.is_some() {
+            debug!(target: "job", "No se pudo procesar la orden");
+        }
+        debug!(target: "job", "Descansa un poco");
+        Box::pin(
+            sleep(self.intervalo)
+                .into_actor(self)
+                .map(move |_result, _me, ctx| {
+                    ctx.notify(EntregarOrdenJobOrdenes);
+                }),
+        )
+    }
+}
+
+impl Handler<Caer> for JobOrdenes {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Caer, ctx: &mut Self::Context) -> Self::Result {
+        ctx.stop();
+    }
+}
+
+/// Nuevo intervalo entre ventas del Vendedor o entre entregas del Job
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CambiarIntervalo(pub Duration);
+
+/// Cambia cada cuanto el Job entrega o cancela una orden, se aplica desde la proxima
+impl Handler<CambiarIntervalo> for JobOrdenes {
+    type Result = ();
+
+    fn handle(&mut self, msg: CambiarIntervalo, _ctx: &mut Self::Context) -> Self::Result {
+        self.intervalo = msg.0;
+    }
+}
+
+/// Lee el stock del local, las filas invalidas se informan con su linea y se saltean
+pub fn instanciar_local(dir_archivo: &str) -> Result<Local, io::Error> {
+    let filas = leer_stock(dir_archivo).map_err(|e| match e {
+        ErrorCsv::Io(e) => e,
+        otro => io::Error::new(io::ErrorKind::InvalidData, otro.to_string()),
+    })?;
+    for error in filas.invalidas {
+        warn!(target: "local", archivo = dir_archivo, "Stock invalido: {}", error);
+    }
+    let productos: Productos = filas
+        .validas
+        .into_iter()
+        .map(|(_, producto)| (producto.id_producto, producto))
+        .collect();
+
+    Ok(Local::new(productos))
+}
+
+/// Guarda el stock del local con el mismo formato que lee `instanciar_local`
+fn guardar_stock(local: &Local, dir_archivo: &str) -> Result<(), io::Error> {
+    let mut writer = csv::Writer::from_path(dir_archivo)?;
+    writer.write_record(["id_producto", "stock"])?;
+    let mut productos: Vec<&StockProducto> = local.productos_en_stock.values().collect();
+    productos.sort_by_key(|producto| producto.id_producto);
+    for producto in productos {
+        writer.write_record([producto.id_producto.to_string(), producto.stock.to_string()])?;
+    }
+    writer.flush()
+}
+
+/// Lee el estado que guardó el local para retomar después de caerse
+pub fn recuperar_local(dir_archivo: &str) -> Result<Local, io::Error> {
+    let estado = serde_json::from_slice(&fs::read(dir_archivo)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Local::desde_estado(estado))
+}
+
+/// Guarda el estado del local en un archivo temporal y lo renombra, así si el proceso se
+/// cae mientras escribe queda el estado anterior
+fn guardar_estado(local: &Local, dir_archivo: &str) -> Result<(), io::Error> {
+    let temporal = format!("{}.tmp", dir_archivo);
+    fs::write(&temporal, serde_json::to_vec(&local.estado())?)?;
+    fs::rename(temporal, dir_archivo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::politica_entrega::CriterioDeSeleccion;
+    use crate::common::reloj::RelojSistema;
+
+    #[test]
+    fn test_instanciar_local_leyendo_stock() {
+        let dir_archivo_test = "test_local.csv";
+        let mut wtr = csv::Writer::from_path(dir_archivo_test).unwrap();
+        wtr.write_record(["id_producto", "stock"]).unwrap();
+        wtr.write_record(["1", "30"]).unwrap();
+        wtr.flush().unwrap();
+
+        let local = instanciar_local(dir_archivo_test);
+
+        std::fs::remove_file(dir_archivo_test).unwrap();
+
+        assert!(local.is_ok());
+
+        let local = local.unwrap();
+        assert_eq!(local.productos_en_stock.len(), 1);
+    }
+
+    #[test]
+    fn test_instanciar_local_saltea_filas_invalidas() {
+        let dir_archivo_test = "test_local_filas_invalidas.csv";
+        std::fs::write(
+            dir_archivo_test,
+            "id_producto,stock\n1,30\n2,muchos\n3\n4,10\n1,5\n",
+        )
+        .unwrap();
+
+        let local = instanciar_local(dir_archivo_test);
+
+        std::fs::remove_file(dir_archivo_test).unwrap();
+
+        let local = local.unwrap();
+        assert_eq!(local.productos_en_stock.len(), 2);
+        assert_eq!(local.productos_en_stock.get(&1).unwrap().stock, 30);
+        assert_eq!(local.productos_en_stock.get(&4).unwrap().stock, 10);
+    }
+
+    #[test]
+    fn test_guardar_stock_se_puede_volver_a_instanciar() {
+        let dir_archivo_test = "test_estado_local.csv";
+        let mut productos: Productos = HashMap::new();
+        productos.insert(2, StockProducto::new_con_bloqueados(2, 15, 3));
+        productos.insert(1, StockProducto::new(1, 30));
+
+        guardar_stock(&Local::new(productos), dir_archivo_test).unwrap();
+        let local = instanciar_local(dir_archivo_test);
+
+        std::fs::remove_file(dir_archivo_test).unwrap();
+
+        let local = local.unwrap();
+        assert_eq!(local.productos_en_stock.len(), 2);
+        assert_eq!(local.productos_en_stock.get(&1).unwrap().stock, 30);
+        assert_eq!(local.productos_en_stock.get(&2).unwrap().stock, 15);
+        assert_eq!(local.productos_en_stock.get(&2).unwrap().bloqueados, 0);
+    }
+
+    #[test]
+    fn test_el_estado_guardado_se_puede_recuperar() {
+        let dir_archivo_test = "test_recuperacion_local.json";
+        let mut productos: Productos = HashMap::new();
+        productos.insert(1, StockProducto::new(1, 30));
+        let mut local = Local::new(productos);
+        local
+            .agregar_orden(Orden {
+                id: Some("2-0".to_string()),
+                ..Orden::new(1, 4, 0.0, 0.0)
+            })
+            .unwrap();
+
+        guardar_estado(&local, dir_archivo_test).unwrap();
+        let recuperado = recuperar_local(dir_archivo_test);
+
+        std::fs::remove_file(dir_archivo_test).unwrap();
+
+        let recuperado = recuperado.unwrap();
+        assert_eq!(recuperado.estado(), local.estado());
+        assert_eq!(recuperado.ordenes_en_progreso.len(), 1);
+        assert_eq!(recuperado.decision("2-0"), Some(true));
+    }
+
+    #[test]
+    fn test_el_reinicio_de_un_actor_supervisado_queda_en_los_contadores() {
+        let reinicios = System::new().block_on(async {
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let reloj: Arc<dyn Reloj> = Arc::new(RelojSistema);
+            let fallas = Arc::new(Fallas::new(
+                "local-1".to_string(),
+                ConfiguracionFallas::default(),
+                reloj.clone(),
+            ));
+            let configuracion = ConfiguracionActores {
+                id: 1,
+                politica: PoliticaDeEntrega::new(CriterioDeSeleccion::Fifo, 0.0, 1),
+                plazo_ordenes: Duration::from_secs(30),
+                reposicion_automatica: None,
+                proveedor: None,
+                vecinos: vec![],
+                direcciones_locales: HashMap::new(),
+                capacidad_mailbox: 8,
+                archivo_estado: "test_reinicios_estado.txt".to_string(),
+                archivo_recuperacion: None,
+                intervalo_entregas: Duration::from_secs(60),
+                reloj,
+                detener_sistema: false,
+            };
+            let actores =
+                iniciar_actores(configuracion, Local::new(HashMap::new()), socket, fallas);
+
+            // el supervisor reinicia al Job porque todavia se tiene su dirección
+            actores.job_ordenes.send(Caer).await.unwrap();
+            sleep(Duration::from_millis(50)).await;
+
+            actores
+                .local
+                .send(ConsultarResumen)
+                .await
+                .unwrap()
+                .contadores
+                .reinicios
+        });
+
+        assert_eq!(reinicios.get("JobOrdenes"), Some(&1));
+        assert_eq!(reinicios.get("UdpClientActor"), Some(&0));
+    }
+}
@@ -0,0 +1,248 @@
+use crate::common::reposicion::Reposicion;
+use std::time::Duration;
+
+pub const AYUDA: &str = "Comandos:
+  s                                   stock por producto
+  o                                   ordenes en progreso
+  e <indice>                          entrega la orden en progreso del indice
+  x <indice>                          cancela la orden en progreso del indice
+  r <id_producto> <cantidad>          repone stock del producto
+  t <id_local> <id_producto> <cant>   pide una transferencia de stock a otro local
+  v <ms>                              cada cuanto vende el Vendedor
+  j <ms>                              cada cuanto entrega/cancela el Job de ordenes
+  f drop <p> | f delay <ms> [<jitter>] | f dup <p> | f reorder <p> | f off
+                                      falla simulada en los paquetes udp
+  c | l                               deja de aceptar / vuelve a aceptar ordenes
+  log <filtro>                        cambia el filtro de los logs, por ejemplo info,udp=debug
+  q                                   cierra el local
+  h                                   muestra esta ayuda";
+
+/// Falla simulada sobre los paquetes que recibe el local por udp
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ModoFalla {
+    Ninguna,
+    /// Descarta cada paquete con la probabilidad dada
+    Descartar(f64),
+    /// Procesa cada paquete recien despues de la demora dada, más un extra al azar
+    /// de hasta `jitter`
+    Demorar {
+        demora: Duration,
+        jitter: Duration,
+    },
+    /// Procesa dos veces cada paquete con la probabilidad dada
+    Duplicar(f64),
+    /// Procesa cada paquete despues de los siguientes con la probabilidad dada
+    Reordenar(f64),
+}
+
+/// Comando que un operador escribe en la consola del local
+#[derive(Debug, PartialEq)]
+pub enum Comando {
+    CerrarConexion,
+    LevantarConexion,
+    Stock,
+    Ordenes,
+    Entregar(usize),
+    Cancelar(usize),
+    Reponer(Reposicion),
+    Transferir {
+        id_local: usize,
+        id_producto: usize,
+        cantidad: usize,
+    },
+    IntervaloVendedor(Duration),
+    IntervaloJob(Duration),
+    Falla(ModoFalla),
+    /// Nuevo filtro de logs, como el de RUST_LOG
+    Logs(String),
+    Ayuda,
+    Salir,
+}
+
+impl Comando {
+    /// Parsea una linea de la consola, si no es valida devuelve el uso del comando
+    pub fn parsear(linea: &str) -> Result<Comando, String> {
+        let linea = linea.trim();
+        let (nombre, argumentos) = linea.split_once(' ').unwrap_or((linea, ""));
+        let uso = |uso: &str| format!("Uso: {}", uso);
+        match nombre {
+            "c" => Ok(Comando::CerrarConexion),
+            "l" => Ok(Comando::LevantarConexion),
+            "s" => Ok(Comando::Stock),
+            "o" => Ok(Comando::Ordenes),
+            "h" | "?" => Ok(Comando::Ayuda),
+            "q" => Ok(Comando::Salir),
+            "e" => parsear_valores::<1>(argumentos)
+                .map(|[indice]| Comando::Entregar(indice))
+                .ok_or(uso("e <indice>")),
+            "x" => parsear_valores::<1>(argumentos)
+                .map(|[indice]| Comando::Cancelar(indice))
+                .ok_or(uso("x <indice>")),
+            "r" => parsear_valores::<2>(argumentos)
+                .map(|[id_producto, cantidad]| {
+                    Comando::Reponer(Reposicion::new(id_producto, cantidad))
+                })
+                .ok_or(uso("r <id_producto> <cantidad>")),
+            "t" => parsear_valores::<3>(argumentos)
+                .map(|[id_local, id_producto, cantidad]| Comando::Transferir {
+                    id_local,
+                    id_producto,
+                    cantidad,
+                })
+                .ok_or(uso("t <id_local> <id_producto> <cantidad>")),
+            "v" => parsear_valores::<1>(argumentos)
+                .map(|[ms]| Comando::IntervaloVendedor(Duration::from_millis(ms as u64)))
+                .ok_or(uso("v <ms>")),
+            "j" => parsear_valores::<1>(argumentos)
+                .map(|[ms]| Comando::IntervaloJob(Duration::from_millis(ms as u64)))
+                .ok_or(uso("j <ms>")),
+            "log" if !argumentos.trim().is_empty() => {
+                Ok(Comando::Logs(argumentos.trim().to_string()))
+            }
+            "log" => Err(uso("log <filtro>")),
+            "f" => parsear_modo_falla(argumentos)
+                .map(Comando::Falla)
+                .ok_or(uso(
+                    "f drop <p> | f delay <ms> [<jitter>] | f dup <p> | f reorder <p> | f off",
+                )),
+            otro => Err(format!(
+                "Comando desconocido: {}, h para ver la ayuda",
+                otro
+            )),
+        }
+    }
+}
+
+fn parsear_modo_falla(texto: &str) -> Option<ModoFalla> {
+    let probabilidad = |texto: &str| {
+        texto
+            .parse::<f64>()
+            .ok()
+            .filter(|p| (0.0..=1.0).contains(p))
+    };
+    let ms = |texto: &str| texto.parse().ok().map(Duration::from_millis);
+    let partes: Vec<&str> = texto.split_whitespace().collect();
+    match partes[..] {
+        ["off"] => Some(ModoFalla::Ninguna),
+        ["drop", p] => probabilidad(p).map(ModoFalla::Descartar),
+        ["dup", p] => probabilidad(p).map(ModoFalla::Duplicar),
+        ["reorder", p] => probabilidad(p).map(ModoFalla::Reordenar),
+        ["delay", demora] => Some(ModoFalla::Demorar {
+            demora: ms(demora)?,
+            jitter: Duration::ZERO,
+        }),
+        ["delay", demora, jitter] => Some(ModoFalla::Demorar {
+            demora: ms(demora)?,
+            jitter: ms(jitter)?,
+        }),
+        _ => None,
+    }
+}
+
+/// Parsea exactamente N numeros separados por espacios
+pub fn parsear_valores<const N: usize>(texto: &str) -> Option<[usize; N]> {
+    let valores = texto
+        .split_whitespace()
+        .map(|valor| valor.parse::<usize>().ok())
+        .collect::<Option<Vec<usize>>>()?;
+    valores.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsear_comandos_sin_argumentos() {
+        assert_eq!(Comando::parsear("s"), Ok(Comando::Stock));
+        assert_eq!(Comando::parsear("o "), Ok(Comando::Ordenes));
+        assert_eq!(Comando::parsear("q"), Ok(Comando::Salir));
+        assert_eq!(Comando::parsear("c"), Ok(Comando::CerrarConexion));
+    }
+
+    #[test]
+    fn test_parsear_reposicion() {
+        assert_eq!(
+            Comando::parsear("r 3 10"),
+            Ok(Comando::Reponer(Reposicion::new(3, 10)))
+        );
+        assert!(Comando::parsear("r 3").is_err());
+        assert!(Comando::parsear("r 3 -1").is_err());
+    }
+
+    #[test]
+    fn test_parsear_entregar_y_cancelar_orden() {
+        assert_eq!(Comando::parsear("e 2"), Ok(Comando::Entregar(2)));
+        assert_eq!(Comando::parsear("x 0"), Ok(Comando::Cancelar(0)));
+        assert!(Comando::parsear("e").is_err());
+    }
+
+    #[test]
+    fn test_parsear_intervalos() {
+        assert_eq!(
+            Comando::parsear("v 500"),
+            Ok(Comando::IntervaloVendedor(Duration::from_millis(500)))
+        );
+        assert_eq!(
+            Comando::parsear("j 100"),
+            Ok(Comando::IntervaloJob(Duration::from_millis(100)))
+        );
+    }
+
+    #[test]
+    fn test_parsear_filtro_de_logs() {
+        assert_eq!(
+            Comando::parsear("log info,udp=debug"),
+            Ok(Comando::Logs("info,udp=debug".to_string()))
+        );
+        assert!(Comando::parsear("log").is_err());
+    }
+
+    #[test]
+    fn test_parsear_modos_de_falla() {
+        assert_eq!(
+            Comando::parsear("f drop 0.3"),
+            Ok(Comando::Falla(ModoFalla::Descartar(0.3)))
+        );
+        assert_eq!(
+            Comando::parsear("f delay 200"),
+            Ok(Comando::Falla(ModoFalla::Demorar {
+                demora: Duration::from_millis(200),
+                jitter: Duration::ZERO,
+            }))
+        );
+        assert_eq!(
+            Comando::parsear("f delay 200 50"),
+            Ok(Comando::Falla(ModoFalla::Demorar {
+                demora: Duration::from_millis(200),
+                jitter: Duration::from_millis(50),
+            }))
+        );
+        assert_eq!(
+            Comando::parsear("f dup 0.1"),
+            Ok(Comando::Falla(ModoFalla::Duplicar(0.1)))
+        );
+        assert_eq!(
+            Comando::parsear("f reorder 0.5"),
+            Ok(Comando::Falla(ModoFalla::Reordenar(0.5)))
+        );
+        assert_eq!(
+            Comando::parsear("f off"),
+            Ok(Comando::Falla(ModoFalla::Ninguna))
+        );
+        assert!(Comando::parsear("f drop 2").is_err());
+        assert!(Comando::parsear("f off 1").is_err());
+    }
+
+    #[test]
+    fn test_parsear_comando_desconocido_devuelve_error() {
+        assert!(Comando::parsear("z").is_err());
+    }
+
+    #[test]
+    fn test_parsear_valores_requiere_la_cantidad_exacta_de_numeros() {
+        assert_eq!(parsear_valores::<3>("2 1 5"), Some([2, 1, 5]));
+        assert_eq!(parsear_valores::<3>("2 1"), None);
+        assert_eq!(parsear_valores::<3>("2 1 5 4"), None);
+    }
+}
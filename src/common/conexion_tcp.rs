@@ -1,3 +1,4 @@
+use crate::common::fallas::Fallas;
 use crate::common::mensaje_protocolo::{Conexion, MensajeProtocolo, TipoDeMensaje};
 use crate::common::socket::{ErrorSocket, TipodeError};
 use std::cmp;
@@ -5,26 +6,42 @@ use std::error::Error;
 use std::io::{Read, Write};
 use std::net::{SocketAddr, TcpStream};
 use std::sync::{Arc, Mutex};
+use tracing::{error, trace};
 
 pub struct ConexionTcp {
     stream_lectura: Arc<Mutex<TcpStream>>,
     stream_escritrua: Arc<Mutex<TcpStream>>,
     addr: SocketAddr,
     detalles_conexion: Option<Conexion>,
+    fallas: Arc<Fallas>,
+    /// Nombre del nodo del otro lado, para las particiones
+    remoto: Option<String>,
 }
 
 impl ConexionTcp {
-    pub fn new(stream: TcpStream, addr: SocketAddr) -> Self {
+    pub fn new(stream: TcpStream, addr: SocketAddr, fallas: Arc<Fallas>) -> Self {
+        // los mensajes son chicos y van de a uno esperando respuesta, sin esto cada permiso
+        // espera el ack demorado de tcp
+        let _ = stream.set_nodelay(true);
         let stream_escritrua = Arc::new(Mutex::new(stream.try_clone().unwrap()));
         ConexionTcp {
             stream_lectura: Arc::new(Mutex::new(stream)),
             stream_escritrua,
             addr,
             detalles_conexion: None,
+            fallas,
+            remoto: None,
         }
     }
 
+    pub fn con_remoto(mut self, remoto: String) -> Self {
+        self.remoto = Some(remoto);
+        self
+    }
+
+    /// El nombre con el que se presenta el otro nodo es el de las particiones
     pub fn set_detalles_conexion(&mut self, conexion: Conexion) {
+        self.remoto = Some(conexion.nombre.clone());
         self.detalles_conexion = Some(conexion);
     }
 
@@ -33,6 +50,7 @@ impl ConexionTcp {
     }
 
     pub fn enviar_mensaje(&self, mensaje: &MensajeProtocolo) -> Result<(), Box<dyn Error>> {
+        self.fallas.esperar_comunicacion(self.remoto.as_deref());
         let mut stream = self.stream_escritrua.lock().unwrap();
         let mut buffer = Vec::new();
         buffer.push(mensaje.get_tipo_de_mensaje() as u8);
@@ -40,11 +58,11 @@ impl ConexionTcp {
         buffer.append(&mut mensaje.get_contenido().clone());
         match stream.write_all(&buffer) {
             Ok(_) => {
-                println!("Mensaje enviado a [{}]", self.addr);
+                trace!(target: "tcp", destino = %self.addr, "Mensaje enviado");
                 Ok(())
             }
             Err(e) => {
-                println!("Error al enviar mensaje a [{}], [{}]", self.addr, e);
+                error!(target: "tcp", destino = %self.addr, "Error al enviar mensaje: {}", e);
                 Err(Box::new(e))
             }
         }
@@ -59,7 +77,7 @@ impl ConexionTcp {
         match stream.read_exact(&mut size_buf) {
             Ok(_) => {
                 if size_buf[0] == 0 && size_buf[1] == 0 {
-                    println!("Local respondió algo que el ecommerce no entendió");
+                    error!(target: "tcp", "Se recibió un mensaje que no se entendió");
                     panic!("");
                 }
                 match TipoDeMensaje::new_tipo_de_mensaje(size_buf[0]) {
@@ -102,6 +120,8 @@ impl ConexionTcp {
 
     pub fn esperar_mensaje(&self) -> Result<MensajeProtocolo, Box<dyn Error>> {
         let mut stream = self.stream_lectura.lock().unwrap();
-        Self::read_all(&mut stream)
+        let mensaje = Self::read_all(&mut stream)?;
+        self.fallas.demorar_mensaje_tcp(self.remoto.as_deref());
+        Ok(mensaje)
     }
 }
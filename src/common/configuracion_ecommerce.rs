@@ -0,0 +1,161 @@
+use crate::common::fuente_ordenes::FormatoOrdenes;
+use crate::common::logs::FormatoLogs;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+const PAUSA_DESPACHO_POR_DEFECTO_MS: u64 = 1000;
+
+/// Parametros opcionales con los que se levanta un ecommerce
+pub struct ConfiguracionEcommerce {
+    /// Dirección tcp de la api http, por defecto la del id del ecommerce
+    pub direccion_http: Option<SocketAddr>,
+    /// Archivo de ordenes o `-` para la entrada estandar, por defecto el del ecommerce en data/
+    pub origen_ordenes: Option<String>,
+    /// Formato de las ordenes, por defecto segun la extensión del archivo
+    pub formato_ordenes: Option<FormatoOrdenes>,
+    /// Si esta, al terminar el archivo se espera este tiempo y se buscan ordenes agregadas
+    pub intervalo_seguimiento: Option<Duration>,
+    /// Espera despues de despachar cada orden de la fuente, 0 para medir la carga maxima
+    pub pausa_despacho: Duration,
+    /// Archivo csv con los locales (id_local, latitud, longitud, cobertura), por defecto data/locales_ecommerce.txt
+    pub archivo_locales: Option<String>,
+    /// Con --check solo se valida el archivo de ordenes y se informan las filas invalidas
+    pub solo_validar: bool,
+    /// Filtro de los logs como el de RUST_LOG, por ejemplo `info,udp=debug`
+    pub filtro_logs: Option<String>,
+    pub formato_logs: FormatoLogs,
+    /// Dirección tcp donde se exponen las metricas en formato Prometheus, por defecto la del id
+    pub direccion_metricas: Option<SocketAddr>,
+    /// Archivo donde se agregan los spans en OTLP json, sin él no se exportan trazas
+    pub archivo_trazas: Option<String>,
+    /// Archivo json con las fallas de red a simular, sin él no se simulan fallas
+    pub archivo_fallas: Option<String>,
+    /// Archivo donde se agregan las operaciones y sus resultados para verificarlas despues
+    pub archivo_historial: Option<String>,
+}
+
+impl ConfiguracionEcommerce {
+    /// Lee los parametros opcionales del ecommerce:
+    /// --http <ip:puerto> --ordenes <archivo|-> --formato <csv|jsonl> --seguir <ms> --pausa <ms>
+    /// --locales <archivo> --check --log <filtro> --log-formato <texto|json> --metricas <ip:puerto>
+    /// --trazas <archivo> --fallas <archivo> --historial <archivo>
+    pub fn parsear(
+        mut args: impl Iterator<Item = String>,
+    ) -> Result<ConfiguracionEcommerce, String> {
+        let mut direccion_http = None;
+        let mut origen_ordenes = None;
+        let mut formato_ordenes = None;
+        let mut intervalo_seguimiento = None;
+        let mut pausa_despacho = Duration::from_millis(PAUSA_DESPACHO_POR_DEFECTO_MS);
+        let mut archivo_locales = None;
+        let mut solo_validar = false;
+        let mut filtro_logs = None;
+        let mut formato_logs = FormatoLogs::Texto;
+        let mut direccion_metricas = None;
+        let mut archivo_trazas = None;
+        let mut archivo_fallas = None;
+        let mut archivo_historial = None;
+        while let Some(parametro) = args.next() {
+            if parametro == "--check" {
+                solo_validar = true;
+                continue;
+            }
+            let valor = args
+                .next()
+                .ok_or(format!("Falta el valor de {}", parametro))?;
+            match parametro.as_str() {
+                "--http" => direccion_http = Some(parsear_valor(&parametro, &valor)?),
+                "--ordenes" => origen_ordenes = Some(valor),
+                "--locales" => archivo_locales = Some(valor),
+                "--formato" => formato_ordenes = Some(valor.parse()?),
+                "--seguir" => {
+                    intervalo_seguimiento =
+                        Some(Duration::from_millis(parsear_valor(&parametro, &valor)?))
+                }
+                "--pausa" => {
+                    pausa_despacho = Duration::from_millis(parsear_valor(&parametro, &valor)?)
+                }
+                "--log" => filtro_logs = Some(valor),
+                "--log-formato" => formato_logs = valor.parse()?,
+                "--metricas" => direccion_metricas = Some(parsear_valor(&parametro, &valor)?),
+                "--trazas" => archivo_trazas = Some(valor),
+                "--fallas" => archivo_fallas = Some(valor),
+                "--historial" => archivo_historial = Some(valor),
+                otro => return Err(format!("Parametro desconocido: {}", otro)),
+            }
+        }
+        Ok(ConfiguracionEcommerce {
+            direccion_http,
+            origen_ordenes,
+            formato_ordenes,
+            intervalo_seguimiento,
+            pausa_despacho,
+            archivo_locales,
+            solo_validar,
+            filtro_logs,
+            formato_logs,
+            direccion_metricas,
+            archivo_trazas,
+            archivo_fallas,
+            archivo_historial,
+        })
+    }
+}
+
+fn parsear_valor<T: std::str::FromStr>(parametro: &str, valor: &str) -> Result<T, String> {
+    valor
+        .parse()
+        .map_err(|_| format!("Valor invalido para {}: {}", parametro, valor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(valores: &[&str]) -> impl Iterator<Item = String> {
+        valores
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_parsear_fuente_de_ordenes() {
+        let configuracion = ConfiguracionEcommerce::parsear(args(&[
+            "--ordenes",
+            "-",
+            "--formato",
+            "csv",
+            "--seguir",
+            "250",
+            "--pausa",
+            "0",
+        ]))
+        .unwrap();
+
+        assert_eq!(configuracion.origen_ordenes.as_deref(), Some("-"));
+        assert_eq!(configuracion.formato_ordenes, Some(FormatoOrdenes::Csv));
+        assert_eq!(configuracion.pausa_despacho, Duration::ZERO);
+        assert_eq!(
+            configuracion.intervalo_seguimiento,
+            Some(Duration::from_millis(250))
+        );
+    }
+
+    #[test]
+    fn test_parametros_invalidos_devuelven_error() {
+        assert!(ConfiguracionEcommerce::parsear(args(&["--formato", "xml"])).is_err());
+        assert!(ConfiguracionEcommerce::parsear(args(&["--http", "localhost"])).is_err());
+        assert!(ConfiguracionEcommerce::parsear(args(&["--ordenes"])).is_err());
+    }
+
+    #[test]
+    fn test_check_no_lleva_valor() {
+        let configuracion =
+            ConfiguracionEcommerce::parsear(args(&["--check", "--ordenes", "a.csv"])).unwrap();
+
+        assert!(configuracion.solo_validar);
+        assert_eq!(configuracion.origen_ordenes.as_deref(), Some("a.csv"));
+    }
+}
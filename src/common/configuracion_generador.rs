@@ -0,0 +1,186 @@
+use crate::common::fuente_ordenes::{FormatoOrdenes, ORIGEN_STDIN};
+use crate::common::generador_ordenes::{ParametrosCarga, Zona};
+use std::net::SocketAddr;
+
+const TOTAL_POR_DEFECTO: usize = 1000;
+
+/// A donde van las ordenes generadas
+#[derive(Debug, PartialEq)]
+pub enum DestinoCarga {
+    /// Archivo o `-` para la salida estandar, que se puede pasar al ecommerce con `--ordenes -`
+    Archivo {
+        origen: String,
+        formato: FormatoOrdenes,
+    },
+    /// Api http de un ecommerce, una orden por pedido
+    Http(SocketAddr),
+}
+
+/// Parametros con los que se levanta el generador de carga
+pub struct ConfiguracionGenerador {
+    pub parametros: ParametrosCarga,
+    /// Cantidad de ordenes a generar
+    pub total: usize,
+    pub destino: DestinoCarga,
+}
+
+impl ConfiguracionGenerador {
+    /// Lee los parametros del generador:
+    /// --semilla <n> --total <n> --productos <id[:peso],...> --cantidades <min>-<max>
+    /// --zona <latitud,longitud,radio_km[,peso]> (una por zona) --tasa <ordenes/s>
+    /// --salida <archivo|-> --formato <csv|jsonl> --http <ip:puerto>
+    /// Por defecto escribe en la salida estandar, sin esperas y con una semilla al azar
+    pub fn parsear(
+        mut args: impl Iterator<Item = String>,
+    ) -> Result<ConfiguracionGenerador, String> {
+        let mut parametros = ParametrosCarga {
+            semilla: rand::random(),
+            ..ParametrosCarga::default()
+        };
+        let mut zonas = vec![];
+        let mut total = TOTAL_POR_DEFECTO;
+        let mut salida = None;
+        let mut formato = None;
+        let mut direccion_http = None;
+        while let Some(parametro) = args.next() {
+            let valor = args
+                .next()
+                .ok_or(format!("Falta el valor de {}", parametro))?;
+            match parametro.as_str() {
+                "--semilla" => parametros.semilla = parsear_valor(&parametro, &valor)?,
+                "--total" => total = parsear_valor(&parametro, &valor)?,
+                "--productos" => {
+                    parametros.productos = valor
+                        .split(',')
+                        .map(|producto| parsear_producto(&parametro, producto))
+                        .collect::<Result<_, _>>()?
+                }
+                "--cantidades" => {
+                    let (minimo, maximo) = valor.split_once('-').unwrap_or((&valor, &valor));
+                    parametros.cantidades =
+                        parsear_valor(&parametro, minimo)?..=parsear_valor(&parametro, maximo)?
+                }
+                "--zona" => zonas.push(valor.parse::<Zona>()?),
+                "--tasa" => parametros.tasa = Some(parsear_valor(&parametro, &valor)?),
+                "--salida" => salida = Some(valor),
+                "--formato" => formato = Some(valor.parse()?),
+                "--http" => direccion_http = Some(parsear_valor(&parametro, &valor)?),
+                otro => return Err(format!("Parametro desconocido: {}", otro)),
+            }
+        }
+        if !zonas.is_empty() {
+            parametros.zonas = zonas;
+        }
+        let destino = match (direccion_http, salida) {
+            (Some(_), Some(_)) => return Err("--salida y --http no van juntos".to_string()),
+            (Some(direccion), None) => DestinoCarga::Http(direccion),
+            (None, salida) => {
+                let origen = salida.unwrap_or(ORIGEN_STDIN.to_string());
+                DestinoCarga::Archivo {
+                    formato: formato.unwrap_or(FormatoOrdenes::por_origen(&origen)),
+                    origen,
+                }
+            }
+        };
+        Ok(ConfiguracionGenerador {
+            parametros,
+            total,
+            destino,
+        })
+    }
+}
+
+/// `id` o `id:peso`, el peso por defecto es 1
+fn parsear_producto(parametro: &str, texto: &str) -> Result<(usize, f64), String> {
+    let (id_producto, peso) = texto.split_once(':').unwrap_or((texto, "1"));
+    Ok((
+        parsear_valor(parametro, id_producto)?,
+        parsear_valor(parametro, peso)?,
+    ))
+}
+
+fn parsear_valor<T: std::str::FromStr>(parametro: &str, valor: &str) -> Result<T, String> {
+    valor
+        .trim()
+        .parse()
+        .map_err(|_| format!("Valor invalido para {}: {}", parametro, valor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::orden::Direccion;
+
+    fn args(valores: &[&str]) -> impl Iterator<Item = String> {
+        valores
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_parsear_configuracion_de_la_carga() {
+        let configuracion = ConfiguracionGenerador::parsear(args(&[
+            "--semilla",
+            "7",
+            "--total",
+            "50",
+            "--productos",
+            "1:3,2",
+            "--cantidades",
+            "2-4",
+            "--zona",
+            "1,2,10",
+            "--zona",
+            "3,4,20,2",
+            "--tasa",
+            "500",
+            "--salida",
+            "ventas.csv",
+        ]))
+        .unwrap();
+
+        let parametros = configuracion.parametros;
+        assert_eq!(parametros.semilla, 7);
+        assert_eq!(configuracion.total, 50);
+        assert_eq!(parametros.productos, vec![(1, 3.0), (2, 1.0)]);
+        assert_eq!(parametros.cantidades, 2..=4);
+        assert_eq!(parametros.zonas.len(), 2);
+        assert_eq!(parametros.zonas[1].centro, Direccion::new(3.0, 4.0));
+        assert_eq!(parametros.tasa, Some(500.0));
+        assert_eq!(
+            configuracion.destino,
+            DestinoCarga::Archivo {
+                origen: "ventas.csv".to_string(),
+                formato: FormatoOrdenes::Csv,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parsear_destino_de_la_carga() {
+        let configuracion = ConfiguracionGenerador::parsear(args(&[])).unwrap();
+        assert_eq!(
+            configuracion.destino,
+            DestinoCarga::Archivo {
+                origen: ORIGEN_STDIN.to_string(),
+                formato: FormatoOrdenes::JsonLineas,
+            }
+        );
+
+        let configuracion =
+            ConfiguracionGenerador::parsear(args(&["--http", "127.0.0.1:8082"])).unwrap();
+        assert_eq!(
+            configuracion.destino,
+            DestinoCarga::Http("127.0.0.1:8082".parse().unwrap())
+        );
+        assert!(ConfiguracionGenerador::parsear(args(&[
+            "--http",
+            "127.0.0.1:8082",
+            "--salida",
+            "-"
+        ]))
+        .is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use crate::common::logs::FormatoLogs;
+use crate::common::politica_entrega::{CriterioDeSeleccion, PoliticaDeEntrega};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+const PLAZO_ORDENES_POR_DEFECTO_MS: u64 = 30000;
+const CANTIDAD_REPOSICION_POR_DEFECTO: usize = 20;
+const CAPACIDAD_MAILBOX_POR_DEFECTO: usize = 64;
+const INTERVALO_VENTAS_POR_DEFECTO_MS: u64 = 2000;
+
+/// Parametros opcionales con los que se levanta un local
+pub struct ConfiguracionLocal {
+    pub politica: PoliticaDeEntrega,
+    /// Tiempo que puede estar una orden en progreso antes de cancelarse automaticamente
+    pub plazo_ordenes: Duration,
+    /// Archivo csv con reposiciones (id_producto, cantidad) que ingresan al local
+    pub archivo_reposiciones: Option<String>,
+    /// Unidades disponibles a partir de las cuales se pide reposición automaticamente
+    pub umbral_reposicion: Option<usize>,
+    /// Unidades que se piden en cada pedido de reposición automatica
+    pub cantidad_reposicion: usize,
+    /// Dirección udp a la que se envian los pedidos de reposición
+    pub proveedor: Option<SocketAddr>,
+    /// Ids de los locales a los que se les pide transferencia cuando falta stock para una orden
+    pub vecinos: Vec<usize>,
+    /// Mensajes que pueden esperar en el mailbox del ActorLocal, con el mailbox lleno
+    /// se le responde al ecommerce que el local esta ocupado
+    pub capacidad_mailbox: usize,
+    /// Archivo csv con el stock inicial, por defecto el del local en data/
+    pub archivo_stock: Option<String>,
+    /// Archivo csv donde se guarda el stock al cerrar el local
+    pub archivo_estado: Option<String>,
+    /// Archivo json donde el local guarda su estado despues de cada cambio. Si existe al
+    /// levantar el local, retoma desde él en vez de leer el stock inicial
+    pub archivo_recuperacion: Option<String>,
+    /// Archivo csv con las ventas en el local, por defecto el del local en data/
+    pub archivo_ventas: Option<String>,
+    /// Espera del Vendedor entre una venta y la siguiente
+    pub intervalo_ventas: Duration,
+    /// Dirección tcp del socket de control, por defecto la del id del local
+    pub direccion_control: Option<SocketAddr>,
+    /// Con --check solo se validan los archivos del local y se informan las filas invalidas
+    pub solo_validar: bool,
+    /// Filtro de los logs como el de RUST_LOG, por ejemplo `info,udp=debug`
+    pub filtro_logs: Option<String>,
+    pub formato_logs: FormatoLogs,
+    /// Dirección tcp donde se exponen las metricas en formato Prometheus, por defecto la del id
+    pub direccion_metricas: Option<SocketAddr>,
+    /// Archivo donde se agregan los spans en OTLP json, sin él no se exportan trazas
+    pub archivo_trazas: Option<String>,
+    /// Archivo json con las fallas de red a simular, sin él no se simulan fallas
+    pub archivo_fallas: Option<String>,
+    /// Archivo donde se agregan las operaciones y sus resultados para verificarlas despues
+    pub archivo_historial: Option<String>,
+}
+
+impl ConfiguracionLocal {
+    /// Lee los parametros opcionales del local:
+    /// --politica <fifo|vencimiento|prioridad|aleatoria> --semilla <n> --prob-cancelacion <p>
+    /// --plazo-ordenes <ms> --reposiciones <archivo> --umbral-reposicion <n>
+    /// --cantidad-reposicion <n> --proveedor <ip:puerto> --vecinos <id,id,...>
+    /// --capacidad-mailbox <n> --stock <archivo> --estado <archivo> --recuperacion <archivo>
+    /// --ventas <archivo> --intervalo-ventas <ms> --control <ip:puerto> --check
+    /// --log <filtro> --log-formato <texto|json> --metricas <ip:puerto> --trazas <archivo> --fallas <archivo> --historial <archivo>
+    /// Por defecto se usa la politica aleatoria con una semilla al azar
+    pub fn parsear(mut args: impl Iterator<Item = String>) -> Result<ConfiguracionLocal, String> {
+        let mut criterio = CriterioDeSeleccion::Aleatorio;
+        let mut semilla: u64 = rand::random();
+        let mut probabilidad_cancelacion = 0.5;
+        let mut plazo_ordenes = Duration::from_millis(PLAZO_ORDENES_POR_DEFECTO_MS);
+        let mut archivo_reposiciones = None;
+        let mut umbral_reposicion = None;
+        let mut cantidad_reposicion = CANTIDAD_REPOSICION_POR_DEFECTO;
+        let mut proveedor = None;
+        let mut vecinos = vec![];
+        let mut capacidad_mailbox = CAPACIDAD_MAILBOX_POR_DEFECTO;
+        let mut archivo_stock = None;
+        let mut archivo_estado = None;
+        let mut archivo_recuperacion = None;
+        let mut archivo_ventas = None;
+        let mut intervalo_ventas = Duration::from_millis(INTERVALO_VENTAS_POR_DEFECTO_MS);
+        let mut direccion_control = None;
+        let mut solo_validar = false;
+        let mut filtro_logs = None;
+        let mut formato_logs = FormatoLogs::Texto;
+        let mut direccion_metricas = None;
+        let mut archivo_trazas = None;
+        let mut archivo_fallas = None;
+        let mut archivo_historial = None;
+        while let Some(parametro) = args.next() {
+            if parametro == "--check" {
+                solo_validar = true;
+                continue;
+            }
+            let valor = args
+                .next()
+                .ok_or(format!("Falta el valor de {}", parametro))?;
+            match parametro.as_str() {
+                "--politica" => criterio = valor.parse()?,
+                "--semilla" => semilla = parsear_valor(&parametro, &valor)?,
+                "--prob-cancelacion" => {
+                    probabilidad_cancelacion = parsear_valor(&parametro, &valor)
+                        .ok()
+                        .filter(|p| (0.0..=1.0).contains(p))
+                        .ok_or(format!("Probabilidad de cancelacion invalida: {}", valor))?
+                }
+                "--plazo-ordenes" => {
+                    plazo_ordenes = Duration::from_millis(parsear_valor(&parametro, &valor)?)
+                }
+                "--reposiciones" => archivo_reposiciones = Some(valor),
+                "--umbral-reposicion" => {
+                    umbral_reposicion = Some(parsear_valor(&parametro, &valor)?)
+                }
+                "--cantidad-reposicion" => cantidad_reposicion = parsear_valor(&parametro, &valor)?,
+                "--proveedor" => proveedor = Some(parsear_valor(&parametro, &valor)?),
+                "--vecinos" => {
+                    vecinos = valor
+                        .split(',')
+                        .map(|id| parsear_valor(&parametro, id))
+                        .collect::<Result<_, _>>()?
+                }
+                "--capacidad-mailbox" => {
+                    capacidad_mailbox = parsear_valor(&parametro, &valor)
+                        .ok()
+                        .filter(|capacidad| *capacidad > 0)
+                        .ok_or(format!("Capacidad de mailbox invalida: {}", valor))?
+                }
+                "--stock" => archivo_stock = Some(valor),
+                "--estado" => archivo_estado = Some(valor),
+                "--recuperacion" => archivo_recuperacion = Some(valor),
+                "--ventas" => archivo_ventas = Some(valor),
+                "--intervalo-ventas" => {
+                    intervalo_ventas = Duration::from_millis(parsear_valor(&parametro, &valor)?)
+                }
+                "--control" => direccion_control = Some(parsear_valor(&parametro, &valor)?),
+                "--log" => filtro_logs = Some(valor),
+                "--log-formato" => formato_logs = valor.parse()?,
+                "--metricas" => direccion_metricas = Some(parsear_valor(&parametro, &valor)?),
+                "--trazas" => archivo_trazas = Some(valor),
+                "--fallas" => archivo_fallas = Some(valor),
+                "--historial" => archivo_historial = Some(valor),
+                otro => return Err(format!("Parametro desconocido: {}", otro)),
+            }
+        }
+        Ok(ConfiguracionLocal {
+            politica: PoliticaDeEntrega::new(criterio, probabilidad_cancelacion, semilla),
+            plazo_ordenes,
+            archivo_reposiciones,
+            umbral_reposicion,
+            cantidad_reposicion,
+            proveedor,
+            vecinos,
+            capacidad_mailbox,
+            archivo_stock,
+            archivo_estado,
+            archivo_recuperacion,
+            archivo_ventas,
+            intervalo_ventas,
+            direccion_control,
+            solo_validar,
+            filtro_logs,
+            formato_logs,
+            direccion_metricas,
+            archivo_trazas,
+            archivo_fallas,
+            archivo_historial,
+        })
+    }
+}
+
+fn parsear_valor<T: std::str::FromStr>(parametro: &str, valor: &str) -> Result<T, String> {
+    valor
+        .parse()
+        .map_err(|_| format!("Valor invalido para {}: {}", parametro, valor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(valores: &[&str]) -> impl Iterator<Item = String> {
+        valores
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_parsear_configuracion_con_parametros() {
+        let configuracion = ConfiguracionLocal::parsear(args(&[
+            "--politica",
+            "prioridad",
+            "--semilla",
+            "7",
+            "--prob-cancelacion",
+            "0",
+            "--plazo-ordenes",
+            "500",
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            configuracion.politica.criterio(),
+            CriterioDeSeleccion::Prioridad
+        );
+        assert_eq!(configuracion.politica.semilla(), 7);
+        assert_eq!(configuracion.plazo_ordenes, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_parsear_configuracion_de_reposicion() {
+        let configuracion = ConfiguracionLocal::parsear(args(&[
+            "--umbral-reposicion",
+            "5",
+            "--proveedor",
+            "127.0.0.1:9000",
+        ]))
+        .unwrap();
+
+        assert_eq!(configuracion.umbral_reposicion, Some(5));
+        assert_eq!(
+            configuracion.cantidad_reposicion,
+            CANTIDAD_REPOSICION_POR_DEFECTO
+        );
+        assert_eq!(
+            configuracion.proveedor,
+            Some("127.0.0.1:9000".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_parsear_configuracion_de_ventas() {
+        let configuracion = ConfiguracionLocal::parsear(args(&[
+            "--ventas",
+            "ventas.csv",
+            "--intervalo-ventas",
+            "0",
+        ]))
+        .unwrap();
+
+        assert_eq!(configuracion.archivo_ventas.as_deref(), Some("ventas.csv"));
+        assert_eq!(configuracion.intervalo_ventas, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_parsear_configuracion_de_vecinos() {
+        let configuracion = ConfiguracionLocal::parsear(args(&["--vecinos", "2,3"])).unwrap();
+
+        assert_eq!(configuracion.vecinos, vec![2, 3]);
+        assert!(ConfiguracionLocal::parsear(args(&["--vecinos", "2,x"])).is_err());
+    }
+
+    #[test]
+    fn test_parsear_configuracion_de_capacidad_mailbox() {
+        let configuracion =
+            ConfiguracionLocal::parsear(args(&["--capacidad-mailbox", "8"])).unwrap();
+
+        assert_eq!(configuracion.capacidad_mailbox, 8);
+        assert!(ConfiguracionLocal::parsear(args(&["--capacidad-mailbox", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_parsear_configuracion_sin_parametros_usa_valores_por_defecto() {
+        let configuracion = ConfiguracionLocal::parsear(args(&[])).unwrap();
+
+        assert_eq!(
+            configuracion.politica.criterio(),
+            CriterioDeSeleccion::Aleatorio
+        );
+        assert_eq!(
+            configuracion.plazo_ordenes,
+            Duration::from_millis(PLAZO_ORDENES_POR_DEFECTO_MS)
+        );
+        assert!(configuracion.archivo_recuperacion.is_none());
+    }
+
+    #[test]
+    fn test_parsear_configuracion_de_recuperacion() {
+        let configuracion =
+            ConfiguracionLocal::parsear(args(&["--recuperacion", "local_1.json"])).unwrap();
+
+        assert_eq!(
+            configuracion.archivo_recuperacion.as_deref(),
+            Some("local_1.json")
+        );
+    }
+
+    #[test]
+    fn test_parsear_configuracion_con_probabilidad_fuera_de_rango_devuelve_error() {
+        assert!(ConfiguracionLocal::parsear(args(&["--prob-cancelacion", "1.5"])).is_err());
+    }
+
+    #[test]
+    fn test_parsear_configuracion_con_parametro_sin_valor_devuelve_error() {
+        assert!(ConfiguracionLocal::parsear(args(&["--semilla"])).is_err());
+    }
+}
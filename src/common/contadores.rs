@@ -0,0 +1,17 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Cantidad de operaciones que hizo el local desde que se levantó
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Contadores {
+    pub ordenes_aceptadas: u64,
+    pub ordenes_rechazadas: u64,
+    pub ordenes_entregadas: u64,
+    pub ordenes_canceladas: u64,
+    pub ordenes_vencidas: u64,
+    pub ventas: u64,
+    pub ventas_rechazadas: u64,
+    pub reposiciones: u64,
+    /// Reinicios de cada actor supervisado
+    pub reinicios: BTreeMap<String, u64>,
+}
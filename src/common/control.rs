@@ -0,0 +1,49 @@
+/// Consultas que acepta el socket de control del local, una por linea
+pub const CONSULTAS: [&str; 5] = ["stock", "ordenes", "estado", "contadores", "ayuda"];
+
+#[derive(Debug, PartialEq)]
+pub enum ConsultaControl {
+    /// Stock, bloqueados y disponibles de cada producto
+    Stock,
+    /// Cantidad de ordenes en progreso
+    Ordenes,
+    /// Si el local acepta ordenes, si esta cerrando y la falla simulada activa
+    Estado,
+    /// Contadores de operaciones desde que se levantó el local
+    Contadores,
+    Ayuda,
+}
+
+impl ConsultaControl {
+    pub fn parsear(linea: &str) -> Option<ConsultaControl> {
+        match linea.trim().to_lowercase().as_str() {
+            "stock" => Some(ConsultaControl::Stock),
+            "ordenes" => Some(ConsultaControl::Ordenes),
+            "estado" => Some(ConsultaControl::Estado),
+            "contadores" => Some(ConsultaControl::Contadores),
+            "ayuda" => Some(ConsultaControl::Ayuda),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsear_todas_las_consultas() {
+        for consulta in CONSULTAS {
+            assert!(ConsultaControl::parsear(consulta).is_some());
+        }
+        assert_eq!(
+            ConsultaControl::parsear(" Stock\r\n"),
+            Some(ConsultaControl::Stock)
+        );
+    }
+
+    #[test]
+    fn test_parsear_consulta_desconocida() {
+        assert_eq!(ConsultaControl::parsear("borrar todo"), None);
+    }
+}
@@ -1,8 +1,13 @@
-use std::sync::{Arc, Mutex};
-use std::{net::TcpStream, thread};
+use std::error::Error;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
 
 use crate::common::conexion_tcp::ConexionTcp;
+use crate::common::fallas::{nombre_ecommerce, Fallas};
+use crate::common::historial::{Historial, Operacion};
 use std_semaphore::Semaphore;
+use tracing::{debug, error, info, warn};
 
 use crate::common::mensaje_protocolo::{Conexion, MensajeProtocolo, TipoDeMensaje};
 
@@ -12,7 +17,7 @@ pub struct Cordinador {
 }
 
 impl Cordinador {
-    pub fn new(id: u32) -> Cordinador {
+    pub fn new(_id: u32) -> Cordinador {
         // TODO reemplazar con el algoritmo de lider
         Cordinador {
             permiso: Semaphore::new(1),
@@ -28,48 +33,86 @@ impl Cordinador {
     }
 }
 
-pub fn setup_conexion(stream: TcpStream) -> ConexionTcp {
+pub fn setup_conexion(stream: TcpStream, fallas: Arc<Fallas>) -> ConexionTcp {
     let addr = stream.peer_addr().unwrap();
-    let mut conexion = ConexionTcp::new(stream, addr);
-    println!("Conexion recibida de: {}", addr);
+    let mut conexion = ConexionTcp::new(stream, addr, fallas);
+    info!(target: "coordinador", remitente = %addr, "Conexion recibida");
     match conexion.esperar_mensaje() {
         Ok(msg) => {
             if msg.get_tipo_de_mensaje().value() == TipoDeMensaje::Conexion.value() {
                 let conexion_msg = Conexion::deserializar(msg.get_contenido()).unwrap();
                 conexion.set_detalles_conexion(conexion_msg);
             } else {
-                println!(
+                warn!(
+                    target: "coordinador",
                     "Mensaje no reconocido: {}",
                     msg.get_tipo_de_mensaje().value()
                 );
             }
         }
         Err(e) => {
-            println!("Error al recibir mensaje: {}", e);
+            error!(target: "coordinador", "Error al recibir mensaje: {}", e);
         }
     }
     conexion
 }
 
-pub(crate) fn connect_to_leader(direccion: String, id: u32) -> ConexionTcp {
-    println!("Conectando a {}", direccion);
-    let stream = TcpStream::connect(direccion).unwrap();
-    let addr = stream.peer_addr().unwrap();
-    let conexion = ConexionTcp::new(stream, addr);
-    conexion
-        .enviar_mensaje(
-            &MensajeProtocolo::new_conexion(Conexion {
-                nombre: format!("eccomerce_{}", id),
-            })
-            .unwrap(),
-        )
-        .unwrap();
-    conexion
+/// El lider es siempre el ecommerce 1
+pub(crate) fn connect_to_leader(direccion: String, id: u32, fallas: Arc<Fallas>) -> ConexionTcp {
+    conectar_al_lider(direccion, id, fallas).unwrap()
 }
 
-pub fn coordinar(stream: TcpStream, cordinador: Arc<Mutex<Cordinador>>) {
-    // TODO agregar a la lista de conexiones y liberar todos juntos
-    let _ = thread::spawn(move || coordinar_conexion(stream.try_clone().unwrap(), cordinador));
+/// Se conecta al lider y se presenta, devuelve el error si no esta escuchando
+pub(crate) fn conectar_al_lider(
+    direccion: String,
+    id: u32,
+    fallas: Arc<Fallas>,
+) -> Result<ConexionTcp, Box<dyn Error>> {
+    info!(target: "coordinador", lider = %direccion, "Conectando al lider");
+    let stream = TcpStream::connect(direccion)?;
+    let addr = stream.peer_addr()?;
+    let conexion = ConexionTcp::new(stream, addr, fallas).con_remoto(nombre_ecommerce(1));
+    conexion.enviar_mensaje(&MensajeProtocolo::new_conexion(Conexion {
+        nombre: nombre_ecommerce(id as usize),
+    })?)?;
+    Ok(conexion)
+}
+
+/// Atiende la conexión en su propio thread, que termina cuando el ecommerce se desconecta
+pub fn coordinar(
+    stream: TcpStream,
+    cordinador: Arc<Mutex<Cordinador>>,
+    fallas: Arc<Fallas>,
+    historial: Option<Historial>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        coordinar_conexion(stream.try_clone().unwrap(), cordinador, fallas, historial)
+    })
+}
+
+/// Registra en el historial del lider el permiso que se libera, antes de liberarlo
+/// para que no se superponga con el siguiente que se otorga
+fn registrar_liberado(
+    historial: &Option<Historial>,
+    conexion: &ConexionTcp,
+    cursor: i32,
+    avanzo: bool,
+) {
+    if let Some(historial) = historial {
+        historial.registrar(Operacion::PermisoLiberado {
+            ecommerce: nombre_remoto(conexion),
+            cursor: cursor as usize,
+            avanzo,
+        });
+    }
+}
+
+fn nombre_remoto(conexion: &ConexionTcp) -> String {
+    conexion
+        .get_detalles_conexion()
+        .as_ref()
+        .map(|detalles| detalles.nombre.clone())
+        .unwrap_or_default()
 }
 
 // espero mensaje para mandar ordenes
@@ -79,22 +122,37 @@ pub fn coordinar(stream: TcpStream, cordinador: Arc<Mutex<Cordinador>>) {
 // libero el permiso
 // si me envian algo que no es lo que espero tambien libero el permiso
 
-fn coordinar_conexion(stream: TcpStream, cordinador: Arc<Mutex<Cordinador>>) {
-    let conexion = setup_conexion(stream);
-    println!("Cliente agregado a la lista");
+fn coordinar_conexion(
+    stream: TcpStream,
+    cordinador: Arc<Mutex<Cordinador>>,
+    fallas: Arc<Fallas>,
+    historial: Option<Historial>,
+) {
+    let conexion = setup_conexion(stream, fallas);
+    debug!(target: "coordinador", "Cliente agregado a la lista");
     loop {
         match conexion.esperar_mensaje() {
             Ok(msg) => match msg.get_tipo_de_mensaje() {
                 TipoDeMensaje::QuieroMandarOrdenes => {
-                    println!(
-                        "Recibi un pedido de querer mandar ordenes de parte de [{}]",
-                        conexion.get_detalles_conexion().as_ref().unwrap().nombre
+                    info!(
+                        target: "coordinador",
+                        ecommerce = conexion.get_detalles_conexion().as_ref().unwrap().nombre,
+                        "Pide permiso para mandar ordenes"
                     );
-                    let mut cordinador_lock = cordinador.try_lock().unwrap();
+                    // el lock se mantiene hasta que devuelve el permiso, asi los demas esperan
+                    let mut cordinador_lock =
+                        cordinador.lock().unwrap_or_else(PoisonError::into_inner);
                     cordinador_lock.get_permso();
-                    println!(
-                        "Le di permiso a [{}] para mandar ordenes",
-                        conexion.get_detalles_conexion().as_ref().unwrap().nombre
+                    if let Some(historial) = &historial {
+                        historial.registrar(Operacion::PermisoOtorgado {
+                            ecommerce: nombre_remoto(&conexion),
+                            cursor: cordinador_lock.cursor as usize,
+                        });
+                    }
+                    info!(
+                        target: "coordinador",
+                        ecommerce = conexion.get_detalles_conexion().as_ref().unwrap().nombre,
+                        "Se le da el permiso"
                     );
                     conexion
                         .enviar_mensaje(&MensajeProtocolo::new_puedo_mandar_ordenes(
@@ -104,58 +162,108 @@ fn coordinar_conexion(stream: TcpStream, cordinador: Arc<Mutex<Cordinador>>) {
                     match conexion.esperar_mensaje() {
                         Ok(msg_final) => match msg_final.get_tipo_de_mensaje() {
                             TipoDeMensaje::TermineDeMandarOrdenes => {
+                                registrar_liberado(
+                                    &historial,
+                                    &conexion,
+                                    cordinador_lock.cursor,
+                                    true,
+                                );
                                 cordinador_lock.cursor += 1;
-                                println!(
-                                    "Recibi un pedido de terminar de mandar ordenes de parte de [{}]",
-                                    conexion.get_detalles_conexion().as_ref().unwrap().nombre
+                                info!(
+                                    target: "coordinador",
+                                    ecommerce = conexion.get_detalles_conexion().as_ref().unwrap().nombre,
+                                    "Termino de mandar la orden"
                                 );
                                 cordinador_lock.release_permiso();
                                 drop(cordinador_lock);
-                                println!(
-                                    "Le quite el permiso a [{}] para mandar ordenes",
-                                    conexion.get_detalles_conexion().as_ref().unwrap().nombre
+                                debug!(
+                                    target: "coordinador",
+                                    ecommerce = conexion.get_detalles_conexion().as_ref().unwrap().nombre,
+                                    "Se le quita el permiso"
                                 );
                             }
+                            TipoDeMensaje::NoHayOrdenes => {
+                                debug!(
+                                    target: "coordinador",
+                                    ecommerce = conexion.get_detalles_conexion().as_ref().unwrap().nombre,
+                                    cursor = cordinador_lock.cursor,
+                                    "No tiene ordenes en el cursor, se mantiene"
+                                );
+                                registrar_liberado(
+                                    &historial,
+                                    &conexion,
+                                    cordinador_lock.cursor,
+                                    false,
+                                );
+                                cordinador_lock.release_permiso();
+                                drop(cordinador_lock);
+                            }
                             TipoDeMensaje::Desconexion => {
-                                println!(
-                                    "Recibi una desconexion de parte de [{}]",
-                                    conexion.get_detalles_conexion().as_ref().unwrap().nombre
+                                info!(
+                                    target: "coordinador",
+                                    ecommerce = conexion.get_detalles_conexion().as_ref().unwrap().nombre,
+                                    "Se desconecto"
+                                );
+                                registrar_liberado(
+                                    &historial,
+                                    &conexion,
+                                    cordinador_lock.cursor,
+                                    false,
                                 );
                                 cordinador_lock.release_permiso();
                                 drop(cordinador_lock);
-                                println!(
-                                    "Le quite el permiso a [{}] para mandar ordenes",
-                                    conexion.get_detalles_conexion().as_ref().unwrap().nombre
+                                debug!(
+                                    target: "coordinador",
+                                    ecommerce = conexion.get_detalles_conexion().as_ref().unwrap().nombre,
+                                    "Se le quita el permiso"
                                 );
                                 break;
                             }
                             _ => {
-                                println!(
+                                warn!(
+                                    target: "coordinador",
                                     "Mensaje no reconocido: {}",
                                     msg.get_tipo_de_mensaje().value()
                                 );
-                                cordinador.try_lock().unwrap().release_permiso();
-                                println!(
-                                    "Le quite el permiso a [{}] para mandar ordenes",
-                                    conexion.get_detalles_conexion().as_ref().unwrap().nombre
+                                registrar_liberado(
+                                    &historial,
+                                    &conexion,
+                                    cordinador_lock.cursor,
+                                    false,
+                                );
+                                cordinador_lock.release_permiso();
+                                debug!(
+                                    target: "coordinador",
+                                    ecommerce = conexion.get_detalles_conexion().as_ref().unwrap().nombre,
+                                    "Se le quita el permiso"
                                 );
                             }
                         },
                         Err(e) => {
-                            println!("Error al recibir mensaje: {}", e);
-                            cordinador.try_lock().unwrap().release_permiso();
+                            error!(target: "coordinador", "Error al recibir mensaje: {}", e);
+                            registrar_liberado(
+                                &historial,
+                                &conexion,
+                                cordinador_lock.cursor,
+                                false,
+                            );
+                            cordinador_lock.release_permiso();
                             break;
                         }
                     }
                 }
                 _ => {
-                    println!(
+                    warn!(
+                        target: "coordinador",
                         "Mensaje no reconocido: {}",
                         msg.get_tipo_de_mensaje().value()
                     )
                 }
             },
-            Err(_) => println!("Error al recibir mensaje"),
+            Err(_) => {
+                error!(target: "coordinador", "Error al recibir mensaje, se cierra la conexión");
+                break;
+            }
         }
     }
 }
@@ -0,0 +1,767 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, field, info, info_span, warn};
+
+use crate::common::fallas::SocketUdp;
+use crate::common::fuente_ordenes::{ErrorFuente, FuenteOrdenes};
+use crate::common::historial::{Historial, Operacion};
+use crate::common::info_local::MapaLocales;
+use crate::common::mensaje_local::{MensajeLocal, MotivoCancelacion, RespuestaOrden};
+use crate::common::metricas::MetricasEcommerce;
+use crate::common::orden::Orden;
+use crate::common::registro_ordenes::MotivoRechazo;
+use crate::common::reloj::Reloj;
+use crate::common::socket::{id_to_addr_local, Socket};
+use crate::common::trazas::contexto_actual;
+
+const REINTENTOS_LOCALES_OCUPADOS: usize = 3;
+const ESPERA_LOCALES_OCUPADOS: Duration = Duration::from_millis(500);
+/// Tiempo durante el que no se le envian ordenes a un local que avisó que cerraba
+pub const ESPERA_LOCAL_CERRADO: Duration = Duration::from_secs(10);
+const TAMANIO_BUFFER: usize = 256;
+const TIMEOUT_RESPUESTA_LOCAL: Duration = Duration::from_millis(500);
+/// Tiempo durante el que se le reenvía la confirmación a un local que no contesta
+const PLAZO_CONFIRMACION: Duration = Duration::from_secs(5);
+/// Espera al revisar si llegaron avisos de los locales entre despacho y despacho
+const ESPERA_AVISOS: Duration = Duration::from_millis(1);
+
+#[derive(Debug, PartialEq)]
+pub enum ErrorEcommerce {
+    SocketTimeOut,
+    SinLider,
+}
+
+impl fmt::Display for ErrorEcommerce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ErrorEcommerce::SocketTimeOut => write!(f, "Timeout en socket recv"),
+            ErrorEcommerce::SinLider => write!(f, "Se perdió la conexión con el lider"),
+        }
+    }
+}
+
+/// Lo que contesta un local al ofrecerle una orden
+enum RespuestaLocal {
+    Aceptada,
+    Ocupado,
+    /// Rechazada, cerrando o sin respuesta
+    NoAceptada,
+    /// Podia tomarla pero no contestó la confirmación, no se sabe si la aceptó
+    SinConfirmacion,
+}
+
+/// Un local avisó que canceló una orden que habia aceptado
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cancelacion {
+    pub orden: String,
+    pub local: usize,
+    pub motivo: MotivoCancelacion,
+}
+
+/// Lo que pasó en un turno con el permiso del lider
+#[derive(Debug)]
+pub enum Turno {
+    /// Se leyó la orden del cursor (o la siguiente de una fuente propia, con el cursor del
+    /// permiso) y se despachó, con el local que la aceptó o el motivo del rechazo
+    Despachada {
+        cursor: usize,
+        orden: Orden,
+        resultado: Result<usize, MotivoRechazo>,
+    },
+    SinOrdenesPorAhora,
+    SinMasOrdenes,
+    /// La orden del cursor es invalida y se salteó
+    OrdenInvalida,
+    /// No se pudo leer la fuente, el permiso se devolvió sin avanzar el cursor
+    ErrorDeLectura(ErrorFuente),
+    /// Se perdió la conexión con el lider antes de tener el permiso, no se leyó ninguna orden
+    SinLider,
+}
+
+/// Elige el local para cada orden y se la ofrece por udp
+pub struct Despachador {
+    mapa: MapaLocales,
+    locales: ConexionLocales,
+    /// Donde se registran los permisos, los despachos y sus resultados
+    historial: Option<Historial>,
+    /// Ordenes leidas de una fuente propia de la instancia
+    leidas_propias: usize,
+}
+
+/// Lo necesario para ofrecerle una orden a un local
+struct ConexionLocales {
+    socket: SocketUdp,
+    /// Momento en ms en que cada local avisó que cerraba
+    cerrados: HashMap<usize, u64>,
+    /// Direcciones de los locales que no estan en la dirección por defecto de su id
+    direcciones: HashMap<usize, SocketAddr>,
+    metricas: Arc<Mutex<MetricasEcommerce>>,
+    reloj: Arc<dyn Reloj>,
+    /// Avisos de cancelación recibidos que todavia no se atendieron
+    cancelaciones: Vec<Cancelacion>,
+}
+
+impl Despachador {
+    pub fn new(
+        socket: SocketUdp,
+        mapa: MapaLocales,
+        metricas: Arc<Mutex<MetricasEcommerce>>,
+        reloj: Arc<dyn Reloj>,
+    ) -> Self {
+        Despachador {
+            mapa,
+            locales: ConexionLocales {
+                socket,
+                cerrados: HashMap::new(),
+                direcciones: HashMap::new(),
+                metricas,
+                reloj,
+                cancelaciones: vec![],
+            },
+            historial: None,
+            leidas_propias: 0,
+        }
+    }
+
+    pub fn con_historial(mut self, historial: Option<Historial>) -> Self {
+        self.historial = historial;
+        self
+    }
+
+    fn registrar(&self, operacion: Operacion) {
+        if let Some(historial) = &self.historial {
+            historial.registrar(operacion);
+        }
+    }
+
+    /// Usa estas direcciones en lugar de las por defecto de cada id de local
+    pub fn con_direcciones(mut self, direcciones: HashMap<usize, SocketAddr>) -> Self {
+        self.locales.direcciones = direcciones;
+        self
+    }
+
+    /// Devuelve las cancelaciones que avisaron los locales, tanto las que llegaron
+    /// mientras se esperaba la respuesta a una orden como las que estan en el socket
+    pub fn atender_avisos(&mut self) -> Vec<Cancelacion> {
+        let mut buffer = [0; TAMANIO_BUFFER];
+        while let Ok((size, from)) = self.locales.socket.recibir(&mut buffer, ESPERA_AVISOS) {
+            if !self.locales.atender_aviso(&buffer[..size], from) {
+                debug!(target: "udp", remitente = %from, "Se descarta respuesta fuera de tiempo");
+            }
+        }
+        std::mem::take(&mut self.locales.cancelaciones)
+    }
+
+    /// Pide el permiso al lider, lee la orden del cursor que le da y la despacha.
+    /// El permiso se devuelve apenas se termina de leer, antes de despachar
+    pub fn turno(
+        &mut self,
+        lider: &mut Socket,
+        fuente: &mut dyn FuenteOrdenes,
+        id_ecommerce: u32,
+    ) -> Turno {
+        if !fuente.compartida() {
+            return self.turno_fuente_propia(lider, fuente, id_ecommerce);
+        }
+        // una traza por cada vez que se lee la fuente: el permiso, la lectura y el despacho
+        let span_orden = info_span!(
+            target: "ecommerce",
+            "orden",
+            orden = field::Empty,
+            cursor = field::Empty
+        );
+        let _entrada = span_orden.enter();
+        let Ok(cursor) = self.pedir_permiso(lider) else {
+            return Turno::SinLider;
+        };
+        span_orden.record("cursor", cursor);
+        let devuelto = |avanzo| Operacion::PermisoDevuelto { cursor, avanzo };
+        debug!(target: "fuente", cursor, "Leo una orden");
+        match info_span!(target: "fuente", "leer_orden").in_scope(|| fuente.orden_en(cursor)) {
+            Ok(mut orden) => {
+                orden
+                    .id
+                    .get_or_insert(format!("{}-{}", id_ecommerce, cursor));
+                span_orden.record("orden", orden.id.as_deref());
+                self.registrar(Operacion::Despacho {
+                    cursor,
+                    orden: orden.id.clone().unwrap_or_default(),
+                });
+                // termine de leer libero el permiso
+                self.registrar(devuelto(true));
+                lider.ordenes_enviadas();
+                let resultado = self.despachar(&orden);
+                Turno::Despachada {
+                    cursor,
+                    orden,
+                    resultado,
+                }
+            }
+            Err(ErrorFuente::SinOrdenesPorAhora) => {
+                self.registrar(devuelto(false));
+                lider.no_hay_ordenes();
+                Turno::SinOrdenesPorAhora
+            }
+            // la conexión con el lider sigue abierta para las ordenes que lleguen por http
+            Err(ErrorFuente::SinMasOrdenes) => {
+                self.registrar(devuelto(false));
+                lider.no_hay_ordenes();
+                Turno::SinMasOrdenes
+            }
+            Err(err @ ErrorFuente::OrdenInvalida { .. }) => {
+                // se saltea la orden para no trabar al resto de las instancias
+                warn!(target: "fuente", cursor, "{}", err);
+                self.registrar(devuelto(true));
+                lider.ordenes_enviadas();
+                Turno::OrdenInvalida
+            }
+            Err(err @ ErrorFuente::Io(_)) => {
+                self.registrar(devuelto(false));
+                lider.no_hay_ordenes();
+                Turno::ErrorDeLectura(err)
+            }
+        }
+    }
+
+    /// Lee la siguiente orden de una fuente que solo tiene esta instancia, sin pedir el permiso
+    /// porque no usa el cursor, y la despacha con el permiso como las que llegan por http
+    fn turno_fuente_propia(
+        &mut self,
+        lider: &mut Socket,
+        fuente: &mut dyn FuenteOrdenes,
+        id_ecommerce: u32,
+    ) -> Turno {
+        let numero = self.leidas_propias;
+        // si se pierde el lider se vuelve a leer la misma orden en el siguiente turno
+        let mut orden = match fuente.orden_en(numero) {
+            Ok(orden) => orden,
+            Err(ErrorFuente::SinOrdenesPorAhora) => return Turno::SinOrdenesPorAhora,
+            Err(ErrorFuente::SinMasOrdenes) => return Turno::SinMasOrdenes,
+            Err(err @ ErrorFuente::OrdenInvalida { .. }) => {
+                warn!(target: "fuente", "{}", err);
+                self.leidas_propias += 1;
+                return Turno::OrdenInvalida;
+            }
+            Err(err @ ErrorFuente::Io(_)) => return Turno::ErrorDeLectura(err),
+        };
+        orden
+            .id
+            .get_or_insert(format!("{}-propia-{}", id_ecommerce, numero));
+        let Ok((cursor, resultado)) = self.despachar_sin_avanzar(lider, &orden) else {
+            return Turno::SinLider;
+        };
+        self.leidas_propias += 1;
+        Turno::Despachada {
+            cursor,
+            orden,
+            resultado,
+        }
+    }
+
+    /// Despacha una orden que llegó por http por el mismo camino que las del cursor: se
+    /// pide el permiso al lider y se lo devuelve sin avanzar el cursor antes de despacharla.
+    /// Si se perdió la conexión con el lider no se despacha
+    pub fn turno_http(
+        &mut self,
+        lider: &mut Socket,
+        orden: &Orden,
+    ) -> Result<Result<usize, MotivoRechazo>, ErrorEcommerce> {
+        Ok(self.despachar_sin_avanzar(lider, orden)?.1)
+    }
+
+    /// Despacha con el permiso del lider una orden que no sale del cursor, devuelve el
+    /// cursor del permiso y el resultado del despacho
+    fn despachar_sin_avanzar(
+        &mut self,
+        lider: &mut Socket,
+        orden: &Orden,
+    ) -> Result<(usize, Result<usize, MotivoRechazo>), ErrorEcommerce> {
+        let _span = info_span!(target: "ecommerce", "orden", orden = orden.id.as_deref()).entered();
+        let cursor = self.pedir_permiso(lider)?;
+        self.registrar(Operacion::PermisoDevuelto {
+            cursor,
+            avanzo: false,
+        });
+        lider.no_hay_ordenes();
+        Ok((cursor, self.despachar(orden)))
+    }
+
+    /// Espera el permiso del lider y devuelve el cursor que le dio
+    fn pedir_permiso(&mut self, lider: &mut Socket) -> Result<usize, ErrorEcommerce> {
+        let pedido_permiso = Instant::now();
+        let cursor = info_span!(target: "coordinador", "pedir_permiso")
+            .in_scope(|| lider.quiero_enviar_ordenes());
+        self.locales
+            .metricas
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .espera_permiso
+            .observar(pedido_permiso.elapsed().as_secs_f64());
+        let cursor = usize::try_from(cursor).map_err(|_| ErrorEcommerce::SinLider)?;
+        self.registrar(Operacion::PermisoRecibido { cursor });
+        Ok(cursor)
+    }
+
+    /// Envia la orden al local más cercano que entrega en su dirección, si no la acepta
+    /// prueba con el siguiente. Devuelve el id del local que aceptó la orden
+    pub fn despachar(&mut self, orden: &Orden) -> Result<usize, MotivoRechazo> {
+        let _span = info_span!(target: "ecommerce", "despachar_orden").entered();
+        let resultado = self.ofrecer_a_locales(orden);
+        self.registrar(Operacion::Resultado {
+            orden: orden.id.clone().unwrap_or_default(),
+            local: resultado.ok(),
+            motivo: resultado.err().map(|motivo| motivo.nombre().to_string()),
+        });
+        let mut metricas = self
+            .locales
+            .metricas
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        metricas.ordenes_despachadas += 1;
+        match resultado {
+            Ok(local) => metricas.aceptada(local),
+            Err(motivo) => metricas.sin_local(motivo),
+        }
+        resultado
+    }
+
+    fn ofrecer_a_locales(&mut self, orden: &Orden) -> Result<usize, MotivoRechazo> {
+        let ahora = self.locales.reloj.ahora_en_ms();
+        self.locales.cerrados.retain(|_, cierre| {
+            ahora.saturating_sub(*cierre) < ESPERA_LOCAL_CERRADO.as_millis() as u64
+        });
+        let mut locales_ocupados = vec![];
+
+        let id_orden = orden.id.as_deref();
+        let mut candidatos = self.mapa.que_cubren(&orden.direccion).peekable();
+        if candidatos.peek().is_none() {
+            warn!(
+                target: "ecommerce",
+                orden = id_orden,
+                motivo = %MotivoRechazo::FueraDeCobertura,
+                "Se rechaza la orden"
+            );
+            return Err(MotivoRechazo::FueraDeCobertura);
+        }
+
+        for (local_seleccionado, distancia) in candidatos {
+            if self.locales.cerrados.contains_key(&local_seleccionado) {
+                continue;
+            }
+            debug!(
+                target: "ecommerce",
+                orden = id_orden,
+                local = local_seleccionado,
+                distancia_km = distancia,
+                "Local candidato"
+            );
+            match self.locales.ofrecer_orden(orden, local_seleccionado) {
+                RespuestaLocal::Aceptada => {
+                    info!(target: "ecommerce", orden = id_orden, local = local_seleccionado, "Orden aceptada");
+                    return Ok(local_seleccionado);
+                }
+                RespuestaLocal::Ocupado => locales_ocupados.push(local_seleccionado),
+                RespuestaLocal::NoAceptada => {}
+                RespuestaLocal::SinConfirmacion => return Err(sin_confirmacion(id_orden)),
+            }
+        }
+
+        // Si solo quedan locales ocupados se espera un poco y se les vuelve a ofrecer la orden,
+        // del más cercano al más lejano
+        for _ in 0..REINTENTOS_LOCALES_OCUPADOS {
+            if locales_ocupados.is_empty() {
+                break;
+            }
+            self.locales.reloj.dormir(ESPERA_LOCALES_OCUPADOS);
+            let mut siguen_ocupados = vec![];
+            for local_seleccionado in locales_ocupados {
+                match self.locales.ofrecer_orden(orden, local_seleccionado) {
+                    RespuestaLocal::Aceptada => {
+                        info!(target: "ecommerce", orden = id_orden, local = local_seleccionado, "Orden aceptada");
+                        return Ok(local_seleccionado);
+                    }
+                    RespuestaLocal::Ocupado => siguen_ocupados.push(local_seleccionado),
+                    RespuestaLocal::NoAceptada => {}
+                    RespuestaLocal::SinConfirmacion => return Err(sin_confirmacion(id_orden)),
+                }
+            }
+            locales_ocupados = siguen_ocupados;
+        }
+
+        warn!(
+            target: "ecommerce",
+            orden = id_orden,
+            motivo = %MotivoRechazo::SinLocalDisponible,
+            "Se rechaza la orden"
+        );
+        Err(MotivoRechazo::SinLocalDisponible)
+    }
+}
+
+/// La orden no se le ofrece a otro local porque el que no contestó puede haberla aceptado
+fn sin_confirmacion(id_orden: Option<&str>) -> MotivoRechazo {
+    warn!(
+        target: "ecommerce",
+        orden = id_orden,
+        motivo = %MotivoRechazo::SinConfirmacion,
+        "Se rechaza la orden"
+    );
+    MotivoRechazo::SinConfirmacion
+}
+
+impl ConexionLocales {
+    fn direccion(&self, id_local: usize) -> SocketAddr {
+        self.direcciones.get(&id_local).copied().unwrap_or_else(|| {
+            id_to_addr_local(id_local)
+                .parse()
+                .expect("Dirección de local invalida")
+        })
+    }
+
+    /// Le ofrece la orden a un local y, si la puede tomar, se la confirma. Si el local avisa
+    /// que esta cerrando se lo agrega a los locales cerrados.
+    /// La orden viaja con el contexto de la traza para que el local continue el span de la oferta
+    fn ofrecer_orden(&mut self, orden: &Orden, local_seleccionado: usize) -> RespuestaLocal {
+        let span = info_span!(
+            target: "udp",
+            "ofrecer_orden",
+            local = local_seleccionado,
+            respuesta = field::Empty
+        )
+        .entered();
+        let orden = Orden {
+            traza: contexto_actual(),
+            ..orden.clone()
+        };
+        let orden_serializada = serde_json::to_vec(&orden).unwrap();
+        debug!(
+            target: "udp",
+            local = local_seleccionado,
+            destino = %self.direccion(local_seleccionado),
+            "Envio orden {}",
+            String::from_utf8_lossy(&orden_serializada)
+        );
+
+        let id_orden = orden.id.clone().unwrap_or_default();
+        let Ok(respuesta) =
+            self.enviar_y_esperar(&orden_serializada, &id_orden, false, local_seleccionado)
+        else {
+            // la oferta no compromete nada en el local, se puede seguir con otro
+            span.record("respuesta", "timeout");
+            self.metricas
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .timeout(local_seleccionado);
+            return RespuestaLocal::NoAceptada;
+        };
+        span.record("respuesta", field::debug(respuesta));
+        match respuesta {
+            RespuestaOrden::Disponible => self.confirmar_orden(orden, local_seleccionado),
+            respuesta => self.no_aceptada(respuesta, local_seleccionado),
+        }
+    }
+
+    /// Le confirma la orden al local que la puede tomar. La confirmación se reenvía al mismo
+    /// local hasta que contesta, si no la orden podria quedar aceptada en él y en otro local.
+    /// El local decide una sola vez por orden, y contesta lo mismo a las confirmaciones repetidas.
+    /// Si no contesta en `PLAZO_CONFIRMACION` no se sabe si la aceptó y no se sigue esperando
+    fn confirmar_orden(&mut self, orden: Orden, local_seleccionado: usize) -> RespuestaLocal {
+        let id_orden = orden.id.clone().unwrap_or_default();
+        let confirmacion = MensajeLocal::ConfirmarOrden { orden }
+            .serializar()
+            .expect("No se pudo serializar la confirmación");
+        let limite = self.reloj.ahora_en_ms() + PLAZO_CONFIRMACION.as_millis() as u64;
+        while self.reloj.ahora_en_ms() < limite {
+            match self.enviar_y_esperar(&confirmacion, &id_orden, true, local_seleccionado) {
+                Ok(RespuestaOrden::Aceptada) => return RespuestaLocal::Aceptada,
+                Ok(RespuestaOrden::Ocupado) => {
+                    info!(target: "ecommerce", local = local_seleccionado, "Local ocupado, se reenvía la confirmación");
+                    self.reloj.dormir(ESPERA_LOCALES_OCUPADOS);
+                }
+                Ok(RespuestaOrden::Disponible) | Err(_) => {
+                    warn!(target: "ecommerce", orden = %id_orden, local = local_seleccionado, "Sin respuesta a la confirmación, se reenvía");
+                }
+                Ok(respuesta) => return self.no_aceptada(respuesta, local_seleccionado),
+            }
+        }
+        self.metricas
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .timeout(local_seleccionado);
+        RespuestaLocal::SinConfirmacion
+    }
+
+    fn no_aceptada(
+        &mut self,
+        respuesta: RespuestaOrden,
+        local_seleccionado: usize,
+    ) -> RespuestaLocal {
+        self.metricas
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .rechazada(local_seleccionado);
+        match respuesta {
+            RespuestaOrden::Ocupado => {
+                info!(target: "ecommerce", local = local_seleccionado, "Local ocupado, se reenvía la orden");
+                RespuestaLocal::Ocupado
+            }
+            RespuestaOrden::Cerrado => {
+                info!(
+                    target: "ecommerce",
+                    local = local_seleccionado,
+                    "Local cerrando, no se le envían ordenes por {:?}",
+                    ESPERA_LOCAL_CERRADO
+                );
+                self.cerrados
+                    .insert(local_seleccionado, self.reloj.ahora_en_ms());
+                RespuestaLocal::NoAceptada
+            }
+            _ => RespuestaLocal::NoAceptada,
+        }
+    }
+
+    /// Envia el mensaje y espera la respuesta del local a esa orden y en esa fase, las
+    /// respuestas demoradas de otras ofertas se ignoran. Los avisos de cancelación que
+    /// llegan mientras tanto se confirman y se guardan sin cortar la espera
+    fn enviar_y_esperar(
+        &mut self,
+        mensaje: &[u8],
+        id_orden: &str,
+        confirmacion: bool,
+        local_seleccionado: usize,
+    ) -> Result<RespuestaOrden, ErrorEcommerce> {
+        let direccion = self.direccion(local_seleccionado);
+        if let Err(e) = self.socket.enviar_a(mensaje, direccion) {
+            warn!(target: "udp", local = local_seleccionado, "No se pudo enviar al local: {}", e);
+        }
+
+        let mut buffer = [0; TAMANIO_BUFFER];
+        let limite = self.reloj.ahora_en_ms() + TIMEOUT_RESPUESTA_LOCAL.as_millis() as u64;
+        loop {
+            let restante = Duration::from_millis(limite.saturating_sub(self.reloj.ahora_en_ms()));
+            let (size, from) = match self.socket.recibir(&mut buffer, restante) {
+                Ok((size, from)) => (size, from),
+                Err(err) => {
+                    warn!(target: "udp", local = local_seleccionado, "Timeout esperando respuesta: {}", err);
+                    return Err(ErrorEcommerce::SocketTimeOut);
+                }
+            };
+            if self.atender_aviso(&buffer[..size], from) {
+                continue;
+            }
+            debug!(target: "udp", remitente = %from, "Recibí {}", String::from_utf8_lossy(&buffer[..size]));
+            match MensajeLocal::deserializar(&buffer[..size]) {
+                Ok(MensajeLocal::Respuesta {
+                    orden,
+                    confirmacion: fase,
+                    respuesta,
+                }) if from == direccion && orden == id_orden && fase == confirmacion => {
+                    return Ok(respuesta)
+                }
+                _ => {
+                    debug!(target: "udp", remitente = %from, "Respuesta de otra oferta, se ignora")
+                }
+            }
+        }
+    }
+
+    /// Si el paquete es un aviso de cancelación lo confirma al local y lo guarda, si es el
+    /// aviso de que un local se cierra se lo agrega a los locales cerrados.
+    /// Devuelve si era un aviso
+    fn atender_aviso(&mut self, paquete: &[u8], from: SocketAddr) -> bool {
+        let (orden, local, motivo) = match MensajeLocal::deserializar(paquete) {
+            Ok(MensajeLocal::OrdenCancelada {
+                orden,
+                local,
+                motivo,
+            }) => (orden, local, motivo),
+            Ok(MensajeLocal::LocalCerrando { local }) => {
+                info!(target: "ecommerce", local, "El local avisó que se cierra");
+                self.cerrados.insert(local, self.reloj.ahora_en_ms());
+                return true;
+            }
+            _ => return false,
+        };
+        info!(target: "ecommerce", orden = %orden, local, motivo = ?motivo, "El local canceló la orden");
+        let confirmacion = MensajeLocal::CancelacionRecibida {
+            orden: orden.clone(),
+        };
+        match confirmacion.serializar() {
+            Ok(datos) => {
+                if let Err(e) = self.socket.enviar_a(&datos, from) {
+                    warn!(target: "udp", local, "No se pudo confirmar la cancelación: {}", e);
+                }
+            }
+            Err(e) => warn!(target: "udp", local, "No se pudo confirmar la cancelación: {}", e),
+        }
+        // el local reenvia el aviso hasta recibir la confirmación, se guarda una sola vez
+        let cancelacion = Cancelacion {
+            orden,
+            local,
+            motivo,
+        };
+        if !self.cancelaciones.contains(&cancelacion) {
+            self.cancelaciones.push(cancelacion);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fallas::{ConfiguracionFallas, Fallas};
+    use crate::common::info_local::{Cobertura, InfoLocal};
+    use crate::common::orden::Direccion;
+    use crate::common::reloj::RelojSistema;
+    use std::io::Read;
+    use std::net::{TcpListener, UdpSocket};
+    use std::sync::mpsc::{self, Sender};
+    use std::thread;
+
+    /// Local de prueba que avisa su id con cada oferta y le contesta `respuesta`. Las
+    /// confirmaciones las acepta, salvo las primeras `ignoradas` que no contesta
+    fn local_de_prueba(
+        id: usize,
+        respuesta: RespuestaOrden,
+        mut ignoradas: usize,
+        ofertas: Sender<usize>,
+    ) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let direccion = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buffer = [0; 1024];
+            while let Ok((size, origen)) = socket.recv_from(&mut buffer) {
+                let (orden, confirmacion, respuesta) = match Orden::deserializar(&buffer[..size]) {
+                    Ok(orden) => {
+                        let _ = ofertas.send(id);
+                        (orden, false, respuesta)
+                    }
+                    Err(_) => match MensajeLocal::deserializar(&buffer[..size]) {
+                        Ok(MensajeLocal::ConfirmarOrden { orden }) => {
+                            (orden, true, RespuestaOrden::Aceptada)
+                        }
+                        _ => continue,
+                    },
+                };
+                if confirmacion && ignoradas > 0 {
+                    ignoradas -= 1;
+                    continue;
+                }
+                let contestacion = MensajeLocal::Respuesta {
+                    orden: orden.id.unwrap_or_default(),
+                    confirmacion,
+                    respuesta,
+                };
+                let _ = socket.send_to(&contestacion.serializar().unwrap(), origen);
+            }
+        });
+        direccion
+    }
+
+    /// Despachador con los locales 1 en (5, 5) y 2 en (10, 10) que contestan las respuestas dadas
+    fn despachador_de_prueba(
+        respuestas: [(RespuestaOrden, usize); 2],
+    ) -> (Despachador, mpsc::Receiver<usize>) {
+        let (ofertas, ofrecidas) = mpsc::channel();
+        let mut direcciones = HashMap::new();
+        let mut locales = vec![];
+        for (id, (respuesta, ignoradas)) in (1..).zip(respuestas) {
+            direcciones.insert(
+                id,
+                local_de_prueba(id, respuesta, ignoradas, ofertas.clone()),
+            );
+            let coordenada = (id * 5) as f64;
+            locales.push(InfoLocal {
+                id,
+                direccion: Direccion::new(coordenada, coordenada),
+                cobertura: Cobertura::SinLimite,
+            });
+        }
+        let reloj: Arc<dyn Reloj> = Arc::new(RelojSistema);
+        let fallas = Fallas::new(
+            "ecommerce-1".to_string(),
+            ConfiguracionFallas::default(),
+            reloj.clone(),
+        );
+        let socket = SocketUdp::new(UdpSocket::bind("127.0.0.1:0").unwrap(), Arc::new(fallas));
+        let despachador = Despachador::new(
+            socket,
+            MapaLocales::new(locales),
+            Arc::new(Mutex::new(MetricasEcommerce::default())),
+            reloj,
+        )
+        .con_direcciones(direcciones);
+        (despachador, ofrecidas)
+    }
+
+    #[test]
+    fn test_la_orden_la_toma_el_local_mas_cercano() {
+        let (mut despachador, ofrecidas) = despachador_de_prueba([
+            (RespuestaOrden::Disponible, 0),
+            (RespuestaOrden::Disponible, 0),
+        ]);
+
+        assert_eq!(despachador.despachar(&Orden::new(1, 5, 3.0, 3.0)), Ok(1));
+        assert_eq!(ofrecidas.try_iter().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_si_el_mas_cercano_no_tiene_stock_se_ofrece_al_siguiente() {
+        let (mut despachador, ofrecidas) = despachador_de_prueba([
+            (RespuestaOrden::Disponible, 0),
+            (RespuestaOrden::Rechazada, 0),
+        ]);
+
+        assert_eq!(despachador.despachar(&Orden::new(1, 5, 12.0, 12.0)), Ok(1));
+        assert_eq!(ofrecidas.try_iter().collect::<Vec<_>>(), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_sin_respuesta_a_la_confirmacion_se_reenvia_al_mismo_local() {
+        let (mut despachador, ofrecidas) = despachador_de_prueba([
+            (RespuestaOrden::Disponible, 1),
+            (RespuestaOrden::Disponible, 0),
+        ]);
+        let orden = Orden {
+            id: Some("2-0".to_string()),
+            ..Orden::new(1, 5, 3.0, 3.0)
+        };
+
+        assert_eq!(despachador.despachar(&orden), Ok(1));
+        assert_eq!(ofrecidas.try_iter().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_sin_conexion_con_el_lider_no_se_despacha_y_se_puede_reconectar() {
+        let (mut despachador, ofrecidas) = despachador_de_prueba([
+            (RespuestaOrden::Disponible, 0),
+            (RespuestaOrden::Disponible, 0),
+        ]);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let direccion = listener.local_addr().unwrap().to_string();
+        let lider = thread::spawn(move || {
+            // el primer lider cierra la conexión apenas el ecommerce se presenta
+            let (mut conexion, _) = listener.accept().unwrap();
+            let _ = conexion.read(&mut [0; 64]);
+            drop(conexion);
+            listener.accept().unwrap().0
+        });
+        let fallas = Arc::new(Fallas::new(
+            "ecommerce-2".to_string(),
+            ConfiguracionFallas::default(),
+            Arc::new(RelojSistema),
+        ));
+        let mut socket = Socket::conectar(direccion, 2, fallas);
+
+        let resultado = despachador.turno_http(&mut socket, &Orden::new(1, 5, 3.0, 3.0));
+        assert_eq!(resultado, Err(ErrorEcommerce::SinLider));
+        assert_eq!(ofrecidas.try_iter().count(), 0);
+        assert!(socket.reconectar().is_ok());
+        let _conexion = lider.join().unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Errores al leer registros de los archivos csv (ordenes, stock y reposiciones).
+/// Las lineas son las del archivo, contando el encabezado
+#[derive(Debug)]
+pub enum ErrorCsv {
+    /// No quedan más registros en el csv
+    FinDeArchivo,
+    FaltaColumna {
+        linea: u64,
+        columna: usize,
+        nombre: &'static str,
+    },
+    ValorInvalido {
+        linea: u64,
+        columna: usize,
+        nombre: &'static str,
+        valor: String,
+    },
+    /// El registro no es un csv valido, por ejemplo por tener otra cantidad de columnas
+    Formato {
+        linea: u64,
+        detalle: String,
+    },
+    /// La fila se pudo leer pero sus valores no son validos
+    FilaInvalida {
+        linea: u64,
+        motivo: String,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for ErrorCsv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorCsv::FinDeArchivo => write!(f, "No se encontraron más registros en el csv"),
+            ErrorCsv::FaltaColumna {
+                linea,
+                columna,
+                nombre,
+            } => write!(
+                f,
+                "Linea {}: falta la columna {} ({})",
+                linea, columna, nombre
+            ),
+            ErrorCsv::ValorInvalido {
+                linea,
+                columna,
+                nombre,
+                valor,
+            } => write!(
+                f,
+                "Linea {}, columna {} ({}): valor invalido '{}'",
+                linea, columna, nombre, valor
+            ),
+            ErrorCsv::Formato { linea, detalle } => write!(f, "Linea {}: {}", linea, detalle),
+            ErrorCsv::FilaInvalida { linea, motivo } => write!(f, "Linea {}: {}", linea, motivo),
+            ErrorCsv::Io(e) => write!(f, "Error al leer el csv: {}", e),
+        }
+    }
+}
+
+impl Error for ErrorCsv {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ErrorCsv::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ErrorCsv {
+    fn from(e: io::Error) -> Self {
+        ErrorCsv::Io(e)
+    }
+}
+
+impl From<csv::Error> for ErrorCsv {
+    fn from(e: csv::Error) -> Self {
+        let linea = e.position().map_or(0, |posicion| posicion.line());
+        let detalle = e.to_string();
+        match e.into_kind() {
+            csv::ErrorKind::Io(e) => ErrorCsv::Io(e),
+            _ => ErrorCsv::Formato { linea, detalle },
+        }
+    }
+}
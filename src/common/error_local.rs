@@ -8,6 +8,11 @@ pub enum ErrorLocal {
     StockInsuficiente,
     NoExisteProductoEnLocal,
     CantidadOrdenMayorQueBloqueados,
+    TransferenciaDesconocida,
+    LocalCerrado,
+    NoExisteOrden,
+    StockExcedido,
+    TransferenciaRepetida,
 }
 
 impl fmt::Display for ErrorLocal {
@@ -19,6 +24,17 @@ impl fmt::Display for ErrorLocal {
                 f,
                 "La cantidad de la orden supera a la cantidad de bloqueados"
             ),
+            ErrorLocal::TransferenciaDesconocida => {
+                write!(f, "No existe la transferencia en el local")
+            }
+            ErrorLocal::LocalCerrado => write!(f, "El local esta cerrando"),
+            ErrorLocal::NoExisteOrden => write!(f, "No existe la orden en progreso"),
+            ErrorLocal::StockExcedido => {
+                write!(f, "La reposición supera el stock maximo del producto")
+            }
+            ErrorLocal::TransferenciaRepetida => {
+                write!(f, "La transferencia ya se atendió en el local")
+            }
         }
     }
 }
@@ -29,6 +45,11 @@ impl Error for ErrorLocal {
             ErrorLocal::StockInsuficiente => None,
             ErrorLocal::NoExisteProductoEnLocal => None,
             ErrorLocal::CantidadOrdenMayorQueBloqueados => None,
+            ErrorLocal::TransferenciaDesconocida => None,
+            ErrorLocal::LocalCerrado => None,
+            ErrorLocal::NoExisteOrden => None,
+            ErrorLocal::StockExcedido => None,
+            ErrorLocal::TransferenciaRepetida => None,
         }
     }
 }
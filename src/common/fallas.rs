@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::common::comando_consola::ModoFalla;
+use crate::common::reloj::Reloj;
+use crate::common::socket::{id_to_addr_ecommerce, id_to_addr_local};
+
+/// Demora extra de un paquete reordenado, para que lo pasen los que llegan despues
+const DEMORA_REORDEN: Duration = Duration::from_millis(100);
+/// Cada cuanto se vuelve a probar si se curó una partición en una conexión tcp
+const ESPERA_PARTICION: Duration = Duration::from_millis(50);
+
+/// Fallas a simular en la red, se leen de un archivo json. Los tiempos de las particiones
+/// y caidas son en ms desde que arranca el proceso
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfiguracionFallas {
+    /// Semilla de las fallas aleatorias, por defecto una al azar
+    pub semilla: Option<u64>,
+    /// Probabilidad de perder cada paquete udp
+    pub descartar: f64,
+    /// Demora de cada paquete o mensaje, más un extra al azar de hasta `jitter_ms`
+    pub demora_ms: u64,
+    pub jitter_ms: u64,
+    /// Probabilidad de que un paquete udp llegue dos veces
+    pub duplicar: f64,
+    /// Probabilidad de que un paquete udp llegue despues que los siguientes
+    pub reordenar: f64,
+    pub particiones: Vec<Particion>,
+    pub caidas: Vec<Caida>,
+}
+
+/// Los nodos de un lado no se comunican con los del otro mientras dure
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Particion {
+    pub lado_a: Vec<String>,
+    pub lado_b: Vec<String>,
+    #[serde(default)]
+    pub desde_ms: u64,
+    /// None si no se cura
+    #[serde(default)]
+    pub hasta_ms: Option<u64>,
+}
+
+/// El nodo no envia ni recibe nada mientras dure. En la simulación los locales además se
+/// caen de verdad: pierden lo que tenían en memoria y al levantarse retoman el estado que
+/// guardaron. En los binarios y en los ecommerce es como un proceso pausado, que al
+/// levantarse sigue con el estado que tenía
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Caida {
+    pub nodo: String,
+    #[serde(default)]
+    pub desde_ms: u64,
+    /// None si no se levanta
+    #[serde(default)]
+    pub hasta_ms: Option<u64>,
+}
+
+impl Caida {
+    pub fn activa(&self, ahora_ms: u64) -> bool {
+        activa(self.desde_ms, self.hasta_ms, ahora_ms)
+    }
+}
+
+impl ConfiguracionFallas {
+    pub fn leer(archivo: &str) -> Result<Self, String> {
+        let lector = File::open(archivo)
+            .map(BufReader::new)
+            .map_err(|e| format!("No se pudo abrir {}: {}", archivo, e))?;
+        serde_json::from_reader(lector)
+            .map_err(|e| format!("Fallas invalidas en {}: {}", archivo, e))
+    }
+
+    /// Cambia las fallas aleatorias de los paquetes, las particiones y caidas se mantienen
+    pub fn aplicar(&mut self, modo: ModoFalla) {
+        match modo {
+            ModoFalla::Ninguna => {
+                self.descartar = 0.0;
+                self.demora_ms = 0;
+                self.jitter_ms = 0;
+                self.duplicar = 0.0;
+                self.reordenar = 0.0;
+            }
+            ModoFalla::Descartar(probabilidad) => self.descartar = probabilidad,
+            ModoFalla::Demorar { demora, jitter } => {
+                self.demora_ms = demora.as_millis() as u64;
+                self.jitter_ms = jitter.as_millis() as u64;
+            }
+            ModoFalla::Duplicar(probabilidad) => self.duplicar = probabilidad,
+            ModoFalla::Reordenar(probabilidad) => self.reordenar = probabilidad,
+        }
+    }
+}
+
+fn activa(desde_ms: u64, hasta_ms: Option<u64>, ahora: u64) -> bool {
+    desde_ms <= ahora && hasta_ms.is_none_or(|hasta| ahora < hasta)
+}
+
+/// Inyecta las fallas de la configuración en lo que envia y recibe un nodo. Las fallas de los
+/// paquetes se aplican al recibirlos, las particiones y caidas al enviar y al recibir.
+/// Los nodos se llaman `local-<id>` y `ecommerce-<id>`
+pub struct Fallas {
+    nodo: String,
+    configuracion: Mutex<ConfiguracionFallas>,
+    rng: Mutex<StdRng>,
+    reloj: Arc<dyn Reloj>,
+    inicio_ms: u64,
+    /// Nombre del nodo de cada dirección conocida
+    nodos: HashMap<SocketAddr, String>,
+}
+
+impl Fallas {
+    pub fn new(nodo: String, configuracion: ConfiguracionFallas, reloj: Arc<dyn Reloj>) -> Self {
+        let rng = StdRng::seed_from_u64(configuracion.semilla.unwrap_or_else(rand::random));
+        Fallas {
+            nodo,
+            configuracion: Mutex::new(configuracion),
+            rng: Mutex::new(rng),
+            inicio_ms: reloj.ahora_en_ms(),
+            reloj,
+            nodos: HashMap::new(),
+        }
+    }
+
+    /// Para poder reconocer a los nodos de las particiones por su dirección
+    pub fn con_nodos(mut self, nodos: HashMap<SocketAddr, String>) -> Self {
+        self.nodos = nodos;
+        self
+    }
+
+    pub fn nodo(&self) -> &str {
+        &self.nodo
+    }
+
+    pub fn nombre(&self, direccion: &SocketAddr) -> Option<&str> {
+        self.nodos.get(direccion).map(String::as_str)
+    }
+
+    pub fn configuracion(&self) -> ConfiguracionFallas {
+        self.bloquear_configuracion().clone()
+    }
+
+    pub fn aplicar(&self, modo: ModoFalla) {
+        self.bloquear_configuracion().aplicar(modo);
+    }
+
+    fn bloquear_configuracion(&self) -> std::sync::MutexGuard<'_, ConfiguracionFallas> {
+        self.configuracion
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Indica si este nodo no se puede comunicar con `otro` por una partición o una caida
+    pub fn incomunicado(&self, otro: Option<&str>) -> bool {
+        let ahora = self.reloj.ahora_en_ms().saturating_sub(self.inicio_ms);
+        let configuracion = self.bloquear_configuracion();
+        let caido = |nodo: &str| {
+            configuracion
+                .caidas
+                .iter()
+                .any(|caida| caida.nodo == nodo && activa(caida.desde_ms, caida.hasta_ms, ahora))
+        };
+        if caido(&self.nodo) || otro.is_some_and(caido) {
+            return true;
+        }
+        let Some(otro) = otro else {
+            return false;
+        };
+        configuracion.particiones.iter().any(|particion| {
+            let separa = |a: &[String], b: &[String]| {
+                a.contains(&self.nodo) && b.iter().any(|nodo| nodo == otro)
+            };
+            activa(particion.desde_ms, particion.hasta_ms, ahora)
+                && (separa(&particion.lado_a, &particion.lado_b)
+                    || separa(&particion.lado_b, &particion.lado_a))
+        })
+    }
+
+    /// Demoras con las que se entrega cada copia de un paquete recibido de `origen`,
+    /// vacio si el paquete se pierde
+    pub fn al_recibir(&self, origen: Option<&str>) -> Vec<Duration> {
+        if self.incomunicado(origen) {
+            return vec![];
+        }
+        let configuracion = self.configuracion();
+        let mut rng = self.rng.lock().unwrap_or_else(PoisonError::into_inner);
+        if rng.gen_bool(configuracion.descartar.clamp(0.0, 1.0)) {
+            return vec![];
+        }
+        let copias = if rng.gen_bool(configuracion.duplicar.clamp(0.0, 1.0)) {
+            2
+        } else {
+            1
+        };
+        (0..copias)
+            .map(|_| {
+                let mut demora = Duration::from_millis(
+                    configuracion.demora_ms + rng.gen_range(0..=configuracion.jitter_ms),
+                );
+                if rng.gen_bool(configuracion.reordenar.clamp(0.0, 1.0)) {
+                    demora += DEMORA_REORDEN;
+                }
+                demora
+            })
+            .collect()
+    }
+
+    /// Con tcp una partición no pierde mensajes, los retiene hasta que se cura.
+    /// Si el otro nodo no se levanta nunca, espera para siempre
+    pub fn esperar_comunicacion(&self, otro: Option<&str>) {
+        while self.incomunicado(otro) {
+            self.reloj.dormir(ESPERA_PARTICION);
+        }
+    }
+
+    /// Retiene un mensaje tcp recibido de `origen` hasta que se pueda entregar, con tcp los
+    /// mensajes no se pierden, duplican ni reordenan
+    pub fn demorar_mensaje_tcp(&self, origen: Option<&str>) {
+        self.esperar_comunicacion(origen);
+        let configuracion = self.configuracion();
+        let jitter = self
+            .rng
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .gen_range(0..=configuracion.jitter_ms);
+        let demora = Duration::from_millis(configuracion.demora_ms + jitter);
+        if !demora.is_zero() {
+            self.reloj.dormir(demora);
+        }
+    }
+}
+
+pub fn nombre_local(id: usize) -> String {
+    format!("local-{}", id)
+}
+
+pub fn nombre_ecommerce(id: usize) -> String {
+    format!("ecommerce-{}", id)
+}
+
+/// Nombres de los nodos en sus direcciones udp por defecto
+pub fn nodos_por_defecto() -> HashMap<SocketAddr, String> {
+    (1..=9)
+        .flat_map(|id| {
+            [
+                (id_to_addr_local(id), nombre_local(id)),
+                (id_to_addr_ecommerce(id), nombre_ecommerce(id)),
+            ]
+        })
+        .filter_map(|(direccion, nombre)| Some((direccion.parse().ok()?, nombre)))
+        .collect()
+}
+
+/// Socket udp bloqueante con las fallas del nodo. Los paquetes demorados quedan
+/// pendientes y se entregan en alguna lectura posterior, como en una red real.
+/// Las esperas y demoras se miden con el reloj de las fallas
+pub struct SocketUdp {
+    socket: UdpSocket,
+    fallas: Arc<Fallas>,
+    /// Momento en ms en que se entrega cada paquete demorado
+    pendientes: Vec<(u64, Vec<u8>, SocketAddr)>,
+}
+
+impl SocketUdp {
+    pub fn new(socket: UdpSocket, fallas: Arc<Fallas>) -> Self {
+        SocketUdp {
+            socket,
+            fallas,
+            pendientes: vec![],
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Si el destino esta incomunicado el paquete se pierde sin error, como en la red
+    pub fn enviar_a(&self, datos: &[u8], destino: SocketAddr) -> io::Result<()> {
+        if self.fallas.incomunicado(self.fallas.nombre(&destino)) {
+            debug!(target: "falla", destino = %destino, "Se pierde paquete enviado");
+            return Ok(());
+        }
+        self.socket.send_to(datos, destino).map(|_| ())
+    }
+
+    /// Espera un paquete hasta `timeout`, devuelve error de timeout si no llega ninguno
+    pub fn recibir(
+        &mut self,
+        buffer: &mut [u8],
+        timeout: Duration,
+    ) -> io::Result<(usize, SocketAddr)> {
+        let reloj = self.fallas.reloj.clone();
+        let limite = reloj.ahora_en_ms() + timeout.as_millis() as u64;
+        loop {
+            let ahora = reloj.ahora_en_ms();
+            if let Some(posicion) = self
+                .pendientes
+                .iter()
+                .position(|(listo, _, _)| *listo <= ahora)
+            {
+                let (_, datos, origen) = self.pendientes.remove(posicion);
+                let tamanio = datos.len().min(buffer.len());
+                buffer[..tamanio].copy_from_slice(&datos[..tamanio]);
+                return Ok((tamanio, origen));
+            }
+            let proximo = self
+                .pendientes
+                .iter()
+                .map(|(listo, _, _)| *listo)
+                .min()
+                .map_or(limite, |listo| listo.min(limite));
+            if proximo <= ahora {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "timeout"));
+            }
+            let espera = Duration::from_millis(proximo - ahora);
+            let (tamanio, origen) = match reloj.recibir_de(&self.socket, buffer, espera) {
+                Ok(recibido) => recibido,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e),
+            };
+            let demoras = self.fallas.al_recibir(self.fallas.nombre(&origen));
+            if demoras.is_empty() {
+                debug!(target: "falla", remitente = %origen, "Se descarta paquete");
+            }
+            for demora in demoras {
+                self.pendientes.push((
+                    reloj.ahora_en_ms() + demora.as_millis() as u64,
+                    buffer[..tamanio].to_vec(),
+                    origen,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::reloj::RelojSimulado;
+
+    fn fallas(nodo: &str, configuracion: ConfiguracionFallas) -> (Fallas, Arc<RelojSimulado>) {
+        let reloj = Arc::new(RelojSimulado::default());
+        let fallas = Fallas::new(
+            nodo.to_string(),
+            ConfiguracionFallas {
+                semilla: Some(3),
+                ..configuracion
+            },
+            reloj.clone(),
+        );
+        (fallas, reloj)
+    }
+
+    #[test]
+    fn test_leer_configuracion_de_json() {
+        let configuracion: ConfiguracionFallas = serde_json::from_str(
+            r#"{"descartar": 0.1, "demora_ms": 20, "jitter_ms": 5,
+                "particiones": [{"lado_a": ["local-1"], "lado_b": ["ecommerce-2"], "hasta_ms": 5000}],
+                "caidas": [{"nodo": "local-2", "desde_ms": 1000}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(configuracion.descartar, 0.1);
+        assert_eq!(configuracion.duplicar, 0.0);
+        assert_eq!(configuracion.particiones[0].desde_ms, 0);
+        assert_eq!(configuracion.particiones[0].hasta_ms, Some(5000));
+        assert_eq!(configuracion.caidas[0].hasta_ms, None);
+    }
+
+    #[test]
+    fn test_particion_y_caida_solo_mientras_duran() {
+        let (fallas, reloj) = fallas(
+            "ecommerce-2",
+            ConfiguracionFallas {
+                particiones: vec![Particion {
+                    lado_a: vec!["local-1".to_string()],
+                    lado_b: vec!["ecommerce-2".to_string(), "ecommerce-3".to_string()],
+                    desde_ms: 1000,
+                    hasta_ms: Some(3000),
+                }],
+                caidas: vec![Caida {
+                    nodo: "local-2".to_string(),
+                    desde_ms: 2000,
+                    hasta_ms: None,
+                }],
+                ..Default::default()
+            },
+        );
+
+        assert!(!fallas.incomunicado(Some("local-1")));
+        reloj.avanzar(Duration::from_millis(1500));
+        assert!(fallas.incomunicado(Some("local-1")));
+        assert!(fallas.al_recibir(Some("local-1")).is_empty());
+        assert!(!fallas.incomunicado(Some("local-2")));
+        reloj.avanzar(Duration::from_millis(2000));
+        assert!(!fallas.incomunicado(Some("local-1")));
+        assert!(fallas.incomunicado(Some("local-2")));
+        assert!(!fallas.incomunicado(None));
+    }
+
+    #[test]
+    fn test_descarta_duplica_y_demora_paquetes() {
+        let (fallas, _) = fallas(
+            "local-1",
+            ConfiguracionFallas {
+                descartar: 0.2,
+                duplicar: 0.3,
+                demora_ms: 10,
+                jitter_ms: 5,
+                ..Default::default()
+            },
+        );
+        let entregas: Vec<Vec<Duration>> = (0..1000).map(|_| fallas.al_recibir(None)).collect();
+
+        let perdidos = entregas.iter().filter(|copias| copias.is_empty()).count();
+        let duplicados = entregas.iter().filter(|copias| copias.len() == 2).count();
+        assert!((150..250).contains(&perdidos), "{}", perdidos);
+        assert!((180..300).contains(&duplicados), "{}", duplicados);
+        assert!(entregas.iter().flatten().all(|demora| {
+            (Duration::from_millis(10)..=Duration::from_millis(15)).contains(demora)
+        }));
+    }
+
+    #[test]
+    fn test_socket_udp_entrega_los_paquetes_reordenados_despues() {
+        let (fallas, _) = fallas(
+            "local-1",
+            ConfiguracionFallas {
+                reordenar: 0.5,
+                ..Default::default()
+            },
+        );
+        let mut receptor =
+            SocketUdp::new(UdpSocket::bind("127.0.0.1:0").unwrap(), Arc::new(fallas));
+        let emisor = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for numero in 0..20u8 {
+            emisor
+                .send_to(&[numero], receptor.local_addr().unwrap())
+                .unwrap();
+        }
+
+        let mut buffer = [0; 8];
+        let recibidos: Vec<u8> = (0..20)
+            .map(|_| {
+                receptor
+                    .recibir(&mut buffer, Duration::from_secs(1))
+                    .map(|_| buffer[0])
+                    .unwrap()
+            })
+            .collect();
+        let mut ordenados = recibidos.clone();
+        ordenados.sort();
+        assert_eq!(ordenados, (0..20).collect::<Vec<u8>>());
+        assert_ne!(recibidos, ordenados);
+    }
+}
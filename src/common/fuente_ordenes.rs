@@ -0,0 +1,448 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use csv::{Position, ReaderBuilder, StringRecord, Trim};
+
+use crate::common::error_csv::ErrorCsv;
+use crate::common::lector_csv::LectorCsvIndexado;
+use crate::common::orden::Orden;
+
+/// Origen que indica leer las ordenes de la entrada estandar
+pub const ORIGEN_STDIN: &str = "-";
+
+#[derive(Debug)]
+pub enum ErrorFuente {
+    /// La fuente terminó, no va a tener más ordenes
+    SinMasOrdenes,
+    /// Todavia no hay orden en el cursor pedido, puede aparecer más adelante
+    SinOrdenesPorAhora,
+    /// La orden del cursor no se pudo leer, se puede seguir con la siguiente
+    OrdenInvalida {
+        cursor: usize,
+        detalle: String,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for ErrorFuente {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorFuente::SinMasOrdenes => write!(f, "No quedan más ordenes en la fuente"),
+            ErrorFuente::SinOrdenesPorAhora => write!(f, "Todavia no hay nuevas ordenes"),
+            ErrorFuente::OrdenInvalida { cursor, detalle } => {
+                write!(f, "Orden invalida en el cursor {}: {}", cursor, detalle)
+            }
+            ErrorFuente::Io(e) => write!(f, "Error al leer ordenes: {}", e),
+        }
+    }
+}
+
+impl Error for ErrorFuente {}
+
+impl From<io::Error> for ErrorFuente {
+    fn from(e: io::Error) -> Self {
+        ErrorFuente::Io(e)
+    }
+}
+
+/// Formato de cada orden en la fuente
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FormatoOrdenes {
+    /// id_producto,cantidad,latitud,longitud[,prioridad] con encabezado
+    Csv,
+    /// Una orden en json por linea
+    JsonLineas,
+}
+
+impl FormatoOrdenes {
+    /// Formato segun la extensión del archivo, la entrada estandar es json por linea
+    pub fn por_origen(origen: &str) -> FormatoOrdenes {
+        if origen == ORIGEN_STDIN || origen.ends_with(".jsonl") || origen.ends_with(".json") {
+            FormatoOrdenes::JsonLineas
+        } else {
+            FormatoOrdenes::Csv
+        }
+    }
+
+    /// Parsea una linea, `numero_linea` se usa para informar errores
+    fn parsear(&self, linea: &str, numero_linea: u64) -> Result<Orden, String> {
+        match self {
+            FormatoOrdenes::Csv => {
+                // con el mismo lector de csv que los archivos, para aceptar campos entre comillas
+                let mut record = ReaderBuilder::new()
+                    .has_headers(false)
+                    .trim(Trim::All)
+                    .from_reader(linea.as_bytes())
+                    .records()
+                    .next()
+                    .unwrap_or_else(|| Ok(StringRecord::new()))
+                    .map_err(|e| e.to_string())?;
+                let mut posicion = Position::new();
+                posicion.set_line(numero_linea);
+                record.set_position(Some(posicion));
+                Orden::from_record(record).map_err(|e| e.to_string())
+            }
+            FormatoOrdenes::JsonLineas => {
+                let orden = Orden::deserializar(linea.as_bytes()).map_err(|e| e.to_string())?;
+                orden.validar()?;
+                Ok(orden)
+            }
+        }
+    }
+}
+
+impl FromStr for FormatoOrdenes {
+    type Err = String;
+
+    fn from_str(formato: &str) -> Result<Self, Self::Err> {
+        match formato {
+            "csv" => Ok(FormatoOrdenes::Csv),
+            "jsonl" => Ok(FormatoOrdenes::JsonLineas),
+            otro => Err(format!("Formato de ordenes desconocido: {}", otro)),
+        }
+    }
+}
+
+/// Origen de las ordenes que despacha el ecommerce. El cursor lo reparte el lider
+/// entre las instancias y siempre avanza, por lo que cada fuente lee hacia adelante
+pub trait FuenteOrdenes: Send {
+    fn orden_en(&mut self, cursor: usize) -> Result<Orden, ErrorFuente>;
+
+    /// Si todas las instancias leen las mismas ordenes y se las reparten con el cursor del
+    /// lider. Las ordenes de una fuente propia de la instancia, como la entrada estandar, no
+    /// las lee nadie más: se piden en orden (el cursor es la posición en la propia fuente)
+    /// y ninguna se saltea
+    fn compartida(&self) -> bool {
+        true
+    }
+}
+
+/// Abre la fuente de ordenes del origen dado, un archivo o `-` para la entrada estandar.
+/// Con `seguir` el archivo se sigue leyendo a medida que se le agregan ordenes
+pub fn abrir_fuente(
+    origen: &str,
+    formato: FormatoOrdenes,
+    seguir: bool,
+) -> io::Result<Box<dyn FuenteOrdenes>> {
+    if origen == ORIGEN_STDIN {
+        return Ok(Box::new(FuenteStdin::new(formato)));
+    }
+    match (formato, seguir) {
+        (FormatoOrdenes::Csv, false) => Ok(Box::new(FuenteCsv::new(origen)?)),
+        _ => Ok(Box::new(FuenteDeLineas::new(origen, formato, seguir)?)),
+    }
+}
+
+/// Csv con indice de posiciones, cada cursor se lee sin volver a recorrer el archivo
+pub struct FuenteCsv {
+    lector: LectorCsvIndexado,
+}
+
+impl FuenteCsv {
+    pub fn new(archivo: &str) -> io::Result<Self> {
+        let lector = LectorCsvIndexado::new(archivo).map_err(|e| match e {
+            ErrorCsv::Io(e) => e,
+            otro => io::Error::new(io::ErrorKind::InvalidData, otro.to_string()),
+        })?;
+        Ok(FuenteCsv { lector })
+    }
+}
+
+impl FuenteOrdenes for FuenteCsv {
+    fn orden_en(&mut self, cursor: usize) -> Result<Orden, ErrorFuente> {
+        self.lector
+            .leer_en(cursor, Orden::from_record)
+            .map_err(|e| match e {
+                ErrorCsv::FinDeArchivo => ErrorFuente::SinMasOrdenes,
+                ErrorCsv::Io(e) => ErrorFuente::Io(e),
+                otro => ErrorFuente::OrdenInvalida {
+                    cursor,
+                    detalle: otro.to_string(),
+                },
+            })
+    }
+}
+
+/// Archivo con una orden por linea. Si se sigue, al llegar al final
+/// se esperan nuevas lineas en vez de terminar. Todas las instancias leen el mismo archivo,
+/// las lineas de los cursores que el lider le dio a otra instancia las despacha esa instancia
+pub struct FuenteDeLineas {
+    lector: BufReader<File>,
+    formato: FormatoOrdenes,
+    seguir: bool,
+    /// Linea leida a medias porque todavia no se terminó de escribir
+    linea: String,
+    /// Lineas completas leidas, contando el encabezado y las vacias
+    lineas_leidas: u64,
+    leyo_encabezado: bool,
+    siguiente: usize,
+}
+
+impl FuenteDeLineas {
+    pub fn new(archivo: &str, formato: FormatoOrdenes, seguir: bool) -> io::Result<Self> {
+        Ok(FuenteDeLineas {
+            lector: BufReader::new(File::open(archivo)?),
+            formato,
+            seguir,
+            linea: String::new(),
+            lineas_leidas: 0,
+            leyo_encabezado: formato != FormatoOrdenes::Csv,
+            siguiente: 0,
+        })
+    }
+
+    /// Lee la proxima linea no vacia completa junto a su numero de linea
+    fn leer_linea(&mut self) -> Result<(u64, String), ErrorFuente> {
+        loop {
+            let leidos = self.lector.read_line(&mut self.linea)?;
+            let completa = self.linea.ends_with('\n');
+            if leidos == 0 || (self.seguir && !completa) {
+                if self.seguir {
+                    return Err(ErrorFuente::SinOrdenesPorAhora);
+                }
+                if self.linea.trim().is_empty() {
+                    return Err(ErrorFuente::SinMasOrdenes);
+                }
+            }
+            let linea = std::mem::take(&mut self.linea);
+            self.lineas_leidas += 1;
+            if linea.trim().is_empty() {
+                continue;
+            }
+            if !self.leyo_encabezado {
+                self.leyo_encabezado = true;
+                continue;
+            }
+            return Ok((self.lineas_leidas, linea));
+        }
+    }
+}
+
+impl FuenteOrdenes for FuenteDeLineas {
+    fn orden_en(&mut self, cursor: usize) -> Result<Orden, ErrorFuente> {
+        if cursor < self.siguiente {
+            return Err(ya_leida(cursor));
+        }
+        loop {
+            let (numero_linea, linea) = self.leer_linea()?;
+            self.siguiente += 1;
+            if self.siguiente > cursor {
+                return self
+                    .formato
+                    .parsear(linea.trim(), numero_linea)
+                    .map_err(|detalle| ErrorFuente::OrdenInvalida { cursor, detalle });
+            }
+        }
+    }
+}
+
+/// Ordenes que llegan por la entrada estandar. Un thread lee las lineas
+/// para no bloquear al ecommerce mientras tiene el permiso del lider.
+/// Es una fuente propia de la instancia, cada linea se devuelve en orden sin mirar el cursor
+pub struct FuenteStdin {
+    /// Cada linea no vacia con su numero de linea, contando el encabezado y las vacias
+    lineas: Receiver<io::Result<(u64, String)>>,
+    formato: FormatoOrdenes,
+}
+
+impl FuenteStdin {
+    pub fn new(formato: FormatoOrdenes) -> Self {
+        FuenteStdin::desde(BufReader::new(io::stdin()), formato)
+    }
+
+    fn desde(lector: impl BufRead + Send + 'static, formato: FormatoOrdenes) -> Self {
+        let (enviar, lineas) = mpsc::channel();
+        thread::spawn(move || {
+            let mut leyo_encabezado = formato != FormatoOrdenes::Csv;
+            for (indice, linea) in lector.lines().enumerate() {
+                let linea = match linea {
+                    Ok(linea) if linea.trim().is_empty() => continue,
+                    Ok(linea) => linea,
+                    Err(e) => {
+                        let _ = enviar.send(Err(e));
+                        break;
+                    }
+                };
+                if !leyo_encabezado {
+                    leyo_encabezado = true;
+                    continue;
+                }
+                if enviar.send(Ok((indice as u64 + 1, linea))).is_err() {
+                    break;
+                }
+            }
+        });
+        FuenteStdin { lineas, formato }
+    }
+}
+
+impl FuenteOrdenes for FuenteStdin {
+    fn orden_en(&mut self, cursor: usize) -> Result<Orden, ErrorFuente> {
+        let (numero_linea, linea) = match self.lineas.try_recv() {
+            Ok(linea) => linea?,
+            Err(TryRecvError::Empty) => return Err(ErrorFuente::SinOrdenesPorAhora),
+            Err(TryRecvError::Disconnected) => return Err(ErrorFuente::SinMasOrdenes),
+        };
+        self.formato
+            .parsear(linea.trim(), numero_linea)
+            .map_err(|detalle| ErrorFuente::OrdenInvalida { cursor, detalle })
+    }
+
+    fn compartida(&self) -> bool {
+        false
+    }
+}
+
+/// Ordenes ya cargadas en memoria, la usa la simulación
+pub struct FuenteMemoria {
+    ordenes: Vec<Orden>,
+}
+
+impl FuenteMemoria {
+    pub fn new(ordenes: Vec<Orden>) -> Self {
+        FuenteMemoria { ordenes }
+    }
+}
+
+impl FuenteOrdenes for FuenteMemoria {
+    fn orden_en(&mut self, cursor: usize) -> Result<Orden, ErrorFuente> {
+        self.ordenes
+            .get(cursor)
+            .cloned()
+            .ok_or(ErrorFuente::SinMasOrdenes)
+    }
+}
+
+fn ya_leida(cursor: usize) -> ErrorFuente {
+    ErrorFuente::OrdenInvalida {
+        cursor,
+        detalle: "la fuente ya avanzó más allá de este cursor".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::orden::Direccion;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    #[test]
+    fn test_fuente_csv_lee_hacia_adelante_salteando_cursores() {
+        let archivo = "test_fuente_csv_lee_hacia_adelante.csv";
+        fs::write(
+            archivo,
+            "id_producto, cantidad, latitud, longitud\n1,5,1,2\n2,3,1,2\n3,4,1,2\n",
+        )
+        .unwrap();
+
+        let mut fuente = abrir_fuente(archivo, FormatoOrdenes::Csv, false).unwrap();
+        let primera = fuente.orden_en(0);
+        let tercera = fuente.orden_en(2);
+        let siguiente = fuente.orden_en(3);
+        fs::remove_file(archivo).unwrap();
+
+        assert_eq!(primera.unwrap().id_producto, 1);
+        assert_eq!(tercera.unwrap().id_producto, 3);
+        assert!(matches!(siguiente, Err(ErrorFuente::SinMasOrdenes)));
+    }
+
+    #[test]
+    fn test_fuente_json_lineas_informa_orden_invalida_y_sigue() {
+        let archivo = "test_fuente_json_lineas_informa_orden_invalida.jsonl";
+        fs::write(
+            archivo,
+            "{\"id_producto\":1,\"cantidad\":2,\"direccion\":{\"latitud\":1,\"longitud\":1}}\n\
+             no es json\n\n\
+             {\"id_producto\":2,\"cantidad\":2,\"direccion\":{\"latitud\":1,\"longitud\":1}}\n",
+        )
+        .unwrap();
+
+        let mut fuente = abrir_fuente(archivo, FormatoOrdenes::por_origen(archivo), false).unwrap();
+        let primera = fuente.orden_en(0);
+        let invalida = fuente.orden_en(1);
+        let tercera = fuente.orden_en(2);
+        fs::remove_file(archivo).unwrap();
+
+        assert_eq!(primera.unwrap().id_producto, 1);
+        assert!(matches!(
+            invalida,
+            Err(ErrorFuente::OrdenInvalida { cursor: 1, .. })
+        ));
+        assert_eq!(tercera.unwrap().id_producto, 2);
+    }
+
+    #[test]
+    fn test_fuente_stdin_informa_el_numero_de_linea_de_la_entrada() {
+        let entrada = "id_producto, cantidad, latitud, longitud\n\n1,5,1,2\n\nno es orden\n";
+        let mut fuente = FuenteStdin::desde(io::Cursor::new(entrada), FormatoOrdenes::Csv);
+
+        let mut resultados = vec![];
+        while resultados.len() < 2 {
+            match fuente.orden_en(resultados.len()) {
+                Err(ErrorFuente::SinOrdenesPorAhora) => thread::yield_now(),
+                resultado => resultados.push(resultado),
+            }
+        }
+
+        assert_eq!(resultados[0].as_ref().unwrap().id_producto, 1);
+        match &resultados[1] {
+            Err(ErrorFuente::OrdenInvalida { detalle, .. }) => {
+                assert!(detalle.starts_with("Linea 5,"), "{}", detalle)
+            }
+            otro => panic!("Se esperaba una orden invalida: {:?}", otro),
+        }
+    }
+
+    #[test]
+    fn test_fuente_stdin_acepta_campos_entre_comillas_como_los_archivos() {
+        let entrada =
+            "id_producto,cantidad,latitud,longitud,prioridad\n\"1\", 5 ,\" 3.5\",\"2\",\"1\"\n";
+        let mut fuente = FuenteStdin::desde(io::Cursor::new(entrada), FormatoOrdenes::Csv);
+
+        let orden = loop {
+            match fuente.orden_en(0) {
+                Err(ErrorFuente::SinOrdenesPorAhora) => thread::yield_now(),
+                resultado => break resultado.unwrap(),
+            }
+        };
+
+        assert_eq!(orden.id_producto, 1);
+        assert_eq!(orden.cantidad, 5);
+        assert_eq!(orden.direccion, Direccion::new(3.5, 2.0));
+        assert_eq!(orden.prioridad, 1);
+    }
+
+    #[test]
+    fn test_fuente_seguida_toma_las_ordenes_agregadas_al_archivo() {
+        let archivo = "test_fuente_seguida_toma_las_ordenes_agregadas.csv";
+        fs::write(
+            archivo,
+            "id_producto, cantidad, latitud, longitud\n1,5,1,2\n",
+        )
+        .unwrap();
+
+        let mut fuente = abrir_fuente(archivo, FormatoOrdenes::Csv, true).unwrap();
+        let primera = fuente.orden_en(0);
+        let sin_ordenes = fuente.orden_en(1);
+        let mut escritor = OpenOptions::new().append(true).open(archivo).unwrap();
+        write!(escritor, "7,1,").unwrap();
+        let linea_a_medias = fuente.orden_en(1);
+        writeln!(escritor, "1,2").unwrap();
+        let agregada = fuente.orden_en(1);
+        fs::remove_file(archivo).unwrap();
+
+        assert_eq!(primera.unwrap().id_producto, 1);
+        assert!(matches!(sin_ordenes, Err(ErrorFuente::SinOrdenesPorAhora)));
+        assert!(matches!(
+            linea_a_medias,
+            Err(ErrorFuente::SinOrdenesPorAhora)
+        ));
+        assert_eq!(agregada.unwrap().id_producto, 7);
+    }
+}
@@ -0,0 +1,328 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rand::distributions::WeightedIndex;
+use rand::prelude::Distribution;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::common::fuente_ordenes::FormatoOrdenes;
+use crate::common::orden::{Direccion, Orden, RADIO_TIERRA_KM};
+
+/// Circulo donde se concentran las ordenes, con el peso relativo de la zona
+#[derive(Debug, PartialEq, Clone)]
+pub struct Zona {
+    pub centro: Direccion,
+    pub radio_km: f64,
+    pub peso: f64,
+}
+
+/// Formato `latitud,longitud,radio_km[,peso]`, el peso por defecto es 1
+impl FromStr for Zona {
+    type Err = String;
+
+    fn from_str(texto: &str) -> Result<Self, Self::Err> {
+        let invalida = || {
+            format!(
+                "Zona invalida: {}, se espera latitud,longitud,radio_km[,peso]",
+                texto
+            )
+        };
+        let valores = texto
+            .split(',')
+            .map(|valor| valor.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalida())?;
+        let (latitud, longitud, radio_km, peso) = match valores[..] {
+            [latitud, longitud, radio_km] => (latitud, longitud, radio_km, 1.0),
+            [latitud, longitud, radio_km, peso] => (latitud, longitud, radio_km, peso),
+            _ => return Err(invalida()),
+        };
+        let centro = Direccion::new(latitud, longitud);
+        centro
+            .validar()
+            .map_err(|e| format!("{}: {}", invalida(), e))?;
+        if radio_km < 0.0 || peso < 0.0 {
+            return Err(invalida());
+        }
+        Ok(Zona {
+            centro,
+            radio_km,
+            peso,
+        })
+    }
+}
+
+/// Como se generan las ordenes sinteticas
+#[derive(Debug, Clone)]
+pub struct ParametrosCarga {
+    pub semilla: u64,
+    /// Id de cada producto con su peso relativo
+    pub productos: Vec<(usize, f64)>,
+    pub cantidades: RangeInclusive<usize>,
+    pub zonas: Vec<Zona>,
+    /// Ordenes por segundo en promedio, con llegadas de Poisson. Sin tasa no hay espera
+    pub tasa: Option<f64>,
+}
+
+impl Default for ParametrosCarga {
+    /// Los productos y los alrededores de los locales de data/
+    fn default() -> Self {
+        ParametrosCarga {
+            semilla: 0,
+            productos: vec![(1, 1.0), (2, 1.0), (3, 1.0)],
+            cantidades: 1..=5,
+            zonas: vec![
+                Zona {
+                    centro: Direccion::new(3.0, 5.0),
+                    radio_km: 300.0,
+                    peso: 1.0,
+                },
+                Zona {
+                    centro: Direccion::new(5.0, 9.0),
+                    radio_km: 300.0,
+                    peso: 1.0,
+                },
+            ],
+            tasa: None,
+        }
+    }
+}
+
+/// Genera ordenes al azar a partir de una semilla, la misma semilla da las mismas ordenes
+pub struct GeneradorOrdenes {
+    rng: StdRng,
+    /// Aparte para que la tasa no cambie las ordenes que salen de una semilla
+    rng_llegadas: StdRng,
+    productos: Vec<usize>,
+    pesos_productos: WeightedIndex<f64>,
+    cantidades: RangeInclusive<usize>,
+    zonas: Vec<Zona>,
+    pesos_zonas: WeightedIndex<f64>,
+    tasa: Option<f64>,
+}
+
+impl GeneradorOrdenes {
+    pub fn new(parametros: ParametrosCarga) -> Result<Self, String> {
+        let pesos_productos =
+            WeightedIndex::new(parametros.productos.iter().map(|(_, peso)| *peso))
+                .map_err(|e| format!("Pesos de productos invalidos: {}", e))?;
+        let pesos_zonas = WeightedIndex::new(parametros.zonas.iter().map(|zona| zona.peso))
+            .map_err(|e| format!("Pesos de zonas invalidos: {}", e))?;
+        if parametros.cantidades.is_empty() || *parametros.cantidades.start() == 0 {
+            return Err("Las cantidades tienen que ser de al menos una unidad".to_string());
+        }
+        if parametros.tasa.is_some_and(|tasa| tasa <= 0.0) {
+            return Err("La tasa tiene que ser positiva".to_string());
+        }
+        let mut rng = StdRng::seed_from_u64(parametros.semilla);
+        Ok(GeneradorOrdenes {
+            rng_llegadas: StdRng::seed_from_u64(rng.gen()),
+            rng,
+            productos: parametros
+                .productos
+                .into_iter()
+                .map(|(id_producto, _)| id_producto)
+                .collect(),
+            pesos_productos,
+            cantidades: parametros.cantidades,
+            zonas: parametros.zonas,
+            pesos_zonas,
+            tasa: parametros.tasa,
+        })
+    }
+
+    pub fn siguiente(&mut self) -> Orden {
+        let id_producto = self.productos[self.pesos_productos.sample(&mut self.rng)];
+        let cantidad = self.rng.gen_range(self.cantidades.clone());
+        let zona = &self.zonas[self.pesos_zonas.sample(&mut self.rng)];
+        // la raiz reparte las ordenes de forma pareja en el area del circulo
+        let distancia = zona.radio_km * self.rng.gen::<f64>().sqrt();
+        let rumbo = self.rng.gen_range(0.0..360.0);
+        Orden {
+            id_producto,
+            cantidad,
+            direccion: desplazar(&zona.centro, distancia, rumbo),
+            prioridad: 0,
+            vencimiento: None,
+            id: None,
+            traza: None,
+        }
+    }
+
+    pub fn con_esperas(&self) -> bool {
+        self.tasa.is_some()
+    }
+
+    /// Tiempo hasta la llegada de la siguiente orden, exponencial con media 1 / tasa
+    pub fn espera(&mut self) -> Duration {
+        match self.tasa {
+            Some(tasa) => {
+                let uniforme: f64 = self.rng_llegadas.gen_range(f64::EPSILON..1.0);
+                Duration::from_secs_f64(-uniforme.ln() / tasa)
+            }
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// Punto a `distancia_km` del origen en la dirección del rumbo, en grados desde el norte
+fn desplazar(origen: &Direccion, distancia_km: f64, rumbo: f64) -> Direccion {
+    let angulo = distancia_km / RADIO_TIERRA_KM;
+    let rumbo = rumbo.to_radians();
+    let latitud = origen.latitud().to_radians();
+    let longitud = origen.longitud().to_radians();
+
+    let nueva_latitud =
+        (latitud.sin() * angulo.cos() + latitud.cos() * angulo.sin() * rumbo.cos()).asin();
+    let nueva_longitud = longitud
+        + (rumbo.sin() * angulo.sin() * latitud.cos())
+            .atan2(angulo.cos() - latitud.sin() * nueva_latitud.sin());
+
+    let longitud_normalizada = (nueva_longitud.to_degrees() + 540.0) % 360.0 - 180.0;
+    Direccion::new(nueva_latitud.to_degrees(), longitud_normalizada)
+}
+
+/// Escribe las ordenes en el formato que leen el Vendedor del local y la fuente del ecommerce
+pub struct EscritorOrdenes<W: Write> {
+    salida: W,
+    formato: FormatoOrdenes,
+}
+
+impl<W: Write> EscritorOrdenes<W> {
+    pub fn new(mut salida: W, formato: FormatoOrdenes) -> io::Result<Self> {
+        if formato == FormatoOrdenes::Csv {
+            writeln!(salida, "id_producto, cantidad, latitud, longitud")?;
+        }
+        Ok(EscritorOrdenes { salida, formato })
+    }
+
+    pub fn escribir(&mut self, orden: &Orden) -> io::Result<()> {
+        match self.formato {
+            FormatoOrdenes::Csv => writeln!(
+                self.salida,
+                "{},{},{:.6},{:.6}",
+                orden.id_producto,
+                orden.cantidad,
+                orden.direccion.latitud(),
+                orden.direccion.longitud()
+            ),
+            FormatoOrdenes::JsonLineas => {
+                let linea = serde_json::to_string(orden).map_err(io::Error::other)?;
+                writeln!(self.salida, "{}", linea)
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.salida.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fuente_ordenes::abrir_fuente;
+
+    #[test]
+    fn test_misma_semilla_genera_las_mismas_ordenes() {
+        let mut generador = GeneradorOrdenes::new(ParametrosCarga::default()).unwrap();
+        let mut otro = GeneradorOrdenes::new(ParametrosCarga::default()).unwrap();
+        let mut distinto = GeneradorOrdenes::new(ParametrosCarga {
+            semilla: 1,
+            ..ParametrosCarga::default()
+        })
+        .unwrap();
+
+        let ordenes: Vec<Orden> = (0..20).map(|_| generador.siguiente()).collect();
+        assert_eq!(
+            ordenes,
+            (0..20).map(|_| otro.siguiente()).collect::<Vec<_>>()
+        );
+        assert_ne!(
+            ordenes,
+            (0..20).map(|_| distinto.siguiente()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_ordenes_respetan_productos_cantidades_y_zonas() {
+        let zona: Zona = "-34.6,-58.4,50".parse().unwrap();
+        let mut generador = GeneradorOrdenes::new(ParametrosCarga {
+            semilla: 7,
+            productos: vec![(4, 1.0), (9, 0.0), (12, 3.0)],
+            cantidades: 2..=3,
+            zonas: vec![zona.clone(), "80,179.9,100,0".parse().unwrap()],
+            tasa: None,
+        })
+        .unwrap();
+
+        for _ in 0..500 {
+            let orden = generador.siguiente();
+            assert!([4, 12].contains(&orden.id_producto));
+            assert!((2..=3).contains(&orden.cantidad));
+            assert!(zona.centro.distancia(&orden.direccion) <= zona.radio_km + 1e-6);
+            assert!(orden.validar().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_espera_promedio_segun_la_tasa() {
+        let mut generador = GeneradorOrdenes::new(ParametrosCarga {
+            tasa: Some(1000.0),
+            ..ParametrosCarga::default()
+        })
+        .unwrap();
+
+        let total: Duration = (0..10000).map(|_| generador.espera()).sum();
+        let promedio = total.as_secs_f64() / 10000.0;
+        assert!((0.0009..0.0011).contains(&promedio), "{}", promedio);
+        assert!(GeneradorOrdenes::new(ParametrosCarga {
+            tasa: Some(0.0),
+            ..ParametrosCarga::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_ordenes_escritas_se_leen_como_fuente() {
+        let archivo = std::env::temp_dir().join("test_generador_ordenes.csv");
+        let mut generador = GeneradorOrdenes::new(ParametrosCarga::default()).unwrap();
+        let ordenes: Vec<Orden> = (0..3).map(|_| generador.siguiente()).collect();
+        let mut escritor = EscritorOrdenes::new(
+            std::fs::File::create(&archivo).unwrap(),
+            FormatoOrdenes::Csv,
+        )
+        .unwrap();
+        for orden in &ordenes {
+            escritor.escribir(orden).unwrap();
+        }
+        escritor.flush().unwrap();
+
+        let mut fuente =
+            abrir_fuente(archivo.to_str().unwrap(), FormatoOrdenes::Csv, false).unwrap();
+        for (cursor, orden) in ordenes.iter().enumerate() {
+            let leida = fuente.orden_en(cursor).unwrap();
+            assert_eq!(leida.id_producto, orden.id_producto);
+            assert_eq!(leida.cantidad, orden.cantidad);
+            assert!(leida.direccion.distancia(&orden.direccion) < 0.001);
+        }
+    }
+
+    #[test]
+    fn test_parsear_zona() {
+        assert_eq!(
+            "1.5,-2,100,3".parse::<Zona>(),
+            Ok(Zona {
+                centro: Direccion::new(1.5, -2.0),
+                radio_km: 100.0,
+                peso: 3.0,
+            })
+        );
+        assert!("1,2".parse::<Zona>().is_err());
+        assert!("91,2,10".parse::<Zona>().is_err());
+        assert!("1,2,-10".parse::<Zona>().is_err());
+    }
+}
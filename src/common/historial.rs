@@ -0,0 +1,262 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::stock_producto::StockProducto;
+use crate::common::transferencia::{IdTransferencia, Transferencia};
+
+/// Operación de un nodo con su resultado, una linea json por evento
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Evento {
+    /// Nanosegundos desde el epoch unix, de relojes de la misma maquina para poder comparar
+    /// eventos de distintos procesos
+    pub ns: u64,
+    pub nodo: String,
+    #[serde(flatten)]
+    pub operacion: Operacion,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operacion {
+    /// El lider le da el permiso a un ecommerce
+    PermisoOtorgado {
+        ecommerce: String,
+        cursor: usize,
+    },
+    /// El lider recibe el permiso de vuelta, `avanzo` si se consumió la orden del cursor
+    PermisoLiberado {
+        ecommerce: String,
+        cursor: usize,
+        avanzo: bool,
+    },
+    /// Lo mismo del lado del ecommerce que tiene el permiso
+    PermisoRecibido {
+        cursor: usize,
+    },
+    PermisoDevuelto {
+        cursor: usize,
+        avanzo: bool,
+    },
+    /// El ecommerce leyó la orden del cursor y la despacha
+    Despacho {
+        cursor: usize,
+        orden: String,
+    },
+    /// Como terminó el despacho de una orden en el ecommerce
+    Resultado {
+        orden: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        local: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        motivo: Option<String>,
+    },
+    /// Stock con el que arranca un local, lo que habia antes en su historial ya no cuenta.
+    /// Un local que retoma su estado después de caerse arranca con sus ordenes en progreso
+    /// y sus transferencias reservadas
+    Inicio {
+        productos: Vec<ProductoHistorial>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        ordenes: Vec<OrdenEnProgreso>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transferencias: Vec<Transferencia>,
+    },
+    /// El local no pudo aceptar la orden
+    Rechazada {
+        orden: String,
+        motivo: String,
+    },
+    /// Operación que cambia el stock de un producto del local, con como quedó el producto
+    Stock {
+        tipo: OperacionStock,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        orden: Option<String>,
+        /// La transferencia que reserva, envia o libera unidades
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transferencia: Option<IdTransferencia>,
+        cantidad: usize,
+        #[serde(flatten)]
+        producto: ProductoHistorial,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperacionStock {
+    /// Bloquea la cantidad de una orden aceptada
+    Aceptada,
+    Entregada,
+    Cancelada,
+    /// Venta en el local, sin orden
+    Venta,
+    /// Reposición del proveedor o transferencia recibida
+    Reposicion,
+    /// Bloquea las unidades que pidió otro local
+    ReservaTransferencia,
+    /// El otro local recibió la transferencia
+    EnvioTransferencia,
+    /// La transferencia no se completó y se liberan las unidades reservadas
+    LiberacionTransferencia,
+}
+
+/// Lo que bloquea una orden en progreso
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrdenEnProgreso {
+    pub orden: String,
+    pub id_producto: usize,
+    pub cantidad: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProductoHistorial {
+    pub id_producto: usize,
+    pub stock: usize,
+    pub bloqueados: usize,
+}
+
+impl From<&StockProducto> for ProductoHistorial {
+    fn from(producto: &StockProducto) -> Self {
+        ProductoHistorial {
+            id_producto: producto.id_producto,
+            stock: producto.stock,
+            bloqueados: producto.bloqueados,
+        }
+    }
+}
+
+/// Agrega los eventos de un nodo a un archivo, varios nodos pueden compartir la salida
+#[derive(Clone)]
+pub struct Historial {
+    nodo: String,
+    salida: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl Historial {
+    pub fn new(archivo: &str, nodo: String) -> Result<Self, String> {
+        let archivo = File::options()
+            .create(true)
+            .append(true)
+            .open(archivo)
+            .map_err(|e| format!("No se pudo abrir el historial {}: {}", archivo, e))?;
+        Ok(Self::con_salida(Box::new(LineWriter::new(archivo)), nodo))
+    }
+
+    pub fn con_salida(salida: Box<dyn Write + Send>, nodo: String) -> Self {
+        Historial {
+            nodo,
+            salida: Arc::new(Mutex::new(salida)),
+        }
+    }
+
+    /// Historial de otro nodo sobre la misma salida
+    pub fn para(&self, nodo: String) -> Self {
+        Historial {
+            nodo,
+            salida: self.salida.clone(),
+        }
+    }
+
+    pub fn registrar(&self, operacion: Operacion) {
+        let evento = Evento {
+            ns: ahora_en_ns(),
+            nodo: self.nodo.clone(),
+            operacion,
+        };
+        let Ok(linea) = serde_json::to_string(&evento) else {
+            return;
+        };
+        let mut salida = self.salida.lock().unwrap_or_else(PoisonError::into_inner);
+        // si no se puede escribir se pierde el evento, no se corta el proceso por el historial
+        let _ = writeln!(salida, "{}", linea);
+    }
+}
+
+/// Salida en memoria, para juntar el historial de una simulación
+#[derive(Clone, Default)]
+pub struct HistorialEnMemoria(Arc<Mutex<Vec<u8>>>);
+
+impl HistorialEnMemoria {
+    pub fn eventos(&self) -> Vec<Evento> {
+        let bytes = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        leer_eventos(bytes.as_slice()).expect("Historial en memoria invalido")
+    }
+}
+
+impl Write for HistorialEnMemoria {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Lee los eventos de un historial, con el numero de cada linea invalida
+pub fn leer_eventos(lector: impl BufRead) -> Result<Vec<Evento>, String> {
+    lector
+        .lines()
+        .enumerate()
+        .filter(|(_, linea)| !linea.as_ref().is_ok_and(|linea| linea.trim().is_empty()))
+        .map(|(numero, linea)| {
+            let linea = linea.map_err(|e| e.to_string())?;
+            serde_json::from_str(&linea).map_err(|e| format!("linea {}: {}", numero + 1, e))
+        })
+        .collect()
+}
+
+pub fn leer_historial(archivo: &str) -> Result<Vec<Evento>, String> {
+    let lector = File::open(archivo)
+        .map(BufReader::new)
+        .map_err(|e| format!("No se pudo abrir {}: {}", archivo, e))?;
+    leer_eventos(lector).map_err(|e| format!("Historial invalido en {}, {}", archivo, e))
+}
+
+fn ahora_en_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duracion| duracion.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eventos_en_una_linea_json_plana() {
+        let evento = Evento {
+            ns: 10,
+            nodo: "local-1".to_string(),
+            operacion: Operacion::Stock {
+                tipo: OperacionStock::Aceptada,
+                orden: Some("2-0".to_string()),
+                transferencia: None,
+                cantidad: 2,
+                producto: ProductoHistorial {
+                    id_producto: 3,
+                    stock: 10,
+                    bloqueados: 2,
+                },
+            },
+        };
+        let linea = serde_json::to_string(&evento).unwrap();
+
+        assert_eq!(
+            linea,
+            r#"{"ns":10,"nodo":"local-1","op":"stock","tipo":"aceptada","orden":"2-0","cantidad":2,"id_producto":3,"stock":10,"bloqueados":2}"#
+        );
+        let leidos = leer_eventos(format!("{}\n\n{}\n", linea, linea).as_bytes()).unwrap();
+        assert_eq!(leidos, vec![evento.clone(), evento]);
+        assert!(leer_eventos("{\"ns\":1}".as_bytes())
+            .unwrap_err()
+            .starts_with("linea 1"));
+    }
+}
@@ -0,0 +1,223 @@
+use serde_json::Value;
+use std::io::{self, BufRead, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+/// Tamaño maximo del cuerpo que se acepta en un pedido
+const TAMANIO_MAXIMO_CUERPO: usize = 64 * 1024;
+
+/// Pedido http/1.1 con lo minimo que necesitan las apis de los binarios
+#[derive(Debug, PartialEq)]
+pub struct PedidoHttp {
+    pub metodo: String,
+    pub ruta: String,
+    pub cuerpo: Vec<u8>,
+}
+
+/// Lee la linea de pedido, los encabezados y el cuerpo indicado por Content-Length
+pub fn leer_pedido(lector: &mut impl BufRead) -> io::Result<PedidoHttp> {
+    let mut linea = String::new();
+    lector.read_line(&mut linea)?;
+    let mut partes = linea.split_whitespace();
+    let (metodo, ruta) = match (partes.next(), partes.next()) {
+        (Some(metodo), Some(ruta)) => (metodo.to_string(), ruta.to_string()),
+        _ => return Err(invalido("Linea de pedido invalida")),
+    };
+
+    let mut largo_cuerpo = 0;
+    loop {
+        let mut encabezado = String::new();
+        if lector.read_line(&mut encabezado)? == 0 {
+            break;
+        }
+        let encabezado = encabezado.trim_end();
+        if encabezado.is_empty() {
+            break;
+        }
+        if let Some((nombre, valor)) = encabezado.split_once(':') {
+            if nombre.trim().eq_ignore_ascii_case("content-length") {
+                largo_cuerpo = valor
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalido("Content-Length invalido"))?;
+            }
+        }
+    }
+    if largo_cuerpo > TAMANIO_MAXIMO_CUERPO {
+        return Err(invalido("El cuerpo del pedido es demasiado grande"));
+    }
+    let mut cuerpo = vec![0; largo_cuerpo];
+    lector.read_exact(&mut cuerpo)?;
+
+    Ok(PedidoHttp {
+        metodo,
+        ruta,
+        cuerpo,
+    })
+}
+
+fn invalido(mensaje: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, mensaje)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RespuestaHttp {
+    pub codigo: u16,
+    pub tipo_contenido: &'static str,
+    pub cuerpo: String,
+}
+
+impl RespuestaHttp {
+    pub fn json(codigo: u16, valor: &Value) -> RespuestaHttp {
+        RespuestaHttp {
+            codigo,
+            tipo_contenido: "application/json",
+            cuerpo: valor.to_string(),
+        }
+    }
+
+    pub fn texto(codigo: u16, tipo_contenido: &'static str, cuerpo: String) -> RespuestaHttp {
+        RespuestaHttp {
+            codigo,
+            tipo_contenido,
+            cuerpo,
+        }
+    }
+
+    /// Escribe la respuesta completa, la conexión se cierra despues de cada pedido
+    pub fn escribir(&self, escritor: &mut impl Write) -> io::Result<()> {
+        write!(
+            escritor,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.codigo,
+            razon(self.codigo),
+            self.tipo_contenido,
+            self.cuerpo.len(),
+            self.cuerpo
+        )?;
+        escritor.flush()
+    }
+}
+
+/// Envia un pedido en una conexión nueva y devuelve el codigo y el cuerpo de la respuesta
+pub fn enviar_pedido(
+    direccion: SocketAddr,
+    metodo: &str,
+    ruta: &str,
+    cuerpo: &str,
+) -> io::Result<(u16, String)> {
+    let mut stream = TcpStream::connect(direccion)?;
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        metodo,
+        ruta,
+        direccion,
+        cuerpo.len(),
+        cuerpo
+    )?;
+    stream.flush()?;
+    let mut respuesta = String::new();
+    stream.read_to_string(&mut respuesta)?;
+    let codigo = respuesta
+        .split_whitespace()
+        .nth(1)
+        .and_then(|codigo| codigo.parse().ok())
+        .ok_or_else(|| invalido("Linea de estado invalida"))?;
+    let cuerpo = respuesta
+        .split_once("\r\n\r\n")
+        .map(|(_, cuerpo)| cuerpo.to_string())
+        .unwrap_or_default();
+    Ok((codigo, cuerpo))
+}
+
+fn razon(codigo: u16) -> &'static str {
+    match codigo {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::{BufReader, Cursor};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_leer_pedido_post_con_cuerpo() {
+        let mut pedido = Cursor::new(
+            "POST /ordenes HTTP/1.1\r\nHost: localhost\r\ncontent-length: 4\r\n\r\n{\"a\"}",
+        );
+
+        let pedido = leer_pedido(&mut pedido).unwrap();
+
+        assert_eq!(pedido.metodo, "POST");
+        assert_eq!(pedido.ruta, "/ordenes");
+        assert_eq!(pedido.cuerpo, b"{\"a\"");
+    }
+
+    #[test]
+    fn test_leer_pedido_get_sin_cuerpo() {
+        let mut pedido = Cursor::new("GET /ordenes/3 HTTP/1.1\r\n\r\n");
+
+        let pedido = leer_pedido(&mut pedido).unwrap();
+
+        assert_eq!(pedido.metodo, "GET");
+        assert_eq!(pedido.ruta, "/ordenes/3");
+        assert!(pedido.cuerpo.is_empty());
+    }
+
+    #[test]
+    fn test_leer_pedido_invalido_devuelve_error() {
+        assert!(leer_pedido(&mut Cursor::new("\r\n")).is_err());
+        assert!(leer_pedido(&mut Cursor::new(
+            "POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n"
+        ))
+        .is_err());
+        assert!(leer_pedido(&mut Cursor::new(
+            "POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\ncorto"
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_escribir_respuesta_json() {
+        let mut salida = vec![];
+
+        RespuestaHttp::json(202, &json!({ "id": 1 }))
+            .escribir(&mut salida)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(salida).unwrap(),
+            "HTTP/1.1 202 Accepted\r\nContent-Type: application/json\r\nContent-Length: 8\r\nConnection: close\r\n\r\n{\"id\":1}"
+        );
+    }
+
+    #[test]
+    fn test_enviar_pedido_devuelve_codigo_y_cuerpo() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let direccion = listener.local_addr().unwrap();
+        let servidor = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let pedido = leer_pedido(&mut BufReader::new(&stream)).unwrap();
+            RespuestaHttp::json(202, &json!({ "id": 1 }))
+                .escribir(&mut &stream)
+                .unwrap();
+            pedido
+        });
+
+        let respuesta = enviar_pedido(direccion, "POST", "/ordenes", "{\"a\":1}").unwrap();
+
+        assert_eq!(respuesta, (202, "{\"id\":1}".to_string()));
+        let pedido = servidor.join().unwrap();
+        assert_eq!(pedido.ruta, "/ordenes");
+        assert_eq!(pedido.cuerpo, b"{\"a\":1}");
+    }
+}
@@ -0,0 +1,244 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::common::orden::{Direccion, RADIO_TIERRA_KM};
+
+type Punto = [f64; 3];
+
+/// Indice de las direcciones de los locales para recorrerlos del más cercano al más lejano.
+/// Es un k-d tree sobre los puntos de la esfera unitaria: la distancia en linea recta entre
+/// dos puntos crece con la distancia sobre la superficie, asi que ordenar por una ordena por la otra
+pub struct IndiceEspacial {
+    nodos: Vec<Nodo>,
+    raiz: Option<usize>,
+}
+
+struct Nodo {
+    id: usize,
+    punto: Punto,
+    izquierda: Option<usize>,
+    derecha: Option<usize>,
+    /// Caja que contiene a todos los puntos del subarbol, para acotar su distancia
+    minimo: Punto,
+    maximo: Punto,
+}
+
+impl IndiceEspacial {
+    pub fn new(locales: impl IntoIterator<Item = (usize, Direccion)>) -> Self {
+        let mut puntos: Vec<(usize, Punto)> = locales
+            .into_iter()
+            .map(|(id, direccion)| (id, a_punto(&direccion)))
+            .collect();
+        let mut indice = IndiceEspacial {
+            nodos: Vec::with_capacity(puntos.len()),
+            raiz: None,
+        };
+        indice.raiz = indice.construir(&mut puntos, 0);
+        indice
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodos.is_empty()
+    }
+
+    /// Recorre los locales de a uno, del más cercano al más lejano de la dirección, con su distancia en km
+    pub fn cercanos(&self, direccion: &Direccion) -> Cercanos<'_> {
+        let mut pendientes = BinaryHeap::new();
+        if let Some(raiz) = self.raiz {
+            pendientes.push(Candidato {
+                cota: 0.0,
+                elemento: Elemento::Subarbol(raiz),
+            });
+        }
+        Cercanos {
+            indice: self,
+            consulta: a_punto(direccion),
+            pendientes,
+        }
+    }
+
+    fn construir(&mut self, puntos: &mut [(usize, Punto)], profundidad: usize) -> Option<usize> {
+        if puntos.is_empty() {
+            return None;
+        }
+        let eje = profundidad % 3;
+        puntos.sort_by(|a, b| a.1[eje].total_cmp(&b.1[eje]));
+        let medio = puntos.len() / 2;
+        let (id, punto) = puntos[medio];
+        let (minimo, maximo) = caja(puntos);
+
+        let posicion = self.nodos.len();
+        self.nodos.push(Nodo {
+            id,
+            punto,
+            izquierda: None,
+            derecha: None,
+            minimo,
+            maximo,
+        });
+        let (izquierda, resto) = puntos.split_at_mut(medio);
+        let izquierda = self.construir(izquierda, profundidad + 1);
+        let derecha = self.construir(&mut resto[1..], profundidad + 1);
+        self.nodos[posicion].izquierda = izquierda;
+        self.nodos[posicion].derecha = derecha;
+        Some(posicion)
+    }
+}
+
+/// Iterador de `IndiceEspacial::cercanos`. Visita primero lo que tiene menor cota de distancia,
+/// cada local sale recien cuando ningun subarbol pendiente puede tener uno más cercano
+pub struct Cercanos<'a> {
+    indice: &'a IndiceEspacial,
+    consulta: Punto,
+    pendientes: BinaryHeap<Candidato>,
+}
+
+impl Iterator for Cercanos<'_> {
+    type Item = (usize, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(candidato) = self.pendientes.pop() {
+            let posicion = match candidato.elemento {
+                Elemento::Local(id) => return Some((id, a_kilometros(candidato.cota))),
+                Elemento::Subarbol(posicion) => posicion,
+            };
+            let nodo = &self.indice.nodos[posicion];
+            self.pendientes.push(Candidato {
+                cota: distancia(&nodo.punto, &self.consulta),
+                elemento: Elemento::Local(nodo.id),
+            });
+            for hijo in [nodo.izquierda, nodo.derecha].into_iter().flatten() {
+                let hijo_nodo = &self.indice.nodos[hijo];
+                self.pendientes.push(Candidato {
+                    cota: distancia_a_caja(&self.consulta, &hijo_nodo.minimo, &hijo_nodo.maximo),
+                    elemento: Elemento::Subarbol(hijo),
+                });
+            }
+        }
+        None
+    }
+}
+
+enum Elemento {
+    Local(usize),
+    Subarbol(usize),
+}
+
+/// Entrada del heap ordenada al reves para sacar primero la menor cota
+struct Candidato {
+    cota: f64,
+    elemento: Elemento,
+}
+
+impl PartialEq for Candidato {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidato {}
+
+impl PartialOrd for Candidato {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidato {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // a igual cota primero el local, asi no se abre un subarbol de más
+        other.cota.total_cmp(&self.cota).then_with(|| {
+            matches!(self.elemento, Elemento::Local(_))
+                .cmp(&matches!(other.elemento, Elemento::Local(_)))
+        })
+    }
+}
+
+fn a_punto(direccion: &Direccion) -> Punto {
+    let latitud = direccion.latitud().to_radians();
+    let longitud = direccion.longitud().to_radians();
+    [
+        latitud.cos() * longitud.cos(),
+        latitud.cos() * longitud.sin(),
+        latitud.sin(),
+    ]
+}
+
+/// Pasa la distancia en linea recta sobre la esfera unitaria a km sobre la superficie
+fn a_kilometros(cuerda: f64) -> f64 {
+    2.0 * RADIO_TIERRA_KM * (cuerda / 2.0).min(1.0).asin()
+}
+
+fn distancia(a: &Punto, b: &Punto) -> f64 {
+    (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f64>().sqrt()
+}
+
+fn distancia_a_caja(punto: &Punto, minimo: &Punto, maximo: &Punto) -> f64 {
+    (0..3)
+        .map(|i| {
+            (minimo[i] - punto[i])
+                .max(punto[i] - maximo[i])
+                .max(0.0)
+                .powi(2)
+        })
+        .sum::<f64>()
+        .sqrt()
+}
+
+fn caja(puntos: &[(usize, Punto)]) -> (Punto, Punto) {
+    let mut minimo = [f64::MAX; 3];
+    let mut maximo = [f64::MIN; 3];
+    for (_, punto) in puntos {
+        for i in 0..3 {
+            minimo[i] = minimo[i].min(punto[i]);
+            maximo[i] = maximo[i].max(punto[i]);
+        }
+    }
+    (minimo, maximo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_recorre_los_locales_en_orden_de_distancia() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let locales: Vec<(usize, Direccion)> = (0..300)
+            .map(|id| {
+                let direccion =
+                    Direccion::new(rng.gen_range(-34.8..-34.5), rng.gen_range(-58.6..-58.3));
+                (id, direccion)
+            })
+            .collect();
+        let indice = IndiceEspacial::new(locales.clone());
+        let orden = Direccion::new(-34.6037, -58.3816);
+
+        let mut esperado: Vec<(usize, f64)> = locales
+            .iter()
+            .map(|(id, direccion)| (*id, orden.distancia(direccion)))
+            .collect();
+        esperado.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let recorridos: Vec<(usize, f64)> = indice.cercanos(&orden).collect();
+
+        assert_eq!(recorridos.len(), locales.len());
+        for ((id, distancia), (id_esperado, distancia_esperada)) in
+            recorridos.iter().zip(esperado.iter())
+        {
+            assert_eq!(id, id_esperado);
+            assert!((distancia - distancia_esperada).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_indice_vacio_no_devuelve_locales() {
+        let indice = IndiceEspacial::new(vec![]);
+        assert!(indice.is_empty());
+        assert_eq!(indice.cercanos(&Direccion::new(0.0, 0.0)).next(), None);
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use csv::StringRecord;
+
+use crate::common::error_csv::ErrorCsv;
+use crate::common::indice_espacial::IndiceEspacial;
+use crate::common::lector_csv::{leer_columna, leer_columna_opcional, linea};
+use crate::common::orden::Direccion;
+
+/// Local al que el ecommerce le puede enviar ordenes
+#[derive(Debug, Clone, PartialEq)]
+pub struct InfoLocal {
+    pub id: usize,
+    pub direccion: Direccion,
+    pub cobertura: Cobertura,
+}
+
+/// Direcciones a las que un local puede entregar
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cobertura {
+    SinLimite,
+    /// Hasta esta distancia en km desde el local
+    Radio(f64),
+    /// Dentro del poligono con estos vertices
+    Zona(Vec<Direccion>),
+}
+
+impl Cobertura {
+    /// Indica si cubre la dirección, que está a `distancia` km del local
+    pub fn cubre(&self, direccion: &Direccion, distancia: f64) -> bool {
+        match self {
+            Cobertura::SinLimite => true,
+            Cobertura::Radio(radio) => distancia <= *radio,
+            Cobertura::Zona(vertices) => dentro_del_poligono(direccion, vertices),
+        }
+    }
+
+    /// Distancia en km desde el local más alla de la cual no cubre ninguna dirección
+    pub fn alcance(&self, local: &Direccion) -> f64 {
+        match self {
+            Cobertura::SinLimite => f64::INFINITY,
+            Cobertura::Radio(radio) => *radio,
+            Cobertura::Zona(vertices) => vertices
+                .iter()
+                .map(|vertice| local.distancia(vertice))
+                .fold(0.0, f64::max),
+        }
+    }
+}
+
+/// Vacio es sin limite, un numero es el radio en km y una zona son
+/// al menos tres vertices `latitud longitud` separados por `;`
+impl FromStr for Cobertura {
+    type Err = String;
+
+    fn from_str(texto: &str) -> Result<Self, Self::Err> {
+        let texto = texto.trim();
+        if texto.is_empty() {
+            return Ok(Cobertura::SinLimite);
+        }
+        if let Ok(radio) = texto.parse::<f64>() {
+            if radio >= 0.0 {
+                return Ok(Cobertura::Radio(radio));
+            }
+            return Err(format!("Radio invalido: {}", radio));
+        }
+        let vertices = texto
+            .split(';')
+            .map(|vertice| {
+                let mut coordenadas = vertice.split_whitespace().map(str::parse::<f64>);
+                match (coordenadas.next(), coordenadas.next(), coordenadas.next()) {
+                    (Some(Ok(latitud)), Some(Ok(longitud)), None) => {
+                        let direccion = Direccion::new(latitud, longitud);
+                        direccion.validar().map(|_| direccion)
+                    }
+                    _ => Err(format!("Vertice invalido: {}", vertice)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if vertices.len() < 3 {
+            return Err("La zona necesita al menos tres vertices".to_string());
+        }
+        Ok(Cobertura::Zona(vertices))
+    }
+}
+
+/// Locales indexados por ubicación para elegir los que entregan en una dirección
+pub struct MapaLocales {
+    indice: IndiceEspacial,
+    coberturas: HashMap<usize, Cobertura>,
+    /// Mayor alcance entre todos los locales, más lejos no hace falta seguir buscando
+    alcance: f64,
+}
+
+impl MapaLocales {
+    pub fn new(locales: Vec<InfoLocal>) -> Self {
+        let alcance = locales
+            .iter()
+            .map(|local| local.cobertura.alcance(&local.direccion))
+            .fold(0.0, f64::max);
+        let indice = IndiceEspacial::new(
+            locales
+                .iter()
+                .map(|local| (local.id, local.direccion.clone())),
+        );
+        let coberturas = locales
+            .into_iter()
+            .map(|local| (local.id, local.cobertura))
+            .collect();
+        MapaLocales {
+            indice,
+            coberturas,
+            alcance,
+        }
+    }
+
+    /// Locales que cubren la dirección, del más cercano al más lejano, con su distancia en km
+    pub fn que_cubren<'a>(
+        &'a self,
+        direccion: &'a Direccion,
+    ) -> impl Iterator<Item = (usize, f64)> + 'a {
+        self.indice
+            .cercanos(direccion)
+            .take_while(|(_, distancia)| *distancia <= self.alcance)
+            .filter(|(id, distancia)| {
+                self.coberturas
+                    .get(id)
+                    .is_some_and(|cobertura| cobertura.cubre(direccion, *distancia))
+            })
+    }
+}
+
+/// Ray casting sobre latitud y longitud, alcanza para zonas del tamaño de una ciudad
+fn dentro_del_poligono(direccion: &Direccion, vertices: &[Direccion]) -> bool {
+    let (y, x) = (direccion.latitud(), direccion.longitud());
+    let mut dentro = false;
+    let mut anterior = vertices.len() - 1;
+    for (actual, vertice) in vertices.iter().enumerate() {
+        let (yi, xi) = (vertice.latitud(), vertice.longitud());
+        let (yj, xj) = (vertices[anterior].latitud(), vertices[anterior].longitud());
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            dentro = !dentro;
+        }
+        anterior = actual;
+    }
+    dentro
+}
+
+impl InfoLocal {
+    /// Crea InfoLocal a partir de un record de csv con id_local, latitud, longitud
+    /// y opcionalmente la cobertura
+    pub fn from_record(record: StringRecord) -> Result<Self, ErrorCsv> {
+        let linea = linea(&record);
+        let id = leer_columna(&record, 0, "id_local")?;
+        let direccion = Direccion::new(
+            leer_columna(&record, 1, "latitud")?,
+            leer_columna(&record, 2, "longitud")?,
+        );
+        direccion
+            .validar()
+            .map_err(|motivo| ErrorCsv::FilaInvalida { linea, motivo })?;
+        let cobertura =
+            leer_columna_opcional(&record, 3, "cobertura")?.unwrap_or(Cobertura::SinLimite);
+        Ok(InfoLocal {
+            id,
+            direccion,
+            cobertura,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_record_lee_local_y_valida_coordenadas() {
+        let local = InfoLocal::from_record(StringRecord::from(vec!["3", "-34.6", "-58.38"]));
+        assert_eq!(
+            local.unwrap(),
+            InfoLocal {
+                id: 3,
+                direccion: Direccion::new(-34.6, -58.38),
+                cobertura: Cobertura::SinLimite,
+            }
+        );
+        let fuera_de_rango = InfoLocal::from_record(StringRecord::from(vec!["3", "0", "200"]));
+        assert!(matches!(fuera_de_rango, Err(ErrorCsv::FilaInvalida { .. })));
+    }
+
+    #[test]
+    fn test_cobertura_por_radio() {
+        let local =
+            InfoLocal::from_record(StringRecord::from(vec!["1", "0", "0", "10.5"])).unwrap();
+        assert_eq!(local.cobertura, Cobertura::Radio(10.5));
+
+        let cerca = Direccion::new(0.05, 0.0);
+        let lejos = Direccion::new(1.0, 0.0);
+        assert!(local
+            .cobertura
+            .cubre(&cerca, local.direccion.distancia(&cerca)));
+        assert!(!local
+            .cobertura
+            .cubre(&lejos, local.direccion.distancia(&lejos)));
+    }
+
+    #[test]
+    fn test_cobertura_por_zona() {
+        let zona: Cobertura = "-34.58 -58.42; -34.58 -58.36; -34.63 -58.36; -34.63 -58.42"
+            .parse()
+            .unwrap();
+        let adentro = Direccion::new(-34.6037, -58.3816);
+        let afuera = Direccion::new(-34.6, -58.45);
+
+        assert!(zona.cubre(&adentro, 0.0));
+        assert!(!zona.cubre(&afuera, 0.0));
+        assert!("-34.58 -58.42; -34.58 -58.36".parse::<Cobertura>().is_err());
+        assert!("-5".parse::<Cobertura>().is_err());
+    }
+
+    #[test]
+    fn test_mapa_devuelve_solo_los_locales_que_cubren_la_direccion() {
+        let local = |id, latitud, longitud, cobertura: &str| InfoLocal {
+            id,
+            direccion: Direccion::new(latitud, longitud),
+            cobertura: cobertura.parse().unwrap(),
+        };
+        let mapa = MapaLocales::new(vec![
+            local(1, -34.60, -58.38, "5"),
+            local(2, -34.61, -58.40, "1"),
+            local(3, -31.42, -64.18, "50"),
+        ]);
+
+        let orden = Direccion::new(-34.6037, -58.3816);
+        let ids: Vec<usize> = mapa.que_cubren(&orden).map(|(id, _)| id).collect();
+        assert_eq!(ids, vec![1]);
+        let lejos = Direccion::new(-32.0, -32.0);
+        assert_eq!(mapa.que_cubren(&lejos).next(), None);
+    }
+}
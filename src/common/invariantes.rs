@@ -0,0 +1,586 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::common::historial::{Evento, Operacion, OperacionStock, ProductoHistorial};
+use crate::common::transferencia::IdTransferencia;
+
+/// Verifica sobre los eventos de todos los nodos las propiedades que tiene que cumplir cualquier
+/// ejecución, devuelve las que no se cumplen:
+/// - nunca dos ecommerce tienen el permiso a la vez, ni según el lider ni según los ecommerce
+/// - cada cursor se despacha a lo sumo una vez
+/// - el stock nunca queda negativo ni por debajo de los bloqueados, y cada producto cambia
+///   solo por lo que dice su operación
+/// - los bloqueados de cada producto son los de sus ordenes en progreso y transferencias
+///   reservadas según las operaciones del local, y ninguna transferencia se reserva dos veces
+pub fn verificar(eventos: &[Evento]) -> Vec<String> {
+    let mut eventos: Vec<&Evento> = eventos.iter().collect();
+    eventos.sort_by_key(|evento| evento.ns);
+    let mut violaciones = vec![];
+    verificar_permisos(&eventos, &mut violaciones);
+    verificar_cursores(&eventos, &mut violaciones);
+    verificar_stock(&eventos, &mut violaciones);
+    violaciones
+}
+
+fn verificar_permisos(eventos: &[&Evento], violaciones: &mut Vec<String>) {
+    let segun_lider = eventos.iter().filter_map(|evento| match &evento.operacion {
+        Operacion::PermisoOtorgado { ecommerce, .. } => Some((evento.ns, ecommerce.as_str(), true)),
+        Operacion::PermisoLiberado { ecommerce, .. } => {
+            Some((evento.ns, ecommerce.as_str(), false))
+        }
+        _ => None,
+    });
+    exclusion_mutua("el lider", segun_lider.collect(), violaciones);
+    let segun_ecommerces = eventos.iter().filter_map(|evento| match &evento.operacion {
+        Operacion::PermisoRecibido { .. } => Some((evento.ns, evento.nodo.as_str(), true)),
+        Operacion::PermisoDevuelto { .. } => Some((evento.ns, evento.nodo.as_str(), false)),
+        _ => None,
+    });
+    exclusion_mutua("los ecommerce", segun_ecommerces.collect(), violaciones);
+}
+
+/// Recorre los cambios de titular del permiso `(ns, ecommerce, lo toma)`. Con el mismo ns
+/// primero se devuelve, el que lo devuelve lo registra antes de que se lo den a otro
+fn exclusion_mutua(
+    vista: &str,
+    mut cambios: Vec<(u64, &str, bool)>,
+    violaciones: &mut Vec<String>,
+) {
+    cambios.sort_by_key(|(ns, _, toma)| (*ns, *toma));
+    let mut titular: Option<(&str, u64)> = None;
+    for (ns, ecommerce, toma) in cambios {
+        match (titular, toma) {
+            (None, true) => titular = Some((ecommerce, ns)),
+            (Some((actual, desde)), true) => {
+                violaciones.push(format!(
+                    "Según {}, {} tiene el permiso desde {} ns mientras lo tiene {} desde {} ns",
+                    vista, ecommerce, ns, actual, desde
+                ));
+                titular = Some((ecommerce, ns));
+            }
+            (Some((actual, _)), false) if actual == ecommerce => titular = None,
+            (_, false) => violaciones.push(format!(
+                "Según {}, {} devuelve en {} ns un permiso que no tiene",
+                vista, ecommerce, ns
+            )),
+        }
+    }
+}
+
+fn verificar_cursores(eventos: &[&Evento], violaciones: &mut Vec<String>) {
+    let mut por_cursor: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for evento in eventos {
+        if let Operacion::Despacho { cursor, orden } = &evento.operacion {
+            por_cursor
+                .entry(*cursor)
+                .or_default()
+                .push(format!("{} por {}", orden, evento.nodo));
+        }
+    }
+    for (cursor, despachos) in por_cursor {
+        if despachos.len() > 1 {
+            violaciones.push(format!(
+                "El cursor {} se despachó {} veces: {}",
+                cursor,
+                despachos.len(),
+                despachos.join(", ")
+            ));
+        }
+    }
+}
+
+/// Lo que el verificador sabe de un producto de un local, con signo para ver si queda negativo
+#[derive(Default)]
+struct ProductoVerificado {
+    stock: i64,
+    /// Unidades de las ordenes en progreso y de las transferencias reservadas, más los
+    /// bloqueados con los que arrancó el local
+    en_progreso: i64,
+}
+
+/// Producto y unidades que bloquea una orden o una transferencia
+type Bloqueo = (usize, i64);
+
+/// Modelo del local que arma el verificador solo con las operaciones, de lo que registra el
+/// local después de su stock inicial no toma nada
+struct LocalVerificado {
+    productos: HashMap<usize, ProductoVerificado>,
+    /// Lo que bloquea cada orden en progreso, más de una vez si se aceptó un paquete duplicado
+    ordenes_en_progreso: HashMap<String, Vec<Bloqueo>>,
+    transferencias_reservadas: HashMap<IdTransferencia, Bloqueo>,
+}
+
+fn verificar_stock(eventos: &[&Evento], violaciones: &mut Vec<String>) {
+    let mut locales: HashMap<&str, LocalVerificado> = HashMap::new();
+    let mut sin_inicio = HashSet::new();
+    for evento in eventos {
+        let nodo = evento.nodo.as_str();
+        match &evento.operacion {
+            Operacion::Inicio {
+                productos,
+                ordenes,
+                transferencias,
+            } => {
+                let productos = productos
+                    .iter()
+                    .map(|producto| {
+                        let verificado = ProductoVerificado {
+                            stock: producto.stock as i64,
+                            en_progreso: producto.bloqueados as i64,
+                        };
+                        (producto.id_producto, verificado)
+                    })
+                    .collect();
+                // un local que retoma después de caerse sigue con sus ordenes y transferencias
+                let mut ordenes_en_progreso: HashMap<String, Vec<Bloqueo>> = HashMap::new();
+                for orden in ordenes {
+                    ordenes_en_progreso
+                        .entry(orden.orden.clone())
+                        .or_default()
+                        .push((orden.id_producto, orden.cantidad as i64));
+                }
+                let transferencias_reservadas = transferencias
+                    .iter()
+                    .map(|transferencia| {
+                        let bloqueo = (transferencia.id_producto, transferencia.cantidad as i64);
+                        (transferencia.id, bloqueo)
+                    })
+                    .collect();
+                locales.insert(
+                    nodo,
+                    LocalVerificado {
+                        productos,
+                        ordenes_en_progreso,
+                        transferencias_reservadas,
+                    },
+                );
+            }
+            Operacion::Stock {
+                tipo,
+                orden,
+                transferencia,
+                cantidad,
+                producto,
+            } => {
+                let Some(local) = locales.get_mut(nodo) else {
+                    // sin el stock inicial no se puede verificar el resto del local
+                    if sin_inicio.insert(nodo) {
+                        violaciones.push(format!(
+                            "El historial de {} no empieza con su stock inicial",
+                            nodo
+                        ));
+                    }
+                    continue;
+                };
+                let cantidad = *cantidad as i64;
+                let bloqueo = (producto.id_producto, cantidad);
+                let orden = orden.as_deref();
+                let (stock, bloqueo) = match tipo {
+                    OperacionStock::Aceptada | OperacionStock::Cancelada => (
+                        0,
+                        actualizar_orden(nodo, local, *tipo, orden, bloqueo, violaciones),
+                    ),
+                    OperacionStock::Entregada => (
+                        -cantidad,
+                        actualizar_orden(nodo, local, *tipo, orden, bloqueo, violaciones),
+                    ),
+                    OperacionStock::ReservaTransferencia
+                    | OperacionStock::LiberacionTransferencia => (
+                        0,
+                        actualizar_transferencia(
+                            nodo,
+                            local,
+                            *tipo,
+                            *transferencia,
+                            bloqueo,
+                            violaciones,
+                        ),
+                    ),
+                    OperacionStock::EnvioTransferencia => (
+                        -cantidad,
+                        actualizar_transferencia(
+                            nodo,
+                            local,
+                            *tipo,
+                            *transferencia,
+                            bloqueo,
+                            violaciones,
+                        ),
+                    ),
+                    OperacionStock::Venta => (-cantidad, None),
+                    OperacionStock::Reposicion => (cantidad, None),
+                };
+                verificar_producto(nodo, local, *tipo, stock, bloqueo, producto, violaciones);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Agrega o saca la orden de las en progreso, devuelve el producto y cuanto cambian sus bloqueados
+fn actualizar_orden(
+    nodo: &str,
+    local: &mut LocalVerificado,
+    tipo: OperacionStock,
+    orden: Option<&str>,
+    bloqueo: Bloqueo,
+    violaciones: &mut Vec<String>,
+) -> Option<Bloqueo> {
+    let Some(orden) = orden else {
+        violaciones.push(format!("{} registra {:?} sin id de orden", nodo, tipo));
+        return None;
+    };
+    if tipo == OperacionStock::Aceptada {
+        local
+            .ordenes_en_progreso
+            .entry(orden.to_string())
+            .or_default()
+            .push(bloqueo);
+        return Some(bloqueo);
+    }
+    let liberado = local
+        .ordenes_en_progreso
+        .get_mut(orden)
+        .and_then(|bloqueos| bloqueos.pop());
+    if local
+        .ordenes_en_progreso
+        .get(orden)
+        .is_some_and(Vec::is_empty)
+    {
+        local.ordenes_en_progreso.remove(orden);
+    }
+    let Some((id_producto, cantidad)) = liberado else {
+        violaciones.push(format!(
+            "{} finaliza la orden {} que no tiene en progreso",
+            nodo, orden
+        ));
+        return None;
+    };
+    Some((id_producto, -cantidad))
+}
+
+/// Reserva o cierra la transferencia, devuelve el producto y cuanto cambian sus bloqueados
+fn actualizar_transferencia(
+    nodo: &str,
+    local: &mut LocalVerificado,
+    tipo: OperacionStock,
+    transferencia: Option<IdTransferencia>,
+    bloqueo: Bloqueo,
+    violaciones: &mut Vec<String>,
+) -> Option<Bloqueo> {
+    let Some(id) = transferencia else {
+        violaciones.push(format!(
+            "{} registra {:?} sin id de transferencia",
+            nodo, tipo
+        ));
+        return None;
+    };
+    if tipo == OperacionStock::ReservaTransferencia {
+        if local.transferencias_reservadas.contains_key(&id) {
+            violaciones.push(format!(
+                "{} reserva otra vez la transferencia {}-{}",
+                nodo, id.id_local, id.numero
+            ));
+            return None;
+        }
+        local.transferencias_reservadas.insert(id, bloqueo);
+        return Some(bloqueo);
+    }
+    let Some((id_producto, cantidad)) = local.transferencias_reservadas.remove(&id) else {
+        violaciones.push(format!(
+            "{} cierra la transferencia {}-{} que no tiene reservada",
+            nodo, id.id_local, id.numero
+        ));
+        return None;
+    };
+    Some((id_producto, -cantidad))
+}
+
+/// Aplica al modelo el cambio de stock y de bloqueados de la operación y lo compara con como
+/// quedó el producto según el local
+fn verificar_producto(
+    nodo: &str,
+    local: &mut LocalVerificado,
+    tipo: OperacionStock,
+    stock: i64,
+    bloqueo: Option<Bloqueo>,
+    producto: &ProductoHistorial,
+    violaciones: &mut Vec<String>,
+) {
+    if let Some((id_producto, bloqueados)) = bloqueo {
+        local.productos.entry(id_producto).or_default().en_progreso += bloqueados;
+    }
+    // un producto que no tenía solo puede aparecer por una reposición
+    let verificado = local.productos.entry(producto.id_producto).or_default();
+    verificado.stock += stock;
+    if verificado.stock < 0 {
+        violaciones.push(format!(
+            "El stock del producto {} de {} queda en {} después de {:?}",
+            producto.id_producto, nodo, verificado.stock, tipo
+        ));
+    } else if verificado.en_progreso > verificado.stock {
+        violaciones.push(format!(
+            "El producto {} de {} queda con {} bloqueados y {} de stock después de {:?}",
+            producto.id_producto, nodo, verificado.en_progreso, verificado.stock, tipo
+        ));
+    }
+    if verificado.stock != producto.stock as i64 {
+        violaciones.push(format!(
+            "{} registra {} de stock del producto {} después de {:?} y se esperaba {}",
+            nodo, producto.stock, producto.id_producto, tipo, verificado.stock
+        ));
+    }
+    if verificado.en_progreso != producto.bloqueados as i64 {
+        violaciones.push(format!(
+            "{} tiene {} bloqueados del producto {} y {} en ordenes en progreso y transferencias",
+            nodo, producto.bloqueados, producto.id_producto, verificado.en_progreso
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::historial::OrdenEnProgreso;
+    use crate::common::transferencia::Transferencia;
+
+    fn evento(ns: u64, nodo: &str, operacion: Operacion) -> Evento {
+        Evento {
+            ns,
+            nodo: nodo.to_string(),
+            operacion,
+        }
+    }
+
+    fn stock(
+        tipo: OperacionStock,
+        orden: &str,
+        cantidad: usize,
+        stock: usize,
+        bloqueados: usize,
+    ) -> Operacion {
+        Operacion::Stock {
+            tipo,
+            orden: Some(orden.to_string()).filter(|orden| !orden.is_empty()),
+            transferencia: None,
+            cantidad,
+            producto: ProductoHistorial {
+                id_producto: 1,
+                stock,
+                bloqueados,
+            },
+        }
+    }
+
+    fn transferencia(
+        tipo: OperacionStock,
+        numero: u64,
+        cantidad: usize,
+        stock: usize,
+        bloqueados: usize,
+    ) -> Operacion {
+        Operacion::Stock {
+            tipo,
+            orden: None,
+            transferencia: Some(IdTransferencia {
+                id_local: 2,
+                numero,
+            }),
+            cantidad,
+            producto: ProductoHistorial {
+                id_producto: 1,
+                stock,
+                bloqueados,
+            },
+        }
+    }
+
+    fn inicio(stock: usize) -> Operacion {
+        Operacion::Inicio {
+            productos: vec![ProductoHistorial {
+                id_producto: 1,
+                stock,
+                bloqueados: 0,
+            }],
+            ordenes: vec![],
+            transferencias: vec![],
+        }
+    }
+
+    #[test]
+    fn test_historial_correcto_no_tiene_violaciones() {
+        let eventos = vec![
+            evento(0, "local-1", inicio(10)),
+            evento(1, "ecommerce-2", Operacion::PermisoRecibido { cursor: 0 }),
+            evento(
+                2,
+                "ecommerce-2",
+                Operacion::Despacho {
+                    cursor: 0,
+                    orden: "2-0".to_string(),
+                },
+            ),
+            evento(
+                3,
+                "ecommerce-2",
+                Operacion::PermisoDevuelto {
+                    cursor: 0,
+                    avanzo: true,
+                },
+            ),
+            evento(3, "ecommerce-3", Operacion::PermisoRecibido { cursor: 1 }),
+            evento(
+                4,
+                "local-1",
+                stock(OperacionStock::Aceptada, "2-0", 3, 10, 3),
+            ),
+            evento(5, "local-1", stock(OperacionStock::Venta, "", 7, 3, 3)),
+            evento(
+                6,
+                "local-1",
+                stock(OperacionStock::Entregada, "2-0", 3, 0, 0),
+            ),
+            evento(
+                7,
+                "ecommerce-3",
+                Operacion::PermisoDevuelto {
+                    cursor: 1,
+                    avanzo: false,
+                },
+            ),
+        ];
+
+        assert_eq!(verificar(&eventos), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_detecta_permisos_superpuestos_y_cursores_repetidos() {
+        let despacho = |cursor| Operacion::Despacho {
+            cursor,
+            orden: format!("x-{}", cursor),
+        };
+        let eventos = vec![
+            evento(1, "ecommerce-2", Operacion::PermisoRecibido { cursor: 0 }),
+            evento(2, "ecommerce-2", despacho(0)),
+            evento(3, "ecommerce-3", Operacion::PermisoRecibido { cursor: 0 }),
+            evento(4, "ecommerce-3", despacho(0)),
+            evento(
+                5,
+                "ecommerce-2",
+                Operacion::PermisoDevuelto {
+                    cursor: 0,
+                    avanzo: true,
+                },
+            ),
+        ];
+
+        let violaciones = verificar(&eventos);
+        assert_eq!(violaciones.len(), 3, "{:?}", violaciones);
+        assert!(violaciones[0]
+            .contains("ecommerce-3 tiene el permiso desde 3 ns mientras lo tiene ecommerce-2"));
+        assert!(violaciones[1].contains("ecommerce-2 devuelve en 5 ns un permiso que no tiene"));
+        assert!(violaciones[2].contains("El cursor 0 se despachó 2 veces"));
+    }
+
+    #[test]
+    fn test_detecta_stock_negativo_y_bloqueados_sin_ordenes() {
+        let eventos = vec![
+            evento(0, "local-1", inicio(2)),
+            evento(
+                1,
+                "local-1",
+                stock(OperacionStock::Aceptada, "2-0", 2, 2, 2),
+            ),
+            evento(2, "local-1", stock(OperacionStock::Venta, "", 1, 1, 2)),
+            evento(
+                3,
+                "local-1",
+                stock(OperacionStock::Cancelada, "2-7", 2, 1, 1),
+            ),
+        ];
+
+        let violaciones = verificar(&eventos);
+        assert_eq!(violaciones.len(), 4, "{:?}", violaciones);
+        assert!(violaciones[0].contains("queda con 2 bloqueados y 1 de stock después de Venta"));
+        assert!(violaciones[1].contains("finaliza la orden 2-7 que no tiene en progreso"));
+        assert!(violaciones[2].contains("queda con 2 bloqueados y 1 de stock después de Cancelada"));
+        assert!(violaciones[3].contains("tiene 1 bloqueados del producto 1 y 2 en ordenes"));
+    }
+
+    #[test]
+    fn test_detecta_bloqueados_de_una_transferencia_reservada_dos_veces() {
+        let eventos = vec![
+            evento(0, "local-1", inicio(10)),
+            evento(
+                1,
+                "local-1",
+                transferencia(OperacionStock::ReservaTransferencia, 1, 3, 10, 3),
+            ),
+            evento(
+                2,
+                "local-1",
+                transferencia(OperacionStock::ReservaTransferencia, 1, 3, 10, 6),
+            ),
+            evento(
+                3,
+                "local-1",
+                transferencia(OperacionStock::EnvioTransferencia, 1, 3, 7, 3),
+            ),
+        ];
+
+        let violaciones = verificar(&eventos);
+        assert_eq!(violaciones.len(), 3, "{:?}", violaciones);
+        assert!(violaciones[0].contains("reserva otra vez la transferencia 2-1"));
+        assert!(violaciones[1].contains("tiene 6 bloqueados del producto 1 y 3 en ordenes"));
+        assert!(violaciones[2].contains("tiene 3 bloqueados del producto 1 y 0 en ordenes"));
+    }
+
+    #[test]
+    fn test_un_local_que_retoma_sigue_con_sus_ordenes_y_transferencias() {
+        let retoma = Operacion::Inicio {
+            productos: vec![ProductoHistorial {
+                id_producto: 1,
+                stock: 10,
+                bloqueados: 5,
+            }],
+            ordenes: vec![OrdenEnProgreso {
+                orden: "2-0".to_string(),
+                id_producto: 1,
+                cantidad: 2,
+            }],
+            transferencias: vec![Transferencia::new(
+                IdTransferencia {
+                    id_local: 2,
+                    numero: 1,
+                },
+                1,
+                3,
+            )],
+        };
+        let eventos = vec![
+            evento(0, "local-1", inicio(10)),
+            evento(
+                1,
+                "local-1",
+                stock(OperacionStock::Aceptada, "2-0", 2, 10, 2),
+            ),
+            evento(
+                2,
+                "local-1",
+                transferencia(OperacionStock::ReservaTransferencia, 1, 3, 10, 5),
+            ),
+            evento(3, "local-1", retoma),
+            evento(
+                4,
+                "local-1",
+                stock(OperacionStock::Entregada, "2-0", 2, 8, 3),
+            ),
+            evento(
+                5,
+                "local-1",
+                transferencia(OperacionStock::EnvioTransferencia, 1, 3, 5, 0),
+            ),
+        ];
+
+        assert_eq!(verificar(&eventos), Vec::<String>::new());
+    }
+}
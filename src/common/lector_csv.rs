@@ -1,7 +1,9 @@
-use std::error::Error;
 use std::fs::File;
+use std::str::FromStr;
 
-use csv::{Reader, ReaderBuilder, StringRecord};
+use csv::{Position, Reader, ReaderBuilder, StringRecord};
+
+use crate::common::error_csv::ErrorCsv;
 
 pub fn open_csv(file_path: &str) -> Result<Reader<File>, std::io::Error> {
     let file = File::open(file_path)?;
@@ -9,43 +11,110 @@ pub fn open_csv(file_path: &str) -> Result<Reader<File>, std::io::Error> {
     Ok(reader)
 }
 
-pub fn leer_linea_csv_desde<F>(
+pub fn leer_linea_csv<F>(
     reader: &mut Reader<File>,
-    constructor: fn(StringRecord) -> Result<F, Box<dyn Error>>,
-    cursor: i32,
-) -> Result<F, Box<dyn Error>> {
-    let mut size = 0;
-    for (i, result) in reader.records().into_iter().enumerate() {
-        size += 1;
-        if cursor as usize != i {
-            continue;
-        }
-        let record = result?;
-        let stock_producto = constructor(record)?;
-        return Ok(stock_producto);
-    }
-    println!("sali del for con {}", size);
-    // deberia romper si llega aca y tirar No se encontraron más registros en el csv
-    let result = reader
-        .records()
-        .next()
-        .ok_or("No se encontraron más registros en el csv")?;
+    constructor: fn(StringRecord) -> Result<F, ErrorCsv>,
+) -> Result<F, ErrorCsv> {
+    let result = reader.records().next().ok_or(ErrorCsv::FinDeArchivo)?;
     let record = result?;
-    let stock_producto = constructor(record)?;
-    return Ok(stock_producto);
+    constructor(record)
 }
 
-pub fn leer_linea_csv<F>(
-    reader: &mut Reader<File>,
-    constructor: fn(StringRecord) -> Result<F, Box<dyn Error>>,
-) -> Result<F, Box<dyn Error>> {
-    let result = reader
-        .records()
-        .next()
-        .ok_or("No se encontraron más registros en el csv")?;
-    let record = result?;
-    let stock_producto = constructor(record)?;
-    Ok(stock_producto)
+/// Parsea la columna `indice` (desde 0) del registro, `nombre` se usa para informar el error
+pub fn leer_columna<T: FromStr>(
+    record: &StringRecord,
+    indice: usize,
+    nombre: &'static str,
+) -> Result<T, ErrorCsv> {
+    leer_columna_opcional(record, indice, nombre)?.ok_or(ErrorCsv::FaltaColumna {
+        linea: linea(record),
+        columna: indice + 1,
+        nombre,
+    })
+}
+
+/// Como `leer_columna`, pero si el registro no tiene la columna devuelve None
+pub fn leer_columna_opcional<T: FromStr>(
+    record: &StringRecord,
+    indice: usize,
+    nombre: &'static str,
+) -> Result<Option<T>, ErrorCsv> {
+    let Some(valor) = record.get(indice) else {
+        return Ok(None);
+    };
+    let valor = valor.trim();
+    valor
+        .parse()
+        .map(Some)
+        .map_err(|_| ErrorCsv::ValorInvalido {
+            linea: linea(record),
+            columna: indice + 1,
+            nombre,
+            valor: valor.to_string(),
+        })
+}
+
+/// Linea del archivo de la que se leyó el registro, 0 si no se leyó de un archivo
+pub fn linea(record: &StringRecord) -> u64 {
+    record.position().map_or(0, Position::line)
+}
+
+/// Lector de csv que guarda la posición de cada registro recorrido, para ir a
+/// cualquier cursor ya visto sin volver a leer el archivo desde el principio
+pub struct LectorCsvIndexado {
+    reader: Reader<File>,
+    /// Posición en el archivo de cada registro, el indice es el cursor
+    posiciones: Vec<Position>,
+    /// Posición siguiente al ultimo registro indexado
+    fin_indexado: Position,
+}
+
+impl LectorCsvIndexado {
+    pub fn new(file_path: &str) -> Result<Self, ErrorCsv> {
+        let mut reader = open_csv(file_path)?;
+        reader.headers()?;
+        let fin_indexado = reader.position().clone();
+        Ok(LectorCsvIndexado {
+            reader,
+            posiciones: vec![],
+            fin_indexado,
+        })
+    }
+
+    /// Lee el registro numero `cursor`, contando desde el primero despues del encabezado
+    pub fn leer_en<F>(
+        &mut self,
+        cursor: usize,
+        constructor: fn(StringRecord) -> Result<F, ErrorCsv>,
+    ) -> Result<F, ErrorCsv> {
+        let mut record = StringRecord::new();
+        if let Some(posicion) = self.posiciones.get(cursor) {
+            self.reader.seek(posicion.clone())?;
+            if !self.reader.read_record(&mut record)? {
+                return Err(ErrorCsv::FinDeArchivo);
+            }
+            return constructor(record);
+        }
+
+        // Los registros entre el ultimo indexado y el cursor se recorren una sola vez
+        self.reader.seek(self.fin_indexado.clone())?;
+        while self.posiciones.len() <= cursor {
+            let posicion = self.reader.position().clone();
+            let leido = self.reader.read_record(&mut record);
+            if let Ok(false) = leido {
+                return Err(ErrorCsv::FinDeArchivo);
+            }
+            // un registro mal formado igual ocupa su cursor
+            self.posiciones.push(posicion);
+            self.fin_indexado = self.reader.position().clone();
+            if let Err(e) = leido {
+                if self.posiciones.len() > cursor {
+                    return Err(e.into());
+                }
+            }
+        }
+        constructor(record)
+    }
 }
 
 #[cfg(test)]
@@ -108,28 +177,60 @@ mod tests {
         std::fs::remove_file(test_file).unwrap();
 
         assert!(primera_orden.is_ok());
-        assert!(segunda_orden.is_err());
+        assert!(matches!(segunda_orden, Err(ErrorCsv::FinDeArchivo)));
     }
 
     #[test]
-    fn test_leer_linea_csv_desde_devuelve_error_si_no_tiene_mas_ordenes() {
-        // Assuming a CSV file with two columns: "Value1,Value2"
-        let test_file = "test_leer_linea_csv_desde_devuelve_error_si_no_tiene_mas_ordenes.csv";
-        let mut wtr = csv::Writer::from_path(test_file).unwrap();
+    fn test_lector_indexado_lee_cualquier_cursor_ya_visto() {
+        let test_file = "test_lector_indexado_lee_cualquier_cursor.csv";
+        let mut wtr = crear_archivo(test_file);
         wtr.write_record(["id_producto", "cantidad", "latitud", "longitud"])
             .unwrap();
-        wtr.write_record(["1", "5", "-32", "47"]).unwrap();
+        for id in 1..=4 {
+            wtr.write_record([&id.to_string(), "5", "1", "2"]).unwrap();
+        }
         wtr.flush().unwrap();
 
-        let mut reader = open_csv(test_file).unwrap();
-        let primera_orden = leer_linea_csv_desde(&mut reader, Orden::from_record, 0);
-        let segunda_orden = leer_linea_csv_desde(&mut reader, Orden::from_record, 1);
+        let mut lector = LectorCsvIndexado::new(test_file).unwrap();
+        let tercera = lector.leer_en(2, Orden::from_record).unwrap();
+        let primera = lector.leer_en(0, Orden::from_record).unwrap();
+        let cuarta = lector.leer_en(3, Orden::from_record).unwrap();
+        let fin = lector.leer_en(4, Orden::from_record);
 
-        // Clean up the test file
         std::fs::remove_file(test_file).unwrap();
 
-        assert!(primera_orden.is_ok());
-        assert!(segunda_orden.is_err());
+        assert_eq!(tercera.id_producto, 3);
+        assert_eq!(primera.id_producto, 1);
+        assert_eq!(cuarta.id_producto, 4);
+        assert!(matches!(fin, Err(ErrorCsv::FinDeArchivo)));
+    }
+
+    #[test]
+    fn test_registro_invalido_informa_linea_y_columna() {
+        let test_file = "test_registro_invalido_informa_linea_y_columna.csv";
+        std::fs::write(
+            test_file,
+            "id_producto,cantidad,latitud,longitud\n1,5,1,2\n2,x,1,2\n3,5,1\n",
+        )
+        .unwrap();
+
+        let mut lector = LectorCsvIndexado::new(test_file).unwrap();
+        let invalida = lector.leer_en(1, Orden::from_record);
+        let incompleta = lector.leer_en(2, Orden::from_record);
+        std::fs::remove_file(test_file).unwrap();
+
+        assert!(matches!(
+            invalida,
+            Err(ErrorCsv::ValorInvalido {
+                linea: 3,
+                columna: 2,
+                ..
+            })
+        ));
+        assert!(matches!(
+            incompleta,
+            Err(ErrorCsv::Formato { linea: 4, .. })
+        ));
     }
 
     // Leer stock
@@ -142,7 +243,7 @@ mod tests {
         wtr.flush().unwrap();
 
         let mut reader = open_csv(test_file).unwrap();
-        let result: Result<StockProducto, Box<dyn Error>> =
+        let result: Result<StockProducto, ErrorCsv> =
             leer_linea_csv(&mut reader, StockProducto::from_record);
 
         // Clean up the test file
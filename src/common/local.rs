@@ -1,23 +1,111 @@
 use crate::common::error_local::ErrorLocal;
+use crate::common::historial::{
+    Historial, Operacion, OperacionStock, OrdenEnProgreso, ProductoHistorial,
+};
 use crate::common::orden::Orden;
+use crate::common::politica_entrega::Accion;
+use crate::common::recientes::IdsRecientes;
 use crate::common::stock_producto::StockProducto;
+use crate::common::transferencia::{IdTransferencia, Transferencia};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::hash::Hash;
+use tracing::error;
 
 pub type Productos = HashMap<usize, StockProducto>;
 
+/// Cantidad de ids de transferencias terminadas que se recuerdan para reconocer duplicados
+const TRANSFERENCIAS_RECORDADAS: usize = 1024;
+/// Cantidad de ids de ordenes decididas que se recuerdan para contestar igual una confirmación repetida
+const ORDENES_RECORDADAS: usize = 1024;
+
 pub struct Local {
     pub productos_en_stock: Productos,
     pub ordenes_en_progreso: Vec<Orden>,
+    /// Transferencias pedidas a otros locales que todavía no llegaron
+    pub transferencias_entrantes: HashMap<IdTransferencia, Transferencia>,
+    /// Transferencias enviadas a otros locales, sus unidades siguen bloqueadas hasta
+    /// que el otro local confirma la recepción
+    pub transferencias_salientes: HashMap<IdTransferencia, Transferencia>,
+    /// Transferencias pedidas que ya llegaron, un envio repetido no vuelve a reponer
+    transferencias_recibidas: IdsRecientes<IdTransferencia>,
+    /// Transferencias pedidas que se dejaron de esperar, si llegan tarde se reciben igual
+    transferencias_vencidas: IdsRecientes<IdTransferencia>,
+    /// Transferencias enviadas que ya se confirmaron o liberaron, un pedido repetido se ignora
+    transferencias_terminadas: IdsRecientes<IdTransferencia>,
+    /// Ordenes que se aceptaron o rechazaron, la decisión sobre una orden no cambia
+    ordenes_aceptadas: IdsRecientes<String>,
+    ordenes_rechazadas: IdsRecientes<String>,
+    /// Si esta, cada operación sobre el stock queda registrada con como quedó el producto
+    historial: Option<Historial>,
+}
+
+/// Todo lo que necesita el local para retomar si se cae el proceso
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct EstadoLocal {
+    pub productos: Vec<StockProducto>,
+    pub ordenes_en_progreso: Vec<Orden>,
+    pub transferencias_entrantes: Vec<Transferencia>,
+    pub transferencias_salientes: Vec<Transferencia>,
+    pub transferencias_recibidas: Vec<IdTransferencia>,
+    pub transferencias_vencidas: Vec<IdTransferencia>,
+    pub transferencias_terminadas: Vec<IdTransferencia>,
+    pub ordenes_aceptadas: Vec<String>,
+    pub ordenes_rechazadas: Vec<String>,
 }
 
 impl Local {
-    /// Agrega la orden al local y bloquea la cantidad del producto especificado
-    pub fn agregar_orden(&mut self, orden: Orden) -> Result<(), ErrorLocal> {
+    /// Si hay stock disponible para la orden, sin bloquearlo
+    pub fn puede_aceptar(&self, orden: &Orden) -> Result<(), ErrorLocal> {
         let producto = self
             .productos_en_stock
-            .get_mut(&orden.id_producto)
+            .get(&orden.id_producto)
             .ok_or(ErrorLocal::NoExisteProductoEnLocal)?;
-        producto.bloquear(orden.cantidad)?;
+        if producto.disponibles() < orden.cantidad {
+            return Err(ErrorLocal::StockInsuficiente);
+        }
+        Ok(())
+    }
+
+    /// Si ya se decidió la orden con este id devuelve si se aceptó
+    pub fn decision(&self, id_orden: &str) -> Option<bool> {
+        let id = id_orden.to_string();
+        if self.ordenes_aceptadas.contiene(&id) {
+            return Some(true);
+        }
+        self.ordenes_rechazadas.contiene(&id).then_some(false)
+    }
+
+    /// Agrega la orden al local y bloquea la cantidad del producto especificado.
+    /// Se recuerda si la orden se aceptó o se rechazó
+    pub fn agregar_orden(&mut self, orden: Orden) -> Result<(), ErrorLocal> {
+        let bloqueo = self
+            .productos_en_stock
+            .get_mut(&orden.id_producto)
+            .ok_or(ErrorLocal::NoExisteProductoEnLocal)
+            .and_then(|producto| producto.bloquear(orden.cantidad));
+        if let Err(e) = bloqueo {
+            if let Some(id) = &orden.id {
+                self.ordenes_rechazadas.agregar(id.clone());
+            }
+            if let Some(historial) = &self.historial {
+                historial.registrar(Operacion::Rechazada {
+                    orden: orden.id.clone().unwrap_or_default(),
+                    motivo: e.to_string(),
+                });
+            }
+            return Err(e);
+        }
+        if let Some(id) = &orden.id {
+            self.ordenes_aceptadas.agregar(id.clone());
+        }
+        self.registrar(
+            OperacionStock::Aceptada,
+            Some(&orden),
+            None,
+            orden.id_producto,
+            orden.cantidad,
+        );
         self.ordenes_en_progreso.push(orden);
         Ok(())
     }
@@ -29,53 +117,427 @@ impl Local {
             .get_mut(&orden.id_producto)
             .ok_or(ErrorLocal::NoExisteProductoEnLocal)?;
         producto.vender(orden.cantidad)?;
+        self.registrar(
+            OperacionStock::Venta,
+            None,
+            None,
+            orden.id_producto,
+            orden.cantidad,
+        );
+        Ok(())
+    }
+
+    /// Aumenta el stock del producto, si el local no lo tenia lo agrega.
+    /// Si el stock se pasaria del maximo no se repone nada
+    pub fn reponer(&mut self, id_producto: usize, cantidad: usize) -> Result<(), ErrorLocal> {
+        self.productos_en_stock
+            .entry(id_producto)
+            .or_insert_with(|| StockProducto::new(id_producto, 0))
+            .reponer(cantidad)?;
+        self.registrar(
+            OperacionStock::Reposicion,
+            None,
+            None,
+            id_producto,
+            cantidad,
+        );
+        Ok(())
+    }
+
+    /// Registra una transferencia pedida a otro local como en transito
+    pub fn registrar_transferencia_entrante(&mut self, transferencia: Transferencia) {
+        self.transferencias_entrantes
+            .insert(transferencia.id, transferencia);
+    }
+
+    /// Llegaron las unidades de una transferencia pedida, aumenta el stock del producto.
+    /// Un envio repetido devuelve `TransferenciaRepetida` sin reponer. Uno que llega despues
+    /// de dejar de esperarlo se recibe igual, otro desconocido no. Si no se puede reponer
+    /// la transferencia deja de estar en transito
+    pub fn recibir_transferencia(
+        &mut self,
+        recibida: &Transferencia,
+    ) -> Result<Transferencia, ErrorLocal> {
+        let id = &recibida.id;
+        if self.transferencias_recibidas.contiene(id) {
+            return Err(ErrorLocal::TransferenciaRepetida);
+        }
+        let transferencia = match self.transferencias_entrantes.remove(id) {
+            Some(transferencia) => transferencia,
+            None if self.transferencias_vencidas.quitar(id) => recibida.clone(),
+            None => return Err(ErrorLocal::TransferenciaDesconocida),
+        };
+        self.reponer(transferencia.id_producto, transferencia.cantidad)?;
+        self.transferencias_recibidas.agregar(*id);
+        Ok(transferencia)
+    }
+
+    /// El otro local rechazó la transferencia, deja de estar en transito
+    pub fn cancelar_transferencia_entrante(
+        &mut self,
+        id: &IdTransferencia,
+    ) -> Result<Transferencia, ErrorLocal> {
+        self.transferencias_vencidas.quitar(id);
+        self.transferencias_entrantes
+            .remove(id)
+            .ok_or(ErrorLocal::TransferenciaDesconocida)
+    }
+
+    /// Se deja de esperar la transferencia pedida, sus unidades ya no cuentan como entrantes
+    pub fn vencer_transferencia_entrante(
+        &mut self,
+        id: &IdTransferencia,
+    ) -> Result<Transferencia, ErrorLocal> {
+        let transferencia = self
+            .transferencias_entrantes
+            .remove(id)
+            .ok_or(ErrorLocal::TransferenciaDesconocida)?;
+        self.transferencias_vencidas.agregar(*id);
+        Ok(transferencia)
+    }
+
+    /// Bloquea las unidades que pidió otro local y registra la transferencia como en transito.
+    /// Un pedido repetido de una transferencia en transito no vuelve a bloquear, el de una
+    /// que ya terminó devuelve `TransferenciaRepetida`
+    pub fn reservar_transferencia_saliente(
+        &mut self,
+        transferencia: Transferencia,
+    ) -> Result<(), ErrorLocal> {
+        if self
+            .transferencias_salientes
+            .contains_key(&transferencia.id)
+        {
+            return Ok(());
+        }
+        if self.transferencias_terminadas.contiene(&transferencia.id) {
+            return Err(ErrorLocal::TransferenciaRepetida);
+        }
+        let producto = self
+            .productos_en_stock
+            .get_mut(&transferencia.id_producto)
+            .ok_or(ErrorLocal::NoExisteProductoEnLocal)?;
+        producto.bloquear(transferencia.cantidad)?;
+        self.registrar(
+            OperacionStock::ReservaTransferencia,
+            None,
+            Some(transferencia.id),
+            transferencia.id_producto,
+            transferencia.cantidad,
+        );
+        self.transferencias_salientes
+            .insert(transferencia.id, transferencia);
         Ok(())
     }
 
+    /// El otro local recibió la transferencia, se descuentan stock y bloqueados.
+    /// Si falla la transferencia sigue en transito
+    pub fn confirmar_transferencia_saliente(
+        &mut self,
+        id: &IdTransferencia,
+    ) -> Result<Transferencia, ErrorLocal> {
+        let transferencia = self
+            .transferencias_salientes
+            .get(id)
+            .ok_or(ErrorLocal::TransferenciaDesconocida)?;
+        let producto = self
+            .productos_en_stock
+            .get_mut(&transferencia.id_producto)
+            .ok_or(ErrorLocal::NoExisteProductoEnLocal)?;
+        producto.entregar(transferencia.cantidad)?;
+        let (id_producto, cantidad) = (transferencia.id_producto, transferencia.cantidad);
+        self.registrar(
+            OperacionStock::EnvioTransferencia,
+            None,
+            Some(*id),
+            id_producto,
+            cantidad,
+        );
+        self.transferencias_terminadas.agregar(*id);
+        self.transferencias_salientes
+            .remove(id)
+            .ok_or(ErrorLocal::TransferenciaDesconocida)
+    }
+
+    /// La transferencia enviada no se completó, se liberan sus unidades bloqueadas
+    pub fn liberar_transferencia_saliente(
+        &mut self,
+        id: &IdTransferencia,
+    ) -> Result<Transferencia, ErrorLocal> {
+        let transferencia = self
+            .transferencias_salientes
+            .get(id)
+            .ok_or(ErrorLocal::TransferenciaDesconocida)?;
+        let producto = self
+            .productos_en_stock
+            .get_mut(&transferencia.id_producto)
+            .ok_or(ErrorLocal::NoExisteProductoEnLocal)?;
+        producto.cancelar(transferencia.cantidad)?;
+        let (id_producto, cantidad) = (transferencia.id_producto, transferencia.cantidad);
+        self.registrar(
+            OperacionStock::LiberacionTransferencia,
+            None,
+            Some(*id),
+            id_producto,
+            cantidad,
+        );
+        self.transferencias_terminadas.agregar(*id);
+        self.transferencias_salientes
+            .remove(id)
+            .ok_or(ErrorLocal::TransferenciaDesconocida)
+    }
+
+    /// Unidades del producto pedidas a otros locales que todavía no llegaron
+    pub fn unidades_entrantes(&self, id_producto: usize) -> usize {
+        self.transferencias_entrantes
+            .values()
+            .filter(|transferencia| transferencia.id_producto == id_producto)
+            .map(|transferencia| transferencia.cantidad)
+            .sum()
+    }
+
     pub fn new(productos: Productos) -> Local {
         Local {
             productos_en_stock: productos,
             ordenes_en_progreso: vec![],
+            transferencias_entrantes: HashMap::new(),
+            transferencias_salientes: HashMap::new(),
+            transferencias_recibidas: IdsRecientes::new(TRANSFERENCIAS_RECORDADAS),
+            transferencias_vencidas: IdsRecientes::new(TRANSFERENCIAS_RECORDADAS),
+            transferencias_terminadas: IdsRecientes::new(TRANSFERENCIAS_RECORDADAS),
+            ordenes_aceptadas: IdsRecientes::new(ORDENES_RECORDADAS),
+            ordenes_rechazadas: IdsRecientes::new(ORDENES_RECORDADAS),
+            historial: None,
         }
     }
 
-    /// Elige orden de forma aleatoria, reduce stock y bloqueados del producto
-    /// segun el id_producto y la cantidad indicada en la orden
-    pub fn entregar_orden(&mut self, rng: impl Fn() -> usize) -> Result<(), ErrorLocal> {
-        let indice_random = rng();
-        if self.ordenes_en_progreso.get(indice_random).is_some() {
-            let orden = self.ordenes_en_progreso.remove(indice_random);
-            let producto = self
-                .productos_en_stock
-                .get_mut(&orden.id_producto)
-                .ok_or(ErrorLocal::NoExisteProductoEnLocal)?;
-            // Transaccionalidad: si falla este método entonces removí la orden pero no desconté los productos de la orden.
-            producto.entregar(orden.cantidad)?
-        } else {
-            eprintln!("[Job - Error] numero random esta por encima del largo del array");
+    /// Local que retoma con el estado guardado
+    pub fn desde_estado(estado: EstadoLocal) -> Local {
+        let por_id = |transferencias: Vec<Transferencia>| {
+            transferencias
+                .into_iter()
+                .map(|transferencia| (transferencia.id, transferencia))
+                .collect()
+        };
+        Local {
+            productos_en_stock: estado
+                .productos
+                .into_iter()
+                .map(|producto| (producto.id_producto, producto))
+                .collect(),
+            ordenes_en_progreso: estado.ordenes_en_progreso,
+            transferencias_entrantes: por_id(estado.transferencias_entrantes),
+            transferencias_salientes: por_id(estado.transferencias_salientes),
+            transferencias_recibidas: recientes(
+                TRANSFERENCIAS_RECORDADAS,
+                estado.transferencias_recibidas,
+            ),
+            transferencias_vencidas: recientes(
+                TRANSFERENCIAS_RECORDADAS,
+                estado.transferencias_vencidas,
+            ),
+            transferencias_terminadas: recientes(
+                TRANSFERENCIAS_RECORDADAS,
+                estado.transferencias_terminadas,
+            ),
+            ordenes_aceptadas: recientes(ORDENES_RECORDADAS, estado.ordenes_aceptadas),
+            ordenes_rechazadas: recientes(ORDENES_RECORDADAS, estado.ordenes_rechazadas),
+            historial: None,
         }
+    }
 
-        Ok(())
+    /// Estado del local para guardarlo, ordenado para que el mismo estado se guarde igual
+    pub fn estado(&self) -> EstadoLocal {
+        let mut productos: Vec<StockProducto> = self.productos_en_stock.values().cloned().collect();
+        productos.sort_by_key(|producto| producto.id_producto);
+        let ordenadas = |transferencias: &HashMap<IdTransferencia, Transferencia>| {
+            let mut transferencias: Vec<Transferencia> = transferencias.values().cloned().collect();
+            transferencias.sort_by_key(|transferencia| transferencia.id);
+            transferencias
+        };
+        EstadoLocal {
+            productos,
+            ordenes_en_progreso: self.ordenes_en_progreso.clone(),
+            transferencias_entrantes: ordenadas(&self.transferencias_entrantes),
+            transferencias_salientes: ordenadas(&self.transferencias_salientes),
+            transferencias_recibidas: self.transferencias_recibidas.iter().copied().collect(),
+            transferencias_vencidas: self.transferencias_vencidas.iter().copied().collect(),
+            transferencias_terminadas: self.transferencias_terminadas.iter().copied().collect(),
+            ordenes_aceptadas: self.ordenes_aceptadas.iter().cloned().collect(),
+            ordenes_rechazadas: self.ordenes_rechazadas.iter().cloned().collect(),
+        }
     }
 
-    /// Elige orden de forma aleatoria, reduce stock y bloqueados del producto
-    /// segun el id_producto y la cantidad indicada en la orden
-    pub fn cancelar_orden(&mut self, rng: impl Fn() -> usize) -> Result<(), ErrorLocal> {
-        let indice_random = rng();
-        if self.ordenes_en_progreso.get(indice_random).is_some() {
-            let orden = self.ordenes_en_progreso.remove(indice_random);
-            let producto = self
-                .productos_en_stock
-                .get_mut(&orden.id_producto)
-                .ok_or(ErrorLocal::NoExisteProductoEnLocal)?;
-            // Transaccionalidad: si falla este método entonces removí la orden pero no libere los productos bloqueados.
-            producto.cancelar(orden.cantidad)?
-        } else {
-            eprintln!("[Job - Error] numero random esta por encima del largo del array");
+    /// Registra el stock con el que arranca y desde ahí cada operación sobre el stock. Si
+    /// retoma un estado guardado también registra sus ordenes en progreso y las
+    /// transferencias que tiene reservadas
+    pub fn con_historial(mut self, historial: Historial) -> Local {
+        let mut productos: Vec<_> = self.productos_en_stock.values().map(Into::into).collect();
+        productos.sort_by_key(|producto: &ProductoHistorial| producto.id_producto);
+        let ordenes = self
+            .ordenes_en_progreso
+            .iter()
+            .map(|orden| OrdenEnProgreso {
+                orden: orden.id.clone().unwrap_or_default(),
+                id_producto: orden.id_producto,
+                cantidad: orden.cantidad,
+            })
+            .collect();
+        let transferencias = self.estado().transferencias_salientes;
+        historial.registrar(Operacion::Inicio {
+            productos,
+            ordenes,
+            transferencias,
+        });
+        self.historial = Some(historial);
+        self
+    }
+
+    fn registrar(
+        &self,
+        tipo: OperacionStock,
+        orden: Option<&Orden>,
+        transferencia: Option<IdTransferencia>,
+        id_producto: usize,
+        cantidad: usize,
+    ) {
+        let (Some(historial), Some(producto)) =
+            (&self.historial, self.productos_en_stock.get(&id_producto))
+        else {
+            return;
+        };
+        historial.registrar(Operacion::Stock {
+            tipo,
+            orden: orden.and_then(|orden| orden.id.clone()),
+            transferencia,
+            cantidad,
+            producto: producto.into(),
+        });
+    }
+
+    /// Entrega la orden en progreso del indice que devuelve `elegir` (el de la politica de
+    /// entrega), reduce stock y bloqueados del producto segun la cantidad de la orden.
+    /// Si falla no se modifican ni las ordenes en progreso ni el stock
+    pub fn entregar_orden(&mut self, elegir: impl Fn() -> usize) -> Result<(), ErrorLocal> {
+        self.finalizar_orden_elegida(elegir(), Accion::Entregar)
+    }
+
+    /// Cancela la orden en progreso del indice que devuelve `elegir` (el de la politica de
+    /// entrega), reduce bloqueados del producto segun la cantidad de la orden.
+    /// Si falla no se modifican ni las ordenes en progreso ni el stock
+    pub fn cancelar_orden(&mut self, elegir: impl Fn() -> usize) -> Result<(), ErrorLocal> {
+        self.finalizar_orden_elegida(elegir(), Accion::Cancelar)
+    }
+
+    /// Entrega la orden en progreso del indice dado, la elige un operador desde la consola
+    pub fn entregar_orden_en(&mut self, indice: usize) -> Result<Orden, ErrorLocal> {
+        if indice >= self.ordenes_en_progreso.len() {
+            return Err(ErrorLocal::NoExisteOrden);
+        }
+        self.finalizar_orden(indice, Accion::Entregar)
+    }
+
+    /// Cancela la orden en progreso del indice dado, la elige un operador desde la consola
+    pub fn cancelar_orden_en(&mut self, indice: usize) -> Result<Orden, ErrorLocal> {
+        if indice >= self.ordenes_en_progreso.len() {
+            return Err(ErrorLocal::NoExisteOrden);
         }
+        self.finalizar_orden(indice, Accion::Cancelar)
+    }
 
+    fn finalizar_orden_elegida(&mut self, indice: usize, accion: Accion) -> Result<(), ErrorLocal> {
+        if indice >= self.ordenes_en_progreso.len() {
+            error!(target: "job", "el indice elegido esta por encima del largo del array");
+            return Ok(());
+        }
+        self.finalizar_orden(indice, accion)?;
         Ok(())
     }
+
+    /// Cancela todas las ordenes en progreso vencidas en el momento `ahora` liberando sus
+    /// bloqueados y las devuelve. Una orden que no se puede cancelar se descarta igual, para
+    /// no reintentarla para siempre, liberando lo que quede bloqueado de ella
+    pub fn cancelar_vencidas(&mut self, ahora: u64) -> Vec<Orden> {
+        let mut vencidas = vec![];
+        let mut indice = 0;
+        while indice < self.ordenes_en_progreso.len() {
+            if !self.ordenes_en_progreso[indice].vencida(ahora) {
+                indice += 1;
+                continue;
+            }
+            match self.finalizar_orden(indice, Accion::Cancelar) {
+                Ok(orden) => vencidas.push(orden),
+                Err(e) => {
+                    let orden = self.ordenes_en_progreso.remove(indice);
+                    let liberados = self
+                        .productos_en_stock
+                        .get_mut(&orden.id_producto)
+                        .map(|producto| {
+                            let liberados = producto.bloqueados.min(orden.cantidad);
+                            producto.bloqueados -= liberados;
+                            liberados
+                        })
+                        .unwrap_or(0);
+                    error!(
+                        target: "vencimiento",
+                        orden = orden.id.as_deref(),
+                        liberados,
+                        "No se pudo cancelar la orden vencida, se descarta: {}",
+                        e
+                    );
+                    vencidas.push(orden);
+                }
+            }
+        }
+        vencidas
+    }
+
+    /// Cancela todas las ordenes en progreso, se usa al cerrar el local para no dejar
+    /// productos bloqueados. Las ordenes que no se pueden cancelar quedan en progreso
+    pub fn cancelar_ordenes_en_progreso(&mut self) -> Vec<Orden> {
+        let mut canceladas = vec![];
+        let mut indice = 0;
+        while indice < self.ordenes_en_progreso.len() {
+            match self.finalizar_orden(indice, Accion::Cancelar) {
+                Ok(orden) => canceladas.push(orden),
+                Err(e) => {
+                    error!(target: "cierre", "{}", e);
+                    indice += 1;
+                }
+            }
+        }
+        canceladas
+    }
+
+    /// Entrega o cancela la orden en el indice dado y solo si tiene exito remueve la orden
+    /// de las ordenes en progreso y la devuelve
+    fn finalizar_orden(&mut self, indice: usize, accion: Accion) -> Result<Orden, ErrorLocal> {
+        let orden = &self.ordenes_en_progreso[indice];
+        let producto = self
+            .productos_en_stock
+            .get_mut(&orden.id_producto)
+            .ok_or(ErrorLocal::NoExisteProductoEnLocal)?;
+        let tipo = match accion {
+            Accion::Entregar => {
+                producto.entregar(orden.cantidad)?;
+                OperacionStock::Entregada
+            }
+            Accion::Cancelar => {
+                producto.cancelar(orden.cantidad)?;
+                OperacionStock::Cancelada
+            }
+        };
+        let orden = self.ordenes_en_progreso.remove(indice);
+        self.registrar(tipo, Some(&orden), None, orden.id_producto, orden.cantidad);
+        Ok(orden)
+    }
+}
+
+fn recientes<T: Eq + Hash + Clone>(capacidad: usize, ids: Vec<T>) -> IdsRecientes<T> {
+    let mut recientes = IdsRecientes::new(capacidad);
+    for id in ids {
+        recientes.agregar(id);
+    }
+    recientes
 }
 
 #[cfg(test)]
@@ -90,7 +552,7 @@ mod tests {
         fn crear_producto(&mut self, stock: usize, bloqueados: usize) -> StockProducto {
             let id_producto = self.id_actual;
             self.id_actual += 1;
-            return StockProducto::new_con_bloqueados(id_producto, stock, bloqueados);
+            StockProducto::new_con_bloqueados(id_producto, stock, bloqueados)
         }
     }
 
@@ -112,7 +574,7 @@ mod tests {
         productos.insert(1, StockProducto::new(1, 10));
         let mut local = crear_local(productos);
 
-        let orden = Orden::new(1, 3, 33, 22);
+        let orden = Orden::new(1, 3, 33.0, 22.0);
 
         assert!(local.agregar_orden(orden).is_ok());
         assert_eq!(local.productos_en_stock.get(&1usize).unwrap().bloqueados, 3);
@@ -127,7 +589,7 @@ mod tests {
         productos.insert(id_producto, StockProducto::new(id_producto, 1));
         let mut local = crear_local(productos);
 
-        let orden = Orden::new(1, 3, 33, 22);
+        let orden = Orden::new(1, 3, 33.0, 22.0);
         let resultado = local.agregar_orden(orden);
         assert!(resultado.is_err());
         assert_eq!(resultado.unwrap_err(), ErrorLocal::StockInsuficiente);
@@ -141,7 +603,7 @@ mod tests {
         productos.insert(id_producto, StockProducto::new(id_producto, 1));
         let mut local = crear_local(productos);
 
-        let orden = Orden::new(2398462734, 3, 33, 22);
+        let orden = Orden::new(2398462734, 3, 33.0, 22.0);
         let resultado = local.agregar_orden(orden);
         assert!(resultado.is_err());
         assert_eq!(resultado.unwrap_err(), ErrorLocal::NoExisteProductoEnLocal);
@@ -154,7 +616,7 @@ mod tests {
         productos.insert(id_producto, StockProducto::new(id_producto, 10));
         let mut local = crear_local(productos);
 
-        let orden = Orden::new(1, 3, 33, 22);
+        let orden = Orden::new(1, 3, 33.0, 22.0);
 
         assert!(local.agregar_orden(orden).is_ok());
         assert_eq!(local.ordenes_en_progreso.len(), 1);
@@ -169,7 +631,7 @@ mod tests {
         productos.insert(id_producto, StockProducto::new(id_producto, 20));
         let mut local = crear_local(productos);
 
-        let orden = Orden::new(1, 10, 33, 22);
+        let orden = Orden::new(1, 10, 33.0, 22.0);
 
         assert!(local.vender(orden).is_ok());
         assert_eq!(local.productos_en_stock.get(&1usize).unwrap().stock, 10);
@@ -183,7 +645,7 @@ mod tests {
         productos.insert(id_producto, StockProducto::new(id_producto, 5));
         let mut local = crear_local(productos);
 
-        let orden = Orden::new(1, 10, 33, 22);
+        let orden = Orden::new(1, 10, 33.0, 22.0);
 
         assert!(local.vender(orden).is_err());
         assert_eq!(local.productos_en_stock.get(&1usize).unwrap().stock, 5);
@@ -201,7 +663,7 @@ mod tests {
         );
         let mut local = crear_local(productos);
 
-        let orden = Orden::new(1, 10, 33, 22);
+        let orden = Orden::new(1, 10, 33.0, 22.0);
 
         assert!(local.vender(orden).is_ok());
         assert_eq!(local.productos_en_stock.get(&1usize).unwrap().stock, 5);
@@ -218,7 +680,7 @@ mod tests {
             StockProducto::new_con_bloqueados(id_producto, 15, 0),
         );
         let mut local = crear_local(productos);
-        let orden = Orden::new(id_producto, 5, 33, 22);
+        let orden = Orden::new(id_producto, 5, 33.0, 22.0);
         let _ = local.agregar_orden(orden);
 
         let resultado = local.entregar_orden(|| 0);
@@ -250,8 +712,8 @@ mod tests {
         );
         let mut local = crear_local(productos);
 
-        let orden = Orden::new(id_producto, 5, 33, 22);
-        let orden_2 = Orden::new(id_producto, 10, 33, 22);
+        let orden = Orden::new(id_producto, 5, 33.0, 22.0);
+        let orden_2 = Orden::new(id_producto, 10, 33.0, 22.0);
         let _ = local.agregar_orden(orden);
         let _ = local.agregar_orden(orden_2);
 
@@ -280,7 +742,7 @@ mod tests {
             StockProducto::new_con_bloqueados(id_producto, 15, 0),
         );
         let mut local = crear_local(productos);
-        let orden = Orden::new(id_producto, 10, 33, 22);
+        let orden = Orden::new(id_producto, 10, 33.0, 22.0);
         let _ = local.agregar_orden(orden);
 
         let resultado = local.cancelar_orden(|| 0);
@@ -311,8 +773,8 @@ mod tests {
         productos.insert(producto.id_producto, producto);
         let mut local = crear_local(productos);
 
-        let orden = Orden::new(id_producto, 5, 33, 22);
-        let orden_2 = Orden::new(id_producto, 10, 33, 22);
+        let orden = Orden::new(id_producto, 5, 33.0, 22.0);
+        let orden_2 = Orden::new(id_producto, 10, 33.0, 22.0);
         let _ = local.agregar_orden(orden);
         let _ = local.agregar_orden(orden_2);
 
@@ -333,4 +795,465 @@ mod tests {
             5
         );
     }
+
+    #[test]
+    fn test_entregar_orden_de_producto_inexistente_devuelve_error_y_no_remueve_la_orden() {
+        let mut productos: Productos = HashMap::new();
+        let id_producto = 1;
+        productos.insert(id_producto, StockProducto::new(id_producto, 15));
+        let mut local = crear_local(productos);
+        local.ordenes_en_progreso.push(Orden::new(2, 5, 33.0, 22.0));
+
+        let resultado = local.entregar_orden(|| 0);
+
+        assert_eq!(resultado.unwrap_err(), ErrorLocal::NoExisteProductoEnLocal);
+        assert_eq!(local.ordenes_en_progreso.len(), 1);
+        assert_eq!(
+            local.productos_en_stock.get(&id_producto).unwrap().stock,
+            15
+        );
+    }
+
+    #[test]
+    fn test_cancelar_orden_con_mas_cantidad_que_bloqueados_devuelve_error_y_no_remueve_la_orden() {
+        let mut productos: Productos = HashMap::new();
+        let id_producto = 1;
+        productos.insert(
+            id_producto,
+            StockProducto::new_con_bloqueados(id_producto, 15, 2),
+        );
+        let mut local = crear_local(productos);
+        local
+            .ordenes_en_progreso
+            .push(Orden::new(id_producto, 5, 33.0, 22.0));
+
+        let resultado = local.cancelar_orden(|| 0);
+
+        assert_eq!(
+            resultado.unwrap_err(),
+            ErrorLocal::CantidadOrdenMayorQueBloqueados
+        );
+        assert_eq!(local.ordenes_en_progreso.len(), 1);
+        let producto = local.productos_en_stock.get(&id_producto).unwrap();
+        assert_eq!(producto.stock, 15);
+        assert_eq!(producto.bloqueados, 2);
+    }
+
+    #[test]
+    fn test_cancelar_vencidas_libera_los_bloqueados_solo_de_las_ordenes_vencidas() {
+        let mut productos: Productos = HashMap::new();
+        let id_producto = 1;
+        productos.insert(id_producto, StockProducto::new(id_producto, 20));
+        let mut local = crear_local(productos);
+        let mut orden_vencida = Orden::new(id_producto, 5, 33.0, 22.0);
+        orden_vencida.vencimiento = Some(100);
+        let mut orden_en_plazo = Orden::new(id_producto, 3, 33.0, 22.0);
+        orden_en_plazo.vencimiento = Some(500);
+        let _ = local.agregar_orden(orden_vencida);
+        let _ = local.agregar_orden(orden_en_plazo);
+        let _ = local.agregar_orden(Orden::new(id_producto, 2, 33.0, 22.0));
+
+        let vencidas = local.cancelar_vencidas(200);
+
+        assert_eq!(vencidas.len(), 1);
+        assert_eq!(vencidas[0].cantidad, 5);
+        assert_eq!(local.ordenes_en_progreso.len(), 2);
+        let producto = local.productos_en_stock.get(&id_producto).unwrap();
+        assert_eq!(producto.stock, 20);
+        assert_eq!(producto.bloqueados, 5);
+    }
+
+    #[test]
+    fn test_orden_vencida_que_no_se_puede_cancelar_se_descarta() {
+        let mut local = crear_local(HashMap::new());
+        let mut orden = Orden::new(7, 5, 33.0, 22.0);
+        orden.vencimiento = Some(100);
+        local.ordenes_en_progreso.push(orden);
+
+        let vencidas = local.cancelar_vencidas(200);
+
+        assert_eq!(vencidas.len(), 1);
+        assert!(local.ordenes_en_progreso.is_empty());
+        assert!(local.cancelar_vencidas(300).is_empty());
+    }
+
+    #[test]
+    fn test_orden_vencida_que_no_se_puede_cancelar_no_deja_unidades_bloqueadas() {
+        let mut productos: Productos = HashMap::new();
+        productos.insert(1, StockProducto::new_con_bloqueados(1, 10, 3));
+        let mut local = crear_local(productos);
+        let mut orden = Orden::new(1, 5, 33.0, 22.0);
+        orden.vencimiento = Some(100);
+        local.ordenes_en_progreso.push(orden);
+
+        let vencidas = local.cancelar_vencidas(200);
+
+        assert_eq!(vencidas.len(), 1);
+        assert!(local.ordenes_en_progreso.is_empty());
+        let producto = local.productos_en_stock.get(&1).unwrap();
+        assert_eq!(producto.stock, 10);
+        assert_eq!(producto.bloqueados, 0);
+    }
+
+    #[test]
+    fn test_entregar_y_cancelar_orden_en_indice_dado() {
+        let mut productos: Productos = HashMap::new();
+        productos.insert(1, StockProducto::new(1, 20));
+        let mut local = crear_local(productos);
+        let _ = local.agregar_orden(Orden::new(1, 5, 33.0, 22.0));
+        let _ = local.agregar_orden(Orden::new(1, 3, 33.0, 22.0));
+
+        assert_eq!(local.cancelar_orden_en(1).unwrap().cantidad, 3);
+        assert_eq!(local.entregar_orden_en(1), Err(ErrorLocal::NoExisteOrden));
+        assert_eq!(local.entregar_orden_en(0).unwrap().cantidad, 5);
+
+        let producto = local.productos_en_stock.get(&1).unwrap();
+        assert_eq!((producto.stock, producto.bloqueados), (15, 0));
+    }
+
+    #[test]
+    fn test_cancelar_ordenes_en_progreso_cancela_todas_y_no_deja_bloqueados() {
+        let mut productos: Productos = HashMap::new();
+        productos.insert(1, StockProducto::new(1, 20));
+        productos.insert(2, StockProducto::new(2, 10));
+        let mut local = crear_local(productos);
+        let _ = local.agregar_orden(Orden::new(1, 5, 33.0, 22.0));
+        let _ = local.agregar_orden(Orden::new(2, 4, 33.0, 22.0));
+        let _ = local.agregar_orden(Orden::new(1, 3, 33.0, 22.0));
+
+        let canceladas = local.cancelar_ordenes_en_progreso();
+
+        assert_eq!(canceladas.len(), 3);
+        assert!(local.ordenes_en_progreso.is_empty());
+        let producto_1 = local.productos_en_stock.get(&1).unwrap();
+        assert_eq!((producto_1.stock, producto_1.bloqueados), (20, 0));
+        let producto_2 = local.productos_en_stock.get(&2).unwrap();
+        assert_eq!((producto_2.stock, producto_2.bloqueados), (10, 0));
+    }
+
+    #[test]
+    fn test_reponer_producto_existente_aumenta_su_stock() {
+        let mut productos: Productos = HashMap::new();
+        productos.insert(1, StockProducto::new_con_bloqueados(1, 10, 4));
+        let mut local = crear_local(productos);
+
+        local.reponer(1, 5).unwrap();
+
+        let producto = local.productos_en_stock.get(&1).unwrap();
+        assert_eq!(producto.stock, 15);
+        assert_eq!(producto.bloqueados, 4);
+    }
+
+    #[test]
+    fn test_reponer_producto_inexistente_lo_agrega_al_local() {
+        let mut local = crear_local(HashMap::new());
+
+        local.reponer(7, 5).unwrap();
+
+        assert_eq!(local.productos_en_stock.get(&7).unwrap().stock, 5);
+    }
+
+    #[test]
+    fn test_transferencia_entre_dos_locales_mueve_el_stock_del_origen_al_destino() {
+        let mut productos_origen: Productos = HashMap::new();
+        productos_origen.insert(1, StockProducto::new(1, 20));
+        let mut origen = crear_local(productos_origen);
+        let mut destino = crear_local(HashMap::new());
+        let id = IdTransferencia {
+            id_local: 2,
+            numero: 0,
+        };
+        let transferencia = Transferencia::new(id, 1, 8);
+
+        destino.registrar_transferencia_entrante(transferencia.clone());
+        assert!(origen
+            .reservar_transferencia_saliente(transferencia.clone())
+            .is_ok());
+        assert_eq!(origen.productos_en_stock.get(&1).unwrap().bloqueados, 8);
+        assert_eq!(destino.unidades_entrantes(1), 8);
+
+        assert!(destino.recibir_transferencia(&transferencia).is_ok());
+        assert!(origen.confirmar_transferencia_saliente(&id).is_ok());
+
+        let producto_origen = origen.productos_en_stock.get(&1).unwrap();
+        assert_eq!(producto_origen.stock, 12);
+        assert_eq!(producto_origen.bloqueados, 0);
+        assert_eq!(destino.productos_en_stock.get(&1).unwrap().stock, 8);
+        assert_eq!(destino.unidades_entrantes(1), 0);
+        assert!(origen.transferencias_salientes.is_empty());
+    }
+
+    #[test]
+    fn test_reservar_transferencia_sin_stock_suficiente_devuelve_error_y_no_queda_en_transito() {
+        let mut productos: Productos = HashMap::new();
+        productos.insert(1, StockProducto::new_con_bloqueados(1, 10, 5));
+        let mut local = crear_local(productos);
+        let id = IdTransferencia {
+            id_local: 2,
+            numero: 0,
+        };
+
+        let resultado = local.reservar_transferencia_saliente(Transferencia::new(id, 1, 6));
+
+        assert_eq!(resultado.unwrap_err(), ErrorLocal::StockInsuficiente);
+        assert!(local.transferencias_salientes.is_empty());
+        assert_eq!(local.productos_en_stock.get(&1).unwrap().bloqueados, 5);
+    }
+
+    #[test]
+    fn test_recibir_dos_veces_la_misma_transferencia_repone_una_sola_vez() {
+        let mut local = crear_local(HashMap::new());
+        let id = IdTransferencia {
+            id_local: 1,
+            numero: 3,
+        };
+        let transferencia = Transferencia::new(id, 1, 4);
+        local.registrar_transferencia_entrante(transferencia.clone());
+
+        assert!(local.recibir_transferencia(&transferencia).is_ok());
+        assert_eq!(
+            local.recibir_transferencia(&transferencia).unwrap_err(),
+            ErrorLocal::TransferenciaRepetida
+        );
+        assert_eq!(local.productos_en_stock.get(&1).unwrap().stock, 4);
+    }
+
+    #[test]
+    fn test_transferencia_que_llega_despues_de_vencida_se_recibe_y_una_desconocida_no() {
+        let mut local = crear_local(HashMap::new());
+        let vencida = Transferencia::new(
+            IdTransferencia {
+                id_local: 1,
+                numero: 3,
+            },
+            1,
+            4,
+        );
+        let desconocida = Transferencia::new(
+            IdTransferencia {
+                id_local: 1,
+                numero: 4,
+            },
+            1,
+            6,
+        );
+        local.registrar_transferencia_entrante(vencida.clone());
+
+        assert!(local.vencer_transferencia_entrante(&vencida.id).is_ok());
+        assert_eq!(local.unidades_entrantes(1), 0);
+
+        assert!(local.recibir_transferencia(&vencida).is_ok());
+        assert_eq!(
+            local.recibir_transferencia(&desconocida).unwrap_err(),
+            ErrorLocal::TransferenciaDesconocida
+        );
+        assert_eq!(local.productos_en_stock.get(&1).unwrap().stock, 4);
+    }
+
+    #[test]
+    fn test_pedido_repetido_no_bloquea_dos_veces_y_liberar_devuelve_las_unidades() {
+        let mut productos: Productos = HashMap::new();
+        productos.insert(1, StockProducto::new(1, 20));
+        let mut local = crear_local(productos);
+        let transferencia = Transferencia::new(
+            IdTransferencia {
+                id_local: 2,
+                numero: 0,
+            },
+            1,
+            8,
+        );
+
+        assert!(local
+            .reservar_transferencia_saliente(transferencia.clone())
+            .is_ok());
+        assert!(local
+            .reservar_transferencia_saliente(transferencia.clone())
+            .is_ok());
+        assert_eq!(local.productos_en_stock.get(&1).unwrap().bloqueados, 8);
+
+        assert!(local
+            .liberar_transferencia_saliente(&transferencia.id)
+            .is_ok());
+        assert_eq!(local.productos_en_stock.get(&1).unwrap().bloqueados, 0);
+        assert_eq!(
+            local
+                .reservar_transferencia_saliente(transferencia)
+                .unwrap_err(),
+            ErrorLocal::TransferenciaRepetida
+        );
+        assert_eq!(local.productos_en_stock.get(&1).unwrap().bloqueados, 0);
+    }
+
+    #[test]
+    fn test_recuerda_si_acepto_o_rechazo_cada_orden() {
+        let mut productos: Productos = HashMap::new();
+        productos.insert(1, StockProducto::new(1, 4));
+        let mut local = crear_local(productos);
+        let orden = |id: &str, cantidad| Orden {
+            id: Some(id.to_string()),
+            ..Orden::new(1, cantidad, 0.0, 0.0)
+        };
+
+        assert!(local.puede_aceptar(&orden("2-0", 3)).is_ok());
+        assert_eq!(local.decision("2-0"), None);
+        assert!(local.agregar_orden(orden("2-0", 3)).is_ok());
+        assert!(local.agregar_orden(orden("2-1", 3)).is_err());
+
+        assert_eq!(local.decision("2-0"), Some(true));
+        assert_eq!(local.decision("2-1"), Some(false));
+        assert_eq!(
+            local.puede_aceptar(&orden("2-2", 2)),
+            Err(ErrorLocal::StockInsuficiente)
+        );
+    }
+
+    #[test]
+    fn test_el_estado_guardado_retoma_ordenes_transferencias_y_decisiones() {
+        let mut productos: Productos = HashMap::new();
+        productos.insert(1, StockProducto::new(1, 20));
+        productos.insert(2, StockProducto::new(2, 5));
+        let mut local = crear_local(productos);
+        local
+            .agregar_orden(Orden {
+                id: Some("2-0".to_string()),
+                ..Orden::new(1, 3, 0.0, 0.0)
+            })
+            .unwrap();
+        let id = IdTransferencia {
+            id_local: 3,
+            numero: 7,
+        };
+        local
+            .reservar_transferencia_saliente(Transferencia::new(id, 2, 4))
+            .unwrap();
+
+        let serializado = serde_json::to_string(&local.estado()).unwrap();
+        let mut retomado = Local::desde_estado(serde_json::from_str(&serializado).unwrap());
+
+        assert_eq!(retomado.estado(), local.estado());
+        assert_eq!(retomado.productos_en_stock.get(&1).unwrap().bloqueados, 3);
+        assert_eq!(retomado.decision("2-0"), Some(true));
+        assert!(retomado.confirmar_transferencia_saliente(&id).is_ok());
+        assert_eq!(retomado.productos_en_stock.get(&2).unwrap().stock, 1);
+    }
+
+    mod propiedades {
+        use super::*;
+        use proptest::prelude::*;
+
+        const CANTIDAD_PRODUCTOS: usize = 3;
+
+        #[derive(Debug, Clone)]
+        enum Operacion {
+            AgregarOrden(usize, usize, Option<u64>),
+            Vender(usize, usize),
+            Entregar(usize),
+            Cancelar(usize),
+            CancelarVencidas(u64),
+            Reponer(usize, usize),
+            ReservarTransferencia(u64, usize, usize),
+            ConfirmarTransferencia(u64),
+            LiberarTransferencia(u64),
+        }
+
+        fn operacion() -> impl Strategy<Value = Operacion> {
+            let id_producto = 0..CANTIDAD_PRODUCTOS + 1;
+            prop_oneof![
+                (id_producto.clone(), 0..15usize, prop::option::of(0..100u64)).prop_map(
+                    |(id, cantidad, vencimiento)| Operacion::AgregarOrden(
+                        id,
+                        cantidad,
+                        vencimiento
+                    )
+                ),
+                (id_producto, 0..15usize)
+                    .prop_map(|(id, cantidad)| Operacion::Vender(id, cantidad)),
+                (0..10usize).prop_map(Operacion::Entregar),
+                (0..10usize).prop_map(Operacion::Cancelar),
+                (0..100u64).prop_map(Operacion::CancelarVencidas),
+                (0..CANTIDAD_PRODUCTOS, 0..15usize)
+                    .prop_map(|(id, cantidad)| Operacion::Reponer(id, cantidad)),
+                (0..10u64, 0..CANTIDAD_PRODUCTOS + 1, 0..15usize).prop_map(
+                    |(numero, id, cantidad)| Operacion::ReservarTransferencia(numero, id, cantidad)
+                ),
+                (0..10u64).prop_map(Operacion::ConfirmarTransferencia),
+                (0..10u64).prop_map(Operacion::LiberarTransferencia),
+            ]
+        }
+
+        /// Verifica que ningún producto tenga mas bloqueados que stock y que los bloqueados
+        /// de cada producto sean la suma de las cantidades de sus ordenes en progreso y
+        /// de sus transferencias salientes
+        fn verificar_invariantes(local: &Local) {
+            for producto in local.productos_en_stock.values() {
+                assert!(producto.bloqueados <= producto.stock);
+                let bloqueados_en_ordenes: usize = local
+                    .ordenes_en_progreso
+                    .iter()
+                    .filter(|orden| orden.id_producto == producto.id_producto)
+                    .map(|orden| orden.cantidad)
+                    .sum();
+                let bloqueados_en_transferencias: usize = local
+                    .transferencias_salientes
+                    .values()
+                    .filter(|transferencia| transferencia.id_producto == producto.id_producto)
+                    .map(|transferencia| transferencia.cantidad)
+                    .sum();
+                assert_eq!(
+                    producto.bloqueados,
+                    bloqueados_en_ordenes + bloqueados_en_transferencias
+                );
+            }
+        }
+
+        proptest! {
+            #[test]
+            fn test_secuencia_de_operaciones_mantiene_invariantes_de_stock(
+                stocks in prop::collection::vec(0..40usize, CANTIDAD_PRODUCTOS),
+                operaciones in prop::collection::vec(operacion(), 0..60),
+            ) {
+                let productos: Productos = stocks
+                    .iter()
+                    .enumerate()
+                    .map(|(id, stock)| (id, StockProducto::new(id, *stock)))
+                    .collect();
+                let mut local = crear_local(productos);
+
+                for operacion in operaciones {
+                    let cantidad_ordenes = local.ordenes_en_progreso.len();
+                    let resultado = match operacion {
+                        Operacion::AgregarOrden(id, cantidad, vencimiento) => {
+                            let mut orden = Orden::new(id, cantidad, 0.0, 0.0);
+                            orden.vencimiento = vencimiento;
+                            local.agregar_orden(orden)
+                        }
+                        Operacion::Vender(id, cantidad) => local.vender(Orden::new(id, cantidad, 0.0, 0.0)),
+                        Operacion::Entregar(indice) => local.entregar_orden(|| indice),
+                        Operacion::Cancelar(indice) => local.cancelar_orden(|| indice),
+                        Operacion::CancelarVencidas(ahora) => {
+                            local.cancelar_vencidas(ahora);
+                            prop_assert!(local.ordenes_en_progreso.iter().all(|orden| !orden.vencida(ahora)));
+                            Ok(())
+                        }
+                        Operacion::Reponer(id, cantidad) => local.reponer(id, cantidad),
+                        Operacion::ReservarTransferencia(numero, id_producto, cantidad) => {
+                            let id = IdTransferencia { id_local: 9, numero };
+                            local.reservar_transferencia_saliente(Transferencia::new(id, id_producto, cantidad))
+                        }
+                        Operacion::ConfirmarTransferencia(numero) => local
+                            .confirmar_transferencia_saliente(&IdTransferencia { id_local: 9, numero })
+                            .map(|_| ()),
+                        Operacion::LiberarTransferencia(numero) => local
+                            .liberar_transferencia_saliente(&IdTransferencia { id_local: 9, numero })
+                            .map(|_| ()),
+                    };
+                    if resultado.is_err() {
+                        prop_assert_eq!(local.ordenes_en_progreso.len(), cantidad_ordenes);
+                    }
+                    verificar_invariantes(&local);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,355 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::Record;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::common::trazas::CapaTrazas;
+
+/// Filtro que se usa si no se pasa --log ni esta la variable RUST_LOG
+const FILTRO_POR_DEFECTO: &str = "info";
+
+/// Formato de cada linea de log
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FormatoLogs {
+    Texto,
+    /// Un objeto json por linea, para juntar los logs de varios procesos
+    Json,
+}
+
+impl FromStr for FormatoLogs {
+    type Err = String;
+
+    fn from_str(texto: &str) -> Result<Self, Self::Err> {
+        match texto {
+            "texto" => Ok(FormatoLogs::Texto),
+            "json" => Ok(FormatoLogs::Json),
+            otro => Err(format!("Formato de logs desconocido: {}", otro)),
+        }
+    }
+}
+
+/// Permite cambiar el filtro de los logs con el proceso andando
+pub struct ControlLogs {
+    filtro: reload::Handle<EnvFilter, Registry>,
+}
+
+impl ControlLogs {
+    /// El filtro es como el de RUST_LOG, por ejemplo `info,udp=debug,job=warn`
+    pub fn cambiar_filtro(&self, filtro: &str) -> Result<(), String> {
+        let filtro = EnvFilter::try_new(filtro).map_err(|e| e.to_string())?;
+        self.filtro.reload(filtro).map_err(|e| e.to_string())
+    }
+}
+
+/// Inicia los logs del proceso. Cada linea lleva el binario y el id de la instancia, y el
+/// componente es el target del evento (`udp`, `job`, `coordinador`...), asi se puede filtrar por él.
+/// El filtro solo aplica a los logs, si se exportan trazas se exportan todos los spans
+pub fn iniciar_logs(
+    binario: &'static str,
+    instancia: usize,
+    formato: FormatoLogs,
+    filtro: Option<&str>,
+    trazas: Option<CapaTrazas>,
+) -> Result<ControlLogs, String> {
+    let filtro = match filtro {
+        Some(filtro) => EnvFilter::try_new(filtro).map_err(|e| e.to_string())?,
+        None => {
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(FILTRO_POR_DEFECTO))
+        }
+    };
+    let (filtro, handle) = reload::Layer::new(filtro);
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .fmt_fields(CamposSpan)
+                .event_format(FormatoEvento {
+                    binario,
+                    instancia,
+                    formato,
+                })
+                .with_filter(filtro),
+        )
+        .with(trazas)
+        .try_init()
+        .map_err(|e| e.to_string())?;
+    Ok(ControlLogs { filtro: handle })
+}
+
+/// Escribe cada evento con el momento, el nivel, la instancia, el componente, los campos
+/// de los spans en los que ocurrió y los suyos
+struct FormatoEvento {
+    binario: &'static str,
+    instancia: usize,
+    formato: FormatoLogs,
+}
+
+impl<S, N> FormatEvent<S, N> for FormatoEvento
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        contexto: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        evento: &Event<'_>,
+    ) -> fmt::Result {
+        let mut momento = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut momento))?;
+        let mut campos = Campos::default();
+        // desde el span más externo, asi los campos del más interno y del evento pisan a los demás
+        for span in contexto
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+        {
+            let extensiones = span.extensions();
+            let Some(formateados) = extensiones.get::<FormattedFields<N>>() else {
+                continue;
+            };
+            if let Ok(Value::Object(json)) = serde_json::from_str(&formateados.fields) {
+                for (nombre, valor) in json {
+                    campos.insertar(nombre, valor);
+                }
+            }
+        }
+        evento.record(&mut campos);
+        writeln!(writer, "{}", self.formatear(&momento, evento, campos))
+    }
+}
+
+impl FormatoEvento {
+    fn formatear(&self, momento: &str, evento: &Event<'_>, campos: Campos) -> String {
+        let metadata = evento.metadata();
+        match self.formato {
+            FormatoLogs::Json => {
+                let mut json = Map::new();
+                json.insert("momento".to_string(), momento.into());
+                json.insert("nivel".to_string(), metadata.level().as_str().into());
+                json.insert("binario".to_string(), self.binario.into());
+                json.insert("instancia".to_string(), self.instancia.into());
+                json.insert("componente".to_string(), metadata.target().into());
+                json.insert("mensaje".to_string(), campos.mensaje.into());
+                for (nombre, valor) in campos.resto {
+                    json.insert(nombre, valor);
+                }
+                Value::Object(json).to_string()
+            }
+            FormatoLogs::Texto => {
+                let mut linea = format!(
+                    "{} {:>5} {}[{}] {}: {}",
+                    momento,
+                    metadata.level(),
+                    self.binario,
+                    self.instancia,
+                    metadata.target(),
+                    campos.mensaje
+                );
+                for (nombre, valor) in campos.resto {
+                    match valor {
+                        Value::String(texto) => linea.push_str(&format!(" {}={}", nombre, texto)),
+                        otro => linea.push_str(&format!(" {}={}", nombre, otro)),
+                    }
+                }
+                linea
+            }
+        }
+    }
+}
+
+/// Mensaje y campos de un evento, en el orden en que se escribieron
+#[derive(Default)]
+struct Campos {
+    mensaje: String,
+    resto: Vec<(String, Value)>,
+}
+
+impl Visit for Campos {
+    fn record_str(&mut self, campo: &Field, valor: &str) {
+        self.agregar(campo, valor.into());
+    }
+
+    fn record_u64(&mut self, campo: &Field, valor: u64) {
+        self.agregar(campo, valor.into());
+    }
+
+    fn record_i64(&mut self, campo: &Field, valor: i64) {
+        self.agregar(campo, valor.into());
+    }
+
+    fn record_f64(&mut self, campo: &Field, valor: f64) {
+        self.agregar(campo, valor.into());
+    }
+
+    fn record_bool(&mut self, campo: &Field, valor: bool) {
+        self.agregar(campo, valor.into());
+    }
+
+    fn record_debug(&mut self, campo: &Field, valor: &dyn fmt::Debug) {
+        self.agregar(campo, format!("{:?}", valor).into());
+    }
+}
+
+impl Campos {
+    fn agregar(&mut self, campo: &Field, valor: Value) {
+        if campo.name() == "message" {
+            self.mensaje = match valor {
+                Value::String(texto) => texto,
+                otro => otro.to_string(),
+            };
+        } else {
+            self.insertar(campo.name().to_string(), valor);
+        }
+    }
+
+    /// Agrega el campo o reemplaza el valor si ya estaba
+    fn insertar(&mut self, nombre: String, valor: Value) {
+        match self
+            .resto
+            .iter_mut()
+            .find(|(existente, _)| *existente == nombre)
+        {
+            Some((_, existente)) => *existente = valor,
+            None => self.resto.push((nombre, valor)),
+        }
+    }
+}
+
+/// Guarda los campos de cada span como un objeto json, para agregarlos a sus eventos
+struct CamposSpan;
+
+impl<'writer> FormatFields<'writer> for CamposSpan {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        campos: R,
+    ) -> fmt::Result {
+        let mut visitor = Campos::default();
+        campos.record(&mut visitor);
+        let json: Map<String, Value> = visitor.resto.into_iter().collect();
+        write!(writer, "{}", Value::Object(json))
+    }
+
+    /// Los campos que se completan despues de crear el span se suman al mismo objeto
+    fn add_fields(
+        &self,
+        actuales: &'writer mut FormattedFields<Self>,
+        campos: &Record<'_>,
+    ) -> fmt::Result {
+        let mut json: Map<String, Value> =
+            serde_json::from_str(&actuales.fields).unwrap_or_default();
+        let mut visitor = Campos::default();
+        campos.record(&mut visitor);
+        json.extend(visitor.resto);
+        actuales.fields = Value::Object(json).to_string();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::fmt::MakeWriter;
+
+    /// Junta en memoria lo que escriben los logs
+    #[derive(Clone, Default)]
+    struct Salida(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Salida {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Salida {
+        type Writer = Salida;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn loguear(formato: FormatoLogs, filtro: &str) -> String {
+        loguear_con(formato, filtro, || {
+            tracing::info!(target: "udp", orden = "1-4", id_producto = 3, "Orden agregada");
+            tracing::debug!(target: "job", "no hay ordenes en progreso");
+        })
+    }
+
+    fn loguear_con(formato: FormatoLogs, filtro: &str, logs: impl FnOnce()) -> String {
+        let salida = Salida::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(EnvFilter::new(filtro))
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(salida.clone())
+                    .fmt_fields(CamposSpan)
+                    .event_format(FormatoEvento {
+                        binario: "local",
+                        instancia: 2,
+                        formato,
+                    }),
+            );
+        tracing::subscriber::with_default(subscriber, logs);
+        let bytes = salida.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_log_en_json_tiene_instancia_componente_y_campos() {
+        let salida = loguear(FormatoLogs::Json, "info");
+        let lineas: Vec<&str> = salida.lines().collect();
+        assert_eq!(lineas.len(), 1);
+
+        let json: Value = serde_json::from_str(lineas[0]).unwrap();
+        assert_eq!(json["nivel"], "INFO");
+        assert_eq!(json["binario"], "local");
+        assert_eq!(json["instancia"], 2);
+        assert_eq!(json["componente"], "udp");
+        assert_eq!(json["mensaje"], "Orden agregada");
+        assert_eq!(json["orden"], "1-4");
+        assert_eq!(json["id_producto"], 3);
+    }
+
+    #[test]
+    fn test_filtro_por_componente() {
+        let salida = loguear(FormatoLogs::Texto, "warn,job=debug");
+        let lineas: Vec<&str> = salida.lines().collect();
+        assert_eq!(lineas.len(), 1);
+        assert!(lineas[0].ends_with("DEBUG local[2] job: no hay ordenes en progreso"));
+    }
+
+    #[test]
+    fn test_log_dentro_de_un_span_tiene_los_campos_del_span() {
+        let salida = loguear_con(FormatoLogs::Json, "info", || {
+            let span = tracing::info_span!("orden", orden = tracing::field::Empty, cursor = 7);
+            let _entrada = span.enter();
+            span.record("orden", "2-7");
+            tracing::info!(target: "ecommerce", local = 1, "Orden aceptada");
+        });
+
+        let json: Value = serde_json::from_str(salida.lines().next().unwrap()).unwrap();
+        assert_eq!(json["mensaje"], "Orden aceptada");
+        assert_eq!(json["orden"], "2-7");
+        assert_eq!(json["cursor"], 7);
+        assert_eq!(json["local"], 1);
+    }
+}
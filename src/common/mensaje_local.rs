@@ -0,0 +1,130 @@
+use crate::common::orden::Orden;
+use crate::common::reposicion::Reposicion;
+use crate::common::transferencia::{IdTransferencia, Transferencia};
+use serde::{Deserialize, Serialize};
+
+/// Mensajes que viajan por udp hacia o desde un local, ademas de las ordenes del ecommerce
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "tipo")]
+pub enum MensajeLocal {
+    /// Ingresan unidades de un producto al local
+    Reposicion(Reposicion),
+    /// El local pide reposición de un producto con stock bajo
+    PedidoReposicion { id_local: usize, pedido: Reposicion },
+    /// Un local le pide unidades de un producto a otro local
+    PedidoTransferencia(Transferencia),
+    /// El local que recibió el pedido bloqueó las unidades y las envia
+    EnvioTransferencia(Transferencia),
+    /// El local que pidió la transferencia recibió las unidades
+    RecepcionTransferencia { id: IdTransferencia },
+    /// El local que recibió el pedido no tiene stock suficiente para enviarlo
+    TransferenciaRechazada { id: IdTransferencia },
+    /// El local canceló una orden que le había aceptado al ecommerce, se reenvía hasta
+    /// que el ecommerce confirma que lo recibió
+    OrdenCancelada {
+        orden: String,
+        local: usize,
+        motivo: MotivoCancelacion,
+    },
+    /// El ecommerce recibió el aviso de cancelación de la orden
+    CancelacionRecibida { orden: String },
+    /// El local se está cerrando, se le avisa a los ecommerce que le enviaron ordenes
+    LocalCerrando { local: usize },
+    /// El ecommerce le confirma la orden al local que le contestó que la puede tomar, se
+    /// reenvía hasta que el local contesta
+    ConfirmarOrden { orden: Orden },
+    /// Lo que contesta el local a la oferta o, si `confirmacion`, a la confirmación de la orden
+    Respuesta {
+        orden: String,
+        confirmacion: bool,
+        respuesta: RespuestaOrden,
+    },
+}
+
+/// Respuesta de un local a una orden. A la oferta contesta si la puede tomar y a la
+/// confirmación si la tomó, la misma respuesta cada vez que se repite la confirmación
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RespuestaOrden {
+    /// Tiene stock para la orden, la toma si el ecommerce se la confirma
+    Disponible,
+    /// Tomó la orden confirmada
+    Aceptada,
+    Rechazada,
+    /// El mailbox del ActorLocal estaba lleno, no se atendió el mensaje
+    Ocupado,
+    /// El local se está cerrando
+    Cerrado,
+}
+
+/// Por que un local canceló una orden que ya había aceptado
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MotivoCancelacion {
+    /// Se venció el plazo de la orden sin que se entregue
+    Vencida,
+    /// La canceló la politica de entrega o el operador del local
+    Cancelada,
+    /// El local se cerró con la orden en progreso
+    Cierre,
+}
+
+impl MensajeLocal {
+    pub fn deserializar(data: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(data)
+    }
+
+    pub fn serializar(&self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializacion_y_deserializacion_de_reposicion() {
+        let mensaje = MensajeLocal::Reposicion(Reposicion::new(1, 10));
+
+        let serializado = mensaje.serializar().unwrap();
+
+        assert_eq!(MensajeLocal::deserializar(&serializado).unwrap(), mensaje);
+    }
+
+    #[test]
+    fn test_serializacion_de_aviso_de_cancelacion() {
+        let mensaje = MensajeLocal::OrdenCancelada {
+            orden: "2-7".to_string(),
+            local: 3,
+            motivo: MotivoCancelacion::Vencida,
+        };
+
+        let serializado = mensaje.serializar().unwrap();
+
+        assert_eq!(
+            String::from_utf8(serializado.clone()).unwrap(),
+            r#"{"tipo":"OrdenCancelada","orden":"2-7","local":3,"motivo":"vencida"}"#
+        );
+        assert_eq!(MensajeLocal::deserializar(&serializado).unwrap(), mensaje);
+    }
+
+    #[test]
+    fn test_la_confirmacion_de_una_orden_no_se_deserializa_como_orden() {
+        let mensaje = MensajeLocal::ConfirmarOrden {
+            orden: Orden::new(1, 5, 3.0, 3.0),
+        };
+
+        let serializado = mensaje.serializar().unwrap();
+
+        assert!(Orden::deserializar(&serializado).is_err());
+        assert_eq!(MensajeLocal::deserializar(&serializado).unwrap(), mensaje);
+    }
+
+    #[test]
+    fn test_una_orden_no_se_deserializa_como_mensaje_local() {
+        let orden = serde_json::to_vec(&Orden::new(1, 5, 3.0, 3.0)).unwrap();
+
+        assert!(MensajeLocal::deserializar(&orden).is_err());
+    }
+}
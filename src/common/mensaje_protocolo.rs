@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::common::error_local::ErrorLocal;
-use crate::common::orden::{Direccion, Orden};
+use crate::common::orden::Orden;
 
 use crate::common::socket::TipodeError;
 
@@ -21,6 +21,9 @@ pub enum TipoDeMensaje {
     PuedoMandarOrdenes,
     TermineDeMandarOrdenes,
     Desconexion,
+    LocalOcupado,
+    LocalCerrado,
+    NoHayOrdenes,
 }
 
 impl TipoDeMensaje {
@@ -34,6 +37,9 @@ impl TipoDeMensaje {
             TipoDeMensaje::PuedoMandarOrdenes => "PuedoMandarOrdenes",
             TipoDeMensaje::TermineDeMandarOrdenes => "TermineDeMandarOrdenes",
             TipoDeMensaje::Desconexion => "Desconexion",
+            TipoDeMensaje::LocalOcupado => "LocalOcupado",
+            TipoDeMensaje::LocalCerrado => "LocalCerrado",
+            TipoDeMensaje::NoHayOrdenes => "NoHayOrdenes",
         }
     }
 
@@ -47,6 +53,9 @@ impl TipoDeMensaje {
             5 => Ok(TipoDeMensaje::PuedoMandarOrdenes),
             6 => Ok(TipoDeMensaje::TermineDeMandarOrdenes),
             7 => Ok(TipoDeMensaje::Desconexion),
+            8 => Ok(TipoDeMensaje::LocalOcupado),
+            9 => Ok(TipoDeMensaje::LocalCerrado),
+            10 => Ok(TipoDeMensaje::NoHayOrdenes),
             _ => Err(TipodeError::ErrorTipoDeMensaje),
         }
     }
@@ -56,6 +65,11 @@ impl TipoDeMensaje {
             ErrorLocal::StockInsuficiente => TipoDeMensaje::StockInsuficiente,
             ErrorLocal::NoExisteProductoEnLocal => TipoDeMensaje::StockInsuficiente,
             ErrorLocal::CantidadOrdenMayorQueBloqueados => TipoDeMensaje::StockInsuficiente,
+            ErrorLocal::TransferenciaDesconocida => TipoDeMensaje::StockInsuficiente,
+            ErrorLocal::TransferenciaRepetida => TipoDeMensaje::StockInsuficiente,
+            ErrorLocal::LocalCerrado => TipoDeMensaje::LocalCerrado,
+            ErrorLocal::NoExisteOrden => TipoDeMensaje::StockInsuficiente,
+            ErrorLocal::StockExcedido => TipoDeMensaje::StockInsuficiente,
         }
     }
 }
@@ -102,6 +116,10 @@ impl MensajeProtocolo {
         MensajeProtocolo::new(TipoDeMensaje::TermineDeMandarOrdenes, Vec::new())
     }
 
+    pub fn new_no_hay_ordenes() -> Self {
+        MensajeProtocolo::new(TipoDeMensaje::NoHayOrdenes, Vec::new())
+    }
+
     pub fn new_orden_aceptada() -> Self {
         MensajeProtocolo::new(TipoDeMensaje::OrdenAceptada, Vec::new())
     }
@@ -155,7 +173,11 @@ mod tests {
         let orden = Orden {
             id_producto: 1,
             cantidad: 5,
-            direccion: Direccion::new(0, 0),
+            direccion: Direccion::new(0.0, 0.0),
+            prioridad: 0,
+            vencimiento: None,
+            id: None,
+            traza: None,
         };
         let serialized = serde_json::to_string(&orden).unwrap();
 
@@ -168,7 +190,11 @@ mod tests {
         let orden = Orden {
             id_producto: 1,
             cantidad: 5,
-            direccion: Direccion::new(0, 0),
+            direccion: Direccion::new(0.0, 0.0),
+            prioridad: 0,
+            vencimiento: None,
+            id: None,
+            traza: None,
         };
         let serialized = serde_json::to_string(&orden).unwrap();
 
@@ -0,0 +1,349 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{error, info};
+
+use crate::common::http::{leer_pedido, RespuestaHttp};
+use crate::common::registro_ordenes::MotivoRechazo;
+
+/// Tipo de contenido del formato de texto de Prometheus
+pub const TIPO_CONTENIDO_PROMETHEUS: &str = "text/plain; version=0.0.4";
+/// Limites en segundos de los buckets de los histogramas de tiempos de espera
+const BUCKETS_ESPERA: [f64; 10] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 2.5, 5.0, 10.0];
+const TIMEOUT_PEDIDO: Duration = Duration::from_secs(5);
+
+/// Arma el texto de las metricas en el formato de exposición de Prometheus
+#[derive(Default)]
+pub struct TextoMetricas {
+    texto: String,
+}
+
+impl TextoMetricas {
+    /// Agrega una metrica con su ayuda, su tipo (`counter` o `gauge`) y una muestra por
+    /// cada combinación de etiquetas
+    pub fn metrica<'a>(
+        &mut self,
+        nombre: &str,
+        tipo: &str,
+        ayuda: &str,
+        muestras: impl IntoIterator<Item = (Vec<(&'a str, String)>, f64)>,
+    ) {
+        self.encabezado(nombre, tipo, ayuda);
+        for (etiquetas, valor) in muestras {
+            self.muestra(nombre, &etiquetas, valor);
+        }
+    }
+
+    /// Metrica sin etiquetas
+    pub fn simple(&mut self, nombre: &str, tipo: &str, ayuda: &str, valor: f64) {
+        self.metrica(nombre, tipo, ayuda, [(vec![], valor)]);
+    }
+
+    pub fn histograma(&mut self, nombre: &str, ayuda: &str, histograma: &Histograma) {
+        self.encabezado(nombre, "histogram", ayuda);
+        let mut acumuladas = 0;
+        for (limite, cantidad) in histograma.limites.iter().zip(&histograma.cantidades) {
+            acumuladas += cantidad;
+            self.muestra(
+                &format!("{}_bucket", nombre),
+                &[("le", limite.to_string())],
+                acumuladas as f64,
+            );
+        }
+        self.muestra(
+            &format!("{}_bucket", nombre),
+            &[("le", "+Inf".to_string())],
+            histograma.total as f64,
+        );
+        self.muestra(&format!("{}_sum", nombre), &[], histograma.suma);
+        self.muestra(&format!("{}_count", nombre), &[], histograma.total as f64);
+    }
+
+    pub fn terminar(self) -> String {
+        self.texto
+    }
+
+    fn encabezado(&mut self, nombre: &str, tipo: &str, ayuda: &str) {
+        let _ = writeln!(self.texto, "# HELP {} {}", nombre, ayuda);
+        let _ = writeln!(self.texto, "# TYPE {} {}", nombre, tipo);
+    }
+
+    fn muestra(&mut self, nombre: &str, etiquetas: &[(&str, String)], valor: f64) {
+        self.texto.push_str(nombre);
+        if !etiquetas.is_empty() {
+            let etiquetas: Vec<String> = etiquetas
+                .iter()
+                .map(|(etiqueta, valor)| format!("{}=\"{}\"", etiqueta, escapar(valor)))
+                .collect();
+            let _ = write!(self.texto, "{{{}}}", etiquetas.join(","));
+        }
+        let _ = writeln!(self.texto, " {}", valor);
+    }
+}
+
+fn escapar(valor: &str) -> String {
+    valor
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Distribución de valores en buckets, como la cuenta Prometheus
+#[derive(Debug, Clone)]
+pub struct Histograma {
+    limites: &'static [f64],
+    /// Cantidad de valores de cada bucket, sin acumular
+    cantidades: Vec<u64>,
+    suma: f64,
+    total: u64,
+}
+
+impl Histograma {
+    pub fn new(limites: &'static [f64]) -> Self {
+        Histograma {
+            limites,
+            cantidades: vec![0; limites.len()],
+            suma: 0.0,
+            total: 0,
+        }
+    }
+
+    /// Histograma para tiempos de espera en segundos, de 1ms a 10s
+    pub fn de_esperas() -> Self {
+        Histograma::new(&BUCKETS_ESPERA)
+    }
+
+    pub fn observar(&mut self, valor: f64) {
+        if let Some(bucket) = self.limites.iter().position(|limite| valor <= *limite) {
+            self.cantidades[bucket] += 1;
+        }
+        self.suma += valor;
+        self.total += 1;
+    }
+}
+
+/// Cuenta los mensajes que esperan en el mailbox de un actor: quien envia suma al encolar
+/// y el actor resta al atender el mensaje
+#[derive(Debug, Clone, Default)]
+pub struct EnCola(Arc<AtomicUsize>);
+
+impl EnCola {
+    pub fn encolado(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn atendido(&self) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |cantidad| {
+                Some(cantidad.saturating_sub(1))
+            });
+    }
+
+    pub fn cantidad(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Metricas del despacho de ordenes de un ecommerce
+#[derive(Debug, Clone)]
+pub struct MetricasEcommerce {
+    pub ordenes_despachadas: u64,
+    pub aceptadas_por_local: BTreeMap<usize, u64>,
+    /// Ofertas que el local contestó sin aceptar: ocupado, sin stock o cerrando
+    pub rechazadas_por_local: BTreeMap<usize, u64>,
+    pub timeouts_por_local: BTreeMap<usize, u64>,
+    /// Ordenes que ningun local tomó, por motivo
+    pub sin_local: BTreeMap<&'static str, u64>,
+    /// Tiempo esperando el permiso del lider para leer la siguiente orden
+    pub espera_permiso: Histograma,
+}
+
+impl Default for MetricasEcommerce {
+    fn default() -> Self {
+        MetricasEcommerce {
+            ordenes_despachadas: 0,
+            aceptadas_por_local: BTreeMap::new(),
+            rechazadas_por_local: BTreeMap::new(),
+            timeouts_por_local: BTreeMap::new(),
+            sin_local: BTreeMap::new(),
+            espera_permiso: Histograma::de_esperas(),
+        }
+    }
+}
+
+impl MetricasEcommerce {
+    pub fn aceptada(&mut self, local: usize) {
+        *self.aceptadas_por_local.entry(local).or_default() += 1;
+    }
+
+    pub fn rechazada(&mut self, local: usize) {
+        *self.rechazadas_por_local.entry(local).or_default() += 1;
+    }
+
+    pub fn timeout(&mut self, local: usize) {
+        *self.timeouts_por_local.entry(local).or_default() += 1;
+    }
+
+    pub fn sin_local(&mut self, motivo: MotivoRechazo) {
+        *self.sin_local.entry(motivo.nombre()).or_default() += 1;
+    }
+
+    pub fn exponer(&self) -> String {
+        let por_local = |valores: &BTreeMap<usize, u64>| {
+            valores
+                .iter()
+                .map(|(local, cantidad)| (vec![("local", local.to_string())], *cantidad as f64))
+                .collect::<Vec<_>>()
+        };
+        let mut texto = TextoMetricas::default();
+        texto.simple(
+            "ecommerce_ordenes_despachadas_total",
+            "counter",
+            "Ordenes que el ecommerce intentó despachar",
+            self.ordenes_despachadas as f64,
+        );
+        texto.metrica(
+            "ecommerce_ordenes_aceptadas_total",
+            "counter",
+            "Ordenes aceptadas por cada local",
+            por_local(&self.aceptadas_por_local),
+        );
+        texto.metrica(
+            "ecommerce_ordenes_rechazadas_total",
+            "counter",
+            "Ofertas de ordenes que cada local contestó sin aceptar",
+            por_local(&self.rechazadas_por_local),
+        );
+        texto.metrica(
+            "ecommerce_timeouts_udp_total",
+            "counter",
+            "Ofertas de ordenes que cada local no contestó a tiempo",
+            por_local(&self.timeouts_por_local),
+        );
+        texto.metrica(
+            "ecommerce_ordenes_sin_local_total",
+            "counter",
+            "Ordenes que ningun local tomó, por motivo",
+            self.sin_local
+                .iter()
+                .map(|(motivo, cantidad)| (vec![("motivo", motivo.to_string())], *cantidad as f64)),
+        );
+        texto.histograma(
+            "ecommerce_espera_permiso_segundos",
+            "Tiempo esperando el permiso del lider para leer una orden",
+            &self.espera_permiso,
+        );
+        texto.terminar()
+    }
+}
+
+/// Atiende `GET /metrics` en la dirección dada, cada pedido arma las metricas con `exponer`.
+/// Bloquea el thread que lo llama
+pub fn servir_metricas(direccion: SocketAddr, exponer: impl Fn() -> String) {
+    let listener = match TcpListener::bind(direccion) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(target: "metricas", "No se pudo escuchar en {}: {}", direccion, e);
+            return;
+        }
+    };
+    info!(target: "metricas", direccion = %direccion, "Metricas en /metrics");
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => atender_pedido(stream, &exponer),
+            Err(e) => error!(target: "metricas", "Conexión http: {}", e),
+        }
+    }
+}
+
+fn atender_pedido(stream: TcpStream, exponer: &impl Fn() -> String) {
+    let _ = stream.set_read_timeout(Some(TIMEOUT_PEDIDO));
+    let respuesta = match leer_pedido(&mut BufReader::new(&stream)) {
+        Ok(pedido) if pedido.metodo == "GET" && pedido.ruta == "/metrics" => {
+            RespuestaHttp::texto(200, TIPO_CONTENIDO_PROMETHEUS, exponer())
+        }
+        Ok(_) => RespuestaHttp::texto(404, "text/plain", "Ruta desconocida".to_string()),
+        Err(e) => RespuestaHttp::texto(400, "text/plain", e.to_string()),
+    };
+    if let Err(e) = respuesta.escribir(&mut &stream) {
+        error!(target: "metricas", "No se pudo responder pedido http: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrica_con_etiquetas_en_formato_prometheus() {
+        let mut texto = TextoMetricas::default();
+        texto.metrica(
+            "local_stock",
+            "gauge",
+            "Stock por producto",
+            [
+                (vec![("id_producto", "1".to_string())], 30.0),
+                (vec![("id_producto", "a\"b".to_string())], 2.5),
+            ],
+        );
+
+        assert_eq!(
+            texto.terminar(),
+            "# HELP local_stock Stock por producto\n\
+             # TYPE local_stock gauge\n\
+             local_stock{id_producto=\"1\"} 30\n\
+             local_stock{id_producto=\"a\\\"b\"} 2.5\n"
+        );
+    }
+
+    #[test]
+    fn test_histograma_acumula_los_buckets() {
+        let mut histograma = Histograma::new(&[0.1, 1.0]);
+        histograma.observar(0.05);
+        histograma.observar(0.5);
+        histograma.observar(3.0);
+        let mut texto = TextoMetricas::default();
+        texto.histograma("espera", "Espera", &histograma);
+
+        let texto = texto.terminar();
+        assert!(texto.contains("espera_bucket{le=\"0.1\"} 1\n"));
+        assert!(texto.contains("espera_bucket{le=\"1\"} 2\n"));
+        assert!(texto.contains("espera_bucket{le=\"+Inf\"} 3\n"));
+        assert!(texto.contains("espera_sum 3.55\n"));
+        assert!(texto.contains("espera_count 3\n"));
+    }
+
+    #[test]
+    fn test_metricas_ecommerce_por_local_y_motivo() {
+        let mut metricas = MetricasEcommerce {
+            ordenes_despachadas: 2,
+            ..MetricasEcommerce::default()
+        };
+        metricas.aceptada(2);
+        metricas.timeout(1);
+        metricas.sin_local(MotivoRechazo::FueraDeCobertura);
+
+        let texto = metricas.exponer();
+        assert!(texto.contains("ecommerce_ordenes_despachadas_total 2\n"));
+        assert!(texto.contains("ecommerce_ordenes_aceptadas_total{local=\"2\"} 1\n"));
+        assert!(texto.contains("ecommerce_timeouts_udp_total{local=\"1\"} 1\n"));
+        assert!(
+            texto.contains("ecommerce_ordenes_sin_local_total{motivo=\"fuera_de_cobertura\"} 1\n")
+        );
+    }
+
+    #[test]
+    fn test_en_cola_no_baja_de_cero() {
+        let en_cola = EnCola::default();
+        en_cola.encolado();
+        en_cola.atendido();
+        en_cola.atendido();
+        assert_eq!(en_cola.cantidad(), 0);
+    }
+}
@@ -1,29 +1,43 @@
+use crate::common::error_csv::ErrorCsv;
+use crate::common::lector_csv::{leer_columna, leer_columna_opcional, linea};
+use crate::common::trazas::ContextoTraza;
 use csv::StringRecord;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
 use std::fmt;
 
+/// Radio de la tierra en km
+pub const RADIO_TIERRA_KM: f64 = 6371.0;
+
+/// Ubicación en grados decimales
 #[derive(Debug, PartialEq, PartialOrd, Serialize, Deserialize, Clone)]
 pub struct Direccion {
-    latitud: i32,
-    longitud: i32,
+    latitud: f64,
+    longitud: f64,
 }
 
 impl Direccion {
-    pub fn new(latitud: i32, longitud: i32) -> Self {
+    pub fn new(latitud: f64, longitud: f64) -> Self {
         Direccion { latitud, longitud }
     }
 
+    pub fn latitud(&self) -> f64 {
+        self.latitud
+    }
+
+    pub fn longitud(&self) -> f64 {
+        self.longitud
+    }
+
     pub fn distancia(&self, other: &Direccion) -> f64 {
         // Formula Haversine
-        const R: f64 = 6371.0; // Radio de la tierra
+        const R: f64 = RADIO_TIERRA_KM;
 
-        let d_lat = ((other.latitud - self.latitud) as f64).to_radians();
-        let d_lon = ((other.longitud - self.longitud) as f64).to_radians();
+        let d_lat = (other.latitud - self.latitud).to_radians();
+        let d_lon = (other.longitud - self.longitud).to_radians();
 
         let a = (d_lat / 2.0).sin() * (d_lat / 2.0).sin()
-            + (self.latitud as f64).to_radians().cos()
-                * (other.latitud as f64).to_radians().cos()
+            + self.latitud.to_radians().cos()
+                * other.latitud.to_radians().cos()
                 * (d_lon / 2.0).sin()
                 * (d_lon / 2.0).sin();
 
@@ -31,49 +45,88 @@ impl Direccion {
 
         R * c
     }
+
+    /// La latitud tiene que estar entre -90 y 90 y la longitud entre -180 y 180
+    pub fn validar(&self) -> Result<(), String> {
+        if !(-90.0..=90.0).contains(&self.latitud) {
+            return Err(format!("latitud {} fuera de rango", self.latitud));
+        }
+        if !(-180.0..=180.0).contains(&self.longitud) {
+            return Err(format!("longitud {} fuera de rango", self.longitud));
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, PartialOrd, Serialize, Deserialize, Clone)]
 pub struct Orden {
     pub id_producto: usize,
     pub cantidad: usize,
     pub direccion: Direccion,
+    /// Clase de prioridad de la orden, a mayor valor mayor prioridad
+    #[serde(default)]
+    pub prioridad: u8,
+    /// Momento limite de entrega en milisegundos, None si la orden no vence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vencimiento: Option<u64>,
+    /// Identificador que le asigna el ecommerce al despacharla, para seguirla en los logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Span del proceso que envió la orden, para continuar su traza
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traza: Option<ContextoTraza>,
 }
 
 impl Orden {
     /// Solo para testing
-    pub fn new(id_producto: usize, cantidad: usize, latitud: i32, longitud: i32) -> Orden {
+    pub fn new(id_producto: usize, cantidad: usize, latitud: f64, longitud: f64) -> Orden {
         Orden {
             id_producto,
             cantidad,
             direccion: Direccion::new(latitud, longitud),
+            prioridad: 0,
+            vencimiento: None,
+            id: None,
+            traza: None,
         }
     }
 
     /// Crea Orden a partir de un record de csv
-    pub fn from_record(record: StringRecord) -> Result<Self, Box<dyn Error>> {
-        let id_producto = record
-            .get(0)
-            .ok_or("Orden no tiene id_producto")?
-            .parse::<usize>()?;
-        let cantidad = record
-            .get(1)
-            .ok_or("Orden no tiene cantidad")?
-            .parse::<usize>()?;
-        let latitud = record
-            .get(2)
-            .ok_or("Orden no tiene latitud")?
-            .parse::<i32>()?;
-        let longitud = record
-            .get(3)
-            .ok_or("Orden no tiene longitud")?
-            .parse::<i32>()?;
-
-        Ok(Orden {
+    pub fn from_record(record: StringRecord) -> Result<Self, ErrorCsv> {
+        let linea = linea(&record);
+        let id_producto = leer_columna(&record, 0, "id_producto")?;
+        let cantidad = leer_columna(&record, 1, "cantidad")?;
+        let latitud = leer_columna(&record, 2, "latitud")?;
+        let longitud = leer_columna(&record, 3, "longitud")?;
+        let prioridad = leer_columna_opcional(&record, 4, "prioridad")?.unwrap_or(0);
+
+        let orden = Orden {
             id_producto,
             cantidad,
             direccion: Direccion::new(latitud, longitud),
-        })
+            prioridad,
+            vencimiento: None,
+            id: None,
+            traza: None,
+        };
+        orden
+            .validar()
+            .map_err(|motivo| ErrorCsv::FilaInvalida { linea, motivo })?;
+        Ok(orden)
+    }
+
+    /// Una orden tiene que pedir al menos una unidad y tener una dirección valida
+    pub fn validar(&self) -> Result<(), String> {
+        if self.cantidad == 0 {
+            return Err("la cantidad tiene que ser mayor a 0".to_string());
+        }
+        self.direccion.validar()
+    }
+
+    /// Indica si la orden tiene vencimiento y ya pasó el momento `ahora` en milisegundos
+    pub fn vencida(&self, ahora: u64) -> bool {
+        self.vencimiento
+            .is_some_and(|vencimiento| vencimiento <= ahora)
     }
 
     /// Crea orden a partir de un array de bytes en json
@@ -99,12 +152,16 @@ mod tests {
         let orden = Orden {
             id_producto: 1,
             cantidad: 5,
-            direccion: Direccion::new(32, 43),
+            direccion: Direccion::new(32.0, 43.0),
+            prioridad: 0,
+            vencimiento: None,
+            id: None,
+            traza: None,
         };
         let json = json!(orden).to_string();
         let orden_serializada = json.as_bytes();
 
-        let orden_deserializada = Orden::deserializar(&orden_serializada).unwrap();
+        let orden_deserializada = Orden::deserializar(orden_serializada).unwrap();
 
         assert_eq!(orden_deserializada.cantidad, orden.cantidad);
         assert_eq!(orden_deserializada.id_producto, orden.id_producto);
@@ -115,7 +172,11 @@ mod tests {
         let orden = Orden {
             id_producto: 1,
             cantidad: 256,
-            direccion: Direccion::new(12, 43),
+            direccion: Direccion::new(12.0, 43.0),
+            prioridad: 0,
+            vencimiento: None,
+            id: None,
+            traza: None,
         };
         let json = json!(orden).to_string();
         let orden_serializada = json.as_bytes();
@@ -127,7 +188,11 @@ mod tests {
             Orden {
                 id_producto: 1,
                 cantidad: 256,
-                direccion: Direccion::new(12, 43)
+                direccion: Direccion::new(12.0, 43.0),
+                prioridad: 0,
+                vencimiento: None,
+                id: None,
+                traza: None,
             }
         );
     }
@@ -137,7 +202,11 @@ mod tests {
         let orden = Orden {
             id_producto: 1,
             cantidad: 123456,
-            direccion: Direccion::new(33, 19),
+            direccion: Direccion::new(33.0, 19.0),
+            prioridad: 0,
+            vencimiento: None,
+            id: None,
+            traza: None,
         };
         let json = json!(orden).to_string();
         let orden_serializada = json.as_bytes();
@@ -149,21 +218,58 @@ mod tests {
             Orden {
                 id_producto: 1,
                 cantidad: 123456,
-                direccion: Direccion::new(33, 19)
+                direccion: Direccion::new(33.0, 19.0),
+                prioridad: 0,
+                vencimiento: None,
+                id: None,
+                traza: None,
             }
         );
     }
 
     #[test]
     fn test_distancia_al_mismo_punto_es_cero() {
-        let direccion = Direccion::new(10, 20);
+        let direccion = Direccion::new(10.0, 20.0);
         assert_eq!(direccion.distancia(&direccion), 0.0);
     }
 
     #[test]
     fn test_dsitancia_entre_dos_puntos() {
-        let direccion = Direccion::new(45, 45);
-        let direccion_2 = Direccion::new(50, 50);
+        let direccion = Direccion::new(45.0, 45.0);
+        let direccion_2 = Direccion::new(50.0, 50.0);
         assert!((direccion.distancia(&direccion_2) - 671.27f64) < 1.0f64);
     }
+
+    #[test]
+    fn test_distancia_entre_puntos_de_una_misma_ciudad() {
+        let obelisco = Direccion::new(-34.603722, -58.381592);
+        let congreso = Direccion::new(-34.609833, -58.392167);
+        let distancia = obelisco.distancia(&congreso);
+        assert!((distancia - 1.19).abs() < 0.05, "distancia {}", distancia);
+    }
+
+    #[test]
+    fn test_from_record_lee_coordenadas_decimales_y_valida_rango() {
+        let orden = Orden::from_record(StringRecord::from(vec!["1", "2", "-34.6037", "-58.3816"]));
+        assert_eq!(orden.unwrap().direccion, Direccion::new(-34.6037, -58.3816));
+
+        let fuera_de_rango = Orden::from_record(StringRecord::from(vec!["1", "2", "-90.5", "0"]));
+        assert!(matches!(fuera_de_rango, Err(ErrorCsv::FilaInvalida { .. })));
+        let no_es_numero = Orden::from_record(StringRecord::from(vec!["1", "2", "NaN", "0"]));
+        assert!(no_es_numero.is_err());
+    }
+
+    #[test]
+    fn test_orden_sin_vencimiento_nunca_vence() {
+        let orden = Orden::new(1, 5, 3.0, 3.0);
+        assert!(!orden.vencida(u64::MAX));
+    }
+
+    #[test]
+    fn test_orden_vence_al_llegar_a_su_vencimiento() {
+        let mut orden = Orden::new(1, 5, 3.0, 3.0);
+        orden.vencimiento = Some(100);
+        assert!(!orden.vencida(99));
+        assert!(orden.vencida(100));
+    }
 }
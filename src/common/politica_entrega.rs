@@ -0,0 +1,190 @@
+use crate::common::orden::Orden;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::str::FromStr;
+
+/// Criterio con el que se elige cual de las ordenes en progreso se procesa
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CriterioDeSeleccion {
+    /// La orden que llegó primero
+    Fifo,
+    /// La orden con el vencimiento mas cercano, las que no vencen van al final
+    PrimeroVencimiento,
+    /// La orden con mayor prioridad, entre iguales la que llegó primero
+    Prioridad,
+    /// Una orden cualquiera, pensado para pruebas de caos
+    Aleatorio,
+}
+
+impl FromStr for CriterioDeSeleccion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fifo" => Ok(CriterioDeSeleccion::Fifo),
+            "vencimiento" => Ok(CriterioDeSeleccion::PrimeroVencimiento),
+            "prioridad" => Ok(CriterioDeSeleccion::Prioridad),
+            "aleatoria" => Ok(CriterioDeSeleccion::Aleatorio),
+            otro => Err(format!("Politica de entrega desconocida: {}", otro)),
+        }
+    }
+}
+
+impl fmt::Display for CriterioDeSeleccion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CriterioDeSeleccion::Fifo => write!(f, "fifo"),
+            CriterioDeSeleccion::PrimeroVencimiento => write!(f, "vencimiento"),
+            CriterioDeSeleccion::Prioridad => write!(f, "prioridad"),
+            CriterioDeSeleccion::Aleatorio => write!(f, "aleatoria"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Accion {
+    Entregar,
+    Cancelar,
+}
+
+/// Decide que orden en progreso procesar y si se entrega o se cancela.
+/// Toda decisión aleatoria sale de un rng con semilla, asi una simulación se puede
+/// repetir exactamente usando la misma semilla
+pub struct PoliticaDeEntrega {
+    criterio: CriterioDeSeleccion,
+    probabilidad_cancelacion: f64,
+    semilla: u64,
+    rng: StdRng,
+}
+
+impl PoliticaDeEntrega {
+    pub fn new(
+        criterio: CriterioDeSeleccion,
+        probabilidad_cancelacion: f64,
+        semilla: u64,
+    ) -> PoliticaDeEntrega {
+        PoliticaDeEntrega {
+            criterio,
+            probabilidad_cancelacion,
+            semilla,
+            rng: StdRng::seed_from_u64(semilla),
+        }
+    }
+
+    pub fn criterio(&self) -> CriterioDeSeleccion {
+        self.criterio
+    }
+
+    pub fn semilla(&self) -> u64 {
+        self.semilla
+    }
+
+    /// Devuelve el indice de la orden a procesar y la acción a realizar,
+    /// None si no hay ordenes en progreso
+    pub fn elegir(&mut self, ordenes: &[Orden]) -> Option<(usize, Accion)> {
+        if ordenes.is_empty() {
+            return None;
+        }
+        let indice = match self.criterio {
+            CriterioDeSeleccion::Fifo => 0,
+            CriterioDeSeleccion::PrimeroVencimiento => ordenes
+                .iter()
+                .enumerate()
+                .min_by_key(|(indice, orden)| (orden.vencimiento.unwrap_or(u64::MAX), *indice))
+                .map(|(indice, _)| indice)?,
+            CriterioDeSeleccion::Prioridad => ordenes
+                .iter()
+                .enumerate()
+                .max_by_key(|(indice, orden)| (orden.prioridad, usize::MAX - *indice))
+                .map(|(indice, _)| indice)?,
+            CriterioDeSeleccion::Aleatorio => self.rng.gen_range(0..ordenes.len()),
+        };
+        let accion = if self.rng.gen_bool(self.probabilidad_cancelacion) {
+            Accion::Cancelar
+        } else {
+            Accion::Entregar
+        };
+        Some((indice, accion))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crear_orden(prioridad: u8, vencimiento: Option<u64>) -> Orden {
+        let mut orden = Orden::new(1, 1, 0.0, 0.0);
+        orden.prioridad = prioridad;
+        orden.vencimiento = vencimiento;
+        orden
+    }
+
+    #[test]
+    fn test_sin_ordenes_no_elige_nada() {
+        let mut politica = PoliticaDeEntrega::new(CriterioDeSeleccion::Fifo, 0.0, 1);
+
+        assert_eq!(politica.elegir(&[]), None);
+    }
+
+    #[test]
+    fn test_fifo_elige_la_primera_orden_y_sin_probabilidad_de_cancelacion_la_entrega() {
+        let mut politica = PoliticaDeEntrega::new(CriterioDeSeleccion::Fifo, 0.0, 1);
+        let ordenes = vec![crear_orden(0, None), crear_orden(5, Some(10))];
+
+        assert_eq!(politica.elegir(&ordenes), Some((0, Accion::Entregar)));
+    }
+
+    #[test]
+    fn test_primero_vencimiento_elige_la_orden_que_vence_antes_y_deja_al_final_las_que_no_vencen() {
+        let mut politica = PoliticaDeEntrega::new(CriterioDeSeleccion::PrimeroVencimiento, 0.0, 1);
+        let ordenes = vec![
+            crear_orden(0, None),
+            crear_orden(0, Some(300)),
+            crear_orden(0, Some(100)),
+        ];
+
+        assert_eq!(politica.elegir(&ordenes), Some((2, Accion::Entregar)));
+    }
+
+    #[test]
+    fn test_prioridad_elige_la_de_mayor_prioridad_y_entre_iguales_la_que_llego_primero() {
+        let mut politica = PoliticaDeEntrega::new(CriterioDeSeleccion::Prioridad, 0.0, 1);
+        let ordenes = vec![
+            crear_orden(1, None),
+            crear_orden(3, None),
+            crear_orden(3, None),
+        ];
+
+        assert_eq!(politica.elegir(&ordenes), Some((1, Accion::Entregar)));
+    }
+
+    #[test]
+    fn test_con_probabilidad_de_cancelacion_1_siempre_cancela() {
+        let mut politica = PoliticaDeEntrega::new(CriterioDeSeleccion::Fifo, 1.0, 1);
+        let ordenes = vec![crear_orden(0, None)];
+
+        assert_eq!(politica.elegir(&ordenes), Some((0, Accion::Cancelar)));
+    }
+
+    #[test]
+    fn test_politica_aleatoria_con_la_misma_semilla_repite_las_mismas_decisiones() {
+        let ordenes: Vec<Orden> = (0..10).map(|_| crear_orden(0, None)).collect();
+        let mut politica_1 = PoliticaDeEntrega::new(CriterioDeSeleccion::Aleatorio, 0.5, 42);
+        let mut politica_2 = PoliticaDeEntrega::new(CriterioDeSeleccion::Aleatorio, 0.5, 42);
+
+        let decisiones_1: Vec<_> = (0..20).map(|_| politica_1.elegir(&ordenes)).collect();
+        let decisiones_2: Vec<_> = (0..20).map(|_| politica_2.elegir(&ordenes)).collect();
+
+        assert_eq!(decisiones_1, decisiones_2);
+    }
+
+    #[test]
+    fn test_criterio_desde_texto() {
+        assert_eq!(
+            "vencimiento".parse::<CriterioDeSeleccion>(),
+            Ok(CriterioDeSeleccion::PrimeroVencimiento)
+        );
+        assert!("otra".parse::<CriterioDeSeleccion>().is_err());
+    }
+}
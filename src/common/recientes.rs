@@ -0,0 +1,72 @@
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+
+/// Conjunto de los ultimos ids vistos, al llenarse olvida los más viejos. Sirve para
+/// reconocer mensajes duplicados sin guardar todos los ids para siempre
+pub struct IdsRecientes<T> {
+    capacidad: usize,
+    ids: HashSet<T>,
+    orden: VecDeque<T>,
+}
+
+impl<T: Eq + Hash + Clone> IdsRecientes<T> {
+    pub fn new(capacidad: usize) -> IdsRecientes<T> {
+        IdsRecientes {
+            capacidad,
+            ids: HashSet::new(),
+            orden: VecDeque::new(),
+        }
+    }
+
+    /// Agrega el id, devuelve false si ya estaba
+    pub fn agregar(&mut self, id: T) -> bool {
+        if !self.ids.insert(id.clone()) {
+            return false;
+        }
+        self.orden.push_back(id);
+        if self.orden.len() > self.capacidad {
+            if let Some(viejo) = self.orden.pop_front() {
+                self.ids.remove(&viejo);
+            }
+        }
+        true
+    }
+
+    pub fn contiene(&self, id: &T) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// Saca el id, devuelve si estaba
+    pub fn quitar(&mut self, id: &T) -> bool {
+        if !self.ids.remove(id) {
+            return false;
+        }
+        self.orden.retain(|otro| otro != id);
+        true
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.orden.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconoce_repetidos_y_olvida_los_mas_viejos() {
+        let mut recientes = IdsRecientes::new(2);
+
+        assert!(recientes.agregar(1));
+        assert!(!recientes.agregar(1));
+        assert!(recientes.agregar(2));
+        assert!(recientes.agregar(3));
+
+        assert!(!recientes.contiene(&1));
+        assert!(recientes.contiene(&2));
+        assert!(recientes.quitar(&2));
+        assert!(!recientes.quitar(&2));
+        assert_eq!(recientes.iter().collect::<Vec<_>>(), vec![&3]);
+    }
+}
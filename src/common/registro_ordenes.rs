@@ -0,0 +1,235 @@
+use crate::common::mensaje_local::MotivoCancelacion;
+use crate::common::orden::Orden;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/// Estado de una orden recibida por la api http del ecommerce
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "estado", rename_all = "snake_case")]
+pub enum EstadoOrden {
+    /// Todavia no se le envió a ningun local
+    Pendiente,
+    Aceptada {
+        local: usize,
+    },
+    Rechazada {
+        motivo: MotivoRechazo,
+    },
+    /// El local que la aceptó la canceló despues
+    Cancelada {
+        local: usize,
+        motivo: MotivoCancelacion,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MotivoRechazo {
+    /// Ningun local entrega en la dirección de la orden
+    FueraDeCobertura,
+    /// Ningun local de los que la cubren pudo aceptarla
+    SinLocalDisponible,
+    /// El local que la podia tomar no contestó la confirmación, puede haberla aceptado
+    SinConfirmacion,
+}
+
+impl MotivoRechazo {
+    /// Nombre del motivo como aparece en el json
+    pub fn nombre(&self) -> &'static str {
+        match self {
+            MotivoRechazo::FueraDeCobertura => "fuera_de_cobertura",
+            MotivoRechazo::SinLocalDisponible => "sin_local_disponible",
+            MotivoRechazo::SinConfirmacion => "sin_confirmacion",
+        }
+    }
+}
+
+impl fmt::Display for MotivoRechazo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MotivoRechazo::FueraDeCobertura => {
+                write!(f, "la dirección está fuera de la cobertura de los locales")
+            }
+            MotivoRechazo::SinLocalDisponible => write!(f, "ningun local pudo aceptar la orden"),
+            MotivoRechazo::SinConfirmacion => {
+                write!(
+                    f,
+                    "el local no contestó la confirmación, no se sabe si la aceptó"
+                )
+            }
+        }
+    }
+}
+
+/// Cantidad de ordenes ya despachadas de las que se recuerda el estado
+const ORDENES_TERMINADAS_RECORDADAS: usize = 10_000;
+
+/// Ordenes recibidas por http que esperan ser despachadas y el estado de cada una
+pub struct RegistroOrdenes {
+    proximo_id: u64,
+    pendientes: VecDeque<(u64, Orden)>,
+    estados: HashMap<u64, EstadoOrden>,
+    /// Ordenes ya despachadas en el orden en que terminaron, al pasar el maximo se
+    /// olvida el estado de las más viejas
+    terminadas: VecDeque<u64>,
+    maximo_terminadas: usize,
+}
+
+impl Default for RegistroOrdenes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegistroOrdenes {
+    pub fn new() -> RegistroOrdenes {
+        RegistroOrdenes {
+            proximo_id: 1,
+            pendientes: VecDeque::new(),
+            estados: HashMap::new(),
+            terminadas: VecDeque::new(),
+            maximo_terminadas: ORDENES_TERMINADAS_RECORDADAS,
+        }
+    }
+
+    pub fn con_maximo_terminadas(mut self, maximo: usize) -> RegistroOrdenes {
+        self.maximo_terminadas = maximo;
+        self
+    }
+
+    /// Encola la orden para despacharla y devuelve su id
+    pub fn agregar(&mut self, orden: Orden) -> u64 {
+        let id = self.proximo_id;
+        self.proximo_id += 1;
+        self.pendientes.push_back((id, orden));
+        self.estados.insert(id, EstadoOrden::Pendiente);
+        id
+    }
+
+    /// Saca la orden pendiente más antigua
+    pub fn siguiente_pendiente(&mut self) -> Option<(u64, Orden)> {
+        self.pendientes.pop_front()
+    }
+
+    /// Vuelve a poner primera una orden que se sacó y no se pudo despachar
+    pub fn devolver_pendiente(&mut self, id: u64, orden: Orden) {
+        self.pendientes.push_front((id, orden));
+    }
+
+    /// Guarda el estado de la orden despachada y olvida el de la más vieja si se pasa del maximo
+    pub fn actualizar(&mut self, id: u64, estado: EstadoOrden) {
+        if self.estados.insert(id, estado) == Some(EstadoOrden::Pendiente) {
+            self.terminadas.push_back(id);
+        }
+        while self.terminadas.len() > self.maximo_terminadas {
+            if let Some(vieja) = self.terminadas.pop_front() {
+                self.estados.remove(&vieja);
+            }
+        }
+    }
+
+    /// Marca como cancelada una orden aceptada, solo si la canceló el local que la aceptó
+    pub fn cancelar(&mut self, id: u64, local: usize, motivo: MotivoCancelacion) {
+        if let Some(estado) = self.estados.get_mut(&id) {
+            if *estado == (EstadoOrden::Aceptada { local }) {
+                *estado = EstadoOrden::Cancelada { local, motivo };
+            }
+        }
+    }
+
+    pub fn estado(&self, id: u64) -> Option<EstadoOrden> {
+        self.estados.get(&id).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_ordenes_se_despachan_en_orden_de_llegada() {
+        let mut registro = RegistroOrdenes::new();
+        let primera = registro.agregar(Orden::new(1, 5, 3.0, 3.0));
+        let segunda = registro.agregar(Orden::new(2, 1, 3.0, 3.0));
+
+        assert_eq!(registro.siguiente_pendiente().unwrap().0, primera);
+        assert_eq!(registro.siguiente_pendiente().unwrap().0, segunda);
+        assert!(registro.siguiente_pendiente().is_none());
+    }
+
+    #[test]
+    fn test_estado_de_orden_pendiente_y_despachada() {
+        let mut registro = RegistroOrdenes::new();
+        let id = registro.agregar(Orden::new(1, 5, 3.0, 3.0));
+
+        assert_eq!(registro.estado(id), Some(EstadoOrden::Pendiente));
+        registro.actualizar(id, EstadoOrden::Aceptada { local: 2 });
+        assert_eq!(
+            registro.estado(id),
+            Some(EstadoOrden::Aceptada { local: 2 })
+        );
+        assert_eq!(registro.estado(id + 1), None);
+    }
+
+    #[test]
+    fn test_se_olvida_el_estado_de_las_ordenes_terminadas_mas_viejas() {
+        let mut registro = RegistroOrdenes::new().con_maximo_terminadas(1);
+        let primera = registro.agregar(Orden::new(1, 5, 3.0, 3.0));
+        let segunda = registro.agregar(Orden::new(2, 1, 3.0, 3.0));
+        let pendiente = registro.agregar(Orden::new(2, 1, 3.0, 3.0));
+
+        registro.actualizar(primera, EstadoOrden::Aceptada { local: 2 });
+        registro.actualizar(segunda, EstadoOrden::Aceptada { local: 1 });
+
+        assert_eq!(registro.estado(primera), None);
+        assert_eq!(
+            registro.estado(segunda),
+            Some(EstadoOrden::Aceptada { local: 1 })
+        );
+        assert_eq!(registro.estado(pendiente), Some(EstadoOrden::Pendiente));
+    }
+
+    #[test]
+    fn test_solo_el_local_que_acepto_la_orden_puede_cancelarla() {
+        let mut registro = RegistroOrdenes::new();
+        let id = registro.agregar(Orden::new(1, 5, 3.0, 3.0));
+        registro.actualizar(id, EstadoOrden::Aceptada { local: 2 });
+
+        registro.cancelar(id, 3, MotivoCancelacion::Vencida);
+        assert_eq!(
+            registro.estado(id),
+            Some(EstadoOrden::Aceptada { local: 2 })
+        );
+        registro.cancelar(id, 2, MotivoCancelacion::Vencida);
+        assert_eq!(
+            registro.estado(id),
+            Some(EstadoOrden::Cancelada {
+                local: 2,
+                motivo: MotivoCancelacion::Vencida
+            })
+        );
+    }
+
+    #[test]
+    fn test_serializacion_de_estado() {
+        assert_eq!(
+            json!(EstadoOrden::Aceptada { local: 1 }),
+            json!({ "estado": "aceptada", "local": 1 })
+        );
+        assert_eq!(
+            json!(EstadoOrden::Rechazada {
+                motivo: MotivoRechazo::FueraDeCobertura
+            }),
+            json!({ "estado": "rechazada", "motivo": "fuera_de_cobertura" })
+        );
+        assert_eq!(
+            json!(EstadoOrden::Cancelada {
+                local: 2,
+                motivo: MotivoCancelacion::Vencida
+            }),
+            json!({ "estado": "cancelada", "local": 2, "motivo": "vencida" })
+        );
+    }
+}
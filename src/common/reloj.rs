@@ -0,0 +1,87 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Milisegundos desde el epoch unix, se usa para los vencimientos de las ordenes
+pub fn ahora_en_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duracion| duracion.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Fuente del tiempo, para poder simular el paso del tiempo en las pruebas
+pub trait Reloj: Send + Sync {
+    fn ahora_en_ms(&self) -> u64;
+    fn dormir(&self, duracion: Duration);
+
+    /// Espera hasta `espera` un paquete en el socket. Un reloj que no es el del sistema
+    /// tiene que hacer pasar su tiempo mientras espera
+    fn recibir_de(
+        &self,
+        socket: &UdpSocket,
+        buffer: &mut [u8],
+        espera: Duration,
+    ) -> io::Result<(usize, SocketAddr)> {
+        socket.set_read_timeout(Some(espera))?;
+        socket.recv_from(buffer)
+    }
+}
+
+/// El reloj del sistema
+pub struct RelojSistema;
+
+impl Reloj for RelojSistema {
+    fn ahora_en_ms(&self) -> u64 {
+        ahora_en_ms()
+    }
+
+    fn dormir(&self, duracion: Duration) {
+        sleep(duracion)
+    }
+}
+
+/// Reloj que solo avanza cuando alguien duerme o se lo avanza a mano, dormir no espera
+#[derive(Default)]
+pub struct RelojSimulado {
+    ms: AtomicU64,
+}
+
+impl RelojSimulado {
+    pub fn avanzar(&self, duracion: Duration) {
+        self.ms
+            .fetch_add(duracion.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Reloj for RelojSimulado {
+    fn ahora_en_ms(&self) -> u64 {
+        self.ms.load(Ordering::SeqCst)
+    }
+
+    fn dormir(&self, duracion: Duration) {
+        self.avanzar(duracion)
+    }
+
+    /// Espera de verdad y, si no llega nada, avanza el reloj lo que esperó
+    fn recibir_de(
+        &self,
+        socket: &UdpSocket,
+        buffer: &mut [u8],
+        espera: Duration,
+    ) -> io::Result<(usize, SocketAddr)> {
+        socket.set_read_timeout(Some(espera))?;
+        let recibido = socket.recv_from(buffer);
+        if recibido.as_ref().is_err_and(|e| {
+            matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            )
+        }) {
+            self.avanzar(espera);
+        }
+        recibido
+    }
+}
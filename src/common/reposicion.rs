@@ -0,0 +1,100 @@
+use crate::common::error_csv::ErrorCsv;
+use crate::common::lector_csv::leer_columna;
+use crate::common::local::Productos;
+use csv::StringRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Cantidad de unidades de un producto que ingresan al local
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Reposicion {
+    pub id_producto: usize,
+    pub cantidad: usize,
+}
+
+impl Reposicion {
+    pub fn new(id_producto: usize, cantidad: usize) -> Reposicion {
+        Reposicion {
+            id_producto,
+            cantidad,
+        }
+    }
+
+    /// Crea Reposicion a partir de un record de csv con id_producto y cantidad
+    pub fn from_record(record: StringRecord) -> Result<Self, ErrorCsv> {
+        let id_producto = leer_columna(&record, 0, "id_producto")?;
+        let cantidad = leer_columna(&record, 1, "cantidad")?;
+
+        Ok(Reposicion::new(id_producto, cantidad))
+    }
+}
+
+/// Pide reposición de los productos cuyas unidades disponibles quedan en el umbral o por debajo.
+/// Mientras un pedido esta pendiente no se vuelve a pedir el mismo producto
+pub struct ReposicionAutomatica {
+    umbral: usize,
+    cantidad: usize,
+    pendientes: HashSet<usize>,
+}
+
+impl ReposicionAutomatica {
+    pub fn new(umbral: usize, cantidad: usize) -> ReposicionAutomatica {
+        ReposicionAutomatica {
+            umbral,
+            cantidad,
+            pendientes: HashSet::new(),
+        }
+    }
+
+    /// Devuelve los pedidos de reposición nuevos para los productos con stock bajo
+    pub fn pedidos(&mut self, productos: &Productos) -> Vec<Reposicion> {
+        let mut pedidos: Vec<Reposicion> = productos
+            .values()
+            .filter(|producto| producto.disponibles() <= self.umbral)
+            .filter(|producto| self.pendientes.insert(producto.id_producto))
+            .map(|producto| Reposicion::new(producto.id_producto, self.cantidad))
+            .collect();
+        pedidos.sort_by_key(|pedido| pedido.id_producto);
+        pedidos
+    }
+
+    /// Marca que llegó reposición del producto, si vuelve a bajar del umbral se pide de nuevo
+    pub fn recibida(&mut self, id_producto: usize) {
+        self.pendientes.remove(&id_producto);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::stock_producto::StockProducto;
+    use std::collections::HashMap;
+
+    fn crear_productos() -> Productos {
+        let mut productos: Productos = HashMap::new();
+        productos.insert(1, StockProducto::new_con_bloqueados(1, 10, 8));
+        productos.insert(2, StockProducto::new(2, 30));
+        productos
+    }
+
+    #[test]
+    fn test_pide_reposicion_de_productos_con_disponibles_en_el_umbral() {
+        let mut reposicion = ReposicionAutomatica::new(2, 20);
+
+        let pedidos = reposicion.pedidos(&crear_productos());
+
+        assert_eq!(pedidos, vec![Reposicion::new(1, 20)]);
+    }
+
+    #[test]
+    fn test_no_repite_pedido_pendiente_hasta_recibir_la_reposicion() {
+        let mut reposicion = ReposicionAutomatica::new(2, 20);
+        let productos = crear_productos();
+        let _ = reposicion.pedidos(&productos);
+
+        assert!(reposicion.pedidos(&productos).is_empty());
+
+        reposicion.recibida(1);
+        assert_eq!(reposicion.pedidos(&productos), vec![Reposicion::new(1, 20)]);
+    }
+}
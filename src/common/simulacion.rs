@@ -0,0 +1,948 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::io;
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+use actix_rt::{System, SystemRunner};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::runtime::{Builder, Handle};
+use tokio::time::{sleep, Instant};
+
+use crate::common::actor_local::{
+    iniciar_actores, instanciar_local, recuperar_local, ActoresLocal, Caer, Cerrar,
+    ConfiguracionActores,
+};
+use crate::common::despacho::{Despachador, Turno};
+use crate::common::fallas::{
+    nombre_ecommerce, nombre_local, Caida, ConfiguracionFallas, Fallas, SocketUdp,
+};
+use crate::common::fuente_ordenes::FuenteMemoria;
+use crate::common::historial::{Evento, Historial, HistorialEnMemoria, Operacion, OperacionStock};
+use crate::common::info_local::{Cobertura, InfoLocal, MapaLocales};
+use crate::common::local::{Local, Productos};
+use crate::common::metricas::MetricasEcommerce;
+use crate::common::orden::{Direccion, Orden};
+use crate::common::politica_entrega::{CriterioDeSeleccion, PoliticaDeEntrega};
+use crate::common::registro_ordenes::MotivoRechazo;
+use crate::common::reloj::Reloj;
+use crate::common::socket::Socket;
+use crate::common::stock_producto::StockProducto;
+
+/// Lo que avanza el tiempo después de cada turno de un ecommerce
+const ESPERA_ENTRE_TURNOS: Duration = Duration::from_millis(250);
+/// Lo más que avanza el tiempo de una vez, así los actores atienden cada paquete apenas llega
+const PASO: Duration = Duration::from_millis(1);
+/// Tiempo entre entregas del Job de ordenes de cada local
+const INTERVALO_ENTREGAS: Duration = Duration::from_millis(500);
+/// Cada cuanto los ecommerce confirman los avisos de los locales mientras no despachan
+const INTERVALO_AVISOS: Duration = Duration::from_millis(100);
+/// Lo más que se espera a que terminen de cerrar los locales
+const ESPERA_CIERRE: Duration = Duration::from_secs(10);
+const ID_LIDER: u32 = 1;
+/// Centro de la ciudad simulada, los locales y las ordenes se reparten alrededor
+const CENTRO: (f64, f64) = (-34.60, -58.40);
+const TAMANIO_BUFFER: usize = 2048;
+
+thread_local! {
+    /// Sistema de actix de la simulación que corre en este thread
+    static SISTEMA: RefCell<Option<SystemRunner>> = const { RefCell::new(None) };
+    /// Locales de la simulación que corre en este thread, el reloj los tira y los levanta
+    static LOCALES: RefCell<Vec<LocalSimulado>> = const { RefCell::new(vec![]) };
+}
+
+/// Corre los actores de la simulación hasta que termina el futuro
+fn correr<F: Future>(futuro: F) -> F::Output {
+    SISTEMA.with(|sistema| {
+        sistema
+            .borrow()
+            .as_ref()
+            .expect("No hay una simulación en este thread")
+            .block_on(futuro)
+    })
+}
+
+/// Reloj de la simulación, el tiempo pausado del runtime donde corren los actores de los
+/// locales. Solo lo avanza el planificador: dormir o esperar un paquete en su thread corre
+/// los actores mientras pasa ese tiempo y aplica las caidas de los locales, en otro thread
+/// espera a que lo avance él
+struct RelojSimulacion {
+    runtime: Handle,
+    inicio: Instant,
+    planificador: ThreadId,
+}
+
+impl Reloj for RelojSimulacion {
+    fn ahora_en_ms(&self) -> u64 {
+        let _contexto = self.runtime.enter();
+        Instant::now().duration_since(self.inicio).as_millis() as u64
+    }
+
+    fn dormir(&self, duracion: Duration) {
+        let hasta = self.ahora_en_ms() + duracion.as_millis() as u64;
+        while self.ahora_en_ms() < hasta {
+            if thread::current().id() == self.planificador {
+                correr(async { sleep(PASO).await });
+                aplicar_caidas(self.ahora_en_ms());
+            } else {
+                thread::sleep(PASO);
+            }
+        }
+    }
+
+    fn recibir_de(
+        &self,
+        socket: &UdpSocket,
+        buffer: &mut [u8],
+        espera: Duration,
+    ) -> io::Result<(usize, SocketAddr)> {
+        socket.set_nonblocking(true)?;
+        let hasta = self.ahora_en_ms() + espera.as_millis() as u64;
+        loop {
+            match socket.recv_from(buffer) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                recibido => return recibido,
+            }
+            if self.ahora_en_ms() >= hasta {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "timeout"));
+            }
+            self.dormir(PASO);
+        }
+    }
+}
+
+/// Parametros de una simulación, todo lo aleatorio sale de la semilla
+#[derive(Debug, Clone)]
+pub struct Escenario {
+    /// Ecommerce que despachan ordenes, además del lider
+    pub ecommerces: usize,
+    pub locales: usize,
+    pub productos: usize,
+    pub stock_por_producto: usize,
+    pub ordenes: usize,
+    pub radio_locales_km: f64,
+    pub plazo_ordenes: Duration,
+    pub probabilidad_cancelacion: f64,
+    /// Mensajes que entran en el mailbox del ActorLocal de cada local
+    pub capacidad_mailbox: usize,
+    pub semilla: u64,
+    /// Fallas de red de los locales y los ecommerce, todas se miden con el tiempo simulado.
+    /// Las de la conexión con el lider las aplica solo el ecommerce: el lider no puede
+    /// esperar a que avance el tiempo mientras el planificador espera su respuesta.
+    /// Un local caido detiene sus actores y al levantarse retoma lo que guardó, los que
+    /// siguen caidos al terminar el guion se levantan para cerrarlos
+    pub fallas: ConfiguracionFallas,
+}
+
+impl Escenario {
+    /// Ordenes que leen todos los ecommerce, algunas caen fuera de la cobertura de los locales
+    pub fn guion(&self) -> Vec<Orden> {
+        let mut rng = StdRng::seed_from_u64(self.semilla);
+        (0..self.ordenes)
+            .map(|_| {
+                let id_producto = rng.gen_range(1..=self.productos);
+                let cantidad = rng.gen_range(1..=3);
+                let (latitud, longitud) = alrededor_del_centro(&mut rng, 0.12);
+                Orden::new(id_producto, cantidad, latitud, longitud)
+            })
+            .collect()
+    }
+
+    fn locales(&self) -> Vec<InfoLocal> {
+        let mut rng = StdRng::seed_from_u64(self.semilla.wrapping_add(1));
+        (1..=self.locales)
+            .map(|id| {
+                let (latitud, longitud) = alrededor_del_centro(&mut rng, 0.05);
+                InfoLocal {
+                    id,
+                    direccion: Direccion::new(latitud, longitud),
+                    cobertura: Cobertura::Radio(self.radio_locales_km),
+                }
+            })
+            .collect()
+    }
+
+    /// Cada nodo tiene sus propias fallas, con una semilla distinta pero derivada de la del escenario
+    fn fallas(
+        &self,
+        nodo: String,
+        indice: u64,
+        reloj: Arc<dyn Reloj>,
+        nodos: &HashMap<SocketAddr, String>,
+    ) -> Arc<Fallas> {
+        let configuracion = ConfiguracionFallas {
+            semilla: Some(
+                self.fallas
+                    .semilla
+                    .unwrap_or(self.semilla)
+                    .wrapping_add(indice),
+            ),
+            ..self.fallas.clone()
+        };
+        Arc::new(Fallas::new(nodo, configuracion, reloj).con_nodos(nodos.clone()))
+    }
+
+    fn stock_inicial(&self) -> Productos {
+        (1..=self.productos)
+            .map(|id| (id, StockProducto::new(id, self.stock_por_producto)))
+            .collect()
+    }
+}
+
+fn alrededor_del_centro(rng: &mut StdRng, grados: f64) -> (f64, f64) {
+    (
+        CENTRO.0 + rng.gen_range(-grados..grados),
+        CENTRO.1 + rng.gen_range(-grados..grados),
+    )
+}
+
+/// Un turno despachado por un ecommerce
+#[derive(Debug, Clone, PartialEq)]
+pub struct Despacho {
+    pub ecommerce: u32,
+    pub cursor: usize,
+    pub id_orden: String,
+    pub resultado: Result<usize, MotivoRechazo>,
+}
+
+/// Como quedó un local al terminar la simulación, después de cerrarlo. Las ordenes y las
+/// transferencias salen de su historial y el stock del archivo que guardó al cerrar
+#[derive(Debug)]
+pub struct FinalLocal {
+    pub id: usize,
+    pub stock_inicial: Productos,
+    pub productos: Productos,
+    pub aceptadas: Vec<Orden>,
+    pub entregadas: Vec<Orden>,
+    pub canceladas: Vec<Orden>,
+    /// Unidades de cada producto que llegaron por transferencias
+    pub recibidas: HashMap<usize, usize>,
+    /// Unidades de cada producto que se enviaron a otros locales
+    pub enviadas: HashMap<usize, usize>,
+}
+
+#[derive(Debug)]
+pub struct ResultadoSimulacion {
+    pub ordenes: usize,
+    pub despachos: Vec<Despacho>,
+    pub locales: Vec<FinalLocal>,
+    /// Eventos de todos los nodos, como los que registran los binarios con --historial
+    pub historial: Vec<Evento>,
+}
+
+/// Un ecommerce de la simulación con su conexión al lider y la fuente del guion
+struct EcommerceSimulado {
+    id: u32,
+    lider: Socket,
+    despachador: Despachador,
+    fuente: FuenteMemoria,
+    terminado: bool,
+}
+
+/// Un local de la simulación con sus actores, donde guarda el estado y lo necesario para
+/// volver a levantarlo despues de una caida
+struct LocalSimulado {
+    id: usize,
+    /// None mientras esta caido
+    actores: Option<ActoresLocal>,
+    /// Se queda con el socket para que el local se levante en la misma dirección
+    socket: UdpSocket,
+    fallas: Arc<Fallas>,
+    configuracion: Box<dyn Fn() -> ConfiguracionActores>,
+    historial: Historial,
+    caidas: Vec<Caida>,
+    archivo_estado: String,
+    archivo_recuperacion: String,
+}
+
+impl LocalSimulado {
+    fn iniciar(&mut self, local: Local) {
+        let socket = self
+            .socket
+            .try_clone()
+            .expect("No se pudo preparar el socket del local");
+        let configuracion = (self.configuracion)();
+        let fallas = self.fallas.clone();
+        let local = local.con_historial(self.historial.clone());
+        self.actores = Some(correr(async move {
+            let socket = actix_rt::net::UdpSocket::from_std(socket)
+                .expect("No se pudo preparar el socket del local");
+            iniciar_actores(configuracion, local, socket, fallas)
+        }));
+    }
+
+    /// Los actores se detienen sin guardar nada, lo que tenía en memoria se pierde
+    fn caer(&mut self) {
+        if let Some(actores) = self.actores.take() {
+            actores.local.do_send(Caer);
+        }
+    }
+
+    /// Se levanta con el estado que guardó. Lo que llegó al socket mientras estaba caido
+    /// se pierde, como con el proceso caido
+    fn levantar(&mut self) {
+        let mut buffer = [0; TAMANIO_BUFFER];
+        while self.socket.recv_from(&mut buffer).is_ok() {}
+        let local = recuperar_local(&self.archivo_recuperacion)
+            .expect("No se pudo recuperar el estado del local");
+        self.iniciar(local);
+    }
+}
+
+/// Tira los locales que tienen una caida en curso y levanta los que ya se recuperaron
+fn aplicar_caidas(ahora_ms: u64) {
+    LOCALES.with(|locales| {
+        for local in locales.borrow_mut().iter_mut() {
+            let caido = local.caidas.iter().any(|caida| caida.activa(ahora_ms));
+            match (caido, local.actores.is_some()) {
+                (true, true) => local.caer(),
+                (false, false) => local.levantar(),
+                _ => {}
+            }
+        }
+    })
+}
+
+/// Levanta en el proceso al lider, los ecommerce y los locales del escenario, con puertos
+/// efimeros, y despacha el guion hasta que no quedan ordenes. Los locales son los actores
+/// del binario en un sistema de actix con el tiempo pausado y los ecommerce usan el mismo
+/// Despachador y el mismo protocolo con el lider que el binario.
+/// Un solo planificador, en el thread que llama, elige con la semilla que ecommerce despacha
+/// en cada turno y hace avanzar el tiempo, asi la misma semilla da la misma ejecución
+pub fn simular(escenario: &Escenario) -> ResultadoSimulacion {
+    let sistema = System::with_tokio_rt(|| {
+        Builder::new_current_thread()
+            .enable_all()
+            .start_paused(true)
+            .build()
+            .expect("No se pudo crear el runtime de la simulación")
+    });
+    let runtime = sistema.runtime().tokio_runtime().handle().clone();
+    SISTEMA.with(|actual| *actual.borrow_mut() = Some(sistema));
+    let resultado = planificar(escenario, runtime);
+    SISTEMA.with(|actual| actual.borrow_mut().take());
+    resultado
+}
+
+fn planificar(escenario: &Escenario, runtime: Handle) -> ResultadoSimulacion {
+    let inicio = {
+        let _contexto = runtime.enter();
+        Instant::now()
+    };
+    let reloj: Arc<dyn Reloj> = Arc::new(RelojSimulacion {
+        runtime,
+        inicio,
+        planificador: thread::current().id(),
+    });
+    let salida_historial = HistorialEnMemoria::default();
+    let historial = Historial::con_salida(Box::new(salida_historial.clone()), String::new());
+    let guion = escenario.guion();
+    let info_locales = escenario.locales();
+
+    // todos los sockets se abren antes de arrancar para conocer el nodo de cada dirección
+    let sockets_locales: Vec<UdpSocket> = info_locales
+        .iter()
+        .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+        .collect();
+    let sockets_ecommerces: Vec<UdpSocket> = (0..escenario.ecommerces)
+        .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+        .collect();
+    let direcciones: HashMap<usize, SocketAddr> = info_locales
+        .iter()
+        .zip(&sockets_locales)
+        .map(|(info, socket)| (info.id, socket.local_addr().unwrap()))
+        .collect();
+    let mut nodos: HashMap<SocketAddr, String> = direcciones
+        .iter()
+        .map(|(id, direccion)| (*direccion, nombre_local(*id)))
+        .collect();
+    for (indice, socket) in sockets_ecommerces.iter().enumerate() {
+        nodos.insert(
+            socket.local_addr().unwrap(),
+            nombre_ecommerce(ID_LIDER as usize + 1 + indice),
+        );
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("No se pudo levantar al lider");
+    let direccion_lider = listener.local_addr().unwrap().to_string();
+    let fallas_lider = Arc::new(
+        Fallas::new(
+            nombre_ecommerce(ID_LIDER as usize),
+            ConfiguracionFallas::default(),
+            reloj.clone(),
+        )
+        .con_nodos(nodos.clone()),
+    );
+    let historial_lider = historial.para(nombre_ecommerce(ID_LIDER as usize));
+    let terminar = Arc::new(AtomicBool::new(false));
+    let terminar_lider = terminar.clone();
+    let lider = thread::spawn(move || {
+        Socket::lider(listener, ID_LIDER, fallas_lider)
+            .con_historial(Some(historial_lider))
+            .esperar_conexiones_hasta(ID_LIDER, &terminar_lider)
+    });
+
+    for (indice, (info, socket)) in info_locales.iter().zip(sockets_locales).enumerate() {
+        let fallas = escenario.fallas(
+            nombre_local(info.id),
+            100 + indice as u64,
+            reloj.clone(),
+            &nodos,
+        );
+        let archivo = |nombre: &str, extension: &str| {
+            std::env::temp_dir()
+                .join(format!(
+                    "simulacion_{}_{}_{}.{}",
+                    nombre,
+                    info.id,
+                    socket.local_addr().unwrap().port(),
+                    extension
+                ))
+                .to_string_lossy()
+                .into_owned()
+        };
+        let archivo_estado = archivo("local", "txt");
+        let archivo_recuperacion = archivo("recuperacion", "json");
+        let configuracion = {
+            let (escenario, info_locales) = (escenario.clone(), info_locales.clone());
+            let (id, direcciones, reloj) = (info.id, direcciones.clone(), reloj.clone());
+            let (archivo_estado, archivo_recuperacion) =
+                (archivo_estado.clone(), archivo_recuperacion.clone());
+            move || ConfiguracionActores {
+                id,
+                politica: PoliticaDeEntrega::new(
+                    CriterioDeSeleccion::Aleatorio,
+                    escenario.probabilidad_cancelacion,
+                    escenario.semilla.wrapping_add(indice as u64 + 2),
+                ),
+                plazo_ordenes: escenario.plazo_ordenes,
+                reposicion_automatica: None,
+                proveedor: None,
+                vecinos: info_locales
+                    .iter()
+                    .map(|otro| otro.id)
+                    .filter(|otro| *otro != id)
+                    .collect(),
+                direcciones_locales: direcciones.clone(),
+                capacidad_mailbox: escenario.capacidad_mailbox,
+                archivo_estado: archivo_estado.clone(),
+                archivo_recuperacion: Some(archivo_recuperacion.clone()),
+                intervalo_entregas: INTERVALO_ENTREGAS,
+                reloj: reloj.clone(),
+                detener_sistema: false,
+            }
+        };
+        socket
+            .set_nonblocking(true)
+            .expect("No se pudo preparar el socket del local");
+        let nodo = nombre_local(info.id);
+        let mut local = LocalSimulado {
+            id: info.id,
+            actores: None,
+            socket,
+            fallas,
+            configuracion: Box::new(configuracion),
+            historial: historial.para(nodo.clone()),
+            caidas: escenario
+                .fallas
+                .caidas
+                .iter()
+                .filter(|caida| caida.nodo == nodo)
+                .cloned()
+                .collect(),
+            archivo_estado,
+            archivo_recuperacion,
+        };
+        local.iniciar(Local::new(escenario.stock_inicial()));
+        LOCALES.with(|locales| locales.borrow_mut().push(local));
+    }
+
+    let mut ecommerces: Vec<EcommerceSimulado> = sockets_ecommerces
+        .into_iter()
+        .enumerate()
+        .map(|(indice, socket)| {
+            let id = ID_LIDER + 1 + indice as u32;
+            let fallas = escenario.fallas(
+                nombre_ecommerce(id as usize),
+                id as u64,
+                reloj.clone(),
+                &nodos,
+            );
+            EcommerceSimulado {
+                id,
+                lider: Socket::conectar(direccion_lider.clone(), id, fallas.clone()),
+                despachador: Despachador::new(
+                    SocketUdp::new(socket, fallas),
+                    MapaLocales::new(info_locales.clone()),
+                    Arc::new(Mutex::new(MetricasEcommerce::default())),
+                    reloj.clone(),
+                )
+                .con_direcciones(direcciones.clone())
+                .con_historial(Some(historial.para(nombre_ecommerce(id as usize)))),
+                fuente: FuenteMemoria::new(guion.clone()),
+                terminado: false,
+            }
+        })
+        .collect();
+
+    let mut turnos = StdRng::seed_from_u64(escenario.semilla.wrapping_sub(1));
+    let mut despachos = vec![];
+    loop {
+        let activos: Vec<usize> = (0..ecommerces.len())
+            .filter(|indice| !ecommerces[*indice].terminado)
+            .collect();
+        if activos.is_empty() {
+            break;
+        }
+        let ecommerce = &mut ecommerces[activos[turnos.gen_range(0..activos.len())]];
+        match ecommerce
+            .despachador
+            .turno(&mut ecommerce.lider, &mut ecommerce.fuente, ecommerce.id)
+        {
+            Turno::Despachada {
+                cursor,
+                orden,
+                resultado,
+            } => despachos.push(Despacho {
+                ecommerce: ecommerce.id,
+                cursor,
+                id_orden: orden.id.unwrap_or_default(),
+                resultado,
+            }),
+            Turno::SinMasOrdenes | Turno::ErrorDeLectura(_) => ecommerce.terminado = true,
+            Turno::SinOrdenesPorAhora | Turno::OrdenInvalida => {}
+            Turno::SinLider => panic!("El ecommerce {} perdió al lider simulado", ecommerce.id),
+        }
+        atender_avisos(&mut ecommerces);
+        reloj.dormir(ESPERA_ENTRE_TURNOS);
+    }
+
+    // las ordenes que quedan se entregan o vencen antes de cerrar los locales
+    LOCALES.with(|locales| {
+        for local in locales.borrow_mut().iter_mut() {
+            local.caidas.clear();
+        }
+    });
+    aplicar_caidas(reloj.ahora_en_ms());
+    esperar(
+        &mut ecommerces,
+        reloj.as_ref(),
+        escenario.plazo_ordenes,
+        || false,
+    );
+    LOCALES.with(|locales| {
+        for actores in locales.borrow().iter().flat_map(|local| &local.actores) {
+            actores.local.do_send(Cerrar);
+        }
+    });
+    esperar(&mut ecommerces, reloj.as_ref(), ESPERA_CIERRE, || {
+        LOCALES.with(|locales| {
+            locales
+                .borrow()
+                .iter()
+                .flat_map(|local| &local.actores)
+                .all(|actores| !actores.local.connected())
+        })
+    });
+
+    // al cerrarse las conexiones terminan los threads del lider
+    drop(ecommerces);
+    terminar.store(true, Ordering::SeqCst);
+    match lider.join() {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => panic!("El lider simulado falló: {}", e),
+        Err(_) => panic!("Falló el thread del lider simulado"),
+    }
+
+    let historial = salida_historial.eventos();
+    let locales = LOCALES
+        .with(|locales| locales.take())
+        .into_iter()
+        .map(|local| final_local(local, escenario.stock_inicial(), &historial))
+        .collect();
+    ResultadoSimulacion {
+        ordenes: guion.len(),
+        despachos,
+        locales,
+        historial,
+    }
+}
+
+/// Los ecommerce confirman los avisos de cancelación y de cierre de los locales
+fn atender_avisos(ecommerces: &mut [EcommerceSimulado]) {
+    for ecommerce in ecommerces {
+        ecommerce.despachador.atender_avisos();
+    }
+}
+
+/// Hace avanzar el tiempo hasta `duracion` o hasta que se cumple `listo`, mientras tanto
+/// los ecommerce siguen confirmando los avisos
+fn esperar(
+    ecommerces: &mut [EcommerceSimulado],
+    reloj: &dyn Reloj,
+    duracion: Duration,
+    listo: impl Fn() -> bool,
+) {
+    let hasta = reloj.ahora_en_ms() + duracion.as_millis() as u64;
+    while reloj.ahora_en_ms() < hasta && !listo() {
+        atender_avisos(ecommerces);
+        reloj.dormir(INTERVALO_AVISOS);
+    }
+}
+
+/// Lee el stock que guardó el local al cerrar y junta lo que registró en el historial
+fn final_local(local: LocalSimulado, stock_inicial: Productos, historial: &[Evento]) -> FinalLocal {
+    let productos = instanciar_local(&local.archivo_estado)
+        .map(|guardado| guardado.productos_en_stock)
+        .unwrap_or_default();
+    let _ = std::fs::remove_file(&local.archivo_estado);
+    let _ = std::fs::remove_file(&local.archivo_recuperacion);
+    let mut final_local = FinalLocal {
+        id: local.id,
+        stock_inicial,
+        productos,
+        aceptadas: vec![],
+        entregadas: vec![],
+        canceladas: vec![],
+        recibidas: HashMap::new(),
+        enviadas: HashMap::new(),
+    };
+    let nodo = nombre_local(local.id);
+    for evento in historial.iter().filter(|evento| evento.nodo == nodo) {
+        let Operacion::Stock {
+            tipo,
+            orden,
+            cantidad,
+            producto,
+            ..
+        } = &evento.operacion
+        else {
+            continue;
+        };
+        let orden = Orden {
+            id: orden.clone(),
+            ..Orden::new(producto.id_producto, *cantidad, 0.0, 0.0)
+        };
+        match tipo {
+            OperacionStock::Aceptada => final_local.aceptadas.push(orden),
+            OperacionStock::Entregada => final_local.entregadas.push(orden),
+            OperacionStock::Cancelada => final_local.canceladas.push(orden),
+            OperacionStock::Reposicion => {
+                *final_local
+                    .recibidas
+                    .entry(producto.id_producto)
+                    .or_default() += cantidad
+            }
+            OperacionStock::EnvioTransferencia => {
+                *final_local
+                    .enviadas
+                    .entry(producto.id_producto)
+                    .or_default() += cantidad
+            }
+            OperacionStock::Venta
+            | OperacionStock::ReservaTransferencia
+            | OperacionStock::LiberacionTransferencia => {}
+        }
+    }
+    final_local
+}
+
+impl ResultadoSimulacion {
+    /// Invariantes que tienen que valer en cualquier ejecución, devuelve las que no se cumplen
+    pub fn violaciones(&self) -> Vec<String> {
+        let mut violaciones = vec![];
+        self.verificar_despachos(&mut violaciones);
+        for local in &self.locales {
+            verificar_stock(local, &mut violaciones);
+        }
+        violaciones
+    }
+
+    /// Cada orden del guion se despacha una sola vez y la acepta a lo sumo un local,
+    /// el mismo que le contestó al ecommerce
+    fn verificar_despachos(&self, violaciones: &mut Vec<String>) {
+        let mut por_cursor: BTreeMap<usize, Vec<&Despacho>> = BTreeMap::new();
+        for despacho in &self.despachos {
+            por_cursor
+                .entry(despacho.cursor)
+                .or_default()
+                .push(despacho);
+        }
+        for cursor in 0..self.ordenes {
+            match por_cursor.get(&cursor).map(Vec::len).unwrap_or(0) {
+                0 => violaciones.push(format!("La orden del cursor {} se perdió", cursor)),
+                1 => {}
+                veces => violaciones.push(format!(
+                    "La orden del cursor {} se despachó {} veces",
+                    cursor, veces
+                )),
+            }
+        }
+
+        let mut aceptadas: HashMap<&str, Vec<usize>> = HashMap::new();
+        for local in &self.locales {
+            for orden in &local.aceptadas {
+                aceptadas
+                    .entry(orden.id.as_deref().unwrap_or_default())
+                    .or_default()
+                    .push(local.id);
+            }
+        }
+        for despacho in &self.despachos {
+            let locales = aceptadas
+                .remove(despacho.id_orden.as_str())
+                .unwrap_or_default();
+            match (&despacho.resultado, &locales[..]) {
+                (Ok(local), [aceptada]) if local == aceptada => {}
+                (Err(_), []) => {}
+                // el local que no contestó la confirmación puede haberla aceptado
+                (Err(MotivoRechazo::SinConfirmacion), [_]) => {}
+                (resultado, locales) => violaciones.push(format!(
+                    "La orden {} terminó en {:?} pero la aceptaron los locales {:?}",
+                    despacho.id_orden, resultado, locales
+                )),
+            }
+        }
+        for (id_orden, locales) in aceptadas {
+            violaciones.push(format!(
+                "Los locales {:?} aceptaron la orden {} que nadie despachó",
+                locales, id_orden
+            ));
+        }
+    }
+}
+
+/// Al cerrar el local toda orden aceptada terminó entregada o cancelada, y lo que cambió
+/// el stock es lo entregado y lo que se envió o llegó por transferencias
+fn verificar_stock(local: &FinalLocal, violaciones: &mut Vec<String>) {
+    let finalizadas = local.entregadas.len() + local.canceladas.len();
+    if finalizadas != local.aceptadas.len() {
+        violaciones.push(format!(
+            "El local {} aceptó {} ordenes pero entregó o canceló {}",
+            local.id,
+            local.aceptadas.len(),
+            finalizadas
+        ));
+    }
+    let sumar = |ordenes: &[Orden], id_producto: usize| -> usize {
+        ordenes
+            .iter()
+            .filter(|orden| orden.id_producto == id_producto)
+            .map(|orden| orden.cantidad)
+            .sum()
+    };
+    for (id_producto, inicial) in &local.stock_inicial {
+        let Some(producto) = local.productos.get(id_producto) else {
+            violaciones.push(format!(
+                "El local {} perdió el producto {}",
+                local.id, id_producto
+            ));
+            continue;
+        };
+        let entregado = sumar(&local.entregadas, *id_producto);
+        let recibidas = local.recibidas.get(id_producto).copied().unwrap_or(0);
+        let enviadas = local.enviadas.get(id_producto).copied().unwrap_or(0);
+        if producto.stock + entregado + enviadas != inicial.stock + recibidas {
+            violaciones.push(format!(
+                "El local {} tiene {} del producto {}, empezó con {}, entregó {}, envió {} y recibió {}",
+                local.id, producto.stock, id_producto, inicial.stock, entregado, enviadas, recibidas
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fallas::Particion;
+    use crate::common::invariantes::verificar;
+
+    fn escenario(semilla: u64) -> Escenario {
+        Escenario {
+            ecommerces: 3,
+            locales: 4,
+            productos: 3,
+            stock_por_producto: 10,
+            ordenes: 40,
+            radio_locales_km: 10.0,
+            plazo_ordenes: Duration::from_secs(8),
+            probabilidad_cancelacion: 0.3,
+            capacidad_mailbox: 8,
+            semilla,
+            fallas: ConfiguracionFallas::default(),
+        }
+    }
+
+    #[test]
+    fn test_simulacion_no_pierde_ni_duplica_ordenes_y_conserva_el_stock() {
+        for semilla in [7, 42] {
+            let resultado = simular(&escenario(semilla));
+
+            assert_eq!(resultado.violaciones(), Vec::<String>::new());
+            assert_eq!(verificar(&resultado.historial), Vec::<String>::new());
+            assert_eq!(resultado.despachos.len(), 40);
+            assert!(resultado.despachos.iter().any(|d| d.resultado.is_ok()));
+            let ecommerces: std::collections::HashSet<u32> =
+                resultado.despachos.iter().map(|d| d.ecommerce).collect();
+            assert!(ecommerces.len() > 1);
+        }
+    }
+
+    /// Las fallas empiezan en medio de los despachos, con ofertas y confirmaciones en curso,
+    /// y el local 2 se cae con una orden en progreso y se levanta con lo que guardó
+    #[test]
+    fn test_simulacion_con_fallas_de_red_mantiene_los_invariantes() {
+        let nodos = |nombres: &[&str]| nombres.iter().map(|nombre| nombre.to_string()).collect();
+        let resultado = simular(&Escenario {
+            fallas: ConfiguracionFallas {
+                descartar: 0.05,
+                duplicar: 0.1,
+                demora_ms: 5,
+                jitter_ms: 20,
+                reordenar: 0.2,
+                particiones: vec![
+                    Particion {
+                        lado_a: nodos(&["ecommerce-2"]),
+                        lado_b: nodos(&["local-1", "local-3"]),
+                        desde_ms: 1500,
+                        hasta_ms: Some(6000),
+                    },
+                    Particion {
+                        lado_a: nodos(&["ecommerce-3"]),
+                        lado_b: nodos(&["ecommerce-1"]),
+                        desde_ms: 4000,
+                        hasta_ms: Some(9000),
+                    },
+                ],
+                caidas: vec![Caida {
+                    nodo: "local-2".to_string(),
+                    desde_ms: 2000,
+                    hasta_ms: Some(7000),
+                }],
+                ..Default::default()
+            },
+            ..escenario(11)
+        });
+
+        assert_eq!(resultado.violaciones(), Vec::<String>::new());
+        assert_eq!(verificar(&resultado.historial), Vec::<String>::new());
+        assert_eq!(resultado.despachos.len(), 40);
+        assert!(resultado
+            .locales
+            .iter()
+            .all(|local| !local.aceptadas.is_empty()));
+        let inicios: Vec<&Operacion> = resultado
+            .historial
+            .iter()
+            .filter(|evento| evento.nodo == "local-2")
+            .map(|evento| &evento.operacion)
+            .filter(|operacion| matches!(operacion, Operacion::Inicio { .. }))
+            .collect();
+        assert_eq!(inicios.len(), 2);
+        assert!(matches!(inicios[1], Operacion::Inicio { ordenes, .. } if !ordenes.is_empty()));
+    }
+
+    /// El local 2 se cae y no vuelve entre la oferta de una orden y su confirmación, la
+    /// orden queda sin confirmar y eso no traba el despacho de las demas
+    #[test]
+    fn test_un_local_que_no_vuelve_no_traba_los_despachos() {
+        let resultado = simular(&Escenario {
+            fallas: ConfiguracionFallas {
+                demora_ms: 20,
+                jitter_ms: 40,
+                caidas: vec![Caida {
+                    nodo: "local-2".to_string(),
+                    desde_ms: 2702,
+                    hasta_ms: None,
+                }],
+                ..Default::default()
+            },
+            ..escenario(7)
+        });
+
+        assert_eq!(resultado.violaciones(), Vec::<String>::new());
+        assert_eq!(verificar(&resultado.historial), Vec::<String>::new());
+        assert_eq!(resultado.despachos.len(), 40);
+        assert!(resultado
+            .despachos
+            .iter()
+            .any(|d| d.resultado == Err(MotivoRechazo::SinConfirmacion)));
+    }
+
+    #[test]
+    fn test_la_misma_semilla_da_la_misma_ejecucion() {
+        let escenario = Escenario {
+            fallas: ConfiguracionFallas {
+                descartar: 0.1,
+                demora_ms: 5,
+                jitter_ms: 20,
+                reordenar: 0.2,
+                ..Default::default()
+            },
+            ..escenario(5)
+        };
+        // el lider registra en su thread, solo se compara el orden de los eventos de cada nodo
+        let por_nodo = |resultado: &ResultadoSimulacion| -> BTreeMap<String, Vec<Operacion>> {
+            let mut por_nodo: BTreeMap<String, Vec<Operacion>> = BTreeMap::new();
+            for evento in &resultado.historial {
+                por_nodo
+                    .entry(evento.nodo.clone())
+                    .or_default()
+                    .push(evento.operacion.clone());
+            }
+            por_nodo
+        };
+
+        let primera = simular(&escenario);
+        let segunda = simular(&escenario);
+
+        assert_eq!(primera.despachos, segunda.despachos);
+        assert_eq!(por_nodo(&primera), por_nodo(&segunda));
+    }
+
+    #[test]
+    fn test_el_guion_depende_solo_de_la_semilla() {
+        assert_eq!(escenario(3).guion(), escenario(3).guion());
+        assert_ne!(escenario(3).guion(), escenario(4).guion());
+    }
+
+    #[test]
+    fn test_detecta_ordenes_perdidas_duplicadas_y_stock_que_no_cierra() {
+        let orden = Orden {
+            id: Some("2-0".to_string()),
+            ..Orden::new(1, 2, 0.0, 0.0)
+        };
+        let local = |id, stock| FinalLocal {
+            id,
+            stock_inicial: HashMap::from([(1, StockProducto::new(1, 10))]),
+            productos: HashMap::from([(1, StockProducto::new(1, stock))]),
+            aceptadas: vec![orden.clone()],
+            entregadas: vec![orden.clone()],
+            canceladas: vec![],
+            recibidas: HashMap::new(),
+            enviadas: HashMap::new(),
+        };
+        let resultado = ResultadoSimulacion {
+            ordenes: 2,
+            despachos: vec![Despacho {
+                ecommerce: 2,
+                cursor: 0,
+                id_orden: "2-0".to_string(),
+                resultado: Ok(1),
+            }],
+            locales: vec![local(1, 8), local(2, 10)],
+            historial: vec![],
+        };
+
+        let violaciones = resultado.violaciones();
+        assert_eq!(violaciones.len(), 3, "{:?}", violaciones);
+        assert!(violaciones[0].contains("cursor 1 se perdió"));
+        assert!(violaciones[1].contains("la aceptaron los locales [1, 2]"));
+        assert!(violaciones[2].contains("El local 2 tiene 10 del producto 1"));
+    }
+}
@@ -1,15 +1,20 @@
 use crate::common::conexion_tcp::ConexionTcp;
-use crate::common::mensaje_protocolo::{Conexion, MandarOrdenes, MensajeProtocolo, TipoDeMensaje};
-use std::cmp;
+use crate::common::fallas::Fallas;
+use crate::common::historial::Historial;
+use crate::common::mensaje_protocolo::{MandarOrdenes, MensajeProtocolo};
 use std::fmt::{Debug, Display, Formatter};
 use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::{error::Error, fmt};
+use std::time::Duration;
+use std::{error::Error, fmt, io, thread};
+use tracing::{debug, error, info};
 
-use crate::common::cordinador::{connect_to_leader, coordinar, Cordinador};
+use crate::common::cordinador::{conectar_al_lider, connect_to_leader, coordinar, Cordinador};
 
-const ECOMMERCE_PUERTO_BASE: u32 = 1024;
 const ECOMMERCE_ADDR_BASE: &str = "127.0.0.1";
+/// Cada cuanto el lider que puede terminar revisa si llegó una conexión
+const ESPERA_CONEXIONES: Duration = Duration::from_millis(5);
 
 pub struct ErrorSocket {
     pub error: TipodeError,
@@ -36,39 +41,78 @@ impl Debug for ErrorSocket {
 impl Error for ErrorSocket {}
 
 pub struct Socket {
-    direccion: String,
-    puerto: u32,
     listener: Option<TcpListener>,
-    id: u32,
     leader: Option<ConexionTcp>,
     cordinador: Arc<Mutex<Cordinador>>,
+    fallas: Arc<Fallas>,
+    /// Historial del lider, donde registra los permisos que otorga
+    historial: Option<Historial>,
+    /// Dirección del lider al que se conectó el ecommerce, para reconectarse
+    direccion_lider: Option<String>,
+    id: u32,
 }
 
 impl Socket {
-    pub fn new(direccion: String, puerto: u32, id: u32) -> Socket {
-        let leader;
-        let listener;
-        let cordinador = Arc::new(Mutex::new(Cordinador::new(id)));
+    /// Las fallas se aplican a los mensajes con el lider
+    pub fn new(direccion: String, puerto: u32, id: u32, fallas: Arc<Fallas>) -> Socket {
         if id != 1 {
-            listener = None;
-            leader = Some(connect_to_leader(
+            Socket::conectar(
                 format!("{}:{}", String::from(ECOMMERCE_ADDR_BASE), puerto + 1),
                 id,
-            ));
+                fallas,
+            )
         } else {
-            leader = None;
-            listener = Some(TcpListener::bind(format!("{}:{}", direccion, puerto + id)).unwrap());
+            let listener = TcpListener::bind(format!("{}:{}", direccion, puerto + id)).unwrap();
+            Socket::lider(listener, id, fallas)
         }
+    }
+
+    /// Socket del lider, atiende a los ecommerce que se conectan al listener
+    pub fn lider(listener: TcpListener, id: u32, fallas: Arc<Fallas>) -> Socket {
         Socket {
-            direccion: direccion.clone(),
-            puerto,
-            listener,
+            listener: Some(listener),
+            leader: None,
+            cordinador: Arc::new(Mutex::new(Cordinador::new(id))),
+            fallas,
+            historial: None,
+            direccion_lider: None,
             id,
-            leader,
-            cordinador,
         }
     }
 
+    /// Socket de un ecommerce que le pide el permiso al lider de la dirección dada
+    pub fn conectar(direccion_lider: String, id: u32, fallas: Arc<Fallas>) -> Socket {
+        Socket {
+            listener: None,
+            leader: Some(connect_to_leader(
+                direccion_lider.clone(),
+                id,
+                fallas.clone(),
+            )),
+            cordinador: Arc::new(Mutex::new(Cordinador::new(id))),
+            fallas,
+            historial: None,
+            direccion_lider: Some(direccion_lider),
+            id,
+        }
+    }
+
+    /// Vuelve a conectarse al lider despues de perder la conexión
+    pub fn reconectar(&mut self) -> Result<(), Box<dyn Error>> {
+        let direccion = self
+            .direccion_lider
+            .clone()
+            .ok_or("El lider no tiene a quien reconectarse")?;
+        self.leader = Some(conectar_al_lider(direccion, self.id, self.fallas.clone())?);
+        info!(target: "coordinador", "Reconectado al lider");
+        Ok(())
+    }
+
+    pub fn con_historial(mut self, historial: Option<Historial>) -> Socket {
+        self.historial = historial;
+        self
+    }
+
     pub fn get_permiso(&mut self) {
         self.cordinador.clone().try_lock().unwrap().get_permso();
     }
@@ -86,7 +130,7 @@ impl Socket {
     }
 
     pub fn esperar_conexiones(&mut self, id: u32) {
-        println!("Esperando conexiones!");
+        info!(target: "coordinador", "Esperando conexiones");
         let cordinador = Arc::new(Mutex::new(Cordinador::new(id)));
         let mut incoming = self.listener.as_ref().unwrap().incoming();
         loop {
@@ -94,43 +138,100 @@ impl Socket {
             let opt_stream = incoming.next().unwrap();
             match opt_stream {
                 Ok(stream) => {
-                    coordinar(stream, clone_cordinador);
+                    coordinar(
+                        stream,
+                        clone_cordinador,
+                        self.fallas.clone(),
+                        self.historial.clone(),
+                    );
                 }
                 Err(e) => {
-                    println!("Error al conectar: {}", e)
+                    error!(target: "coordinador", "Error al conectar: {}", e)
+                }
+            }
+        }
+    }
+
+    /// Como `esperar_conexiones` pero deja de aceptar conexiones cuando se activa `terminar`
+    /// y vuelve cuando se desconectaron todos los ecommerce que atendió
+    pub fn esperar_conexiones_hasta(&mut self, id: u32, terminar: &AtomicBool) -> io::Result<()> {
+        info!(target: "coordinador", "Esperando conexiones");
+        let cordinador = Arc::new(Mutex::new(Cordinador::new(id)));
+        let Some(listener) = self.listener.as_ref() else {
+            return Ok(());
+        };
+        listener.set_nonblocking(true)?;
+        let mut conexiones = vec![];
+        while !terminar.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    conexiones.push(coordinar(
+                        stream,
+                        cordinador.clone(),
+                        self.fallas.clone(),
+                        self.historial.clone(),
+                    ));
                 }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ESPERA_CONEXIONES),
+                Err(e) => error!(target: "coordinador", "Error al conectar: {}", e),
+            }
+        }
+        for conexion in conexiones {
+            if conexion.join().is_err() {
+                error!(target: "coordinador", "Falló el thread de una conexión");
             }
         }
+        Ok(())
     }
 
+    /// Pide el permiso y devuelve el cursor, o -1 si se perdió la conexión con el lider
     pub fn quiero_enviar_ordenes(&mut self) -> i32 {
-        println!("Esperando mandar ordenes!");
+        debug!(target: "coordinador", "Pide permiso para mandar ordenes");
 
-        self.leader
-            .as_ref()
-            .unwrap()
-            .enviar_mensaje(&MensajeProtocolo::new_quiero_mandar_ordenes())
-            .unwrap();
-        let respuesta = match self.leader.as_ref().unwrap().esperar_mensaje() {
+        let Some(lider) = self.leader.as_ref() else {
+            return -1;
+        };
+        if let Err(e) = lider.enviar_mensaje(&MensajeProtocolo::new_quiero_mandar_ordenes()) {
+            error!(target: "coordinador", "Error al pedir el permiso: {}", e);
+            return -1;
+        }
+        let respuesta = match lider.esperar_mensaje() {
             Ok(msg) => msg,
             Err(e) => {
-                println!("Error al recibir mensaje: {}", e);
+                error!(target: "coordinador", "Error al recibir mensaje: {}", e);
+                return -1;
+            }
+        };
+        let cursor = match serde_json::from_slice::<MandarOrdenes>(respuesta.get_contenido()) {
+            Ok(mandar) => mandar.cursor,
+            Err(e) => {
+                error!(target: "coordinador", "Respuesta invalida del lider: {}", e);
                 return -1;
             }
         };
-        let cursor = serde_json::from_slice::<MandarOrdenes>(respuesta.get_contenido())
-            .unwrap()
-            .cursor;
         self.get_permiso(); //TODO esta al dope
         cursor
     }
 
     pub fn ordenes_enviadas(&mut self) {
-        self.leader
+        self.devolver_permiso(MensajeProtocolo::new_termino_de_mandar_ordenes());
+    }
+
+    /// Devuelve el permiso sin avanzar el cursor, todavia no hay orden en esa posición
+    pub fn no_hay_ordenes(&mut self) {
+        self.devolver_permiso(MensajeProtocolo::new_no_hay_ordenes());
+    }
+
+    /// Si se perdió la conexión el lider ya liberó el permiso al cerrarse
+    fn devolver_permiso(&mut self, mensaje: MensajeProtocolo) {
+        if let Some(Err(e)) = self
+            .leader
             .as_ref()
-            .unwrap()
-            .enviar_mensaje(&MensajeProtocolo::new_termino_de_mandar_ordenes())
-            .unwrap();
+            .map(|lider| lider.enviar_mensaje(&mensaje))
+        {
+            error!(target: "coordinador", "Error al devolver el permiso: {}", e);
+        }
         self.release_permiso();
     }
 
@@ -144,6 +245,56 @@ impl Socket {
     }
 }
 
+/// Los ids del 1 al 9 conservan los puertos de siempre (el id como ultimo digito del puerto),
+/// a partir del 10 van al rango que empieza en `base_extendida` para no pisarse entre servicios
+fn puerto_por_defecto(base: u16, base_extendida: u16, id: usize) -> u16 {
+    let (base, desplazamiento) = if id < 10 {
+        (base, id)
+    } else {
+        (base_extendida, id)
+    };
+    u16::try_from(desplazamiento)
+        .ok()
+        .and_then(|desplazamiento| base.checked_add(desplazamiento))
+        .expect("Id demasiado grande para tener un puerto por defecto")
+}
+
+/// Dirección udp por defecto del local
 pub fn id_to_addr_local(id: usize) -> String {
-    "127.0.0.1:1234".to_owned() + &*id.to_string()
+    format!("127.0.0.1:{}", puerto_por_defecto(12340, 20000, id))
+}
+
+/// Dirección udp por defecto desde la que el ecommerce le ofrece ordenes a los locales
+pub fn id_to_addr_ecommerce(id: usize) -> String {
+    format!("127.0.0.1:{}", puerto_por_defecto(5550, 40000, id))
+}
+
+/// Dirección tcp por defecto del socket de control del local
+pub fn id_to_addr_control(id: usize) -> String {
+    format!("127.0.0.1:{}", puerto_por_defecto(12350, 30000, id))
+}
+
+/// Dirección tcp por defecto de las metricas del local
+pub fn id_to_addr_metricas_local(id: usize) -> String {
+    format!("127.0.0.1:{}", 9100 + id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_direcciones_por_defecto_no_se_pisan_con_ids_de_dos_cifras() {
+        assert_eq!(id_to_addr_local(1), "127.0.0.1:12341");
+        assert_eq!(id_to_addr_control(9), "127.0.0.1:12359");
+        assert_eq!(id_to_addr_ecommerce(2), "127.0.0.1:5552");
+
+        let direcciones: Vec<String> = (1..=30)
+            .flat_map(|id| [id_to_addr_local(id), id_to_addr_control(id)])
+            .collect();
+        for direccion in &direcciones {
+            assert!(direccion.parse::<std::net::SocketAddr>().is_ok());
+            assert_eq!(direcciones.iter().filter(|d| *d == direccion).count(), 1);
+        }
+    }
 }
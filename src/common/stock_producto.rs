@@ -1,8 +1,10 @@
+use crate::common::error_csv::ErrorCsv;
 use crate::common::error_local::ErrorLocal;
+use crate::common::lector_csv::leer_columna;
 use csv::StringRecord;
-use std::error::Error;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StockProducto {
     pub id_producto: usize,
     pub stock: usize,
@@ -30,15 +32,9 @@ impl StockProducto {
         }
     }
 
-    pub fn from_record(record: StringRecord) -> Result<Self, Box<dyn Error>> {
-        let id_producto = record
-            .get(0)
-            .ok_or("registro no tiene atributo id_producto")?
-            .parse::<usize>()?;
-        let stock = record
-            .get(1)
-            .ok_or("registro no tiene atributo stock")?
-            .parse::<usize>()?;
+    pub fn from_record(record: StringRecord) -> Result<Self, ErrorCsv> {
+        let id_producto = leer_columna(&record, 0, "id_producto")?;
+        let stock = leer_columna(&record, 1, "stock")?;
 
         Ok(StockProducto {
             id_producto,
@@ -47,6 +43,20 @@ impl StockProducto {
         })
     }
 
+    /// cantidad de unidades en stock que no estan bloqueadas
+    pub fn disponibles(&self) -> usize {
+        self.stock - self.bloqueados
+    }
+
+    /// aumenta el stock segun cantidad, sin pasarse del maximo que se puede representar
+    pub fn reponer(&mut self, cantidad: usize) -> Result<(), ErrorLocal> {
+        self.stock = self
+            .stock
+            .checked_add(cantidad)
+            .ok_or(ErrorLocal::StockExcedido)?;
+        Ok(())
+    }
+
     /// aumenta la cantidad de bloqueados segun cantidad_a_bloquear
     pub fn bloquear(&mut self, cantidad_a_bloquear: usize) -> Result<(), ErrorLocal> {
         if self.stock - self.bloqueados >= cantidad_a_bloquear {
@@ -154,4 +164,23 @@ mod tests {
             ErrorLocal::CantidadOrdenMayorQueBloqueados
         );
     }
+
+    #[test]
+    fn test_reponer_10_unidades_con_stock_20_y_bloqueados_5_deja_25_disponibles() {
+        let mut stock_producto = StockProducto::new_con_bloqueados(1, 20, 5);
+
+        stock_producto.reponer(10).unwrap();
+
+        assert_eq!(stock_producto.stock, 30);
+        assert_eq!(stock_producto.bloqueados, 5);
+        assert_eq!(stock_producto.disponibles(), 25);
+    }
+
+    #[test]
+    fn test_reponer_mas_alla_del_maximo_devuelve_error_y_no_cambia_el_stock() {
+        let mut stock_producto = StockProducto::new_con_bloqueados(1, usize::MAX - 1, 5);
+
+        assert_eq!(stock_producto.reponer(2), Err(ErrorLocal::StockExcedido));
+        assert_eq!(stock_producto.stock, usize::MAX - 1);
+    }
 }
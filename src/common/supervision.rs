@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+const MAXIMO_REINICIOS_POR_DEFECTO: usize = 5;
+const VENTANA_POR_DEFECTO: Duration = Duration::from_secs(60);
+const ESPERA_BASE_POR_DEFECTO: Duration = Duration::from_millis(500);
+const ESPERA_MAXIMA: Duration = Duration::from_secs(30);
+
+/// Politica de reinicio de un actor supervisado. Cada reinicio espera el doble que el anterior
+/// antes de retomar el trabajo y si el actor se reinicia mas de `maximo_reinicios` veces dentro
+/// de `ventana` se abandona, queda detenido sin tirar abajo al resto del local
+pub struct PoliticaDeReinicio {
+    componente: &'static str,
+    maximo_reinicios: usize,
+    ventana: Duration,
+    espera_base: Duration,
+    reinicios_recientes: VecDeque<Instant>,
+    total_reinicios: usize,
+    espera: Option<Duration>,
+    /// Donde se cuentan los reinicios para informarlos por el control y las metricas
+    reinicios: Option<Reinicios>,
+}
+
+/// Reinicios de cada actor supervisado del local, compartidos entre los actores
+#[derive(Debug, Clone, Default)]
+pub struct Reinicios(Arc<Mutex<BTreeMap<String, u64>>>);
+
+impl Reinicios {
+    fn registrar(&self, componente: &str, reinicios: u64) {
+        *self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(componente.to_string())
+            .or_default() += reinicios;
+    }
+
+    pub fn por_actor(&self) -> BTreeMap<String, u64> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl PoliticaDeReinicio {
+    pub fn new(
+        componente: &'static str,
+        maximo_reinicios: usize,
+        ventana: Duration,
+        espera_base: Duration,
+    ) -> PoliticaDeReinicio {
+        PoliticaDeReinicio {
+            componente,
+            maximo_reinicios,
+            ventana,
+            espera_base,
+            reinicios_recientes: VecDeque::new(),
+            total_reinicios: 0,
+            espera: Some(Duration::ZERO),
+            reinicios: None,
+        }
+    }
+
+    /// Cuenta los reinicios del actor en `reinicios`, donde aparece desde ahora con 0
+    pub fn con_reinicios(mut self, reinicios: Reinicios) -> PoliticaDeReinicio {
+        reinicios.registrar(self.componente, 0);
+        self.reinicios = Some(reinicios);
+        self
+    }
+
+    pub fn por_defecto(componente: &'static str) -> PoliticaDeReinicio {
+        PoliticaDeReinicio::new(
+            componente,
+            MAXIMO_REINICIOS_POR_DEFECTO,
+            VENTANA_POR_DEFECTO,
+            ESPERA_BASE_POR_DEFECTO,
+        )
+    }
+
+    /// Registra un reinicio del actor en el momento `ahora` y calcula cuanto esperar antes de retomar
+    pub fn reiniciando(&mut self, ahora: Instant) {
+        self.total_reinicios += 1;
+        if let Some(reinicios) = &self.reinicios {
+            reinicios.registrar(self.componente, 1);
+        }
+        while self
+            .reinicios_recientes
+            .front()
+            .is_some_and(|reinicio| ahora.duration_since(*reinicio) > self.ventana)
+        {
+            self.reinicios_recientes.pop_front();
+        }
+        self.reinicios_recientes.push_back(ahora);
+
+        let reinicios = self.reinicios_recientes.len();
+        if reinicios > self.maximo_reinicios {
+            error!(
+                target: "supervision",
+                actor = self.componente,
+                "Se reinició {} veces en {:?}, se abandona",
+                reinicios,
+                self.ventana
+            );
+            self.espera = None;
+            return;
+        }
+        let espera = self
+            .espera_base
+            .saturating_mul(1 << (reinicios - 1).min(16))
+            .min(ESPERA_MAXIMA);
+        warn!(
+            target: "supervision",
+            actor = self.componente,
+            reinicio = self.total_reinicios,
+            "Reiniciando, retoma en {:?}",
+            espera
+        );
+        self.espera = Some(espera);
+    }
+
+    /// Tiempo a esperar antes de retomar el trabajo, None si el actor fue abandonado
+    pub fn espera(&self) -> Option<Duration> {
+        self.espera
+    }
+
+    pub fn total_reinicios(&self) -> usize {
+        self.total_reinicios
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crear_politica() -> PoliticaDeReinicio {
+        PoliticaDeReinicio::new(
+            "Test",
+            3,
+            Duration::from_secs(10),
+            Duration::from_millis(100),
+        )
+    }
+
+    #[test]
+    fn test_sin_reinicios_retoma_inmediatamente() {
+        let politica = crear_politica();
+
+        assert_eq!(politica.espera(), Some(Duration::ZERO));
+        assert_eq!(politica.total_reinicios(), 0);
+    }
+
+    #[test]
+    fn test_cada_reinicio_en_la_ventana_duplica_la_espera() {
+        let mut politica = crear_politica();
+        let ahora = Instant::now();
+
+        politica.reiniciando(ahora);
+        assert_eq!(politica.espera(), Some(Duration::from_millis(100)));
+        politica.reiniciando(ahora + Duration::from_secs(1));
+        assert_eq!(politica.espera(), Some(Duration::from_millis(200)));
+        politica.reiniciando(ahora + Duration::from_secs(2));
+        assert_eq!(politica.espera(), Some(Duration::from_millis(400)));
+    }
+
+    #[test]
+    fn test_superar_el_maximo_de_reinicios_en_la_ventana_abandona_el_actor() {
+        let mut politica = crear_politica();
+        let ahora = Instant::now();
+
+        for segundos in 0..4 {
+            politica.reiniciando(ahora + Duration::from_secs(segundos));
+        }
+
+        assert_eq!(politica.espera(), None);
+        assert_eq!(politica.total_reinicios(), 4);
+    }
+
+    #[test]
+    fn test_reinicios_fuera_de_la_ventana_no_cuentan_para_abandonar() {
+        let mut politica = crear_politica();
+        let ahora = Instant::now();
+
+        for segundos in [0, 20, 40, 60] {
+            politica.reiniciando(ahora + Duration::from_secs(segundos));
+        }
+
+        assert_eq!(politica.espera(), Some(Duration::from_millis(100)));
+        assert_eq!(politica.total_reinicios(), 4);
+    }
+
+    #[test]
+    fn test_los_reinicios_se_cuentan_por_actor() {
+        let reinicios = Reinicios::default();
+        let mut politica = crear_politica().con_reinicios(reinicios.clone());
+        let _otra = PoliticaDeReinicio::por_defecto("Otro").con_reinicios(reinicios.clone());
+
+        politica.reiniciando(Instant::now());
+        politica.reiniciando(Instant::now());
+
+        assert_eq!(
+            reinicios.por_actor(),
+            BTreeMap::from([("Otro".to_string(), 0), ("Test".to_string(), 2)])
+        );
+    }
+}
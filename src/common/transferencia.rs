@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Identifica una transferencia por el local que la pidió y un numero propio de ese local
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
+pub struct IdTransferencia {
+    pub id_local: usize,
+    pub numero: u64,
+}
+
+/// Unidades de un producto que un local le pide a otro
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Transferencia {
+    pub id: IdTransferencia,
+    pub id_producto: usize,
+    pub cantidad: usize,
+}
+
+impl Transferencia {
+    pub fn new(id: IdTransferencia, id_producto: usize, cantidad: usize) -> Transferencia {
+        Transferencia {
+            id,
+            id_producto,
+            cantidad,
+        }
+    }
+}
+
+impl fmt::Display for IdTransferencia {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.id_local, self.numero)
+    }
+}
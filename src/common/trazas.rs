@@ -0,0 +1,356 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Span, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{Layer, Registry};
+
+/// Span en curso de otro proceso, viaja en la orden con el formato `traceparent` de W3C:
+/// `00-<trace id>-<span id>-01`
+#[derive(Debug, PartialEq, PartialOrd, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ContextoTraza {
+    pub id_traza: u128,
+    pub id_span: u64,
+}
+
+impl ContextoTraza {
+    fn nuevo(padre: Option<&ContextoTraza>) -> Self {
+        ContextoTraza {
+            id_traza: padre.map_or_else(rand::random, |padre| padre.id_traza),
+            id_span: rand::random(),
+        }
+    }
+}
+
+impl fmt::Display for ContextoTraza {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "00-{:032x}-{:016x}-01", self.id_traza, self.id_span)
+    }
+}
+
+impl FromStr for ContextoTraza {
+    type Err = String;
+
+    fn from_str(texto: &str) -> Result<Self, Self::Err> {
+        let partes: Vec<&str> = texto.split('-').collect();
+        match partes[..] {
+            ["00", id_traza, id_span, _] if id_traza.len() == 32 && id_span.len() == 16 => {
+                Ok(ContextoTraza {
+                    id_traza: u128::from_str_radix(id_traza, 16).map_err(|e| e.to_string())?,
+                    id_span: u64::from_str_radix(id_span, 16).map_err(|e| e.to_string())?,
+                })
+            }
+            _ => Err(format!("Contexto de traza invalido: {}", texto)),
+        }
+    }
+}
+
+impl TryFrom<String> for ContextoTraza {
+    type Error = String;
+
+    fn try_from(texto: String) -> Result<Self, Self::Error> {
+        texto.parse()
+    }
+}
+
+impl From<ContextoTraza> for String {
+    fn from(contexto: ContextoTraza) -> Self {
+        contexto.to_string()
+    }
+}
+
+/// Contexto del span en curso para enviarlo a otro proceso, None si no se exportan trazas
+pub fn contexto_actual() -> Option<ContextoTraza> {
+    Span::current()
+        .with_subscriber(|(id, dispatch)| {
+            let registry = dispatch.downcast_ref::<Registry>()?;
+            let span = registry.span(id)?;
+            let extensions = span.extensions();
+            extensions
+                .get::<DatosSpan>()
+                .map(|datos| datos.contexto.clone())
+        })
+        .flatten()
+}
+
+/// Hace que el span continue la traza de otro proceso, hay que llamarla antes de entrar al span
+pub fn con_padre(span: Span, padre: Option<&ContextoTraza>) -> Span {
+    let Some(padre) = padre else {
+        return span;
+    };
+    span.with_subscriber(|(id, dispatch)| {
+        let Some(registry) = dispatch.downcast_ref::<Registry>() else {
+            return;
+        };
+        if let Some(span) = registry.span(id) {
+            if let Some(datos) = span.extensions_mut().get_mut::<DatosSpan>() {
+                datos.contexto.id_traza = padre.id_traza;
+                datos.padre = Some(padre.id_span);
+            }
+        }
+    });
+    span
+}
+
+/// Exporta cada span al cerrarse como una linea de OTLP json (un ExportTraceServiceRequest),
+/// el archivo se puede abrir con cualquier visor que lea OTLP, como Jaeger
+pub struct CapaTrazas {
+    archivo: Mutex<Box<dyn Write + Send>>,
+    binario: &'static str,
+    instancia: usize,
+}
+
+impl CapaTrazas {
+    pub fn new(archivo: &str, binario: &'static str, instancia: usize) -> Result<Self, String> {
+        let archivo = File::options()
+            .create(true)
+            .append(true)
+            .open(archivo)
+            .map_err(|e| format!("No se pudo abrir el archivo de trazas {}: {}", archivo, e))?;
+        Ok(Self::con_salida(
+            Box::new(LineWriter::new(archivo)),
+            binario,
+            instancia,
+        ))
+    }
+
+    fn con_salida(salida: Box<dyn Write + Send>, binario: &'static str, instancia: usize) -> Self {
+        CapaTrazas {
+            archivo: Mutex::new(salida),
+            binario,
+            instancia,
+        }
+    }
+
+    fn exportar(&self, nombre: &str, datos: &DatosSpan, fin: u128) -> Value {
+        let mut span = json!({
+            "traceId": format!("{:032x}", datos.contexto.id_traza),
+            "spanId": format!("{:016x}", datos.contexto.id_span),
+            "name": nombre,
+            "kind": 1,
+            "startTimeUnixNano": datos.inicio.to_string(),
+            "endTimeUnixNano": fin.to_string(),
+            "attributes": datos.atributos,
+        });
+        if let Some(padre) = datos.padre {
+            span["parentSpanId"] = format!("{:016x}", padre).into();
+        }
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        atributo("service.name", self.binario.into()),
+                        atributo("service.instance.id", self.instancia.to_string().into()),
+                    ]
+                },
+                "scopeSpans": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME") },
+                    "spans": [span]
+                }]
+            }]
+        })
+    }
+}
+
+impl<S> Layer<S> for CapaTrazas
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, atributos: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let padre = span.parent().and_then(|padre| {
+            padre
+                .extensions()
+                .get::<DatosSpan>()
+                .map(|datos| datos.contexto.clone())
+        });
+        let mut datos = DatosSpan {
+            contexto: ContextoTraza::nuevo(padre.as_ref()),
+            padre: padre.map(|padre| padre.id_span),
+            inicio: ahora_en_ns(),
+            atributos: vec![],
+        };
+        atributos.record(&mut datos);
+        span.extensions_mut().insert(datos);
+    }
+
+    fn on_record(&self, id: &Id, valores: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(datos) = span.extensions_mut().get_mut::<DatosSpan>() {
+                valores.record(datos);
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        let Some(datos) = extensions.get::<DatosSpan>() else {
+            return;
+        };
+        let linea = self.exportar(span.name(), datos, ahora_en_ns());
+        let mut archivo = self
+            .archivo
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        // si no se puede escribir se pierde el span, no se corta el proceso por las trazas
+        let _ = writeln!(archivo, "{}", linea);
+    }
+}
+
+/// Lo que se guarda de cada span hasta que se cierra
+struct DatosSpan {
+    contexto: ContextoTraza,
+    padre: Option<u64>,
+    inicio: u128,
+    /// Campos del span como atributos de OTLP
+    atributos: Vec<Value>,
+}
+
+impl Visit for DatosSpan {
+    fn record_str(&mut self, campo: &Field, valor: &str) {
+        self.atributos
+            .push(atributo(campo.name(), json!({ "stringValue": valor })));
+    }
+
+    fn record_u64(&mut self, campo: &Field, valor: u64) {
+        self.atributos.push(atributo(
+            campo.name(),
+            json!({ "intValue": valor.to_string() }),
+        ));
+    }
+
+    fn record_i64(&mut self, campo: &Field, valor: i64) {
+        self.atributos.push(atributo(
+            campo.name(),
+            json!({ "intValue": valor.to_string() }),
+        ));
+    }
+
+    fn record_f64(&mut self, campo: &Field, valor: f64) {
+        self.atributos
+            .push(atributo(campo.name(), json!({ "doubleValue": valor })));
+    }
+
+    fn record_bool(&mut self, campo: &Field, valor: bool) {
+        self.atributos
+            .push(atributo(campo.name(), json!({ "boolValue": valor })));
+    }
+
+    fn record_debug(&mut self, campo: &Field, valor: &dyn fmt::Debug) {
+        self.record_str(campo, &format!("{:?}", valor));
+    }
+}
+
+/// Atributo de OTLP, los textos sueltos se pasan a `stringValue`
+fn atributo(clave: &str, valor: Value) -> Value {
+    let valor = match valor {
+        Value::String(texto) => json!({ "stringValue": texto }),
+        otro => otro,
+    };
+    json!({ "key": clave, "value": valor })
+}
+
+fn ahora_en_ns() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duracion| duracion.as_nanos())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Junta en memoria lo que se exporta
+    #[derive(Clone, Default)]
+    struct Salida(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Salida {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn spans_exportados(salida: &Salida) -> Vec<Value> {
+        let bytes = salida.0.lock().unwrap().clone();
+        String::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .map(|linea| {
+                let json: Value = serde_json::from_str(linea).unwrap();
+                json["resourceSpans"][0]["scopeSpans"][0]["spans"][0].clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_contexto_de_traza_en_formato_traceparent() {
+        let contexto = ContextoTraza {
+            id_traza: 0x4bf92f3577b34da6a3ce929d0e0e4736,
+            id_span: 0x00f067aa0ba902b7,
+        };
+        let texto = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+        assert_eq!(contexto.to_string(), texto);
+        assert_eq!(texto.parse::<ContextoTraza>(), Ok(contexto));
+        assert!("00-4bf9-00f0-01".parse::<ContextoTraza>().is_err());
+    }
+
+    #[test]
+    fn test_spans_hijos_y_de_otro_proceso_comparten_la_traza() {
+        let salida = Salida::default();
+        let subscriber = tracing_subscriber::registry().with(CapaTrazas::con_salida(
+            Box::new(salida.clone()),
+            "e-commerce",
+            2,
+        ));
+        let remoto = tracing::subscriber::with_default(subscriber, || {
+            let _orden = tracing::info_span!("orden", orden = "2-0").entered();
+            let _oferta = tracing::info_span!("ofrecer_orden", local = 1).entered();
+            contexto_actual().unwrap()
+        });
+        let subscriber = tracing_subscriber::registry().with(CapaTrazas::con_salida(
+            Box::new(salida.clone()),
+            "local",
+            1,
+        ));
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = con_padre(tracing::info_span!("agregar_orden"), Some(&remoto)).entered();
+        });
+
+        let spans = spans_exportados(&salida);
+        let nombres: Vec<&str> = spans.iter().map(|s| s["name"].as_str().unwrap()).collect();
+        assert_eq!(nombres, vec!["ofrecer_orden", "orden", "agregar_orden"]);
+        let id_traza = format!("{:032x}", remoto.id_traza);
+        assert!(spans.iter().all(|span| span["traceId"] == id_traza));
+        assert_eq!(spans[0]["parentSpanId"], spans[1]["spanId"]);
+        assert_eq!(spans[2]["parentSpanId"], format!("{:016x}", remoto.id_span));
+        assert!(spans[1].get("parentSpanId").is_none());
+        assert_eq!(
+            spans[0]["attributes"][0],
+            json!({ "key": "local", "value": { "intValue": "1" } })
+        );
+    }
+}
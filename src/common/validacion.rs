@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use csv::StringRecord;
+
+use crate::common::error_csv::ErrorCsv;
+use crate::common::fuente_ordenes::FormatoOrdenes;
+use crate::common::info_local::InfoLocal;
+use crate::common::lector_csv::{linea, open_csv};
+use crate::common::orden::Orden;
+use crate::common::reposicion::Reposicion;
+use crate::common::stock_producto::StockProducto;
+
+/// Filas de un archivo que se pudieron leer, con su numero de linea, y los errores de las que no
+pub struct Filas<F> {
+    pub validas: Vec<(u64, F)>,
+    pub invalidas: Vec<ErrorCsv>,
+}
+
+/// Lee todas las filas del csv sin cortar en la primera invalida
+pub fn leer_filas<F>(
+    archivo: &str,
+    constructor: fn(StringRecord) -> Result<F, ErrorCsv>,
+) -> Result<Filas<F>, ErrorCsv> {
+    let mut reader = open_csv(archivo)?;
+    let mut filas = Filas {
+        validas: vec![],
+        invalidas: vec![],
+    };
+    for resultado in reader.records() {
+        let record = match resultado.map_err(ErrorCsv::from) {
+            Ok(record) => record,
+            Err(ErrorCsv::Io(e)) => return Err(ErrorCsv::Io(e)),
+            Err(e) => {
+                filas.invalidas.push(e);
+                continue;
+            }
+        };
+        let linea = linea(&record);
+        match constructor(record) {
+            Ok(valor) => filas.validas.push((linea, valor)),
+            Err(e) => filas.invalidas.push(e),
+        }
+    }
+    Ok(filas)
+}
+
+/// Lee el stock de un local. Un id_producto repetido es invalido, se queda la primera fila
+pub fn leer_stock(archivo: &str) -> Result<Filas<StockProducto>, ErrorCsv> {
+    let Filas {
+        validas,
+        mut invalidas,
+    } = leer_filas(archivo, StockProducto::from_record)?;
+    let mut lineas_por_producto: HashMap<usize, u64> = HashMap::new();
+    let mut sin_repetidos = vec![];
+    for (linea, producto) in validas {
+        match lineas_por_producto.get(&producto.id_producto) {
+            Some(primera) => invalidas.push(ErrorCsv::FilaInvalida {
+                linea,
+                motivo: format!(
+                    "id_producto {} repetido, ya estaba en la linea {}",
+                    producto.id_producto, primera
+                ),
+            }),
+            None => {
+                lineas_por_producto.insert(producto.id_producto, linea);
+                sin_repetidos.push((linea, producto));
+            }
+        }
+    }
+    invalidas.sort_by_key(linea_del_error);
+    Ok(Filas {
+        validas: sin_repetidos,
+        invalidas,
+    })
+}
+
+/// Errores de cada fila de un archivo de ordenes en el formato dado
+pub fn validar_ordenes(archivo: &str, formato: FormatoOrdenes) -> Result<Vec<ErrorCsv>, ErrorCsv> {
+    if formato == FormatoOrdenes::Csv {
+        return Ok(leer_filas(archivo, Orden::from_record)?.invalidas);
+    }
+    let mut invalidas = vec![];
+    let lector = BufReader::new(File::open(archivo)?);
+    for (indice, linea) in lector.lines().enumerate() {
+        let linea = linea?;
+        if linea.trim().is_empty() {
+            continue;
+        }
+        let resultado = Orden::deserializar(linea.as_bytes())
+            .map_err(|e| e.to_string())
+            .and_then(|orden| orden.validar());
+        if let Err(motivo) = resultado {
+            invalidas.push(ErrorCsv::FilaInvalida {
+                linea: indice as u64 + 1,
+                motivo,
+            });
+        }
+    }
+    Ok(invalidas)
+}
+
+pub fn validar_stock(archivo: &str) -> Result<Vec<ErrorCsv>, ErrorCsv> {
+    Ok(leer_stock(archivo)?.invalidas)
+}
+
+pub fn validar_locales(archivo: &str) -> Result<Vec<ErrorCsv>, ErrorCsv> {
+    Ok(leer_filas(archivo, InfoLocal::from_record)?.invalidas)
+}
+
+pub fn validar_reposiciones(archivo: &str) -> Result<Vec<ErrorCsv>, ErrorCsv> {
+    Ok(leer_filas(archivo, Reposicion::from_record)?.invalidas)
+}
+
+/// Imprime el resultado de validar un archivo, devuelve si es valido
+pub fn informar_validacion(archivo: &str, resultado: Result<Vec<ErrorCsv>, ErrorCsv>) -> bool {
+    match resultado {
+        Ok(invalidas) if invalidas.is_empty() => {
+            println!("{}: ok", archivo);
+            true
+        }
+        Ok(invalidas) => {
+            println!("{}: {} filas invalidas", archivo, invalidas.len());
+            for error in invalidas {
+                println!("  {}", error);
+            }
+            false
+        }
+        Err(e) => {
+            println!("{}: no se pudo leer: {}", archivo, e);
+            false
+        }
+    }
+}
+
+fn linea_del_error(error: &ErrorCsv) -> u64 {
+    match error {
+        ErrorCsv::FaltaColumna { linea, .. }
+        | ErrorCsv::ValorInvalido { linea, .. }
+        | ErrorCsv::Formato { linea, .. }
+        | ErrorCsv::FilaInvalida { linea, .. } => *linea,
+        ErrorCsv::FinDeArchivo | ErrorCsv::Io(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_stock_con_id_producto_repetido_queda_la_primera_fila() {
+        let archivo = "test_stock_con_id_producto_repetido.csv";
+        fs::write(archivo, "id_producto,stock\n1,30\n2,x\n1,5\n3,7\n").unwrap();
+
+        let filas = leer_stock(archivo);
+        fs::remove_file(archivo).unwrap();
+
+        let filas = filas.unwrap();
+        let ids: Vec<(u64, usize)> = filas
+            .validas
+            .iter()
+            .map(|(linea, producto)| (*linea, producto.stock))
+            .collect();
+        assert_eq!(ids, vec![(2, 30), (5, 7)]);
+        assert!(matches!(
+            filas.invalidas[..],
+            [
+                ErrorCsv::ValorInvalido { linea: 3, .. },
+                ErrorCsv::FilaInvalida { linea: 4, .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_validar_ordenes_informa_cantidad_cero_y_coordenadas_fuera_de_rango() {
+        let archivo = "test_validar_ordenes_cantidad_cero.csv";
+        fs::write(
+            archivo,
+            "id_producto,cantidad,latitud,longitud\n1,0,1,2\n2,5,91,2\n3,5,1,-181\n4,5,-90,180\n",
+        )
+        .unwrap();
+
+        let invalidas = validar_ordenes(archivo, FormatoOrdenes::Csv);
+        fs::remove_file(archivo).unwrap();
+
+        let lineas: Vec<u64> = invalidas.unwrap().iter().map(linea_del_error).collect();
+        assert_eq!(lineas, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_validar_ordenes_en_json_por_linea() {
+        let archivo = "test_validar_ordenes_en_json_por_linea.jsonl";
+        fs::write(
+            archivo,
+            "{\"id_producto\":1,\"cantidad\":0,\"direccion\":{\"latitud\":1,\"longitud\":1}}\n\
+             {\"id_producto\":1,\"cantidad\":1,\"direccion\":{\"latitud\":1,\"longitud\":1}}\n",
+        )
+        .unwrap();
+
+        let invalidas = validar_ordenes(archivo, FormatoOrdenes::JsonLineas);
+        fs::remove_file(archivo).unwrap();
+
+        let lineas: Vec<u64> = invalidas.unwrap().iter().map(linea_del_error).collect();
+        assert_eq!(lineas, vec![1]);
+    }
+}
@@ -1,36 +1,38 @@
-use std::net::{SocketAddr, UdpSocket};
+use serde_json::json;
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::thread::sleep;
 use std::time::Duration;
-use std::{env, fmt, thread};
+use std::{env, thread};
+use tracing::{debug, error, info, warn};
 
-mod common;
-use common::lector_csv::{leer_linea_csv_desde, open_csv};
-use common::mensaje_protocolo::TipoDeMensaje;
-use common::orden::{Direccion, Orden};
-use common::socket::{id_to_addr_local, Socket};
+use common::configuracion_ecommerce::ConfiguracionEcommerce;
+use common::despacho::{Despachador, Turno};
+use common::fallas::{nodos_por_defecto, nombre_ecommerce, ConfiguracionFallas, Fallas, SocketUdp};
+use common::fuente_ordenes::{abrir_fuente, FormatoOrdenes, FuenteOrdenes, ORIGEN_STDIN};
+use common::historial::Historial;
+use common::http::{leer_pedido, PedidoHttp, RespuestaHttp};
+use common::info_local::{InfoLocal, MapaLocales};
+use common::logs::iniciar_logs;
+use common::metricas::{servir_metricas, MetricasEcommerce};
+use common::orden::Orden;
+use common::registro_ordenes::{EstadoOrden, RegistroOrdenes};
+use common::reloj::RelojSistema;
+use common::socket::{id_to_addr_ecommerce, Socket};
+use common::trazas::CapaTrazas;
+use common::validacion::{informar_validacion, leer_filas, validar_locales, validar_ordenes};
+use donotunwrap::common;
 
 const ECOMMERCE_PUERTO_BASE: u32 = 1024;
 const ECOMMERCE_ADDR_BASE: &str = "127.0.0.1";
-const ECOMMERCE_UDP_ADDR_BASE: &str = "127.0.0.1:555";
-
-#[derive(Debug, PartialEq)]
-pub enum ErrorEcommerce {
-    SocketTimeOut,
-}
-
-impl fmt::Display for ErrorEcommerce {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            ErrorEcommerce::SocketTimeOut => write!(f, "Timeout en socket recv"),
-        }
-    }
-}
-
-#[derive(Debug)]
-struct InfoLocal {
-    id: usize,
-    direccion: Direccion,
-}
+const ECOMMERCE_HTTP_PUERTO_BASE: u32 = 8080;
+/// Cada cuanto se revisa si llegaron ordenes por http o por la fuente cuando no hay ordenes
+const ESPERA_ORDENES_HTTP: Duration = Duration::from_millis(200);
+const TIMEOUT_HTTP: Duration = Duration::from_secs(5);
+/// Espera antes de volver a intentar conectarse al lider si se perdió la conexión
+const ESPERA_RECONEXION_LIDER: Duration = Duration::from_secs(1);
+const ECOMMERCE_METRICAS_PUERTO_BASE: u16 = 9200;
 
 fn main() {
     let mut args = env::args().skip(1);
@@ -39,171 +41,429 @@ fn main() {
         .expect("Falta parametro del id")
         .parse::<u32>()
         .expect("No es un numero");
+    let configuracion = match ConfiguracionEcommerce::parsear(args) {
+        Ok(configuracion) => configuracion,
+        Err(e) => {
+            eprintln!("[Ecommerce - Error] {}", e);
+            return;
+        }
+    };
+    if configuracion.solo_validar {
+        std::process::exit(if validar_fuente(&configuracion) { 0 } else { 1 });
+    }
+    let trazas = match configuracion
+        .archivo_trazas
+        .as_deref()
+        .map(|archivo| CapaTrazas::new(archivo, "e-commerce", id as usize))
+        .transpose()
+    {
+        Ok(trazas) => trazas,
+        Err(e) => {
+            eprintln!("[Ecommerce - Error] {}", e);
+            return;
+        }
+    };
+    if let Err(e) = iniciar_logs(
+        "e-commerce",
+        id as usize,
+        configuracion.formato_logs,
+        configuracion.filtro_logs.as_deref(),
+        trazas,
+    ) {
+        eprintln!("[Ecommerce - Error] {}", e);
+        return;
+    }
+
+    let configuracion_fallas = match configuracion.archivo_fallas.as_deref() {
+        Some(archivo) => match ConfiguracionFallas::leer(archivo) {
+            Ok(fallas) => fallas,
+            Err(e) => {
+                eprintln!("[Ecommerce - Error] {}", e);
+                return;
+            }
+        },
+        None => ConfiguracionFallas::default(),
+    };
+    let fallas = Arc::new(
+        Fallas::new(
+            nombre_ecommerce(id as usize),
+            configuracion_fallas,
+            Arc::new(RelojSistema),
+        )
+        .con_nodos(nodos_por_defecto()),
+    );
+    let historial = match configuracion
+        .archivo_historial
+        .as_deref()
+        .map(|archivo| Historial::new(archivo, nombre_ecommerce(id as usize)))
+        .transpose()
+    {
+        Ok(historial) => historial,
+        Err(e) => {
+            eprintln!("[Ecommerce - Error] {}", e);
+            return;
+        }
+    };
+
+    let direccion_http = configuracion
+        .direccion_http
+        .map(|direccion| direccion.to_string())
+        .unwrap_or(format!(
+            "{}:{}",
+            ECOMMERCE_ADDR_BASE,
+            ECOMMERCE_HTTP_PUERTO_BASE + id
+        ));
+
+    let direccion_metricas = configuracion
+        .direccion_metricas
+        .unwrap_or(SocketAddr::from((
+            [127, 0, 0, 1],
+            ECOMMERCE_METRICAS_PUERTO_BASE + id as u16,
+        )));
+    let metricas = Arc::new(Mutex::new(MetricasEcommerce::default()));
+    let metricas_expuestas = metricas.clone();
+    thread::spawn(move || {
+        servir_metricas(direccion_metricas, || {
+            metricas_expuestas
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .exponer()
+        })
+    });
 
     let puerto = ECOMMERCE_PUERTO_BASE;
-    let mut socket = Socket::new(String::from(ECOMMERCE_ADDR_BASE), puerto, id);
-    println!("[Ecommerce] id {} con puerto {} creado", id, puerto + id);
+    let mut socket = Socket::new(
+        String::from(ECOMMERCE_ADDR_BASE),
+        puerto,
+        id,
+        fallas.clone(),
+    );
+    info!(target: "ecommerce", puerto = puerto + id, "Ecommerce creado");
 
     thread::spawn(move || {
         if id == 1 {
-            socket.esperar_conexiones(id);
+            socket.con_historial(historial).esperar_conexiones(id);
         } else {
-            leer_orden_y_enviarsela_al_local(&mut socket, id);
+            let registro = Arc::new(Mutex::new(RegistroOrdenes::new()));
+            let registro_http = registro.clone();
+            thread::spawn(move || servir_api_http(direccion_http, registro_http));
+            leer_orden_y_enviarsela_al_local(
+                &mut socket,
+                id,
+                registro,
+                metricas,
+                fallas,
+                historial,
+                &configuracion,
+            );
         }
     })
     .join()
     .unwrap();
 }
 
-fn leer_orden_y_enviarsela_al_local(socket_ecommerce: &mut Socket, id: u32) {
-    let socket = UdpSocket::bind(format!("{}{}", ECOMMERCE_UDP_ADDR_BASE, id)).unwrap();
-    let locales: Vec<InfoLocal> = (1..3)
-        .map(|id| InfoLocal {
-            id,
-            direccion: Direccion::new((id * 2 + 1) as i32, (id * 4 + 1) as i32),
-        })
-        .collect();
+fn leer_orden_y_enviarsela_al_local(
+    socket_ecommerce: &mut Socket,
+    id: u32,
+    registro: Arc<Mutex<RegistroOrdenes>>,
+    metricas: Arc<Mutex<MetricasEcommerce>>,
+    fallas: Arc<Fallas>,
+    historial: Option<Historial>,
+    configuracion: &ConfiguracionEcommerce,
+) {
+    let socket = UdpSocket::bind(id_to_addr_ecommerce(id as usize)).unwrap();
+    let locales = cargar_locales(configuracion);
+    for local in &locales {
+        debug!(target: "ecommerce", local = local.id, cobertura = ?local.cobertura, "Local conocido");
+    }
+    let mut despachador = Despachador::new(
+        SocketUdp::new(socket, fallas),
+        MapaLocales::new(locales),
+        metricas,
+        Arc::new(RelojSistema),
+    )
+    .con_historial(historial);
+    let (origen, formato) = origen_y_formato(configuracion);
+    let espera_sin_ordenes = configuracion
+        .intervalo_seguimiento
+        .unwrap_or(ESPERA_ORDENES_HTTP);
+    info!(target: "fuente", origen = %origen, formato = ?formato, "Abriendo ordenes");
+    let mut fuente: Box<dyn FuenteOrdenes> = abrir_fuente(
+        &origen,
+        formato,
+        configuracion.intervalo_seguimiento.is_some(),
+    )
+    .expect("No se pudo abrir la fuente de ordenes");
 
-    println!("Locales: {:?}", locales);
-    println!("[Ecommerce] Abriendo archivo ordenes");
-    let path = format!(
-        "{}{}",
-        env!("CARGO_MANIFEST_DIR"),
-        "/data/ordenes_ecommerce.txt"
-    );
+    let mut quedan_ordenes_en_fuente = true;
 
     loop {
-        let cursor = socket_ecommerce.quiero_enviar_ordenes();
-        let mut ordenes_reader = open_csv(&path).unwrap();
-        println!("[Ecommerce] Empezando a leer ordenes");
-        println!("[Ecommerce] Leo una orden con cursor {}", cursor);
-        //TODO cambiar el estado o bloquear orden actual o enviar cursor
-        let orden = match leer_linea_csv_desde(&mut ordenes_reader, Orden::from_record, cursor) {
-            Ok(orden) => orden,
-            Err(err) => {
-                eprintln!("Error: {}", err);
-                if err.to_string() == "No se encontraron más registros en el csv" {
-                    socket_ecommerce.desconexion();
-                    break;
-                }
-                panic!("Error al leer ordenes");
+        for cancelacion in despachador.atender_avisos() {
+            if let Some(id_orden) = id_orden_http(&cancelacion.orden, id) {
+                registro
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .cancelar(id_orden, cancelacion.local, cancelacion.motivo);
             }
-        };
-        drop(ordenes_reader);
-        // termine de leer libero el mutex
-        socket_ecommerce.ordenes_enviadas();
-
-        let mut locales_visitados = vec![];
-
-        let orden_serializada = serde_json::to_string(&orden).unwrap();
-        let mut buffer = [0; 100];
-        let mut orden_aceptada = false;
-
-        while !orden_aceptada && (locales_visitados.len() < locales.len()) {
-            let local_seleccionado =
-                seleccionar_local_mas_cercano(&orden, &locales, &locales_visitados);
-            println!(
-                "[Ecommerce] Envio orden {} a local {} con addr {}",
-                orden_serializada,
-                local_seleccionado,
-                id_to_addr_local(local_seleccionado)
-            );
-
-            if let Ok((size, from)) = enviar_orden(
-                &socket,
-                &orden_serializada,
-                &mut buffer,
-                &local_seleccionado,
-            ) {
-                let buffer_sin_ceros = &mut buffer[..size];
-                let mensaje = String::from_utf8(Vec::from(buffer_sin_ceros)).unwrap();
-
-                println!("[Ecommerce] recibí {} de {}", mensaje, from);
-                if TipoDeMensaje::OrdenAceptada.value() == mensaje {
-                    orden_aceptada = true;
-                } else {
-                    locales_visitados.push(local_seleccionado);
+        }
+        // Las ordenes que llegan por http se despachan antes que las del archivo
+        let pendiente = registro
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .siguiente_pendiente();
+        if let Some((id_orden, mut orden)) = pendiente {
+            orden.id = Some(format!("{}-http-{}", id, id_orden));
+            info!(target: "ecommerce", orden = orden.id.as_deref(), "Despacho orden recibida por http");
+            let estado = match despachador.turno_http(socket_ecommerce, &orden) {
+                Ok(Ok(local)) => EstadoOrden::Aceptada { local },
+                Ok(Err(motivo)) => EstadoOrden::Rechazada { motivo },
+                Err(e) => {
+                    // la orden sigue pendiente hasta que vuelva el lider
+                    registro
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .devolver_pendiente(id_orden, orden);
+                    warn!(target: "coordinador", "{}", e);
+                    reconectar_al_lider(socket_ecommerce);
+                    continue;
                 }
-            } else {
-                locales_visitados.push(local_seleccionado);
-                continue;
-            }
+            };
+            registro
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .actualizar(id_orden, estado);
+            continue;
+        }
+        if !quedan_ordenes_en_fuente {
+            sleep(ESPERA_ORDENES_HTTP);
+            continue;
         }
 
-        if !orden_aceptada {
-            println!("[Ecommerce] Ningun local pudo aceptar la orden, se desestima")
+        match despachador.turno(socket_ecommerce, fuente.as_mut(), id) {
+            Turno::Despachada { .. } => sleep(configuracion.pausa_despacho),
+            Turno::SinOrdenesPorAhora => sleep(espera_sin_ordenes),
+            Turno::SinMasOrdenes => {
+                info!(
+                    target: "fuente",
+                    "No quedan ordenes en la fuente, se siguen atendiendo ordenes por http"
+                );
+                quedan_ordenes_en_fuente = false;
+            }
+            Turno::OrdenInvalida => {}
+            Turno::SinLider => {
+                warn!(target: "coordinador", "Se perdió la conexión con el lider");
+                reconectar_al_lider(socket_ecommerce);
+            }
+            Turno::ErrorDeLectura(err) => {
+                error!(
+                    target: "fuente",
+                    "{}, se dejan de leer ordenes y se siguen atendiendo ordenes por http",
+                    err
+                );
+                quedan_ordenes_en_fuente = false;
+            }
         }
-        sleep(Duration::from_millis(1000));
     }
 }
 
-fn enviar_orden(
-    socket: &UdpSocket,
-    orden_serializada: &String,
-    buffer: &mut [u8; 100],
-    local_seleccionado: &usize,
-) -> Result<(usize, SocketAddr), ErrorEcommerce> {
-    socket
-        .send_to(
-            orden_serializada.as_bytes(),
-            id_to_addr_local(*local_seleccionado),
-        )
-        .unwrap();
-
-    socket
-        .set_read_timeout(Some(Duration::from_millis(500)))
-        .unwrap();
-    let (size, from) = match socket.recv_from(buffer) {
-        Ok((size, from)) => (size, from),
-        Err(err) => {
-            println!("[Ecommerce - Error] timeout recv: {}", err);
-            return Err(ErrorEcommerce::SocketTimeOut);
-        }
-    };
+/// Espera y vuelve a intentar conectarse al lider, la api http sigue atendiendo mientras tanto
+fn reconectar_al_lider(socket_ecommerce: &mut Socket) {
+    sleep(ESPERA_RECONEXION_LIDER);
+    if let Err(e) = socket_ecommerce.reconectar() {
+        error!(target: "coordinador", "No se pudo reconectar al lider: {}", e);
+    }
+}
+
+/// Id en el registro de una orden que este ecommerce recibió por http, a partir del id
+/// con el que se la envió al local
+fn id_orden_http(orden: &str, id_ecommerce: u32) -> Option<u64> {
+    orden
+        .strip_prefix(&format!("{}-http-", id_ecommerce))?
+        .parse()
+        .ok()
+}
 
-    Ok((size, from))
+/// Archivo de ordenes y su formato, por defecto el archivo del ecommerce en data/
+fn origen_y_formato(configuracion: &ConfiguracionEcommerce) -> (String, FormatoOrdenes) {
+    let origen = configuracion.origen_ordenes.clone().unwrap_or(format!(
+        "{}{}",
+        env!("CARGO_MANIFEST_DIR"),
+        "/data/ordenes_ecommerce.txt"
+    ));
+    let formato = configuracion
+        .formato_ordenes
+        .unwrap_or(FormatoOrdenes::por_origen(&origen));
+    (origen, formato)
 }
 
-fn seleccionar_local_mas_cercano(
-    orden: &Orden,
-    locales: &[InfoLocal],
-    locales_visitados: &[usize],
-) -> usize {
-    let mut distancia_minima = f64::MAX;
-    let mut id_seleccionado = 0;
+/// Valida los archivos de ordenes y de locales e informa las filas invalidas, devuelve si son validos
+fn validar_fuente(configuracion: &ConfiguracionEcommerce) -> bool {
+    let (origen, formato) = origen_y_formato(configuracion);
+    if origen == ORIGEN_STDIN {
+        eprintln!("[Ecommerce - Error] --check necesita un archivo de ordenes");
+        return false;
+    }
+    let mut valido = informar_validacion(&origen, validar_ordenes(&origen, formato));
+    let archivo = archivo_locales(configuracion);
+    valido &= informar_validacion(&archivo, validar_locales(&archivo));
+    valido
+}
 
-    for local in locales.iter() {
-        if locales_visitados.contains(&local.id) {
-            continue;
+/// Archivo de --locales o el de los locales por defecto en data/locales_ecommerce.txt
+fn archivo_locales(configuracion: &ConfiguracionEcommerce) -> String {
+    configuracion.archivo_locales.clone().unwrap_or(format!(
+        "{}{}",
+        env!("CARGO_MANIFEST_DIR"),
+        "/data/locales_ecommerce.txt"
+    ))
+}
+
+/// Locales del archivo de locales (id_local, latitud, longitud, cobertura)
+fn cargar_locales(configuracion: &ConfiguracionEcommerce) -> Vec<InfoLocal> {
+    let archivo = archivo_locales(configuracion);
+    let filas = leer_filas(&archivo, InfoLocal::from_record).expect("Error al leer los locales");
+    for error in filas.invalidas {
+        warn!(target: "ecommerce", archivo = %archivo, "Local invalido: {}", error);
+    }
+    filas.validas.into_iter().map(|(_, local)| local).collect()
+}
+
+/// Atiende la api http del ecommerce, un pedido por conexión y cada conexión en su thread:
+/// POST /ordenes con una orden en json la encola para despacharla y devuelve su id
+/// GET /ordenes/<id> devuelve el estado de la orden
+fn servir_api_http(direccion: String, registro: Arc<Mutex<RegistroOrdenes>>) {
+    let listener = match TcpListener::bind(&direccion) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(target: "http", "No se pudo escuchar en {}: {}", direccion, e);
+            return;
         }
-        let distancia = orden.direccion.distancia(&local.direccion);
-        if distancia < distancia_minima {
-            distancia_minima = distancia;
-            id_seleccionado = local.id;
+    };
+    info!(target: "http", direccion = %direccion, "Api http escuchando");
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let registro = registro.clone();
+                thread::spawn(move || atender_pedido_http(stream, &registro));
+            }
+            Err(e) => error!(target: "http", "Conexión http: {}", e),
         }
     }
+}
+
+fn atender_pedido_http(stream: TcpStream, registro: &Mutex<RegistroOrdenes>) {
+    let _ = stream.set_read_timeout(Some(TIMEOUT_HTTP));
+    let respuesta = match leer_pedido(&mut BufReader::new(&stream)) {
+        Ok(pedido) => responder_api(&pedido, registro),
+        Err(e) => RespuestaHttp::json(400, &json!({ "error": e.to_string() })),
+    };
+    if let Err(e) = respuesta.escribir(&mut &stream) {
+        error!(target: "http", "No se pudo responder pedido http: {}", e);
+    }
+}
 
-    id_seleccionado
+fn responder_api(pedido: &PedidoHttp, registro: &Mutex<RegistroOrdenes>) -> RespuestaHttp {
+    let mut registro = registro.lock().unwrap_or_else(PoisonError::into_inner);
+    match (pedido.metodo.as_str(), pedido.ruta.as_str()) {
+        ("POST", "/ordenes") => match Orden::deserializar(&pedido.cuerpo)
+            .map_err(|e| e.to_string())
+            .and_then(|orden| orden.validar().map(|_| orden))
+        {
+            Ok(mut orden) => {
+                // El id, la traza y el vencimiento los pone el servidor, no el cliente
+                orden.id = None;
+                orden.traza = None;
+                orden.vencimiento = None;
+                let id = registro.agregar(orden);
+                info!(target: "http", id, "Orden recibida por http");
+                RespuestaHttp::json(202, &json!({ "id": id, "estado": "pendiente" }))
+            }
+            Err(e) => {
+                RespuestaHttp::json(400, &json!({ "error": format!("Orden invalida: {}", e) }))
+            }
+        },
+        ("GET", ruta) if ruta.starts_with("/ordenes/") => {
+            let estado = ruta["/ordenes/".len()..]
+                .parse::<u64>()
+                .ok()
+                .and_then(|id| registro.estado(id).map(|estado| (id, estado)));
+            match estado {
+                Some((id, estado)) => {
+                    let mut respuesta = json!(estado);
+                    respuesta["id"] = json!(id);
+                    RespuestaHttp::json(200, &respuesta)
+                }
+                None => RespuestaHttp::json(404, &json!({ "error": "No existe la orden" })),
+            }
+        }
+        _ => RespuestaHttp::json(404, &json!({ "error": "Ruta desconocida" })),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use common::trazas::ContextoTraza;
+
+    fn pedido(metodo: &str, ruta: &str, cuerpo: &str) -> PedidoHttp {
+        PedidoHttp {
+            metodo: metodo.to_string(),
+            ruta: ruta.to_string(),
+            cuerpo: cuerpo.as_bytes().to_vec(),
+        }
+    }
 
     #[test]
-    fn test_dado_local_latitud_5_longitud_5_y_local_latitud_10_longitud_10_cuando_calculo_local_mas_cercano_a_orden_direccion_3_3_obtengo_primer_local(
-    ) {
-        let locales: Vec<InfoLocal> = (1..3)
-            .map(|id| InfoLocal {
-                id,
-                direccion: Direccion::new((id * 5) as i32, (id * 5) as i32),
-            })
-            .collect();
-        let orden = Orden::new(1, 5, 3, 3);
-        let locales_visitados = vec![];
-        let local_seleccionado =
-            seleccionar_local_mas_cercano(&orden, &locales, &locales_visitados);
-
-        assert_eq!(local_seleccionado, locales[0].id);
+    fn test_post_de_orden_la_encola_y_get_devuelve_su_estado() {
+        let registro = Mutex::new(RegistroOrdenes::new());
+        let orden = serde_json::to_string(&Orden::new(1, 5, 3.0, 3.0)).unwrap();
+
+        let respuesta = responder_api(&pedido("POST", "/ordenes", &orden), &registro);
+        assert_eq!(respuesta.codigo, 202);
+
+        let respuesta = responder_api(&pedido("GET", "/ordenes/1", ""), &registro);
+        let estado: serde_json::Value = serde_json::from_str(&respuesta.cuerpo).unwrap();
+        assert_eq!(respuesta.codigo, 200);
+        assert_eq!(estado, json!({ "id": 1, "estado": "pendiente" }));
+    }
+
+    #[test]
+    fn test_post_de_orden_ignora_el_id_la_traza_y_el_vencimiento_del_cliente() {
+        let registro = Mutex::new(RegistroOrdenes::new());
+        let mut orden = Orden::new(1, 5, 3.0, 3.0);
+        orden.id = Some("2-http-1".to_string());
+        orden.traza = Some(ContextoTraza {
+            id_traza: 1,
+            id_span: 1,
+        });
+        orden.vencimiento = Some(0);
+        let cuerpo = serde_json::to_string(&orden).unwrap();
+
+        let respuesta = responder_api(&pedido("POST", "/ordenes", &cuerpo), &registro);
+        assert_eq!(respuesta.codigo, 202);
+
+        let (id, encolada) = registro.lock().unwrap().siguiente_pendiente().unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(encolada.id, None);
+        assert!(encolada.traza.is_none());
+        assert_eq!(encolada.vencimiento, None);
+    }
+
+    #[test]
+    fn test_id_de_orden_http_solo_para_las_de_este_ecommerce() {
+        assert_eq!(id_orden_http("2-http-15", 2), Some(15));
+        assert_eq!(id_orden_http("3-http-15", 2), None);
+        assert_eq!(id_orden_http("2-15", 2), None);
+    }
+
+    #[test]
+    fn test_orden_invalida_o_inexistente_devuelve_error() {
+        let registro = Mutex::new(RegistroOrdenes::new());
+        let respuesta = responder_api(&pedido("POST", "/ordenes", "{}"), &registro);
+        assert_eq!(respuesta.codigo, 400);
+        let respuesta = responder_api(&pedido("GET", "/ordenes/7", ""), &registro);
+        assert_eq!(respuesta.codigo, 404);
+        let respuesta = responder_api(&pedido("DELETE", "/ordenes/1", ""), &registro);
+        assert_eq!(respuesta.codigo, 404);
     }
 }
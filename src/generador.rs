@@ -0,0 +1,119 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process::exit;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use donotunwrap::common;
+
+use common::configuracion_generador::{ConfiguracionGenerador, DestinoCarga};
+use common::fuente_ordenes::ORIGEN_STDIN;
+use common::generador_ordenes::{EscritorOrdenes, GeneradorOrdenes};
+use common::http::enviar_pedido;
+use common::orden::Orden;
+
+/// Genera ordenes sinteticas a partir de una semilla y las escribe en un archivo o se las
+/// envia a un ecommerce que esta corriendo, a la tasa pedida
+fn main() {
+    let configuracion = match ConfiguracionGenerador::parsear(env::args().skip(1)) {
+        Ok(configuracion) => configuracion,
+        Err(e) => {
+            eprintln!("[Generador - Error] {}", e);
+            exit(2);
+        }
+    };
+    eprintln!(
+        "[Generador] semilla {}, {} ordenes",
+        configuracion.parametros.semilla, configuracion.total
+    );
+    let mut generador = match GeneradorOrdenes::new(configuracion.parametros) {
+        Ok(generador) => generador,
+        Err(e) => {
+            eprintln!("[Generador - Error] {}", e);
+            exit(2);
+        }
+    };
+
+    let resultado = match configuracion.destino {
+        DestinoCarga::Archivo { origen, formato } => abrir_salida(&origen)
+            .and_then(|salida| EscritorOrdenes::new(salida, formato))
+            .and_then(|escritor| escribir(&mut generador, configuracion.total, escritor)),
+        DestinoCarga::Http(direccion) => {
+            let mut aceptadas = 0;
+            let mut rechazadas = 0;
+            let resultado = generar(&mut generador, configuracion.total, |orden| {
+                let cuerpo = serde_json::to_string(orden).map_err(io::Error::other)?;
+                match enviar_pedido(direccion, "POST", "/ordenes", &cuerpo)? {
+                    (202, _) => aceptadas += 1,
+                    (codigo, respuesta) => {
+                        rechazadas += 1;
+                        eprintln!("[Generador] {} {}", codigo, respuesta.trim());
+                    }
+                }
+                Ok(())
+            });
+            eprintln!(
+                "[Generador] {} encoladas en el ecommerce, {} rechazadas",
+                aceptadas, rechazadas
+            );
+            resultado
+        }
+    };
+
+    match resultado {
+        Ok(duracion) => eprintln!(
+            "[Generador] {} ordenes en {:.3}s, {:.0} ordenes/s",
+            configuracion.total,
+            duracion.as_secs_f64(),
+            configuracion.total as f64 / duracion.as_secs_f64().max(f64::EPSILON)
+        ),
+        Err(e) => {
+            eprintln!("[Generador - Error] {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn abrir_salida(origen: &str) -> io::Result<Box<dyn Write>> {
+    if origen == ORIGEN_STDIN {
+        return Ok(Box::new(BufWriter::new(io::stdout().lock())));
+    }
+    Ok(Box::new(BufWriter::new(File::create(origen)?)))
+}
+
+fn escribir(
+    generador: &mut GeneradorOrdenes,
+    total: usize,
+    mut escritor: EscritorOrdenes<Box<dyn Write>>,
+) -> io::Result<Duration> {
+    let con_esperas = generador.con_esperas();
+    let duracion = generar(generador, total, |orden| {
+        escritor.escribir(orden)?;
+        // con esperas el que lee la salida tiene que ver cada orden cuando llega
+        if con_esperas {
+            escritor.flush()?;
+        }
+        Ok(())
+    })?;
+    escritor.flush()?;
+    Ok(duracion)
+}
+
+/// Genera `total` ordenes y las entrega en el momento de su llegada, devuelve cuanto tardó
+fn generar(
+    generador: &mut GeneradorOrdenes,
+    total: usize,
+    mut entregar: impl FnMut(&Orden) -> io::Result<()>,
+) -> io::Result<Duration> {
+    let inicio = Instant::now();
+    let mut llegada = Duration::ZERO;
+    for _ in 0..total {
+        llegada += generador.espera();
+        if let Some(falta) = llegada.checked_sub(inicio.elapsed()) {
+            sleep(falta);
+        }
+        entregar(&generador.siguiente())?;
+    }
+    Ok(inicio.elapsed())
+}
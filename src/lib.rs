@@ -0,0 +1,3 @@
+//! Codigo compartido por los binarios del local, el ecommerce, el generador y el verificador
+
+pub mod common;
@@ -1,376 +1,201 @@
-use actix::io::SinkWrite;
+use actix::dev::SendError;
 use actix::{
-    Actor, ActorFutureExt, Addr, AsyncContext, Context, Handler, Message, Recipient,
-    ResponseActFuture, StreamHandler, WrapFuture,
+    Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Context, Handler, MailboxError,
+    Message, Recipient, ResponseActFuture, Supervised, Supervisor, WrapFuture,
 };
-use actix_rt::net::UdpSocket;
+use actix_rt::net::{TcpListener, TcpStream, UdpSocket};
+use actix_rt::signal::ctrl_c;
+use actix_rt::signal::unix::{signal, SignalKind};
 use actix_rt::{Arbiter, System};
 use csv::Reader;
-use futures::stream::SplitSink;
-use futures::StreamExt;
-use rand::{thread_rng, Rng};
+use futures::executor::block_on;
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufRead;
 use std::net::SocketAddr;
-use std::time::Duration;
-use std::{env, io};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{env, io, thread};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::time::sleep;
-use tokio_util::bytes::{Bytes, BytesMut};
-use tokio_util::codec::BytesCodec;
-use tokio_util::udp::UdpFramed;
+use tracing::{debug, error, info};
 
-mod common;
+use donotunwrap::common;
 
-use common::error_local::ErrorLocal;
+use common::actor_local::{
+    iniciar_actores, instanciar_local, recuperar_local, AceptarOrdenes, ActorLocal,
+    CambiarIntervalo, CambiarModoFalla, Cerrar, ConfiguracionActores, ConsultarEstadoUdp,
+    ConsultarOrdenes, ConsultarResumen, ConsultarStock, EstadoUdp, FinalizarOrden, JobOrdenes,
+    PedirTransferencia, Reponer, ResultadoVenderEnLocal, ResumenLocal, UdpClientActor,
+    VenderEnLocal, ESPERA_LOCAL_OCUPADO,
+};
+use common::comando_consola::{Comando, AYUDA};
+use common::configuracion_local::ConfiguracionLocal;
+use common::control::{ConsultaControl, CONSULTAS};
+use common::fallas::{nodos_por_defecto, nombre_local, ConfiguracionFallas, Fallas};
+use common::fuente_ordenes::FormatoOrdenes;
+use common::historial::Historial;
 use common::lector_csv::{leer_linea_csv, open_csv};
-use common::local::{Local, Productos};
-use common::mensaje_protocolo::TipoDeMensaje;
+use common::logs::{iniciar_logs, ControlLogs};
+use common::metricas::{servir_metricas, EnCola, TextoMetricas};
 use common::orden::Orden;
-use common::socket::id_to_addr_local;
+use common::politica_entrega::Accion;
+use common::reloj::{ahora_en_ms, RelojSistema};
+use common::reposicion::{Reposicion, ReposicionAutomatica};
+use common::socket::{id_to_addr_control, id_to_addr_local, id_to_addr_metricas_local};
 use common::stock_producto::StockProducto;
+use common::supervision::PoliticaDeReinicio;
+use common::trazas::CapaTrazas;
+use common::validacion::{
+    informar_validacion, validar_ordenes, validar_reposiciones, validar_stock,
+};
 
-type SinkItem = (Bytes, SocketAddr);
-type UdpSink = SplitSink<UdpFramed<BytesCodec, UdpSocket>, SinkItem>;
-
-/// Actor que convierte el socket udp en un stream y maneja los mensajes que recibe a través de él
-struct UdpClientActor {
-    sink: SinkWrite<SinkItem, UdpSink>,
-    aceptar_ordenes: bool,
-    recipient_local: Recipient<AgregarOrden>,
-}
+/// Tiempo por defecto entre ventas del Vendedor y entre entregas del Job de ordenes
+const INTERVALO_SIMULACION: Duration = Duration::from_millis(2000);
 
-impl UdpClientActor {
-    /// Inicia el actor a partir de un UdpSocket y un recipient capaz de recibir un mensaje del tipo
-    /// AgregarOrden
-    pub fn start(socket: UdpSocket, recipient: Recipient<AgregarOrden>) -> Addr<UdpClientActor> {
-        let (sink, stream) = UdpFramed::new(socket, BytesCodec::new()).split();
-
-        UdpClientActor::create(|ctx| {
-            ctx.add_stream(stream.filter_map(
-                |item: Result<(BytesMut, SocketAddr), io::Error>| async {
-                    item.map(|(data, sender)| UdpPacket(data, sender)).ok()
-                },
-            ));
-
-            UdpClientActor {
-                sink: SinkWrite::new(sink, ctx),
-                aceptar_ordenes: true,
-                recipient_local: recipient,
-            }
-        })
-    }
+struct Vendedor {
+    recipient_local: Recipient<VenderEnLocal>,
+    en_cola_local: EnCola,
+    ordenes_reader: Reader<File>,
+    intervalo: Duration,
+    supervision: PoliticaDeReinicio,
 }
 
-impl Actor for UdpClientActor {
+impl Actor for Vendedor {
     type Context = Context<Self>;
-}
 
-#[derive(Message)]
-#[rtype(result = "()")]
-struct UdpPacket(BytesMut, SocketAddr);
-
-/// Cada vez que un mensaje entra por el socket, el mensaje entra en la queue del Actor
-/// El socket caido se simula con self.aceptar_ordenes en false
-/// Deserializa la orden validando que tenga el formato correcto, si lo tiene le envia un msg al actor Local
-impl StreamHandler<UdpPacket> for UdpClientActor {
-    fn handle(&mut self, item: UdpPacket, _ctx: &mut Self::Context) {
-        println!("[UDP] Recibí: ({:?}, {:?})", item.0, item.1);
-        // TODO: ver de sacar este atomicbool
-        if !self.aceptar_ordenes {
-            println!("[UDP] Simulando local caido, no acepta orden");
-            return;
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if let Some(espera) = self.supervision.espera() {
+            ctx.notify_later(VenderEnLocalVendedor, espera);
         }
-        match Orden::deserializar(item.0.iter().as_slice()) {
-            Ok(orden) => {
-                self.recipient_local
-                    .try_send(AgregarOrden(orden, item.1))
-                    .unwrap();
-            }
-            Err(_e) => {
-                println!("[UDP] Mensaje no reconocido:");
-                if self
-                    .sink
-                    .write(("MENSAJE NO RECONOCIDO".as_bytes().into(), item.1))
-                    .is_ok()
-                {
-                } else {
-                    eprintln!("[UDP - No se pudo enviar mensaje] MENSAJE NO RECONOCIDO");
-                };
-            }
-        };
     }
 }
 
-impl actix::io::WriteHandler<io::Error> for UdpClientActor {}
-
-#[derive(Message)]
-#[rtype(result = "()")]
-struct ResultadoAgregarOrden(Option<ErrorLocal>, SocketAddr);
-
-/// Recibe el resultado de agregar una orden del actor Local y envia la respuesta
-/// a la dirección del ecommerce que le envio la orden
-impl Handler<ResultadoAgregarOrden> for UdpClientActor {
-    type Result = ();
-
-    fn handle(&mut self, msg: ResultadoAgregarOrden, _ctx: &mut Self::Context) -> Self::Result {
-        match msg.0 {
-            None => {
-                println!("[UDP] Orden agregada");
-                // TODO: puede ser que justo un ecommerce se caiga, en este caso simplemente no hacer nada
-                if self
-                    .sink
-                    .write((
-                        TipoDeMensaje::OrdenAceptada.value().as_bytes().into(),
-                        msg.1,
-                    ))
-                    .is_ok()
-                {
-                } else {
-                    // TODO: si pasa esto el ecommerce va a hacer timeout y enviar la misma orden a otro local, duplicandola
-                    eprintln!("[UDP - Error] No se pudo enviar mensaje OrdenAceptada");
-                }
-            }
-            Some(e) => {
-                println!("[UDP - Error] {:?}", e);
-                if self
-                    .sink
-                    .write((
-                        TipoDeMensaje::from_error_local(&e)
-                            .value()
-                            .as_bytes()
-                            .into(),
-                        msg.1,
-                    ))
-                    .is_ok()
-                {
-                } else {
-                    eprintln!("[UDP - Error] No se pudo enviar mensaje {:?}", e);
-                }
-            }
-        }
+/// Si no puede enviarle la venta al actor Local se detiene y el supervisor lo reinicia
+impl Supervised for Vendedor {
+    fn restarting(&mut self, _ctx: &mut Self::Context) {
+        self.supervision.reiniciando(Instant::now());
     }
 }
 
 #[derive(Message)]
 #[rtype(result = "()")]
-struct AceptarOrdenes(bool);
-
-impl Handler<AceptarOrdenes> for UdpClientActor {
-    type Result = ();
-
-    fn handle(&mut self, msg: AceptarOrdenes, _ctx: &mut Self::Context) -> Self::Result {
-        println!("[DEBUG] aceptar ordenes = {}", msg.0);
-        self.aceptar_ordenes = msg.0;
-    }
-}
-
-// Empieza ActorLocal
-/// Es encargado de manejar el stock y ordenes del local, el resto de los actores
-/// les envian mensajes con el tipo de acción a realizar: agregar una orden, entregarla/cancelarla
-/// vender en local y les responde el resultado de la operación
-struct ActorLocal {
-    local: Local,
-    recipient_recibir_ordenes: Recipient<ResultadoAgregarOrden>,
-    recipient_vender_en_local: Recipient<ResultadoVenderEnLocal>,
-    recipient_job_ordenes: Recipient<ResultadoEntregarOrden>,
-}
-
-impl Actor for ActorLocal {
-    type Context = Context<Self>;
-}
-
-#[derive(Message)]
-#[rtype(result = "()")]
-struct AgregarOrden(Orden, SocketAddr);
+struct VenderEnLocalVendedor;
 
-/// Agregar orden del ecommerce al local, devuelve resultado a UdpClientActor
-impl Handler<AgregarOrden> for ActorLocal {
+/// Lee una orden y se la envia al actor Local para que entregue los productos
+impl Handler<VenderEnLocalVendedor> for Vendedor {
     type Result = ();
 
-    fn handle(&mut self, msg: AgregarOrden, _ctx: &mut Self::Context) -> Self::Result {
-        match self.local.agregar_orden(msg.0) {
-            Ok(_) => {
-                // TODO: DO NOT UNWRAP
-                self.recipient_recibir_ordenes
-                    .try_send(ResultadoAgregarOrden(None, msg.1))
-                    .unwrap();
+    fn handle(&mut self, _msg: VenderEnLocalVendedor, ctx: &mut Self::Context) {
+        // Simulo que las entregas ocurren cada cierto tiempo
+        let orden = leer_linea_csv(&mut self.ordenes_reader, Orden::from_record);
+        match orden {
+            Ok(orden) => {
+                info!(
+                    target: "vendedor",
+                    id_producto = orden.id_producto,
+                    cantidad = orden.cantidad,
+                    "Recibe pedido en local"
+                );
+                self.vender(orden, ctx);
             }
             Err(e) => {
-                // TODO: DO NOT UNWRAP
-                self.recipient_recibir_ordenes
-                    .try_send(ResultadoAgregarOrden(Some(e), msg.1))
-                    .unwrap();
+                error!(target: "vendedor", "{}", e);
             }
         }
     }
 }
 
-#[derive(Message)]
-#[rtype(result = "()")]
-struct VenderEnLocal(Orden);
-
-/// Recibe orden y vende los productos del local, devuelve resultado a actor Vendedor
-impl Handler<VenderEnLocal> for ActorLocal {
-    type Result = ();
-    fn handle(&mut self, msg: VenderEnLocal, _ctx: &mut Self::Context) -> Self::Result {
-        match self.local.vender(msg.0) {
-            Ok(_) => {
-                println!("[Vendedor] se vende producto en local");
-                self.recipient_vender_en_local
-                    .try_send(ResultadoVenderEnLocal(None))
-                    .unwrap();
+impl Vendedor {
+    /// Le envia la orden al actor Local, si esta ocupado reintenta más tarde con la misma orden
+    fn vender(&mut self, orden: Orden, ctx: &mut Context<Self>) {
+        match self
+            .recipient_local
+            .try_send(VenderEnLocal(orden, ctx.address().recipient()))
+        {
+            Ok(_) => self.en_cola_local.encolado(),
+            Err(SendError::Full(VenderEnLocal(orden, _))) => {
+                debug!(target: "vendedor", "Local ocupado, reintenta la venta");
+                ctx.run_later(ESPERA_LOCAL_OCUPADO, move |act, ctx| act.vender(orden, ctx));
             }
-            Err(e) => {
-                eprintln!("[Vendedor - Error] {}", e);
-                self.recipient_vender_en_local
-                    .try_send(ResultadoVenderEnLocal(Some(e)))
-                    .unwrap();
+            Err(SendError::Closed(_)) => {
+                error!(target: "vendedor", "El actor Local se detuvo");
+                ctx.stop();
             }
         }
     }
 }
 
-#[derive(Message)]
-#[rtype(result = "()")]
-struct EntregarOrden;
-
-/// Recibe un msg vacio, hace un ranom para determinar y una orden se entrega o cancela
-/// y envia un msg a el JobOrdenes con el resultado
-impl Handler<EntregarOrden> for ActorLocal {
-    type Result = ();
+/// Recibe msg con el resutado de vender en local hace un sleep y se auto envia un nuevo msg
+/// para vender otra orden. Simulando así un loop
+impl Handler<ResultadoVenderEnLocal> for Vendedor {
+    type Result = ResponseActFuture<Self, ()>;
 
-    fn handle(&mut self, _msg: EntregarOrden, _ctx: &mut Self::Context) {
-        if self.local.ordenes_en_progreso.is_empty() {
-            println!("[Job] no hay ordenes en progreso");
-            let _ = self
-                .recipient_job_ordenes
-                .try_send(ResultadoEntregarOrden(None));
-            return;
-        }
-        let random: f64 = rand::random();
-        let indice_maximo_ordenes = self.local.ordenes_en_progreso.len() - 1;
-        let res_random = random > 0.5;
-        let func = if res_random {
-            Local::entregar_orden
-        } else {
-            Local::cancelar_orden
-        };
-        let respuesta = if res_random { "entregada" } else { "cancelada" };
-        match func(&mut self.local, || {
-            thread_rng().gen_range(0..=indice_maximo_ordenes)
-        }) {
-            Ok(_) => {
-                println!("[Job] Orden {}", respuesta);
-                // TODO: do not unwrap
-                self.recipient_job_ordenes
-                    .try_send(ResultadoEntregarOrden(None))
-                    .unwrap();
-            }
-            Err(e) => {
-                eprintln!("[Job - Error] {}", e);
-                // TODO: do not unwrap
-                self.recipient_job_ordenes
-                    .try_send(ResultadoEntregarOrden(Some(e)))
-                    .unwrap();
-            }
+    fn handle(&mut self, msg: ResultadoVenderEnLocal, _ctx: &mut Self::Context) -> Self::Result {
+        if msg.0.is_some() {
+            debug!(target: "vendedor", "No se pudo vender la orden");
         }
+        debug!(target: "vendedor", "Descansa un poco");
+        Box::pin(
+            sleep(self.intervalo)
+                .into_actor(self)
+                .map(move |_result, _me, ctx| {
+                    ctx.notify(VenderEnLocalVendedor);
+                }),
+        )
     }
 }
-// Termina ActorLocal
 
-struct Vendedor {
-    recipient_local: Recipient<VenderEnLocal>,
-    ordenes_reader: Reader<File>,
+/// Lee reposiciones de un archivo y se las envia al actor Local cada cierto tiempo
+struct Repositor {
+    recipient_local: Recipient<Reponer>,
+    reposiciones_reader: Reader<File>,
 }
 
-impl Actor for Vendedor {
+impl Actor for Repositor {
     type Context = Context<Self>;
 }
 
 #[derive(Message)]
 #[rtype(result = "()")]
-struct VenderEnLocalVendedor;
+struct LeerReposicion;
 
-/// Lee una orden y se la envia al actor Local para que entregue los productos
-impl Handler<VenderEnLocalVendedor> for Vendedor {
-    type Result = ();
+impl Handler<LeerReposicion> for Repositor {
+    type Result = ResponseActFuture<Self, ()>;
 
-    fn handle(&mut self, _msg: VenderEnLocalVendedor, _ctx: &mut Self::Context) {
-        // Simulo que las entregas ocurren cada cierto tiempo
-        let orden = leer_linea_csv(&mut self.ordenes_reader, Orden::from_record);
-        match orden {
-            Ok(orden) => {
-                print!("[Vendedor] recibe pedido en local de {}", orden);
-                // TODO: Do not Unwrap
-                self.recipient_local.try_send(VenderEnLocal(orden)).unwrap()
-            }
+    fn handle(&mut self, _msg: LeerReposicion, _ctx: &mut Self::Context) -> Self::Result {
+        match leer_linea_csv(&mut self.reposiciones_reader, Reposicion::from_record) {
+            Ok(reposicion) => self.recipient_local.do_send(Reponer(reposicion)),
             Err(e) => {
-                eprintln!("[Vendedor - Error] {}", e);
+                error!(target: "reposicion", "{}", e);
+                return Box::pin(async {}.into_actor(self));
             }
         }
-    }
-}
-
-#[derive(Message)]
-#[rtype(result = "()")]
-struct ResultadoVenderEnLocal(Option<ErrorLocal>);
-
-/// Recibe msg con el resutado de vender en local hace un sleep y se auto envia un nuevo msg
-/// para vender otra orden. Simulando así un loop
-impl Handler<ResultadoVenderEnLocal> for Vendedor {
-    type Result = ResponseActFuture<Self, ()>;
-
-    fn handle(&mut self, _msg: ResultadoVenderEnLocal, _ctx: &mut Self::Context) -> Self::Result {
-        println!("[Vendedor] descansa un poco");
         Box::pin(sleep(Duration::from_millis(2000)).into_actor(self).map(
             move |_result, _me, ctx| {
-                ctx.address().try_send(VenderEnLocalVendedor).unwrap();
+                ctx.notify(LeerReposicion);
             },
         ))
     }
 }
 
-struct JobOrdenes {
-    recipient_local: Recipient<EntregarOrden>,
-}
-
-impl Actor for JobOrdenes {
-    type Context = Context<Self>;
-}
-
-#[derive(Message)]
-#[rtype(result = "()")]
-struct EntregarOrdenJobOrdenes;
-
-impl Handler<EntregarOrdenJobOrdenes> for JobOrdenes {
+/// Cambia cada cuanto vende el Vendedor, se aplica desde la proxima venta
+impl Handler<CambiarIntervalo> for Vendedor {
     type Result = ();
 
-    fn handle(&mut self, _msg: EntregarOrdenJobOrdenes, _ctx: &mut Self::Context) {
-        // TODO: do not unwrap
-        self.recipient_local.try_send(EntregarOrden).unwrap()
-    }
-}
-
-#[derive(Message)]
-#[rtype(result = "()")]
-struct ResultadoEntregarOrden(Option<ErrorLocal>);
-
-impl Handler<ResultadoEntregarOrden> for JobOrdenes {
-    type Result = ResponseActFuture<Self, ()>;
-
-    fn handle(&mut self, _msg: ResultadoEntregarOrden, _ctx: &mut Self::Context) -> Self::Result {
-        println!("[Job] descansa un poco");
-        Box::pin(sleep(Duration::from_millis(2000)).into_actor(self).map(
-            move |_result, _me, ctx| {
-                ctx.address().try_send(EntregarOrdenJobOrdenes).unwrap();
-            },
-        ))
+    fn handle(&mut self, msg: CambiarIntervalo, _ctx: &mut Self::Context) -> Self::Result {
+        self.intervalo = msg.0;
     }
 }
 
 struct AceptadorOrdenes {
-    recipient_recibir_ordenes: Recipient<AceptarOrdenes>,
+    addr_udp: Addr<UdpClientActor>,
+    addr_local: Addr<ActorLocal>,
+    addr_vendedor: Addr<Vendedor>,
+    addr_job_ordenes: Addr<JobOrdenes>,
+    control_logs: ControlLogs,
 }
 
 impl Actor for AceptadorOrdenes {
@@ -383,10 +208,7 @@ impl Actor for AceptadorOrdenes {
 struct ReadStdin;
 
 /// Actor que lee del stdin, se debe ejecutar en un Arbiter distinto al resto para evitar bloquear
-/// la ejecución
-/// Acepta los siguientes valores
-/// c: evita que UdpClientActor responda a los paquetes
-/// l: vuelve a permitir la entrada de paquetes
+/// la ejecución. Los comandos se describen en `comando_consola::AYUDA`
 impl Handler<ReadStdin> for AceptadorOrdenes {
     type Result = ();
 
@@ -397,26 +219,118 @@ impl Handler<ReadStdin> for AceptadorOrdenes {
 
         // Iterate over lines and handle them
         for line in reader.lines() {
-            match line {
-                Ok(line) => {
-                    if line == "c" {
-                        println!("[UDP] Cerrando conexión");
-                        // TODO: do not unwrap
-                        let _ = self
-                            .recipient_recibir_ordenes
-                            .try_send(AceptarOrdenes(false));
-                    }
-                    if line == "l" {
-                        println!("[UDP] Levantando conexión");
-                        let _ = self
-                            .recipient_recibir_ordenes
-                            .try_send(AceptarOrdenes(true));
+            match line.map(|line| Comando::parsear(&line)) {
+                Ok(Ok(Comando::Salir)) => {
+                    self.addr_local.do_send(Cerrar);
+                    break;
+                }
+                Ok(Ok(comando)) => self.ejecutar(comando),
+                Ok(Err(uso)) => eprintln!("[Consola] {}", uso),
+                Err(e) => {
+                    error!(target: "consola", "No se pudo leer el comando: {}", e);
+                }
+            }
+        }
+    }
+}
+
+impl AceptadorOrdenes {
+    fn ejecutar(&mut self, comando: Comando) {
+        match comando {
+            Comando::CerrarConexion => {
+                info!(target: "consola", "Cerrando conexión");
+                self.addr_udp.do_send(AceptarOrdenes(false));
+            }
+            Comando::LevantarConexion => {
+                info!(target: "consola", "Levantando conexión");
+                self.addr_udp.do_send(AceptarOrdenes(true));
+            }
+            Comando::Stock => match block_on(self.addr_local.send(ConsultarStock)) {
+                Ok(productos) => {
+                    for producto in productos {
+                        println!(
+                            "[Consola] producto {}: stock {}, bloqueados {}, disponibles {}",
+                            producto.id_producto,
+                            producto.stock,
+                            producto.bloqueados,
+                            producto.disponibles()
+                        );
                     }
                 }
-                Err(error) => {
-                    eprintln!("[UDP] Comando invalido: {}", error);
+                Err(e) => eprintln!("[Consola - Error] {}", e),
+            },
+            Comando::Ordenes => match block_on(self.addr_local.send(ConsultarOrdenes)) {
+                Ok(ordenes) if ordenes.is_empty() => {
+                    println!("[Consola] no hay ordenes en progreso")
                 }
+                Ok(ordenes) => {
+                    let ahora = ahora_en_ms();
+                    for (indice, orden) in ordenes.iter().enumerate() {
+                        println!(
+                            "[Consola] {}: producto {}, cantidad {}, prioridad {}, vence en {}",
+                            indice,
+                            orden.id_producto,
+                            orden.cantidad,
+                            orden.prioridad,
+                            orden
+                                .vencimiento
+                                .map(|vencimiento| format!(
+                                    "{}ms",
+                                    vencimiento.saturating_sub(ahora)
+                                ))
+                                .unwrap_or("-".to_string())
+                        );
+                    }
+                }
+                Err(e) => eprintln!("[Consola - Error] {}", e),
+            },
+            Comando::Entregar(indice) => self.finalizar_orden(indice, Accion::Entregar),
+            Comando::Cancelar(indice) => self.finalizar_orden(indice, Accion::Cancelar),
+            Comando::Reponer(reposicion) => self.addr_local.do_send(Reponer(reposicion)),
+            Comando::Transferir {
+                id_local,
+                id_producto,
+                cantidad,
+            } => self.addr_local.do_send(PedirTransferencia {
+                id_local_origen: id_local,
+                id_producto,
+                cantidad,
+            }),
+            Comando::IntervaloVendedor(intervalo) => {
+                info!(target: "consola", "El Vendedor vende cada {:?}", intervalo);
+                self.addr_vendedor.do_send(CambiarIntervalo(intervalo));
+            }
+            Comando::IntervaloJob(intervalo) => {
+                info!(target: "consola", "El Job procesa una orden cada {:?}", intervalo);
+                self.addr_job_ordenes.do_send(CambiarIntervalo(intervalo));
             }
+            Comando::Falla(modo) => {
+                info!(target: "consola", "Falla simulada: {:?}", modo);
+                self.addr_udp.do_send(CambiarModoFalla(modo));
+            }
+            Comando::Logs(filtro) => match self.control_logs.cambiar_filtro(&filtro) {
+                Ok(_) => println!("[Consola] filtro de logs: {}", filtro),
+                Err(e) => eprintln!("[Consola - Error] {}", e),
+            },
+            Comando::Ayuda => println!("{}", AYUDA),
+            Comando::Salir => self.addr_local.do_send(Cerrar),
+        }
+    }
+
+    fn finalizar_orden(&mut self, indice: usize, accion: Accion) {
+        match block_on(self.addr_local.send(FinalizarOrden { indice, accion })) {
+            Ok(Ok(orden)) => println!(
+                "[Consola] orden {} ({} unidades del producto {}) {}",
+                indice,
+                orden.cantidad,
+                orden.id_producto,
+                match accion {
+                    Accion::Entregar => "entregada",
+                    Accion::Cancelar => "cancelada",
+                }
+            ),
+            Ok(Err(e)) => eprintln!("[Consola - Error] {}", e),
+            Err(e) => eprintln!("[Consola - Error] {}", e),
         }
     }
 }
@@ -428,68 +342,211 @@ fn main() {
         .expect("Falta parametro del id")
         .parse::<usize>()
         .expect("No es un numero");
+    let ConfiguracionLocal {
+        politica,
+        plazo_ordenes,
+        archivo_reposiciones,
+        umbral_reposicion,
+        cantidad_reposicion,
+        proveedor,
+        vecinos,
+        capacidad_mailbox,
+        archivo_stock,
+        archivo_estado,
+        archivo_recuperacion,
+        archivo_ventas,
+        intervalo_ventas,
+        direccion_control,
+        solo_validar,
+        filtro_logs,
+        formato_logs,
+        direccion_metricas,
+        archivo_trazas,
+        archivo_fallas,
+        archivo_historial,
+    } = ConfiguracionLocal::parsear(args).expect("Parametros invalidos");
+    let trazas = archivo_trazas
+        .map(|archivo| CapaTrazas::new(&archivo, "local", id))
+        .transpose()
+        .expect("No se pueden exportar las trazas");
+    let control_logs = iniciar_logs("local", id, formato_logs, filtro_logs.as_deref(), trazas)
+        .expect("Filtro de logs invalido");
+    info!(
+        target: "local",
+        politica = %politica.criterio(),
+        semilla = politica.semilla(),
+        "Politica de entrega"
+    );
 
-    let dir_stock = format!(
+    let dir_stock = archivo_stock.unwrap_or(format!(
         "{}{}{}{}",
         env!("CARGO_MANIFEST_DIR"),
         "/data/stock_local_",
         id.clone(),
         ".txt"
-    );
-
-    let local = instanciar_local(&dir_stock).expect("Error al instanciar local");
-
-    let dir_ordenes = format!(
+    ));
+    let archivo_estado = archivo_estado.unwrap_or(format!(
+        "{}{}{}{}",
+        env!("CARGO_MANIFEST_DIR"),
+        "/data/estado_local_",
+        id.clone(),
+        ".txt"
+    ));
+
+    let direccion_control = direccion_control.unwrap_or_else(|| {
+        id_to_addr_control(id)
+            .parse()
+            .expect("Dirección de control invalida")
+    });
+    let direccion_metricas = direccion_metricas.unwrap_or_else(|| {
+        id_to_addr_metricas_local(id)
+            .parse()
+            .expect("Dirección de metricas invalida")
+    });
+
+    let dir_ordenes = archivo_ventas.unwrap_or(format!(
         "{}{}{}{}",
         env!("CARGO_MANIFEST_DIR"),
         "/data/ordenes_local_",
         id.clone(),
         ".txt"
+    ));
+
+    if solo_validar {
+        let mut validos = informar_validacion(&dir_stock, validar_stock(&dir_stock));
+        validos &= informar_validacion(
+            &dir_ordenes,
+            validar_ordenes(&dir_ordenes, FormatoOrdenes::Csv),
+        );
+        if let Some(archivo) = &archivo_reposiciones {
+            validos &= informar_validacion(archivo, validar_reposiciones(archivo));
+        }
+        std::process::exit(if validos { 0 } else { 1 });
+    }
+
+    let mut local = match archivo_recuperacion
+        .as_deref()
+        .filter(|archivo| Path::new(archivo).exists())
+    {
+        Some(archivo) => {
+            info!(target: "local", archivo, "Se retoma el estado guardado");
+            recuperar_local(archivo).expect("Error al recuperar el local")
+        }
+        None => instanciar_local(&dir_stock).expect("Error al instanciar local"),
+    };
+    if let Some(archivo) = archivo_historial {
+        local = local.con_historial(
+            Historial::new(&archivo, nombre_local(id)).expect("Error al abrir el historial"),
+        );
+    }
+    let configuracion_fallas = archivo_fallas
+        .map(|archivo| ConfiguracionFallas::leer(&archivo).expect("Error al leer las fallas"))
+        .unwrap_or_default();
+    let fallas = Arc::new(
+        Fallas::new(
+            nombre_local(id),
+            configuracion_fallas,
+            Arc::new(RelojSistema),
+        )
+        .con_nodos(nodos_por_defecto()),
     );
 
     let ordenes_reader = open_csv(&dir_ordenes).expect("Error al abrir csv");
+    let reposiciones_reader = archivo_reposiciones
+        .map(|archivo| open_csv(&archivo).expect("Error al abrir csv de reposiciones"));
+
+    let socket = match abrir_socket_local(id) {
+        Ok(socket) => socket,
+        Err(e) => {
+            error!(target: "udp", "{}", e);
+            std::process::exit(1);
+        }
+    };
 
     let system = System::new();
 
     let arbiter_1 = Arbiter::new();
 
     let future = async move {
-        let address = id_to_addr_local(id).parse::<SocketAddr>().unwrap();
-        let socket = UdpSocket::bind(&address).await.unwrap();
-
-        let mut addr_udp_ext: Option<Addr<UdpClientActor>> = None;
-
-        ActorLocal::create(|ctx| {
-            let addr_local = ctx.address();
-
-            let addr_udp = UdpClientActor::start(socket, addr_local.clone().recipient());
-            let addr_vendedor = Vendedor {
-                recipient_local: addr_local.clone().recipient(),
-                ordenes_reader,
-            }
-            .start();
-            let addr_job_ordenes = JobOrdenes {
-                recipient_local: addr_local.clone().recipient(),
+        let socket = match UdpSocket::from_std(socket) {
+            Ok(socket) => socket,
+            Err(e) => {
+                error!(target: "udp", "No se pudo preparar el socket del local: {}", e);
+                System::current().stop_with_code(1);
+                return;
             }
-            .start();
-
-            addr_vendedor.do_send(VenderEnLocalVendedor);
-            addr_job_ordenes.do_send(EntregarOrdenJobOrdenes);
-
-            addr_udp_ext = Option::from(addr_udp.clone());
+        };
 
-            ActorLocal {
-                local,
-                recipient_recibir_ordenes: addr_udp.recipient(),
-                recipient_vender_en_local: addr_vendedor.recipient(),
-                recipient_job_ordenes: addr_job_ordenes.recipient(),
+        let actores = iniciar_actores(
+            ConfiguracionActores {
+                id,
+                politica,
+                plazo_ordenes,
+                reposicion_automatica: umbral_reposicion
+                    .map(|umbral| ReposicionAutomatica::new(umbral, cantidad_reposicion)),
+                proveedor,
+                vecinos,
+                direcciones_locales: HashMap::new(),
+                capacidad_mailbox,
+                archivo_estado,
+                archivo_recuperacion,
+                intervalo_entregas: INTERVALO_SIMULACION,
+                reloj: Arc::new(RelojSistema),
+                detener_sistema: true,
+            },
+            local,
+            socket,
+            fallas,
+        );
+        let recipient_vendedor = actores.local.clone().recipient();
+        let en_cola_vendedor = actores.en_cola.clone();
+        let reinicios_vendedor = actores.reinicios.clone();
+        let addr_vendedor = Supervisor::start(move |_| Vendedor {
+            recipient_local: recipient_vendedor,
+            en_cola_local: en_cola_vendedor,
+            ordenes_reader,
+            intervalo: intervalo_ventas,
+            supervision: PoliticaDeReinicio::por_defecto("Vendedor")
+                .con_reinicios(reinicios_vendedor),
+        });
+        if let Some(reposiciones_reader) = reposiciones_reader {
+            Repositor {
+                recipient_local: actores.local.clone().recipient(),
+                reposiciones_reader,
             }
+            .start()
+            .do_send(LeerReposicion);
+        }
+
+        actix_rt::spawn(servir_control(
+            direccion_control,
+            actores.local.clone(),
+            actores.udp.clone(),
+        ));
+        let addr_local_metricas = actores.local.clone();
+        let addr_udp_metricas = actores.udp.clone();
+        let en_cola_local = actores.en_cola.clone();
+        thread::spawn(move || {
+            servir_metricas(direccion_metricas, || {
+                block_on(consultar_metricas(
+                    &addr_local_metricas,
+                    &addr_udp_metricas,
+                    &en_cola_local,
+                    capacidad_mailbox,
+                ))
+                .unwrap_or_default()
+            })
         });
+        actix_rt::spawn(esperar_senal_de_cierre(actores.local.clone().recipient()));
 
         let arbiter_2 = Arbiter::new();
         arbiter_2.spawn(async move {
             let addr = AceptadorOrdenes {
-                recipient_recibir_ordenes: addr_udp_ext.unwrap().recipient(),
+                addr_udp: actores.udp,
+                addr_local: actores.local,
+                addr_vendedor,
+                addr_job_ordenes: actores.job_ordenes,
+                control_logs,
             }
             .start();
             addr.do_send(ReadStdin);
@@ -498,39 +555,314 @@ fn main() {
 
     arbiter_1.spawn(future);
 
-    system.run().unwrap();
+    match system.run_with_code() {
+        Ok(0) => {}
+        Ok(codigo) => std::process::exit(codigo),
+        Err(e) => {
+            error!(target: "local", "El sistema de actores terminó con error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Abre el socket udp del local antes de arrancar los actores, así un puerto ocupado
+/// se informa como error en lugar de tirar abajo el arbiter
+fn abrir_socket_local(id: usize) -> Result<std::net::UdpSocket, String> {
+    let direccion: SocketAddr = id_to_addr_local(id)
+        .parse()
+        .map_err(|e| format!("Dirección udp invalida para el local {}: {}", id, e))?;
+    let socket = std::net::UdpSocket::bind(direccion)
+        .map_err(|e| format!("No se pudo escuchar en {}: {}", direccion, e))?;
+    socket
+        .set_nonblocking(true)
+        .map_err(|e| format!("No se pudo preparar el socket en {}: {}", direccion, e))?;
+    Ok(socket)
+}
+
+/// Atiende el socket de control: cada conexión envia consultas de una linea y recibe
+/// cada respuesta como una linea json, así se puede consultar el local desde scripts
+async fn servir_control(
+    direccion: SocketAddr,
+    addr_local: Addr<ActorLocal>,
+    addr_udp: Addr<UdpClientActor>,
+) {
+    let listener = match TcpListener::bind(direccion).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(target: "control", "No se pudo escuchar en {}: {}", direccion, e);
+            return;
+        }
+    };
+    info!(target: "control", direccion = %direccion, "Escuchando consultas");
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                actix_rt::spawn(atender_control(
+                    stream,
+                    addr_local.clone(),
+                    addr_udp.clone(),
+                ));
+            }
+            Err(e) => error!(target: "control", "{}", e),
+        }
+    }
+}
+
+async fn atender_control(
+    stream: TcpStream,
+    addr_local: Addr<ActorLocal>,
+    addr_udp: Addr<UdpClientActor>,
+) {
+    let (lector, mut escritor) = stream.into_split();
+    let mut lineas = BufReader::new(lector).lines();
+    while let Ok(Some(linea)) = lineas.next_line().await {
+        let respuesta = match ConsultaControl::parsear(&linea) {
+            Some(consulta) => match consultar_control(consulta, &addr_local, &addr_udp).await {
+                Ok(mut respuesta) => {
+                    respuesta["ok"] = json!(true);
+                    respuesta
+                }
+                Err(e) => json!({ "ok": false, "error": e.to_string() }),
+            },
+            None => {
+                json!({ "ok": false, "error": format!("Consulta desconocida: {}", linea.trim()) })
+            }
+        };
+        if escritor
+            .write_all(format!("{}\n", respuesta).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+async fn consultar_control(
+    consulta: ConsultaControl,
+    addr_local: &Addr<ActorLocal>,
+    addr_udp: &Addr<UdpClientActor>,
+) -> Result<Value, MailboxError> {
+    Ok(match consulta {
+        ConsultaControl::Stock => {
+            let stock: Vec<Value> = addr_local
+                .send(ConsultarStock)
+                .await?
+                .iter()
+                .map(|producto| {
+                    json!({
+                        "id_producto": producto.id_producto,
+                        "stock": producto.stock,
+                        "bloqueados": producto.bloqueados,
+                        "disponibles": producto.disponibles(),
+                    })
+                })
+                .collect();
+            json!({ "stock": stock })
+        }
+        ConsultaControl::Ordenes => {
+            let resumen = addr_local.send(ConsultarResumen).await?;
+            json!({ "ordenes_en_progreso": resumen.ordenes_en_progreso })
+        }
+        ConsultaControl::Estado => {
+            let resumen = addr_local.send(ConsultarResumen).await?;
+            let estado_udp = addr_udp.send(ConsultarEstadoUdp).await?;
+            json!({
+                "aceptar_ordenes": estado_udp.aceptar_ordenes,
+                "cerrando": resumen.cerrando,
+                "fallas": estado_udp.fallas,
+            })
+        }
+        ConsultaControl::Contadores => {
+            let resumen = addr_local.send(ConsultarResumen).await?;
+            let estado_udp = addr_udp.send(ConsultarEstadoUdp).await?;
+            let mut contadores = json!(resumen.contadores);
+            contadores["paquetes_descartados"] = json!(estado_udp.paquetes_descartados);
+            contadores["respuestas_ocupado"] = json!(estado_udp.respuestas_ocupado);
+            contadores
+        }
+        ConsultaControl::Ayuda => json!({ "consultas": CONSULTAS }),
+    })
+}
+
+async fn consultar_metricas(
+    addr_local: &Addr<ActorLocal>,
+    addr_udp: &Addr<UdpClientActor>,
+    en_cola_local: &EnCola,
+    capacidad_mailbox: usize,
+) -> Result<String, MailboxError> {
+    let stock = addr_local.send(ConsultarStock).await?;
+    let resumen = addr_local.send(ConsultarResumen).await?;
+    let estado_udp = addr_udp.send(ConsultarEstadoUdp).await?;
+    Ok(exponer_metricas(
+        &stock,
+        &resumen,
+        &estado_udp,
+        en_cola_local.cantidad(),
+        capacidad_mailbox,
+    ))
 }
 
-fn instanciar_local(dir_archivo: &str) -> Result<Local, io::Error> {
-    let mut reader = open_csv(dir_archivo)?;
-    let mut productos: Productos = HashMap::new();
-    while let Ok(producto) = leer_linea_csv(&mut reader, StockProducto::from_record) {
-        productos.insert(producto.id_producto, producto);
+/// Metricas del local en formato Prometheus: contadores de operaciones, stock por producto,
+/// ordenes en progreso y mensajes en el mailbox del ActorLocal
+fn exponer_metricas(
+    stock: &[StockProducto],
+    resumen: &ResumenLocal,
+    estado_udp: &EstadoUdp,
+    en_cola_local: usize,
+    capacidad_mailbox: usize,
+) -> String {
+    let mut texto = TextoMetricas::default();
+    if let Value::Object(contadores) = json!(resumen.contadores) {
+        for (nombre, valor) in contadores {
+            // los reinicios van aparte, con el actor como etiqueta
+            let Some(valor) = valor.as_f64() else {
+                continue;
+            };
+            texto.simple(
+                &format!("local_{}_total", nombre),
+                "counter",
+                &format!(
+                    "Cantidad de {} desde que se levantó el local",
+                    nombre.replace('_', " ")
+                ),
+                valor,
+            );
+        }
     }
+    texto.metrica(
+        "local_reinicios_total",
+        "counter",
+        "Reinicios de cada actor supervisado desde que se levantó el local",
+        resumen
+            .contadores
+            .reinicios
+            .iter()
+            .map(|(actor, reinicios)| (vec![("actor", actor.clone())], *reinicios as f64)),
+    );
+    texto.simple(
+        "local_paquetes_descartados_total",
+        "counter",
+        "Paquetes udp descartados por la falla simulada",
+        estado_udp.paquetes_descartados as f64,
+    );
+    texto.simple(
+        "local_respuestas_ocupado_total",
+        "counter",
+        "Ordenes rechazadas por tener el mailbox del ActorLocal lleno",
+        estado_udp.respuestas_ocupado as f64,
+    );
+    texto.simple(
+        "local_ordenes_en_progreso",
+        "gauge",
+        "Ordenes aceptadas que todavía no se entregaron ni cancelaron",
+        resumen.ordenes_en_progreso as f64,
+    );
+    let por_producto = |valor: fn(&StockProducto) -> usize| {
+        stock
+            .iter()
+            .map(move |producto| {
+                (
+                    vec![("id_producto", producto.id_producto.to_string())],
+                    valor(producto) as f64,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    texto.metrica(
+        "local_stock",
+        "gauge",
+        "Unidades en stock de cada producto",
+        por_producto(|producto| producto.stock),
+    );
+    texto.metrica(
+        "local_bloqueados",
+        "gauge",
+        "Unidades de cada producto bloqueadas por ordenes en progreso",
+        por_producto(|producto| producto.bloqueados),
+    );
+    texto.metrica(
+        "local_mailbox_mensajes",
+        "gauge",
+        "Mensajes que esperan en el mailbox del actor",
+        [(
+            vec![("actor", "ActorLocal".to_string())],
+            en_cola_local as f64,
+        )],
+    );
+    texto.metrica(
+        "local_mailbox_capacidad",
+        "gauge",
+        "Mensajes que entran en el mailbox del actor",
+        [(
+            vec![("actor", "ActorLocal".to_string())],
+            capacidad_mailbox as f64,
+        )],
+    );
+    texto.simple(
+        "local_aceptando_ordenes",
+        "gauge",
+        "1 si el local acepta ordenes del ecommerce",
+        if estado_udp.aceptar_ordenes && !resumen.cerrando {
+            1.0
+        } else {
+            0.0
+        },
+    );
+    texto.terminar()
+}
 
-    Ok(Local::new(productos))
+/// Espera SIGINT o SIGTERM y le pide al actor Local que cierre ordenadamente
+async fn esperar_senal_de_cierre(recipient_cerrar: Recipient<Cerrar>) {
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(e) => {
+            error!(target: "cierre", "No se pudo escuchar SIGTERM: {}", e);
+            return;
+        }
+    };
+    tokio::select! {
+        _ = ctrl_c() => info!(target: "cierre", "Recibí SIGINT"),
+        _ = sigterm.recv() => info!(target: "cierre", "Recibí SIGTERM"),
+    }
+    recipient_cerrar.do_send(Cerrar);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::common::lector_csv::test_util::crear_archivo;
+    use common::contadores::Contadores;
 
     #[test]
-    fn test_instanciar_local_leyendo_stock() {
-        let dir_archivo_test = "test_local.csv";
-        let mut wtr = crear_archivo(dir_archivo_test);
-        wtr.write_record(["id_producto", "stock"]).unwrap();
-        wtr.write_record(["1", "30"]).unwrap();
-        wtr.flush().unwrap();
-
-        let local = instanciar_local(dir_archivo_test);
-
-        std::fs::remove_file(dir_archivo_test).unwrap();
-
-        assert!(local.is_ok());
+    fn test_metricas_del_local_en_formato_prometheus() {
+        let stock = vec![StockProducto::new_con_bloqueados(2, 15, 3)];
+        let resumen = ResumenLocal {
+            ordenes_en_progreso: 1,
+            cerrando: false,
+            contadores: Contadores {
+                ordenes_aceptadas: 4,
+                reinicios: [("JobOrdenes".to_string(), 2)].into(),
+                ..Contadores::default()
+            },
+        };
+        let estado_udp = EstadoUdp {
+            aceptar_ordenes: true,
+            fallas: ConfiguracionFallas::default(),
+            paquetes_descartados: 0,
+            respuestas_ocupado: 2,
+        };
 
-        let local = local.unwrap();
-        assert_eq!(local.productos_en_stock.len(), 1);
+        let texto = exponer_metricas(&stock, &resumen, &estado_udp, 5, 64);
+
+        assert!(texto.contains("# TYPE local_ordenes_aceptadas_total counter\n"));
+        assert!(texto.contains("local_ordenes_aceptadas_total 4\n"));
+        assert!(texto.contains("local_respuestas_ocupado_total 2\n"));
+        assert!(texto.contains("local_reinicios_total{actor=\"JobOrdenes\"} 2\n"));
+        assert!(!texto.contains("local_reinicios_total 0"));
+        assert!(texto.contains("local_ordenes_en_progreso 1\n"));
+        assert!(texto.contains("local_stock{id_producto=\"2\"} 15\n"));
+        assert!(texto.contains("local_bloqueados{id_producto=\"2\"} 3\n"));
+        assert!(texto.contains("local_mailbox_mensajes{actor=\"ActorLocal\"} 5\n"));
+        assert!(texto.contains("local_aceptando_ordenes 1\n"));
     }
 }
@@ -0,0 +1,45 @@
+use std::env;
+use std::process::exit;
+
+use donotunwrap::common;
+
+use common::historial::leer_historial;
+use common::invariantes::verificar;
+
+/// Junta los historiales de los nodos de una ejecución y verifica las invariantes.
+/// Sale con 0 si se cumplen, 1 si hay violaciones y 2 si no se pudieron leer los historiales
+fn main() {
+    let archivos: Vec<String> = env::args().skip(1).collect();
+    if archivos.is_empty() {
+        eprintln!("Uso: verificador <historial> [<historial> ...]");
+        exit(2);
+    }
+    let mut eventos = vec![];
+    for archivo in &archivos {
+        match leer_historial(archivo) {
+            Ok(leidos) => eventos.extend(leidos),
+            Err(e) => {
+                eprintln!("[Verificador - Error] {}", e);
+                exit(2);
+            }
+        }
+    }
+
+    let violaciones = verificar(&eventos);
+    if violaciones.is_empty() {
+        println!(
+            "{} eventos, se cumplen todas las invariantes",
+            eventos.len()
+        );
+        return;
+    }
+    for violacion in &violaciones {
+        println!("{}", violacion);
+    }
+    println!(
+        "{} eventos, {} violaciones",
+        eventos.len(),
+        violaciones.len()
+    );
+    exit(1);
+}